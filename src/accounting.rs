@@ -0,0 +1,488 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use time::OffsetDateTime;
+
+use crate::{
+    api::format_time,
+    extract::pcl_escape_length,
+    jobs::{Job, JobEvent, JobFormat},
+    settings::AppSettings,
+};
+
+// the jobs are read in chunks of this size, so that the memory does not grow with
+// the size of the job
+const CHUNK_SIZE: usize = 1 << 20;
+// PCL escape sequences starting this close to the end of a chunk are counted with
+// the next one
+const MAX_ESCAPE_SIZE: usize = 256;
+// the user name and the copies are looked for in the job header only
+const HEADER_SIZE: usize = 65536;
+const LINES_PER_PAGE: usize = 60;
+// how often the finished periods are looked for
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const JOURNAL_HEADER: &str = "time,connection,host,user,format,pages,copies,bytes,file";
+const REPORT_HEADER: &str = "period,host,user,jobs,pages,bytes,jobs_without_pages";
+
+const ESC: u8 = 0x1b;
+const FORM_FEED: u8 = 0x0c;
+
+// How often the accounting reports are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccountingPeriod {
+    #[default]
+    Off,
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl AccountingPeriod {
+    pub fn parse(text: &str) -> Option<AccountingPeriod> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "" | "off" => Some(AccountingPeriod::Off),
+            "daily" => Some(AccountingPeriod::Daily),
+            "weekly" => Some(AccountingPeriod::Weekly),
+            "monthly" => Some(AccountingPeriod::Monthly),
+            _ => None,
+        }
+    }
+
+    // "2024-05-01", "2024-W18" or "2024-05", the keys sort by time
+    fn key(&self, time: OffsetDateTime) -> Option<String> {
+        match self {
+            AccountingPeriod::Off => None,
+            AccountingPeriod::Daily => Some(format!(
+                "{}-{:02}-{:02}",
+                time.year(),
+                time.month() as u8,
+                time.day()
+            )),
+            AccountingPeriod::Weekly => {
+                let (year, week, _) = time.to_iso_week_date();
+                Some(format!("{year}-W{week:02}"))
+            }
+            AccountingPeriod::Monthly => Some(format!("{}-{:02}", time.year(), time.month() as u8)),
+        }
+    }
+}
+
+// Fields with commas, quotes or line breaks are quoted
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                fields.last_mut().unwrap().push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+// "@PJL SET USERNAME = "jdoe"" is ("USERNAME", "jdoe")
+fn pjl_variable(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    let command = line.get(..8)?;
+    if !command.eq_ignore_ascii_case("@PJL SET") {
+        return None;
+    }
+    let (name, value) = line[8..].split_once('=')?;
+    Some((
+        name.trim().to_ascii_uppercase(),
+        value.trim().trim_matches('"').trim().to_owned(),
+    ))
+}
+
+// The user and the copies set by the driver in the PJL header or the DSC comments
+fn job_header(data: &[u8]) -> (String, u32) {
+    let header = String::from_utf8_lossy(&data[..data.len().min(HEADER_SIZE)]);
+    let mut user = String::new();
+    let mut copies = 1;
+    for line in header.lines() {
+        if let Some((name, value)) = pjl_variable(line) {
+            match name.as_str() {
+                "USERNAME" => user = value,
+                "JOBATTR" => {
+                    if let Some(name) = value.strip_prefix("@JOAU=") {
+                        user = name.to_owned();
+                    }
+                }
+                "COPIES" | "QTY" => {
+                    copies = value.parse().ok().filter(|&n| n > 0).unwrap_or(copies)
+                }
+                _ => {}
+            }
+        } else if let Some(name) = line.strip_prefix("%%For:") {
+            if user.is_empty() {
+                user = name.trim().trim_matches(['(', ')']).to_owned();
+            }
+        }
+    }
+    (user, copies)
+}
+
+// The markers counted in the pages of the formats
+const POSTSCRIPT_MARKERS: &[&[u8]] = &[b"%%Page:", b"showpage"];
+const PDF_MARKERS: &[&[u8]] = &[
+    b"/Type/Page",
+    b"/Type/Pages",
+    b"/Type /Page",
+    b"/Type /Pages",
+];
+const ZPL_MARKERS: &[&[u8]] = &[b"^XZ"];
+
+fn count(data: &[u8], marker: &[u8]) -> usize {
+    data.windows(marker.len())
+        .filter(|window| *window == marker)
+        .count()
+}
+
+// Counts the pages of one copy while the job is read in chunks. The end of a chunk
+// which can be the start of a marker or of an escape sequence is carried over to
+// the next one.
+struct PageCounter {
+    format: JobFormat,
+    carry: Vec<u8>,
+    // the rest of a PCL binary payload which continues in the next chunk
+    skip: usize,
+    // the form feeds of PCL, the pages of text
+    pages: usize,
+    markers: &'static [&'static [u8]],
+    counts: Vec<usize>,
+    // the line breaks of the current text page and whether it has text
+    lines: usize,
+    has_text: bool,
+}
+
+impl PageCounter {
+    fn new(format: JobFormat) -> PageCounter {
+        let markers = match format {
+            JobFormat::PostScript => POSTSCRIPT_MARKERS,
+            JobFormat::Pdf => PDF_MARKERS,
+            JobFormat::Zpl => ZPL_MARKERS,
+            _ => &[],
+        };
+        PageCounter {
+            format,
+            carry: Vec::new(),
+            skip: 0,
+            pages: 0,
+            markers,
+            counts: vec![0; markers.len()],
+            lines: 0,
+            has_text: false,
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        match self.format {
+            JobFormat::Pjl | JobFormat::Pcl | JobFormat::EscP => self.feed_pcl(chunk, false),
+            JobFormat::Text => self.feed_text(chunk),
+            JobFormat::PostScript | JobFormat::Pdf | JobFormat::Zpl => self.feed_markers(chunk),
+            JobFormat::Unknown => {}
+        }
+    }
+
+    // Form feeds outside the escape sequences, the raster data may contain any byte
+    fn feed_pcl(&mut self, chunk: &[u8], last: bool) {
+        let skipped = self.skip.min(chunk.len());
+        self.skip -= skipped;
+        let mut data = std::mem::take(&mut self.carry);
+        data.extend_from_slice(&chunk[skipped..]);
+        let mut pos = 0;
+        while pos < data.len() {
+            match data[pos] {
+                ESC if !last && data.len() - pos < MAX_ESCAPE_SIZE => {
+                    self.carry = data.split_off(pos);
+                    return;
+                }
+                ESC => {
+                    let length = pcl_escape_length(&data[pos..]);
+                    self.skip = (pos + length).saturating_sub(data.len());
+                    pos += length;
+                }
+                FORM_FEED => {
+                    self.pages += 1;
+                    pos += 1;
+                }
+                _ => pos += 1,
+            }
+        }
+    }
+
+    // A marker is counted in the chunk where it ends
+    fn feed_markers(&mut self, chunk: &[u8]) {
+        let longest = self.markers.iter().map(|marker| marker.len()).max();
+        let carried = self.carry.len();
+        self.carry.extend_from_slice(chunk);
+        for (marker, total) in self.markers.iter().zip(&mut self.counts) {
+            let start = carried.saturating_sub(marker.len() - 1);
+            *total += count(&self.carry[start..], marker);
+        }
+        let keep = longest.unwrap_or(1) - 1;
+        self.carry.drain(..self.carry.len().saturating_sub(keep));
+    }
+
+    // The pages of text are separated by form feeds and have up to LINES_PER_PAGE
+    // lines, the blank pages are not counted
+    fn feed_text(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            match byte {
+                FORM_FEED => self.end_text_page(),
+                b'\n' => self.lines += 1,
+                byte if !byte.is_ascii_whitespace() => self.has_text = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn end_text_page(&mut self) {
+        if self.has_text {
+            self.pages += self.lines.div_ceil(LINES_PER_PAGE).max(1);
+        }
+        self.lines = 0;
+        self.has_text = false;
+    }
+
+    // None if the pages can't be told
+    fn finish(mut self) -> Option<usize> {
+        match self.format {
+            JobFormat::Pjl | JobFormat::Pcl | JobFormat::EscP => {
+                self.feed_pcl(&[], true);
+                Some(self.pages.max(1))
+            }
+            JobFormat::Text => {
+                self.end_text_page();
+                Some(self.pages.max(1))
+            }
+            // the page comments of the DSC, or the showpage operators without them
+            JobFormat::PostScript => Some(match self.counts[0] {
+                0 => self.counts[1].max(1),
+                pages => pages,
+            }),
+            // the page objects, the compressed object streams of PDF 1.5 hide them
+            JobFormat::Pdf => {
+                let pages = self.counts[0] - self.counts[1] + self.counts[2] - self.counts[3];
+                (pages > 0).then_some(pages)
+            }
+            // the labels end with ^XZ
+            JobFormat::Zpl => Some(self.counts[0].max(1)),
+            JobFormat::Unknown => None,
+        }
+    }
+}
+
+// The page count and the header of a saved job, the whole job is read in chunks
+fn estimate(path: &Path, format: JobFormat) -> io::Result<(Option<usize>, String, u32)> {
+    let mut file = fs::File::open(path)?;
+    let mut header = Vec::new();
+    (&mut file)
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+    let (user, copies) = job_header(&header);
+
+    let mut counter = PageCounter::new(format);
+    counter.feed(&header);
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        counter.feed(&buffer[..count]);
+    }
+    Ok((counter.finish(), user, copies))
+}
+
+#[derive(Default)]
+struct Totals {
+    jobs: u64,
+    pages: u64,
+    bytes: u64,
+    without_pages: u64,
+}
+
+// Every job is added to the journal of its period, the report of the period is
+// made from the journal when the period is over, so nothing is lost on a restart
+fn record(dir: &Path, key: &str, job: &Job) -> io::Result<()> {
+    let (pages, user, copies) = match job.path {
+        Some(ref path) => estimate(path, job.format)?,
+        None => (None, String::new(), 1),
+    };
+    fs::create_dir_all(dir)?;
+    let mut journal = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(format!("jobs-{key}.csv")))?;
+    let mut line = String::new();
+    if journal.metadata()?.len() == 0 {
+        line.push_str(JOURNAL_HEADER);
+        line.push_str("\r\n");
+    }
+    let fields = [
+        format_time(job.time),
+        job.connection.0.to_string(),
+        job.source.ip().to_string(),
+        user,
+        job.format.to_string(),
+        pages.map(|pages| pages.to_string()).unwrap_or_default(),
+        copies.to_string(),
+        job.size.to_string(),
+        job.path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default(),
+    ];
+    line.push_str(
+        &fields
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    line.push_str("\r\n");
+    journal.write_all(line.as_bytes())
+}
+
+// The jobs, the pages of all copies and the bytes per host and user
+fn write_report(journal: &Path, report: &Path, key: &str) -> io::Result<()> {
+    let mut totals = BTreeMap::<(String, String), Totals>::new();
+    for line in fs::read_to_string(journal)?.lines().skip(1) {
+        let fields = parse_csv_line(line);
+        let [_, _, host, user, _, pages, copies, bytes, ..] = fields.as_slice() else {
+            continue;
+        };
+        let entry = totals.entry((host.clone(), user.clone())).or_default();
+        entry.jobs += 1;
+        entry.bytes += bytes.parse::<u64>().unwrap_or(0);
+        match pages.parse::<u64>() {
+            Ok(pages) => entry.pages += pages * copies.parse::<u64>().unwrap_or(1),
+            Err(_) => entry.without_pages += 1,
+        }
+    }
+
+    let mut text = format!("{REPORT_HEADER}\r\n");
+    for ((host, user), totals) in totals {
+        text.push_str(&format!(
+            "{},{},{},{},{},{},{}\r\n",
+            csv_field(key),
+            csv_field(&host),
+            csv_field(&user),
+            totals.jobs,
+            totals.pages,
+            totals.bytes,
+            totals.without_pages
+        ));
+    }
+    fs::write(report, text)
+}
+
+// The reports of the periods before the current one which have none yet
+fn write_reports(dir: &Path, current_key: &str) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(key) = name
+            .strip_prefix("jobs-")
+            .and_then(|name| name.strip_suffix(".csv"))
+        else {
+            continue;
+        };
+        let report = dir.join(format!("accounting-{key}.csv"));
+        if key == current_key || report.exists() {
+            continue;
+        }
+        match write_report(&entry.path(), &report, key) {
+            Ok(()) => info!("Wrote the accounting report {}", report.display()),
+            Err(e) => warn!("{}: {}", report.display(), e),
+        }
+    }
+}
+
+fn run_accounting(settings: &AppSettings, jobs: mpsc::Receiver<Job>) {
+    let mut invalid_period = String::new();
+    loop {
+        let job = match jobs.recv_timeout(CHECK_INTERVAL) {
+            Ok(job) => Some(job),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        let period = match settings.accounting_period() {
+            Some(period) => {
+                invalid_period.clear();
+                period
+            }
+            None => {
+                let text = settings.accounting_period.read().unwrap().clone();
+                if text != invalid_period {
+                    error!("Invalid accounting_period value: {}", text);
+                    invalid_period = text;
+                }
+                continue;
+            }
+        };
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let Some(current_key) = period.key(now) else {
+            continue;
+        };
+        let dir = settings.accounting_dir();
+        if let Some(job) = job {
+            let key = period.key(job.time).unwrap_or_else(|| current_key.clone());
+            if let Err(e) = record(&dir, &key, &job) {
+                warn!("Cannot account the job {}: {}", job.connection, e);
+            }
+        }
+        write_reports(&dir, &current_key);
+    }
+}
+
+// Keeps the journal of the received jobs with the estimated pages and writes the
+// accounting reports per host and user at the end of every period, so that the
+// jobs can be reconciled with the billing of a print management system
+pub struct Accountant {
+    jobs: Sender<Job>,
+}
+
+impl Accountant {
+    pub fn new(settings: Arc<AppSettings>) -> Accountant {
+        let (jobs, receiver) = mpsc::channel();
+        std::thread::spawn(move || run_accounting(&settings, receiver));
+        Accountant { jobs }
+    }
+
+    // The files are read on the thread of the accounting
+    pub fn handle_event(&self, event: &JobEvent) {
+        if let JobEvent::Completed(job) = event {
+            let _ = self.jobs.send(job.clone());
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+// the transfer rate is checked over windows of this length
+const RATE_WINDOW: Duration = Duration::from_secs(30);
+// how long the shed mode lasts after the last connection rejected at the limit
+const SHED_DURATION: Duration = Duration::from_secs(60);
+// the idle timeout in the shed mode, so that the stalled connections make room
+const SHED_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What happens to a job which is still being received at its deadline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeadlineAction {
+    // the connection is closed and the data received so far is the job
+    #[default]
+    Complete,
+    // the job fails like one whose connection was broken
+    Abort,
+}
+
+impl DeadlineAction {
+    pub fn parse(text: &str) -> Option<DeadlineAction> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "" | "complete" => Some(DeadlineAction::Complete),
+            "abort" => Some(DeadlineAction::Abort),
+            _ => None,
+        }
+    }
+}
+
+// The limits of the connections of a listener, 0 disables a limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    pub max_connections: u32,
+    pub max_host_connections: u32,
+    // a connection without data for this long is closed
+    pub idle_timeout_seconds: u32,
+    // a connection which sends less on average over RATE_WINDOW is closed
+    pub min_bytes_per_second: u32,
+    // the longest a job may take however fast its data comes
+    pub max_job_minutes: u32,
+    pub deadline_action: DeadlineAction,
+}
+
+#[derive(Default)]
+struct State {
+    total: u32,
+    hosts: HashMap<IpAddr, u32>,
+    // the hosts which completed a job, only they are admitted in the shed mode
+    known_hosts: HashSet<IpAddr>,
+    shed_until: Option<Instant>,
+}
+
+// Decides which connections a listener accepts. When the connection limit is hit,
+// e.g. by a flood of connections which send nothing, the listener sheds the load:
+// for a while only the hosts which have sent a job before are accepted and the
+// idle connections are closed sooner.
+pub struct Admission {
+    limits: ConnectionLimits,
+    state: Mutex<State>,
+}
+
+// An admitted connection, it is counted until it is dropped
+pub struct Ticket {
+    admission: Arc<Admission>,
+    source: IpAddr,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut state = self.admission.state.lock().unwrap();
+        state.total -= 1;
+        if let Some(count) = state.hosts.get_mut(&self.source) {
+            *count -= 1;
+            if *count == 0 {
+                state.hosts.remove(&self.source);
+            }
+        }
+    }
+}
+
+impl Ticket {
+    pub fn admission(&self) -> &Admission {
+        &self.admission
+    }
+
+    // The host is admitted in the shed mode from now on
+    pub fn job_completed(&self) {
+        let mut state = self.admission.state.lock().unwrap();
+        state.known_hosts.insert(self.source);
+    }
+}
+
+impl Admission {
+    pub fn new(limits: ConnectionLimits) -> Arc<Admission> {
+        Arc::new(Admission {
+            limits,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    fn is_shedding(state: &mut State) -> bool {
+        match state.shed_until {
+            Some(until) if until > Instant::now() => true,
+            Some(_) => {
+                state.shed_until = None;
+                warn!("The connection load is normal again");
+                false
+            }
+            None => false,
+        }
+    }
+
+    // Err with the reason if the connection is to be closed right away
+    pub fn admit(self: &Arc<Self>, source: IpAddr) -> Result<Ticket, String> {
+        let mut state = self.state.lock().unwrap();
+        let limits = &self.limits;
+        if limits.max_connections > 0 && state.total >= limits.max_connections {
+            if !Self::is_shedding(&mut state) {
+                warn!(
+                    "{} connections are open, accepting only the known hosts",
+                    state.total
+                );
+            }
+            state.shed_until = Some(Instant::now() + SHED_DURATION);
+            return Err(format!(
+                "the limit of {} connections is reached",
+                limits.max_connections
+            ));
+        }
+        if Self::is_shedding(&mut state) && !state.known_hosts.contains(&source) {
+            return Err("only the known hosts are accepted under high load".to_owned());
+        }
+        let host_count = state.hosts.get(&source).copied().unwrap_or_default();
+        if limits.max_host_connections > 0 && host_count >= limits.max_host_connections {
+            return Err(format!(
+                "the limit of {} connections per host is reached",
+                limits.max_host_connections
+            ));
+        }
+        state.total += 1;
+        state.hosts.insert(source, host_count + 1);
+        Ok(Ticket {
+            admission: self.clone(),
+            source,
+        })
+    }
+
+    fn idle_timeout(&self) -> Option<Duration> {
+        let timeout = (self.limits.idle_timeout_seconds > 0)
+            .then(|| Duration::from_secs(self.limits.idle_timeout_seconds as u64));
+        if Self::is_shedding(&mut self.state.lock().unwrap()) {
+            Some(timeout.map_or(SHED_IDLE_TIMEOUT, |timeout| timeout.min(SHED_IDLE_TIMEOUT)))
+        } else {
+            timeout
+        }
+    }
+}
+
+// Watches the data of one connection and fails when it stalls or is too slow, or
+// when the job takes too long and is to be aborted
+pub struct TransferGuard<'a> {
+    admission: &'a Admission,
+    deadline: Option<Instant>,
+    last_data: Instant,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl<'a> TransferGuard<'a> {
+    pub fn new(admission: &'a Admission) -> TransferGuard<'a> {
+        let now = Instant::now();
+        let max_job_minutes = admission.limits.max_job_minutes;
+        TransferGuard {
+            admission,
+            deadline: (max_job_minutes > 0)
+                .then(|| now + Duration::from_secs(max_job_minutes as u64 * 60)),
+            last_data: now,
+            window_start: now,
+            window_bytes: 0,
+        }
+    }
+
+    // The receiving stops then, the job is either complete or update has failed
+    pub fn is_past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // How long to wait for data before the next check, None to wait until it comes.
+    // The shed mode can start while waiting, so the wait is limited.
+    pub fn wait_time(&self) -> Option<Duration> {
+        let rate_check = (self.admission.limits.min_bytes_per_second > 0).then_some(RATE_WINDOW);
+        let idle = self
+            .admission
+            .idle_timeout()
+            .or((self.admission.limits.max_connections > 0).then_some(SHED_IDLE_TIMEOUT));
+        let deadline = self.deadline.map(|deadline| {
+            // a zero timeout is not allowed for the sockets
+            deadline
+                .saturating_duration_since(Instant::now())
+                .max(Duration::from_millis(1))
+        });
+        [idle, rate_check, deadline].into_iter().flatten().min()
+    }
+
+    // Fails if the deadline is passed and the job is to be aborted
+    pub fn check_deadline(&self) -> io::Result<()> {
+        let limits = &self.admission.limits;
+        if limits.deadline_action == DeadlineAction::Abort && self.is_past_deadline() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "the job has taken longer than {} minutes",
+                    limits.max_job_minutes
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    // Called with the size of the received data, 0 when the wait timed out
+    pub fn update(&mut self, received: usize) -> io::Result<()> {
+        self.check_deadline()?;
+        let now = Instant::now();
+        if received > 0 {
+            self.last_data = now;
+            self.window_bytes += received as u64;
+        }
+        if let Some(idle) = self.admission.idle_timeout() {
+            if now - self.last_data >= idle {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no data for {} seconds", idle.as_secs()),
+                ));
+            }
+        }
+        let min_rate = self.admission.limits.min_bytes_per_second as u64;
+        let elapsed = now - self.window_start;
+        if min_rate > 0 && elapsed >= RATE_WINDOW {
+            if self.window_bytes < min_rate * elapsed.as_secs() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "{} bytes in {} seconds is slower than the minimum rate",
+                        self.window_bytes,
+                        elapsed.as_secs()
+                    ),
+                ));
+            }
+            self.window_start = now;
+            self.window_bytes = 0;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,477 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    thread::JoinHandle,
+};
+
+use log::{debug, error, info, warn};
+use time::OffsetDateTime;
+
+use crate::{
+    extract,
+    http::{json_string, Body, ConnectionSlots, Request, Response},
+    jobs::{ActiveTransfers, Job, JobEvent, ListenerState},
+    logger::WindowLogger,
+    resend,
+    settings::AppSettings,
+    ui::window::{user_message, WindowRef},
+    util,
+};
+
+// The web dashboard, it asks for the token and polls the API
+const DASHBOARD: &str = include_str!("dashboard.html");
+// more connections at once are answered with 503 right away
+const MAX_CONNECTIONS: usize = 16;
+
+// Posted to the main window by the API and the control pipe, wparam is 1 to pause
+// the listener and 0 to resume it
+pub const WM_API_PAUSE_LISTENER: u32 = user_message(9);
+// Posted to the main window when a job is deleted by the API, wparam is the job id
+pub const WM_API_JOB_DELETED: u32 = user_message(10);
+
+// The jobs received in this session and the state of the main listener, the job
+// ids are the connection numbers
+#[derive(Default)]
+pub struct JobHistory {
+    jobs: RwLock<Vec<Job>>,
+    listener: RwLock<Option<ListenerState>>,
+}
+
+impl JobHistory {
+    pub fn handle_event(&self, event: &JobEvent) {
+        match event {
+            JobEvent::Completed(job) => self.jobs.write().unwrap().push(job.clone()),
+            JobEvent::Listener(state) => *self.listener.write().unwrap() = Some(state.clone()),
+            _ => {}
+        }
+    }
+
+    pub fn find(&self, id: u32) -> Option<Job> {
+        self.jobs
+            .read()
+            .unwrap()
+            .iter()
+            .find(|job| job.connection.0 == id)
+            .cloned()
+    }
+
+    // The jobs received after the job with the given id
+    pub fn jobs_since(&self, since: u32) -> Vec<Job> {
+        self.jobs
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|job| job.connection.0 > since)
+            .cloned()
+            .collect()
+    }
+
+    pub fn listener_state(&self) -> Option<ListenerState> {
+        self.listener.read().unwrap().clone()
+    }
+
+    // The number of jobs and their total size
+    pub fn totals(&self) -> (usize, u64) {
+        let jobs = self.jobs.read().unwrap();
+        (jobs.len(), jobs.iter().map(|job| job.size).sum())
+    }
+
+    fn remove(&self, id: u32) {
+        self.jobs
+            .write()
+            .unwrap()
+            .retain(|job| job.connection.0 != id);
+    }
+}
+
+// RFC 3339 with the local offset, e.g. 2024-05-01T12:30:00+02:00
+pub fn format_time(time: OffsetDateTime) -> String {
+    let (hour, minute, second) = time.to_hms();
+    let (offset_hours, offset_minutes, _) = time.offset().as_hms();
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        hour,
+        minute,
+        second,
+        if time.offset().is_negative() {
+            '-'
+        } else {
+            '+'
+        },
+        offset_hours.unsigned_abs(),
+        offset_minutes.unsigned_abs()
+    )
+}
+
+pub fn job_json(job: &Job) -> String {
+    let path = job
+        .path
+        .as_ref()
+        .map(|path| json_string(&path.display().to_string()))
+        .unwrap_or_else(|| "null".to_owned());
+    format!(
+        "{{\"id\":{},\"time\":{},\"source\":{},\"size\":{},\"format\":{},\"path\":{},\"peak_memory\":{},\
+         \"duration_ms\":{},\"average_bytes_per_second\":{},\"peak_bytes_per_second\":{},\
+         \"stalls\":{}}}",
+        job.connection.0,
+        json_string(&format_time(job.time)),
+        json_string(&job.source.to_string()),
+        job.size,
+        json_string(&job.format.to_string()),
+        path,
+        job.peak_memory,
+        job.transfer.duration_ms,
+        job.transfer.average_bytes_per_second,
+        job.transfer.peak_bytes_per_second,
+        job.transfer.stalls
+    )
+}
+
+// Shared by the threads of the API server
+pub struct ApiContext {
+    pub settings: Arc<AppSettings>,
+    pub history: Arc<JobHistory>,
+    pub transfers: Arc<ActiveTransfers>,
+    pub paused: Arc<AtomicBool>,
+    pub window: WindowRef,
+    pub logger: Option<&'static WindowLogger>,
+}
+
+impl ApiContext {
+    // Every request needs the "Authorization: Bearer <api_token>" header
+    fn is_authorized(&self, request: &Request) -> bool {
+        self.is_valid_authorization(request.header("Authorization"))
+    }
+
+    // The value of the Authorization header, also used by the gRPC service
+    pub fn is_valid_authorization(&self, authorization: Option<&str>) -> bool {
+        let token = self.settings.api_token.read().unwrap();
+        !token.is_empty()
+            && authorization
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|value| {
+                    util::constant_time_eq(value.trim().as_bytes(), token.as_bytes())
+                })
+    }
+
+    fn respond(&self, request: &Request) -> Response {
+        // the page itself holds no data
+        if request.method == "GET" && request.path == "/" {
+            return Response::new(
+                200,
+                "text/html; charset=utf-8",
+                Body::Bytes(DASHBOARD.as_bytes().to_vec()),
+            );
+        }
+        if !self.is_authorized(request) {
+            return Response::error(401, "invalid or missing token")
+                .header("WWW-Authenticate", "Bearer".to_owned());
+        }
+
+        let segments = request
+            .path
+            .trim_matches('/')
+            .split('/')
+            .collect::<Vec<_>>();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["api", "jobs"]) => self.list_jobs(request),
+            ("GET", ["api", "jobs", id]) => {
+                self.with_job(id, |job| Response::json(200, job_json(&job)))
+            }
+            ("GET", ["api", "jobs", id, "content"]) => self.with_job(id, Self::job_content),
+            ("GET", ["api", "jobs", id, "text"]) => self.with_job(id, |job| self.job_text(job)),
+            ("DELETE", ["api", "jobs", id]) => self.with_job(id, |job| self.delete_job(job)),
+            ("POST", ["api", "jobs", id, "resend"]) => {
+                self.with_job(id, |job| self.resend_job(job, request))
+            }
+            ("POST", ["api", "listener", "pause"]) => self.set_paused(true),
+            ("POST", ["api", "listener", "resume"]) => self.set_paused(false),
+            ("GET", ["api", "stats"]) => self.stats(),
+            ("GET", ["api", "log"]) => self.log(request),
+            ("GET", ["api", "settings"]) => Response::new(
+                200,
+                "text/plain; charset=utf-8",
+                Body::Bytes(self.settings.to_text().into_bytes()),
+            ),
+            (
+                _,
+                ["api", "jobs", ..]
+                | ["api", "listener", ..]
+                | ["api", "stats" | "log" | "settings"],
+            ) => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    fn with_job<F>(&self, id: &str, f: F) -> Response
+    where
+        F: FnOnce(Job) -> Response,
+    {
+        match id.parse().ok().and_then(|id| self.history.find(id)) {
+            Some(job) => f(job),
+            None => Response::error(404, "no such job"),
+        }
+    }
+
+    // ?since=<id> returns only the jobs received after that job
+    fn list_jobs(&self, request: &Request) -> Response {
+        let since = match request.query("since").map(str::parse::<u32>) {
+            Some(Ok(id)) => id,
+            Some(Err(_)) => return Response::error(400, "invalid since parameter"),
+            None => 0,
+        };
+        let jobs = self
+            .history
+            .jobs_since(since)
+            .iter()
+            .map(job_json)
+            .collect::<Vec<_>>();
+        Response::json(200, format!("[{}]", jobs.join(",")))
+    }
+
+    fn job_content(job: Job) -> Response {
+        let Some(path) = job.path else {
+            return Response::error(409, "the job was discarded");
+        };
+        match fs::File::open(&path).and_then(|file| Ok((file.metadata()?.len(), file))) {
+            Ok((length, file)) => {
+                Response::new(200, "application/octet-stream", Body::File(file, length)).header(
+                    "Content-Disposition",
+                    format!(
+                        "attachment; filename={}",
+                        json_string(&path.file_name().unwrap_or_default().to_string_lossy())
+                    ),
+                )
+            }
+            Err(e) => Response::error(404, &e.to_string()),
+        }
+    }
+
+    // The extracted text as in the preview, with the redact_patterns applied
+    fn job_text(&self, job: Job) -> Response {
+        let Some(path) = job.path else {
+            return Response::error(409, "the job was discarded");
+        };
+        match extract::read_text(job.format, &path) {
+            Ok(text) => Response::new(
+                200,
+                "text/plain; charset=utf-8",
+                Body::Bytes(self.settings.redactor().redact(&text).into_bytes()),
+            ),
+            Err(e) => Response::error(404, &e.to_string()),
+        }
+    }
+
+    fn delete_job(&self, job: Job) -> Response {
+        match self.delete(&job) {
+            Ok(()) => Response::no_content(),
+            Err(e) => Response::error(500, &e.to_string()),
+        }
+    }
+
+    // Deletes the saved file and removes the job from the history and the job list
+    pub fn delete(&self, job: &Job) -> io::Result<()> {
+        if let Some(ref path) = job.path {
+            fs::remove_file(path)?;
+            info!("Deleted {} over the API", path.display());
+        }
+        self.history.remove(job.connection.0);
+        let _ = self
+            .window
+            .post_user_message(WM_API_JOB_DELETED, job.connection.0 as usize, 0);
+        Ok(())
+    }
+
+    // ?target=host:port overrides the resend target of the settings
+    fn resend_job(&self, job: Job, request: &Request) -> Response {
+        let Some(path) = job.path else {
+            return Response::error(409, "the job was discarded");
+        };
+        let target = match request.query("target") {
+            Some(target) => target.to_owned(),
+            None => self.settings.resend_target.read().unwrap().clone(),
+        };
+        if target.is_empty() {
+            return Response::error(400, "no resend target");
+        }
+        resend::resend_files(vec![path], target, self.window.clone());
+        Response::json(202, "{}".to_owned())
+    }
+
+    fn set_paused(&self, flag: bool) -> Response {
+        self.pause_listener(flag);
+        Response::no_content()
+    }
+
+    // The flag is changed right away, the main window updates the menu and the status
+    pub fn pause_listener(&self, flag: bool) {
+        self.paused.store(flag, Ordering::SeqCst);
+        let _ = self
+            .window
+            .post_user_message(WM_API_PAUSE_LISTENER, flag as usize, 0);
+    }
+
+    // ?since=<next> returns only the lines added after the previous request
+    fn log(&self, request: &Request) -> Response {
+        let since = match request.query("since").map(str::parse::<u64>) {
+            Some(Ok(since)) => since,
+            Some(Err(_)) => return Response::error(400, "invalid since parameter"),
+            None => 0,
+        };
+        let (lines, next) = self
+            .logger
+            .map(|logger| logger.tail(since))
+            .unwrap_or_default();
+        let lines = lines
+            .iter()
+            .map(|line| json_string(line))
+            .collect::<Vec<_>>();
+        Response::json(
+            200,
+            format!("{{\"lines\":[{}],\"next\":{}}}", lines.join(","), next),
+        )
+    }
+
+    fn stats(&self) -> Response {
+        let jobs = self.history.jobs.read().unwrap();
+        let mut formats = BTreeMap::new();
+        for job in jobs.iter() {
+            *formats.entry(job.format.to_string()).or_insert(0u64) += 1;
+        }
+        let formats = formats
+            .iter()
+            .map(|(format, count)| format!("{}:{}", json_string(format), count))
+            .collect::<Vec<_>>();
+
+        let listener = match *self.history.listener.read().unwrap() {
+            Some(ListenerState::Listening(port)) => {
+                format!("{{\"state\":\"listening\",\"port\":{port}}}")
+            }
+            Some(ListenerState::Paused) => "{\"state\":\"paused\"}".to_owned(),
+            Some(ListenerState::Failed(ref e)) => {
+                format!("{{\"state\":\"failed\",\"error\":{}}}", json_string(e))
+            }
+            None => "{\"state\":\"starting\"}".to_owned(),
+        };
+
+        Response::json(
+            200,
+            format!(
+                "{{\"jobs\":{},\"bytes\":{},\"saved\":{},\"active\":{},\"formats\":{{{}}},\"listener\":{}}}",
+                jobs.len(),
+                jobs.iter().map(|job| job.size).sum::<u64>(),
+                jobs.iter().filter(|job| job.path.is_some()).count(),
+                self.transfers.count(),
+                formats.join(","),
+                listener
+            ),
+        )
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, context: &ApiContext) {
+    let response = match Request::read(&stream) {
+        Ok(request) => {
+            let response = context.respond(&request);
+            debug!("{} {} {}", request.method, request.path, response.status);
+            response
+        }
+        Err(e) => Response::error(400, &e.to_string()),
+    };
+    if let Err(e) = response.write_to(&mut stream) {
+        debug!("{}", e);
+    }
+}
+
+fn run_api_server(address: SocketAddr, stopped: Arc<AtomicBool>, context: Arc<ApiContext>) {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Cannot start the API server on {}: {}", address, e);
+            return;
+        }
+    };
+    info!(
+        "Started the API server on http://{}, the token is stored in {}",
+        address,
+        context.settings.config_path().display()
+    );
+
+    let slots = ConnectionSlots::new(MAX_CONNECTIONS);
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            info!("Stopped the API server on {}", address);
+            return;
+        }
+        match stream {
+            Ok(mut stream) => {
+                let Some(slot) = slots.take() else {
+                    debug!(
+                        "Rejected connection from {}: the limit of {} connections is reached",
+                        stream
+                            .peer_addr()
+                            .map(|a| a.to_string())
+                            .unwrap_or_default(),
+                        MAX_CONNECTIONS
+                    );
+                    let _ = Response::error(503, "too many connections").write_to(&mut stream);
+                    continue;
+                };
+                let context = context.clone();
+                std::thread::spawn(move || {
+                    handle_connection(stream, &context);
+                    drop(slot);
+                });
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+// The HTTP API thread, replaced by a new one when the address is changed
+pub struct ApiServer {
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ApiServer {
+    pub fn start(address: SocketAddr, context: Arc<ApiContext>) -> ApiServer {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            std::thread::spawn(move || run_api_server(address, stopped, context))
+        };
+        ApiServer {
+            address,
+            stopped,
+            thread,
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Woken up like RawListener::stop
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if !self.thread.is_finished() {
+            let ip = if self.address.ip().is_unspecified() {
+                IpAddr::V4(Ipv4Addr::LOCALHOST)
+            } else {
+                self.address.ip()
+            };
+            let _ = TcpStream::connect((ip, self.address.port()));
+        }
+        let _ = self.thread.join();
+    }
+}
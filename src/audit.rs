@@ -0,0 +1,526 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use time::OffsetDateTime;
+use windows::{
+    core::{w, HRESULT, PCWSTR},
+    Win32::{
+        Foundation::{ERROR_HANDLE_EOF, ERROR_INSUFFICIENT_BUFFER, HANDLE},
+        Security::Cryptography::{
+            BCryptCreateHash, BCryptDestroyHash, BCryptFinishHash, BCryptHash, BCryptHashData,
+            BCRYPT_HASH_HANDLE, BCRYPT_SHA256_ALG_HANDLE,
+        },
+        System::EventLog::{
+            CloseEventLog, DeregisterEventSource, OpenEventLogW, ReadEventLogW,
+            RegisterEventSourceW, ReportEventW, EVENTLOGRECORD, EVENTLOG_INFORMATION_TYPE,
+            EVENTLOG_SEQUENTIAL_READ, READ_EVENT_LOG_READ_FLAGS,
+        },
+    },
+};
+
+use crate::{
+    api::{format_time, job_json},
+    http::json_string,
+    http_client,
+    jobs::{JobEvent, ListenerState},
+    settings::AppSettings,
+    utf16z,
+};
+
+// The head of the chain is anchored outside of the log this often if entries were
+// added since the last anchor
+const ANCHOR_INTERVAL: Duration = Duration::from_secs(3600);
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const ANCHOR_EVENT_ID: u32 = 1;
+// EVENTLOG_SEQUENTIAL_READ | EVENTLOG_BACKWARDS_READ, the latter is not defined by
+// the bindings
+const READ_BACKWARDS: READ_EVENT_LOG_READ_FLAGS =
+    READ_EVENT_LOG_READ_FLAGS(EVENTLOG_SEQUENTIAL_READ.0 | 8);
+// how long the exit waits for the final anchor
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+const HASH_FIELD: &str = ",\"hash\":\"";
+const CHUNK_SIZE: usize = 65536;
+// the "prev" of the first entry
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+fn to_hex(hash: &[u8]) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn sha256(data: &[u8]) -> io::Result<String> {
+    let mut hash = [0; 32];
+    unsafe { BCryptHash(BCRYPT_SHA256_ALG_HANDLE, None, data, &mut hash) }.ok()?;
+    Ok(to_hex(&hash))
+}
+
+// Destroys the CNG hash object when dropped
+struct HashHandle(BCRYPT_HASH_HANDLE);
+
+impl Drop for HashHandle {
+    fn drop(&mut self) {
+        let _ = unsafe { BCryptDestroyHash(self.0) };
+    }
+}
+
+// Also used to check the downloaded installers
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut handle = HashHandle(BCRYPT_HASH_HANDLE::default());
+    unsafe { BCryptCreateHash(BCRYPT_SHA256_ALG_HANDLE, &mut handle.0, None, None, 0) }.ok()?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        unsafe { BCryptHashData(handle.0, &buffer[..count], 0) }.ok()?;
+    }
+    let mut hash = [0; 32];
+    unsafe { BCryptFinishHash(handle.0, &mut hash, 0) }.ok()?;
+    Ok(to_hex(&hash))
+}
+
+fn is_hash(text: &str) -> bool {
+    text.len() == GENESIS_HASH.len() && text.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+// The sequence number and the hash of an entry line. The hash is the SHA-256 of the
+// line without the hash field, which ends with the hash of the previous entry.
+fn parse_entry(line: &str) -> Option<(u64, &str, &str)> {
+    let (body, rest) = line.rsplit_once(HASH_FIELD)?;
+    let hash = rest.strip_suffix("\"}").filter(|hash| is_hash(hash))?;
+    let (_, prev) = body.rsplit_once(",\"prev\":\"")?;
+    let prev = prev.strip_suffix('"').filter(|prev| is_hash(prev))?;
+    let seq = body
+        .strip_prefix("{\"seq\":")?
+        .split(',')
+        .next()?
+        .parse()
+        .ok()?;
+    Some((seq, prev, hash))
+}
+
+fn entry_body(line: &str) -> Option<String> {
+    line.rsplit_once(HASH_FIELD)
+        .map(|(body, _)| format!("{body}}}"))
+}
+
+// Closes the event log when dropped
+struct EventLogHandle(HANDLE);
+
+impl Drop for EventLogHandle {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseEventLog(self.0) };
+    }
+}
+
+// A zero-terminated UTF-16 string at the start of the data
+fn wide_string(data: &[u8]) -> String {
+    let chars = data
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .take_while(|c| *c != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&chars)
+}
+
+// The entry number and the hash of an anchor of the log in the text of an event,
+// "<text> <path> <word> <seq> <word> <hash>" in any of the languages
+fn parse_anchor(message: &str, path: &str) -> Option<(u64, String)> {
+    let mut parts = message.rsplitn(5, ' ');
+    let hash = parts.next().filter(|hash| is_hash(hash))?;
+    let seq = parts.nth(1)?.parse().ok()?;
+    parts.next()?;
+    parts
+        .next()?
+        .ends_with(path)
+        .then(|| (seq, hash.to_owned()))
+}
+
+// The latest anchor of the log in the Application event log, read from the newest
+// event backwards
+fn last_anchor(path: &Path) -> io::Result<Option<(u64, String)>> {
+    let log = EventLogHandle(unsafe { OpenEventLogW(PCWSTR::null(), w!("Application")) }?);
+    let path = path.display().to_string();
+    // of u32 for the alignment of the records
+    let mut buffer = vec![0u32; 16384];
+    loop {
+        let mut read = 0;
+        let mut needed = 0;
+        let result = unsafe {
+            ReadEventLogW(
+                log.0,
+                READ_BACKWARDS,
+                0,
+                buffer.as_mut_ptr().cast(),
+                (buffer.len() * 4) as u32,
+                &mut read,
+                &mut needed,
+            )
+        };
+        match result {
+            Ok(()) => {}
+            Err(e) if e.code() == HRESULT::from_win32(ERROR_INSUFFICIENT_BUFFER.0) => {
+                buffer.resize(needed as usize / 4 + 1, 0);
+                continue;
+            }
+            Err(e) if e.code() == HRESULT::from_win32(ERROR_HANDLE_EOF.0) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let bytes =
+            unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), read as usize) };
+        let mut offset = 0;
+        while offset + size_of::<EVENTLOGRECORD>() <= bytes.len() {
+            let record = unsafe {
+                bytes
+                    .as_ptr()
+                    .add(offset)
+                    .cast::<EVENTLOGRECORD>()
+                    .read_unaligned()
+            };
+            let length = record.Length as usize;
+            if length < size_of::<EVENTLOGRECORD>() || offset + length > bytes.len() {
+                break;
+            }
+            let data = &bytes[offset..offset + length];
+            offset += length;
+            // the source name follows the fixed part of the record
+            if record.EventID & 0xffff != ANCHOR_EVENT_ID
+                || record.NumStrings == 0
+                || wide_string(&data[size_of::<EVENTLOGRECORD>()..]) != "MiniRAW NG"
+            {
+                continue;
+            }
+            let message = wide_string(data.get(record.StringOffset as usize..).unwrap_or_default());
+            if let Some(anchor) = parse_anchor(&message, &path) {
+                return Ok(Some(anchor));
+            }
+        }
+    }
+}
+
+// Checks every entry of the log and compares the entry of the last anchor in the
+// event log with it, so that a log which was cut or replaced is found even if its
+// chain is valid. Returns the number of the entries, the hash of the last one and
+// the entry of the anchor if there is one.
+pub fn verify(path: &Path) -> Result<(u64, String, Option<u64>), String> {
+    let anchor =
+        last_anchor(path).map_err(|e| format!("cannot read the Application event log: {e}"))?;
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut count = 0;
+    let mut head = GENESIS_HASH.to_owned();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let number = index + 1;
+        let (seq, prev, hash) =
+            parse_entry(&line).ok_or_else(|| format!("line {number}: invalid entry"))?;
+        if seq != count + 1 {
+            return Err(format!("line {number}: sequence {seq} follows {count}"));
+        }
+        if prev != head {
+            return Err(format!(
+                "line {number}: the chain is broken, the previous hash does not match"
+            ));
+        }
+        let body = entry_body(&line).unwrap_or_default();
+        if sha256(body.as_bytes()).map_err(|e| e.to_string())? != hash {
+            return Err(format!("line {number}: the entry was altered"));
+        }
+        if anchor
+            .as_ref()
+            .is_some_and(|(anchor_seq, anchor_hash)| *anchor_seq == seq && anchor_hash != hash)
+        {
+            return Err(format!(
+                "line {number}: the hash does not match the anchor in the event log"
+            ));
+        }
+        count = seq;
+        head = hash.to_owned();
+    }
+    match anchor {
+        Some((anchor_seq, _)) if anchor_seq > count => Err(format!(
+            "the log ends at entry {count} before the anchored entry {anchor_seq}"
+        )),
+        anchor => Ok((count, head, anchor.map(|(seq, _)| seq))),
+    }
+}
+
+// The open log with the head of its chain
+struct Chain {
+    path: PathBuf,
+    file: File,
+    seq: u64,
+    head: String,
+    anchored_seq: u64,
+}
+
+impl Chain {
+    // Continues the chain of an existing log. Damaged entries at the end are not
+    // repaired, the chain continues from the last valid entry with a chain_break
+    // entry, so that the verification still shows the damage.
+    fn open(path: &Path) -> io::Result<Chain> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut damaged = 0;
+        let mut last = None;
+        for line in text.lines().rev().filter(|line| !line.trim().is_empty()) {
+            match parse_entry(line) {
+                Some((seq, _, hash)) => {
+                    last = Some((seq, hash.to_owned()));
+                    break;
+                }
+                None => damaged += 1,
+            }
+        }
+        let (seq, head) = last.unwrap_or((0, GENESIS_HASH.to_owned()));
+
+        // only appending, the entries are never rewritten
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        let mut chain = Chain {
+            path: path.to_owned(),
+            file: file.try_clone()?,
+            seq,
+            head,
+            anchored_seq: seq,
+        };
+        if damaged > 0 {
+            error!(
+                "The audit log {} ends with {} damaged lines, a chain break is recorded",
+                path.display(),
+                damaged
+            );
+            // a line cut off by a crash is not continued by the next entry
+            if !text.ends_with('\n') {
+                file.write_all(b"\n")?;
+            }
+            chain.append("chain_break", &format!(",\"damaged_lines\":{damaged}"))?;
+        }
+        Ok(chain)
+    }
+
+    // The fields are the JSON members after the event name
+    fn append(&mut self, event: &str, fields: &str) -> io::Result<()> {
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let seq = self.seq + 1;
+        let body = format!(
+            "{{\"seq\":{},\"time\":{},\"event\":{}{},\"prev\":\"{}\"}}",
+            seq,
+            json_string(&format_time(now)),
+            json_string(event),
+            fields,
+            self.head
+        );
+        let hash = sha256(body.as_bytes())?;
+        let line = format!("{}{}{}\"}}\n", &body[..body.len() - 1], HASH_FIELD, hash);
+        self.file.write_all(line.as_bytes())?;
+        // an entry is on the disk before the next one is chained to it
+        self.file.sync_data()?;
+        self.seq = seq;
+        self.head = hash;
+        Ok(())
+    }
+
+    fn append_event(&mut self, event: &JobEvent) -> io::Result<()> {
+        match event {
+            JobEvent::Completed(job) => {
+                // the hash of the saved file ties the entry to the content of the job
+                let sha256 = match &job.path {
+                    Some(path) => match file_sha256(path) {
+                        Ok(hash) => json_string(&hash),
+                        Err(e) => {
+                            warn!("{}: {}", path.display(), e);
+                            "null".to_owned()
+                        }
+                    },
+                    None => "null".to_owned(),
+                };
+                self.append(
+                    "completed",
+                    &format!(",\"job\":{},\"sha256\":{}", job_json(job), sha256),
+                )
+            }
+            JobEvent::Aborted { source } => self.append(
+                "aborted",
+                &format!(",\"source\":{}", json_string(&source.to_string())),
+            ),
+            JobEvent::Listener(state) => {
+                let state = match state {
+                    ListenerState::Listening(port) => {
+                        format!("\"listening\",\"port\":{port}")
+                    }
+                    ListenerState::Paused => "\"paused\"".to_owned(),
+                    ListenerState::Failed(error) => {
+                        format!("\"failed\",\"error\":{}", json_string(error))
+                    }
+                };
+                self.append("listener", &format!(",\"state\":{state}"))
+            }
+            JobEvent::Started { .. } | JobEvent::Progress { .. } => Ok(()),
+        }
+    }
+
+    // Writes an anchor entry and publishes its hash where the log can't be changed
+    // together with it: the Windows event log and optionally a URL of another system
+    fn anchor(&mut self, url: &str) -> io::Result<()> {
+        self.append("anchor", "")?;
+        let message = format!(
+            "Audit log {} entry {} hash {}",
+            self.path.display(),
+            self.seq,
+            self.head
+        );
+        report_event(&message)?;
+        if !url.is_empty() {
+            let payload = format!(
+                "{{\"log\":{},\"seq\":{},\"hash\":{}}}",
+                json_string(&self.path.display().to_string()),
+                self.seq,
+                json_string(&self.head)
+            );
+            let headers = [("Content-Type", "application/json")];
+            match http_client::send("POST", url, &headers, payload.as_bytes()) {
+                Ok(response) if (200..300).contains(&response.status) => {}
+                Ok(response) => warn!(
+                    "The audit anchor URL returned HTTP status {}",
+                    response.status
+                ),
+                Err(e) => warn!("Cannot send the audit anchor: {}", e),
+            }
+        }
+        self.anchored_seq = self.seq;
+        Ok(())
+    }
+}
+
+// An information event in the Application log, the event source does not need to
+// be registered for the text to be kept
+fn report_event(message: &str) -> io::Result<()> {
+    let source = unsafe { RegisterEventSourceW(PCWSTR::null(), w!("MiniRAW NG")) }?;
+    let message = utf16z!(message);
+    let result = unsafe {
+        ReportEventW(
+            source,
+            EVENTLOG_INFORMATION_TYPE,
+            0,
+            ANCHOR_EVENT_ID,
+            None,
+            0,
+            Some(&[PCWSTR(message.as_ptr())]),
+            None,
+        )
+    };
+    let _ = unsafe { DeregisterEventSource(source) };
+    Ok(result?)
+}
+
+enum Message {
+    Event(JobEvent),
+    // the log is anchored a last time, the sender is dropped then
+    Close(Sender<()>),
+}
+
+fn run_audit(settings: &AppSettings, messages: Receiver<Message>) {
+    let mut chain: Option<Chain> = None;
+    let mut last_anchor = Instant::now();
+    loop {
+        let event = match messages.recv_timeout(CHECK_INTERVAL) {
+            Ok(Message::Event(event)) => Some(event),
+            Ok(Message::Close(_closed)) => {
+                if let Some(chain) = chain.as_mut() {
+                    let url = settings.audit_anchor_url.read().unwrap().trim().to_owned();
+                    if let Err(e) = chain.append("closed", "").and_then(|_| chain.anchor(&url)) {
+                        warn!("Cannot anchor the audit log: {}", e);
+                    }
+                }
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        let Some(path) = settings.audit_log() else {
+            chain = None;
+            continue;
+        };
+        if chain.as_ref().is_none_or(|chain| chain.path != path) {
+            chain = match Chain::open(&path) {
+                Ok(mut chain) => {
+                    info!("Writing the audit log {}", path.display());
+                    if let Err(e) = chain.append("opened", "") {
+                        warn!("{}: {}", path.display(), e);
+                    }
+                    Some(chain)
+                }
+                Err(e) => {
+                    error!("Cannot open the audit log {}: {}", path.display(), e);
+                    None
+                }
+            };
+        }
+        let Some(chain) = chain.as_mut() else {
+            continue;
+        };
+
+        if let Some(event) = event {
+            if let Err(e) = chain.append_event(&event) {
+                error!("Cannot write the audit log: {}", e);
+            }
+        }
+        if last_anchor.elapsed() >= ANCHOR_INTERVAL && chain.seq > chain.anchored_seq {
+            let url = settings.audit_anchor_url.read().unwrap().trim().to_owned();
+            if let Err(e) = chain.anchor(&url) {
+                warn!("Cannot anchor the audit log: {}", e);
+            }
+            last_anchor = Instant::now();
+        }
+    }
+}
+
+// Keeps an append-only record of the received jobs and the listener in which every
+// entry is chained to the previous one by its SHA-256 hash. The head of the chain
+// is anchored outside of the log periodically, so a log rewritten after the fact
+// does not match the anchors anymore.
+pub struct AuditLog {
+    messages: Sender<Message>,
+}
+
+impl AuditLog {
+    pub fn new(settings: Arc<AppSettings>) -> AuditLog {
+        let (messages, receiver) = mpsc::channel();
+        std::thread::spawn(move || run_audit(&settings, receiver));
+        AuditLog { messages }
+    }
+
+    // The saved files are hashed on the thread of the log
+    pub fn handle_event(&self, event: &JobEvent) {
+        if !matches!(event, JobEvent::Started { .. } | JobEvent::Progress { .. }) {
+            let _ = self.messages.send(Message::Event(event.clone()));
+        }
+    }
+
+    // Called on exit, the head of the chain is anchored so that the last entries
+    // are covered too
+    pub fn close(&self) {
+        let (closed, receiver) = mpsc::channel();
+        if self.messages.send(Message::Close(closed)).is_ok() {
+            let _ = receiver.recv_timeout(CLOSE_TIMEOUT);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    io::{self, Write},
+    net::{Ipv4Addr, TcpListener},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::{
+    generator::{self, LoadOptions},
+    jobs::{JobEvent, JobEventBus, ListenerState},
+    listener::{ListenerConfig, RawListener, WriteMode},
+    plugins::PluginHost,
+    script::ScriptHooks,
+    settings::AppSettings,
+};
+
+// created in the output directory and removed after the test
+const SELF_TEST_DIR_NAME: &str = "miniraw-self-test";
+// the jobs sent when no options are given, 400 MB in total
+const DEFAULT_OPTIONS: &str = "size=4M count=100 concurrency=4";
+const DISK_TEST_SIZE: usize = 256 << 20;
+const DISK_BLOCK_SIZE: usize = 1 << 20;
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// A port nobody listens on, it may be taken by another program before it is bound
+// again
+fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?
+        .local_addr()?
+        .port())
+}
+
+// Writes a file and waits until it is on the disk, in MB/s
+fn disk_write_speed(dir: &Path) -> io::Result<f64> {
+    let path = dir.join("disk-test.bin");
+    let block = vec![0x55u8; DISK_BLOCK_SIZE];
+    let started = Instant::now();
+    let mut file = fs::File::create(&path)?;
+    for _ in 0..DISK_TEST_SIZE / DISK_BLOCK_SIZE {
+        file.write_all(&block)?;
+    }
+    file.sync_all()?;
+    let seconds = started.elapsed().as_secs_f64().max(0.001);
+    drop(file);
+    fs::remove_file(&path)?;
+    Ok(DISK_TEST_SIZE as f64 / seconds / 1_000_000.0)
+}
+
+// The jobs go through a temporary listener with the same code as the real ones,
+// without the script and the notifications, and are written the way set in the
+// settings
+fn receive_jobs(dir: &Path, options: &str, write_mode: WriteMode) -> Result<String, String> {
+    let port = free_port().map_err(|e| e.to_string())?;
+    let target = format!("{}:{}", Ipv4Addr::LOCALHOST, port);
+    let mut options = LoadOptions::parse(&format!("{DEFAULT_OPTIONS} {options}"), &target)?;
+    // always the temporary listener
+    options.target = target;
+
+    let events = Arc::new(JobEventBus::default());
+    let (sender, receiver) = mpsc::channel();
+    events.subscribe(move |event| {
+        if let JobEvent::Listener(state) = event {
+            let _ = sender.send(state.clone());
+        }
+    });
+    let listener = RawListener::start(
+        ListenerConfig {
+            address: Ipv4Addr::LOCALHOST.into(),
+            port,
+            output_dir: dir.to_owned(),
+            name_template: "{connection}.spl".to_owned(),
+            forward_target: String::new(),
+            discard: Some(false),
+            schedule: Default::default(),
+            off_hours: Default::default(),
+            scanner: Default::default(),
+            job_secret: String::new(),
+            limits: Default::default(),
+            write_mode,
+            forward_down: Default::default(),
+            // the state is published to the events of the test only
+            primary: true,
+        },
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicU32::new(0)),
+        Arc::new(ScriptHooks::default()),
+        Arc::new(PluginHost::default()),
+        events,
+    );
+
+    let result = match receiver.recv_timeout(START_TIMEOUT) {
+        Ok(ListenerState::Listening(_)) => Ok(generator::generate(&options)),
+        Ok(ListenerState::Failed(e)) => Err(e),
+        _ => Err("The listener of the self-test has not started".to_owned()),
+    };
+    listener.stop();
+    let report = result?;
+
+    let disk = disk_write_speed(dir).map_err(|e| e.to_string())?;
+    info!(
+        "Self-test: received {:.1} Mbit/s with {:.1} ms average latency, the disk writes {:.1} MB/s",
+        report.megabits_per_second(),
+        report.average_latency().as_secs_f64() * 1000.0,
+        disk
+    );
+    Ok(format!("{}disk write mb/s: {:.1}\n", report.text(), disk))
+}
+
+// Measures how fast this computer receives and saves the jobs, the options are the
+// ones of the generate command without the target. The jobs are saved into a
+// temporary directory of the output directory and deleted afterwards. Returns the
+// report of the test.
+pub fn run(settings: &AppSettings, options: &str) -> Result<String, String> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("The self-test is already running".to_owned());
+    }
+    let dir = settings.output_dir().join(SELF_TEST_DIR_NAME);
+    info!("Running the self-test in {}", dir.display());
+    let result = fs::create_dir_all(&dir)
+        .map_err(|e| e.to_string())
+        .and_then(|_| receive_jobs(&dir, options, settings.write_mode().unwrap_or_default()));
+    let _ = fs::remove_dir_all(&dir);
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+// Settings given on the command line. They override the stored settings for
+// the current run only, the configuration file is not changed.
+#[derive(Debug, Default)]
+pub struct Arguments {
+    // configuration file used instead of miniraw.toml next to the executable
+    pub config: Option<PathBuf>,
+    pub port: Option<u16>,
+    pub output_dir: Option<PathBuf>,
+    pub discard: bool,
+    // started hidden with only the tray icon shown, e.g. when launched on logon
+    pub minimized: bool,
+    // the stored settings are backed up and replaced with the defaults
+    pub reset_config: bool,
+    // unknown arguments and arguments with invalid values, logged on start
+    pub errors: Vec<String>,
+}
+
+impl Arguments {
+    // Values are given either as "--port 9101" or as "--port=9101"
+    pub fn parse<I>(args: I) -> Arguments
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut arguments = Arguments::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || inline_value.clone().or_else(|| args.next());
+
+            let valid = match name {
+                "--config" => value().map(|v| arguments.config = Some(v.into())).is_some(),
+                "--output-dir" => value()
+                    .map(|v| arguments.output_dir = Some(v.into()))
+                    .is_some(),
+                "--port" => match value().and_then(|v| v.parse().ok()) {
+                    Some(0) | None => false,
+                    port => {
+                        arguments.port = port;
+                        true
+                    }
+                },
+                "--discard" if inline_value.is_none() => {
+                    arguments.discard = true;
+                    true
+                }
+                "--minimized" if inline_value.is_none() => {
+                    arguments.minimized = true;
+                    true
+                }
+                "--reset-config" if inline_value.is_none() => {
+                    arguments.reset_config = true;
+                    true
+                }
+                _ => false,
+            };
+            if !valid {
+                arguments.errors.push(arg.clone());
+            }
+        }
+        arguments
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    mem::size_of,
+    os::windows::io::FromRawHandle,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use log::{debug, info, warn};
+use windows::{
+    core::{w, Error, PCWSTR},
+    Win32::{
+        Foundation::{LocalFree, ERROR_PIPE_CONNECTED, HLOCAL},
+        Security::{
+            Authorization::{
+                ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+            },
+            PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+        },
+        Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    },
+};
+
+use crate::{
+    api::{JobHistory, WM_API_PAUSE_LISTENER},
+    audit, benchmark,
+    generator::{self, LoadOptions},
+    jobs::{ActiveTransfers, ListenerState},
+    settings::AppSettings,
+    toml::Document,
+    ui::window::{user_message, WindowRef},
+    utf16z,
+};
+
+// Only the first instance of the application gets the pipe
+pub const PIPE_NAME: &str = r"\\.\pipe\miniraw";
+const BUFFER_SIZE: u32 = 4096;
+// longer commands are cut off
+const MAX_COMMAND_SIZE: u64 = 1024;
+// full access for the owner of the pipe, the user running the application, and
+// for the system only, the default security would let everyone read from the pipe
+const PIPE_SECURITY: PCWSTR = w!("D:P(A;;GA;;;OW)(A;;GA;;;SY)");
+
+// Posted to the main window to save the log next to the application and clear it
+pub const WM_CONTROL_ROTATE_LOG: u32 = user_message(11);
+
+// Shared by the threads of the control pipe
+pub struct ControlContext {
+    pub settings: Arc<AppSettings>,
+    pub history: Arc<JobHistory>,
+    pub transfers: Arc<ActiveTransfers>,
+    pub paused: Arc<AtomicBool>,
+    pub window: WindowRef,
+}
+
+impl ControlContext {
+    // Runs one command line, the response is "ok" followed by the output lines or
+    // "error: <message>"
+    fn execute(&self, line: &str) -> Result<String, String> {
+        let (command, argument) = line
+            .split_once(' ')
+            .map(|(command, argument)| (command, argument.trim()))
+            .unwrap_or((line, ""));
+        match (command, argument) {
+            ("status", "") => Ok(self.status()),
+            ("pause", "") => self.set_paused(true),
+            ("resume", "") => self.set_paused(false),
+            ("set", argument) => self.set(argument),
+            ("generate", argument) => {
+                let target = format!("127.0.0.1:{}", self.settings.port());
+                let options = LoadOptions::parse(argument, &target)?;
+                Ok(generator::generate(&options).text())
+            }
+            ("self-test", argument) => benchmark::run(&self.settings, argument),
+            ("verify-audit", "") => self.verify_audit(),
+            ("rotate-log", "") => {
+                let _ = self.window.post_user_message(WM_CONTROL_ROTATE_LOG, 0, 0);
+                Ok(String::new())
+            }
+            ("status" | "pause" | "resume" | "verify-audit" | "rotate-log", _) => {
+                Err(format!("{command} has no arguments"))
+            }
+            _ => Err(format!("unknown command: {line}")),
+        }
+    }
+
+    fn status(&self) -> String {
+        let listener = match self.history.listener_state() {
+            Some(ListenerState::Listening(port)) => format!("listening on port {port}"),
+            Some(ListenerState::Paused) => "paused".to_owned(),
+            Some(ListenerState::Failed(e)) => format!("failed: {e}"),
+            None => "starting".to_owned(),
+        };
+        let (jobs, bytes) = self.history.totals();
+        format!(
+            "listener: {}\npaused: {}\ndiscard: {}\nreceiving: {}\njobs: {}\nbytes: {}\n",
+            listener,
+            self.paused.load(Ordering::SeqCst),
+            self.settings.discard.load(Ordering::SeqCst),
+            self.transfers.count(),
+            jobs,
+            bytes
+        )
+    }
+
+    // Like the API, the flag is changed right away and the main window updates the
+    // menu and the status
+    fn set_paused(&self, flag: bool) -> Result<String, String> {
+        self.paused.store(flag, Ordering::SeqCst);
+        let _ = self
+            .window
+            .post_user_message(WM_API_PAUSE_LISTENER, flag as usize, 0);
+        Ok(String::new())
+    }
+
+    // Checks the chain of the audit log and the entry of its last anchor in the
+    // event log
+    fn verify_audit(&self) -> Result<String, String> {
+        let path = self
+            .settings
+            .audit_log()
+            .ok_or_else(|| "the audit log is disabled".to_owned())?;
+        let (entries, head, anchor) = audit::verify(&path)?;
+        let anchor = anchor.map_or_else(|| "none".to_owned(), |seq| seq.to_string());
+        Ok(format!(
+            "entries: {entries}\nhead: {head}\nanchored entry: {anchor}\n"
+        ))
+    }
+
+    // "set <name> <value>" with the value written as in the configuration file,
+    // e.g. set discard true or set output_dir "D:\\Jobs"
+    fn set(&self, argument: &str) -> Result<String, String> {
+        let Some((name, value)) = argument.split_once(' ') else {
+            return Err("usage: set <name> <value>".to_owned());
+        };
+        if self.settings.is_enforced(name) {
+            return Err(format!("{name} is enforced by the administrator"));
+        }
+        let document =
+            Document::parse(&format!("{} = {}", name, value.trim())).map_err(|e| e.to_string())?;
+        let changes = self
+            .settings
+            .changes(&document)
+            .map_err(|errors| format!("invalid setting: {}", errors.join(", ")))?;
+
+        // the main window applies the changes when it is notified
+        self.settings.apply_changes(&changes);
+        info!("Changed over the control pipe: {} = {}", name, value.trim());
+        Ok(String::new())
+    }
+}
+
+// One command per connection, the response is followed by the end of the pipe
+fn handle_connection(pipe: File, context: &ControlContext) -> io::Result<()> {
+    let mut line = String::new();
+    BufReader::new((&pipe).take(MAX_COMMAND_SIZE)).read_line(&mut line)?;
+    let line = line.trim();
+
+    let response = match context.execute(line) {
+        Ok(output) => format!("ok\n{output}"),
+        Err(e) => format!("error: {e}\n"),
+    };
+    debug!("{}: {}", line, response.lines().next().unwrap_or_default());
+    (&pipe).write_all(response.as_bytes())?;
+    // waits until the client has read the response
+    pipe.sync_all()
+}
+
+fn run_control_server(context: Arc<ControlContext>) {
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    if let Err(e) = unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            PIPE_SECURITY,
+            SDDL_REVISION_1,
+            &mut descriptor,
+            None,
+        )
+    } {
+        warn!("Cannot create the control pipe {}: {}", PIPE_NAME, e);
+        return;
+    }
+    serve_pipe(&context, descriptor);
+    unsafe { LocalFree(Some(HLOCAL(descriptor.0))) };
+}
+
+// Returns when a pipe instance cannot be created
+fn serve_pipe(context: &Arc<ControlContext>, descriptor: PSECURITY_DESCRIPTOR) {
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+    let name = utf16z!(PIPE_NAME);
+    let mut open_mode = PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE;
+    loop {
+        // a new instance of the pipe waits for the next client while the connected
+        // one is handled
+        let handle = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(name.as_ptr()),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                Some(&attributes),
+            )
+        };
+        if handle.is_invalid() {
+            warn!(
+                "Cannot create the control pipe {}: {}",
+                PIPE_NAME,
+                Error::from_win32()
+            );
+            return;
+        }
+        open_mode = PIPE_ACCESS_DUPLEX;
+
+        // closed when dropped
+        let pipe = unsafe { File::from_raw_handle(handle.0) };
+        match unsafe { ConnectNamedPipe(handle, None) } {
+            Err(e) if e.code() != ERROR_PIPE_CONNECTED.to_hresult() => warn!("{}", e),
+            _ => {
+                let context = context.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_connection(pipe, &context) {
+                        debug!("{}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+// Accepts the commands of the local scripts for the lifetime of the application.
+// Only the user running the application and the system can connect to the pipe,
+// see PIPE_SECURITY.
+pub fn start_control_server(context: ControlContext) {
+    let context = Arc::new(context);
+    std::thread::spawn(move || run_control_server(context));
+}
@@ -0,0 +1,220 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    os::windows::io::AsRawHandle,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        OnceLock,
+    },
+    thread::{self, ThreadId},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::HANDLE,
+        System::{
+            Diagnostics::Debug::{
+                MiniDumpWithIndirectlyReferencedMemory, MiniDumpWithThreadInfo,
+                MiniDumpWithUnloadedModules, MiniDumpWriteDump, SetUnhandledExceptionFilter,
+                EXCEPTION_POINTERS, MINIDUMP_EXCEPTION_INFORMATION,
+            },
+            Threading::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId},
+        },
+        UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_SETFOREGROUND},
+    },
+};
+
+use crate::{logger::WindowLogger, tr, utf16z, util};
+
+// next to the executable
+const CRASHES_DIR_NAME: &str = "crashes";
+// lets the unhandled exception go on to Windows Error Reporting
+const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+
+// The buffered log records are added to the reports once the logger is created
+static LOGGER: OnceLock<&'static WindowLogger> = OnceLock::new();
+// a crash while the first one is reported is left to Windows
+static CRASHED: AtomicBool = AtomicBool::new(false);
+// the thread running the message loop, a panic there stops the program
+static MAIN_THREAD: OnceLock<ThreadId> = OnceLock::new();
+// the panics of the other threads are only logged after this many reports
+const MAX_THREAD_REPORTS: u32 = 10;
+static REPORTS: AtomicU32 = AtomicU32::new(0);
+
+fn write_minidump(path: &Path, exception: Option<*const EXCEPTION_POINTERS>) -> io::Result<()> {
+    let file = File::create(path)?;
+    let exception = exception.map(|pointers| MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: unsafe { GetCurrentThreadId() },
+        ExceptionPointers: pointers as _,
+        ClientPointers: false.into(),
+    });
+    unsafe {
+        MiniDumpWriteDump(
+            GetCurrentProcess(),
+            GetCurrentProcessId(),
+            HANDLE(file.as_raw_handle()),
+            MiniDumpWithIndirectlyReferencedMemory
+                | MiniDumpWithThreadInfo
+                | MiniDumpWithUnloadedModules,
+            exception.as_ref().map(|exception| exception as *const _),
+            None,
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+// The reason of the crash followed by the log, a logger blocked by the crashed
+// thread is skipped
+fn write_log(path: &Path, reason: &str) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(
+        file,
+        "MiniRAW NG {}\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        reason
+    )?;
+    if let Some(text) = LOGGER.get().and_then(|logger| logger.try_text()) {
+        file.write_all(text.as_bytes())?;
+    }
+    Ok(())
+}
+
+// Writes crash-<timestamp>.dmp and crash-<timestamp>.log to the crashes directory,
+// a number is added to the name of the later reports of the same run. Returns the
+// directory and the paths of the log and the dump.
+fn save_report(
+    reason: &str,
+    exception: Option<*const EXCEPTION_POINTERS>,
+) -> (PathBuf, io::Result<(PathBuf, PathBuf)>) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let name = match REPORTS.fetch_add(1, Ordering::SeqCst) {
+        0 => format!("crash-{timestamp}"),
+        number => format!("crash-{timestamp}-{number}"),
+    };
+    let dir = util::app_dir().join(CRASHES_DIR_NAME);
+    let dump_path = dir.join(format!("{name}.dmp"));
+    let log_path = dir.join(format!("{name}.log"));
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| write_log(&log_path, reason))
+        .and_then(|_| write_minidump(&dump_path, exception))
+        .map(|_| (log_path, dump_path));
+    (dir, result)
+}
+
+// A crash which stops the program, the report is saved and the user is told where
+// to find it
+fn report_fatal(reason: &str, exception: Option<*const EXCEPTION_POINTERS>) {
+    if CRASHED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let (dir, result) = save_report(reason, exception);
+    let text = match result {
+        Ok((log_path, dump_path)) => tr!(
+            "MiniRAW NG has stopped because of an internal error.\n\n\
+             A crash report was saved to {}, please attach the files {} and {} \
+             to a bug report.",
+            dir.display(),
+            log_path.file_name().unwrap_or_default().to_string_lossy(),
+            dump_path.file_name().unwrap_or_default().to_string_lossy()
+        ),
+        Err(e) => tr!(
+            "MiniRAW NG has stopped because of an internal error.\n\n\
+             The crash report cannot be saved to {}: {}\n\n{}",
+            dir.display(),
+            e,
+            reason
+        ),
+    };
+    let text = utf16z!(text);
+    let caption = utf16z!("MiniRAW NG");
+    unsafe {
+        MessageBoxW(
+            None,
+            PCWSTR(text.as_ptr()),
+            PCWSTR(caption.as_ptr()),
+            MB_ICONERROR | MB_SETFOREGROUND,
+        );
+    }
+}
+
+unsafe extern "system" fn exception_filter(pointers: *const EXCEPTION_POINTERS) -> i32 {
+    let code = unsafe {
+        pointers
+            .as_ref()
+            .and_then(|pointers| pointers.ExceptionRecord.as_ref())
+            .map_or(0, |record| record.ExceptionCode.0)
+    };
+    report_fatal(&format!("Unhandled exception 0x{code:08x}"), Some(pointers));
+    EXCEPTION_CONTINUE_SEARCH
+}
+
+fn panic_hook(info: &PanicHookInfo) {
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error");
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_default();
+    let thread = thread::current();
+    let reason = format!(
+        "Panic in thread {} at {}: {}",
+        thread.name().unwrap_or("<unnamed>"),
+        location,
+        message
+    );
+    // with panic = "abort" (the release profile) a panic of any thread stops the program
+    if cfg!(panic = "abort") || MAIN_THREAD.get() == Some(&thread.id()) {
+        report_fatal(&reason, None);
+    } else {
+        report_thread_panic(&reason);
+    }
+}
+
+// A build which unwinds goes on after a panic of a listener or a worker thread, the
+// panic is logged and a report is saved for each of the first ones
+fn report_thread_panic(reason: &str) {
+    error!("{}", reason);
+    if REPORTS.load(Ordering::SeqCst) >= MAX_THREAD_REPORTS {
+        return;
+    }
+    match save_report(reason, None) {
+        (_, Ok((log_path, _))) => error!("A crash report was saved to {}", log_path.display()),
+        (dir, Err(e)) => error!(
+            "The crash report cannot be saved to {}: {}",
+            dir.display(),
+            e
+        ),
+    }
+}
+
+// Must be called on the main thread before any other thread is started
+pub fn install() {
+    let _ = MAIN_THREAD.set(thread::current().id());
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        panic_hook(info);
+        default_hook(info);
+    }));
+    unsafe {
+        SetUnhandledExceptionFilter(Some(Some(exception_filter)));
+    }
+}
+
+pub fn set_logger(logger: &'static WindowLogger) {
+    let _ = LOGGER.set(logger);
+}
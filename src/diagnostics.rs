@@ -0,0 +1,93 @@
+use std::{env, fs, io, path::Path};
+
+use time::OffsetDateTime;
+
+use crate::{i18n, jobs::Job, zip::ZipWriter};
+
+// Number of the most recent jobs described in the bundle
+pub const MAX_JOBS: usize = 20;
+
+fn format_time(time: OffsetDateTime) -> String {
+    let (hour, minute, second) = time.to_hms();
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        hour,
+        minute,
+        second,
+        time.offset()
+    )
+}
+
+// Text files of a diagnostics bundle, written into one ZIP archive
+#[derive(Default)]
+pub struct Diagnostics {
+    files: Vec<(&'static str, String)>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default().file("version.txt", Self::version_info())
+    }
+
+    pub fn file(mut self, name: &'static str, text: String) -> Diagnostics {
+        self.files.push((name, text));
+        self
+    }
+
+    // The metadata of the last MAX_JOBS jobs, the job data is not included
+    pub fn jobs(self, jobs: &[Job]) -> Diagnostics {
+        let lines = jobs
+            .iter()
+            .rev()
+            .take(MAX_JOBS)
+            .map(|job| {
+                format!(
+                    "{} {} {} {} bytes {} ms {} B/s average {} B/s peak {} stalls {} {}",
+                    job.connection,
+                    format_time(job.time),
+                    job.source,
+                    job.size,
+                    job.transfer.duration_ms,
+                    job.transfer.average_bytes_per_second,
+                    job.transfer.peak_bytes_per_second,
+                    job.transfer.stalls,
+                    job.format,
+                    job.path
+                        .as_ref()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|| "(discarded)".to_owned())
+                )
+            })
+            .collect::<Vec<_>>();
+        self.file("jobs.txt", lines.join("\r\n"))
+    }
+
+    fn version_info() -> String {
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let exe = env::current_exe()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+
+        [
+            format!("MiniRAW NG {}", env!("CARGO_PKG_VERSION")),
+            format!("Collected: {}", format_time(now)),
+            format!("Architecture: {}", env::consts::ARCH),
+            format!("Language: {}", i18n::language().code()),
+            format!("Executable: {exe}"),
+        ]
+        .join("\r\n")
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let mut zip = ZipWriter::new(io::BufWriter::new(fs::File::create(path)?), now);
+        for (name, text) in &self.files {
+            zip.add(name, text.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::HashSet,
+    env,
+    io::{self, BufRead, BufReader, Write},
+    net::{IpAddr, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use log::{info, warn};
+use time::OffsetDateTime;
+
+use crate::{
+    jobs::{JobEvent, ListenerState},
+    settings::AppSettings,
+    util,
+};
+
+const SMTP_PORT: u16 = 25;
+const TIMEOUT: Duration = Duration::from_secs(15);
+// messages waiting to be sent, newer ones are dropped while the queue is full
+const QUEUE_SIZE: usize = 20;
+
+// Names of the events in the email_triggers setting
+pub const TRIGGER_NEW_HOST: &str = "new_host";
+pub const TRIGGER_LOW_DISK_SPACE: &str = "low_disk_space";
+pub const TRIGGER_LISTENER_FAILED: &str = "listener_failed";
+
+struct Email {
+    subject: String,
+    body: String,
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+// RFC 2047 encoded word for the header values which are not plain ASCII
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        text.to_owned()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64(text.as_bytes()))
+    }
+}
+
+// RFC 5322 date, e.g. Thu, 16 Oct 2025 10:00:00 +0200
+fn format_date(time: OffsetDateTime) -> String {
+    let (hour, minute, second) = time.to_hms();
+    let (offset_hours, offset_minutes, _) = time.offset().as_hms();
+    format!(
+        "{}, {} {} {} {:02}:{:02}:{:02} {}{:02}{:02}",
+        &time.weekday().to_string()[..3],
+        time.day(),
+        &time.month().to_string()[..3],
+        time.year(),
+        hour,
+        minute,
+        second,
+        if time.offset().is_negative() {
+            '-'
+        } else {
+            '+'
+        },
+        offset_hours.unsigned_abs(),
+        offset_minutes.unsigned_abs()
+    )
+}
+
+// A plain SMTP session without authentication and TLS, so only a mail relay of the
+// local network which accepts the mail of this computer as it is can be used
+struct SmtpSession {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+}
+
+impl SmtpSession {
+    fn connect(server: &str) -> io::Result<SmtpSession> {
+        let address = if server.contains(':') {
+            server.to_socket_addrs()
+        } else {
+            (server, SMTP_PORT).to_socket_addrs()
+        }?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, server.to_owned()))?;
+
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut session = SmtpSession {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        };
+        session.expect(220)?;
+        Ok(session)
+    }
+
+    // Reads a possibly multi-line reply and fails if its code is not the expected one
+    fn expect(&mut self, code: u16) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            // the last line has a space after the code, the others a dash
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        match line.get(..3).and_then(|reply| reply.parse::<u16>().ok()) {
+            Some(reply) if reply == code => Ok(()),
+            Some(530) => Err(io::Error::other(format!(
+                "the mail relay requires authentication or STARTTLS, which are not supported, \
+                 use a relay which accepts the mail of this computer without them: {}",
+                line.trim_end()
+            ))),
+            _ => Err(io::Error::other(line.trim_end().to_owned())),
+        }
+    }
+
+    fn command(&mut self, command: &str, code: u16) -> io::Result<()> {
+        self.stream.write_all(format!("{command}\r\n").as_bytes())?;
+        self.expect(code)
+    }
+
+    fn send(&mut self, from: &str, recipients: &[&str], email: &Email) -> io::Result<()> {
+        let host = env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_owned());
+        self.command(&format!("EHLO {host}"), 250)?;
+        self.command(&format!("MAIL FROM:<{from}>"), 250)?;
+        for recipient in recipients {
+            self.command(&format!("RCPT TO:<{recipient}>"), 250)?;
+        }
+        self.command("DATA", 354)?;
+
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            from,
+            recipients.join(", "),
+            encode_header(&email.subject),
+            format_date(now)
+        );
+        for line in email.body.lines() {
+            // a line starting with a dot would otherwise end the data
+            if line.starts_with('.') {
+                message.push('.');
+            }
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        message.push_str(".\r\n");
+        self.stream.write_all(message.as_bytes())?;
+        self.expect(250)?;
+
+        self.command("QUIT", 221)
+    }
+}
+
+// Sends an email to the email_to recipients through the smtp_server when one of
+// the email_triggers happens: the first job from a host in this session, the free
+// space in the output directory falling below min_free_space_mb or a listener
+// failure. The messages are sent on a background thread.
+pub struct EmailNotifier {
+    settings: Arc<AppSettings>,
+    sender: SyncSender<Email>,
+    hosts: Mutex<HashSet<IpAddr>>,
+    // set while the free space is low, so the warning is sent once
+    low_disk_space: AtomicBool,
+}
+
+impl EmailNotifier {
+    pub fn new(settings: Arc<AppSettings>) -> EmailNotifier {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+        {
+            let settings = settings.clone();
+            std::thread::spawn(move || Self::send_emails(&settings, receiver));
+        }
+        EmailNotifier {
+            settings,
+            sender,
+            hosts: Default::default(),
+            low_disk_space: AtomicBool::new(false),
+        }
+    }
+
+    fn send_emails(settings: &AppSettings, receiver: Receiver<Email>) {
+        for email in receiver {
+            let server = settings.smtp_server.read().unwrap().clone();
+            let from = settings.email_from();
+            let to = settings.email_to.read().unwrap().clone();
+            let recipients = to
+                .split([',', ';'])
+                .map(str::trim)
+                .filter(|recipient| !recipient.is_empty())
+                .collect::<Vec<_>>();
+            if server.is_empty() || recipients.is_empty() {
+                continue;
+            }
+
+            match SmtpSession::connect(&server)
+                .and_then(|mut session| session.send(&from, &recipients, &email))
+            {
+                Ok(()) => info!("Sent the email \"{}\" to {}", email.subject, to),
+                Err(e) => warn!("Cannot send the email to {}: {}", server, e),
+            }
+        }
+    }
+
+    fn is_enabled(&self, trigger: &str) -> bool {
+        !self.settings.smtp_server.read().unwrap().is_empty()
+            && self
+                .settings
+                .email_triggers
+                .read()
+                .unwrap()
+                .split(',')
+                .any(|name| name.trim() == trigger)
+    }
+
+    fn send(&self, subject: String, body: String) {
+        let subject = format!("MiniRAW NG: {subject}");
+        if self.sender.try_send(Email { subject, body }).is_err() {
+            warn!("Too many emails, one was dropped");
+        }
+    }
+
+    fn check_disk_space(&self) {
+        let dir = self.settings.output_dir();
+        let Some((available, free)) = util::free_space(&dir) else {
+            return;
+        };
+        let min_free = self.settings.min_free_space_mb.load(Ordering::SeqCst) as u64;
+        let low = available < min_free << 20;
+        if low && !self.low_disk_space.swap(true, Ordering::SeqCst) {
+            // the volume has more free space than the user may use
+            let body = if available < free {
+                format!(
+                    "Only {} MB of the disk quota are left in {}, the minimum is {} MB.",
+                    available >> 20,
+                    dir.display(),
+                    min_free
+                )
+            } else {
+                format!(
+                    "Only {} MB are free in {}, the minimum is {} MB.",
+                    available >> 20,
+                    dir.display(),
+                    min_free
+                )
+            };
+            self.send("Low disk space".to_owned(), body);
+        } else if !low {
+            self.low_disk_space.store(false, Ordering::SeqCst);
+        }
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        match event {
+            JobEvent::Completed(job) => {
+                if self.is_enabled(TRIGGER_NEW_HOST)
+                    && self.hosts.lock().unwrap().insert(job.source.ip())
+                {
+                    self.send(
+                        format!("First print job from {}", job.source.ip()),
+                        format!(
+                            "A print job of {} bytes ({}) was received from {}.",
+                            job.size, job.format, job.source
+                        ),
+                    );
+                }
+                if self.is_enabled(TRIGGER_LOW_DISK_SPACE) && job.path.is_some() {
+                    self.check_disk_space();
+                }
+            }
+            JobEvent::Listener(ListenerState::Failed(error))
+                if self.is_enabled(TRIGGER_LISTENER_FAILED) =>
+            {
+                self.send("Listener failed".to_owned(), error.clone());
+            }
+            _ => {}
+        }
+    }
+}
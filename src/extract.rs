@@ -0,0 +1,214 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::jobs::JobFormat;
+
+// only the start of longer jobs is read
+const MAX_TEXT_SIZE: u64 = 4 * 1024 * 1024;
+const ESC: u8 = 0x1b;
+const FORM_FEED: u8 = 0x0c;
+const PAGE_BREAK: &str = "\n--- page break ---\n";
+
+// Skips a PCL escape sequence starting at data[0] == ESC, returns the number of bytes consumed
+fn skip_pcl_escape(data: &[u8]) -> usize {
+    pcl_escape_length(data).min(data.len())
+}
+
+// The length of a PCL escape sequence starting at data[0] == ESC with its binary
+// payload, which can go past the end of the data
+pub fn pcl_escape_length(data: &[u8]) -> usize {
+    let Some(&command) = data.get(1) else {
+        return data.len();
+    };
+
+    // two-character sequences like ESC E
+    if !(b'!'..=b'/').contains(&command) {
+        return 2;
+    }
+
+    // ESC % has no group character
+    let mut pos = if command == b'%' { 2 } else { 3 };
+
+    loop {
+        let start = pos;
+        while pos < data.len() && matches!(data[pos], b'0'..=b'9' | b'+' | b'-' | b'.') {
+            pos += 1;
+        }
+
+        let value = std::str::from_utf8(&data[start..pos])
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(0.0);
+
+        let Some(&parameter) = data.get(pos) else {
+            return data.len();
+        };
+        pos += 1;
+
+        // binary payload follows the W parameter, e.g. raster rows or font headers
+        if parameter.eq_ignore_ascii_case(&b'W') {
+            pos += value.max(0.0) as usize;
+        }
+
+        if parameter.is_ascii_uppercase() || !parameter.is_ascii_alphabetic() {
+            return pos;
+        }
+    }
+}
+
+fn extract_pcl_text(data: &[u8]) -> String {
+    let mut text = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let at_line_start = pos == 0 || data[pos - 1] == b'\n';
+
+        if at_line_start && data[pos..].starts_with(b"@PJL") {
+            pos = data[pos..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map(|p| pos + p + 1)
+                .unwrap_or(data.len());
+            continue;
+        }
+
+        match data[pos] {
+            ESC => {
+                pos += skip_pcl_escape(&data[pos..]);
+                continue;
+            }
+            FORM_FEED => text.extend_from_slice(PAGE_BREAK.as_bytes()),
+            b @ (b'\n' | b'\t') => text.push(b),
+            b if b == b' ' || b.is_ascii_graphic() || b >= 0x80 => text.push(b),
+            _ => {}
+        }
+        pos += 1;
+    }
+
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+fn extract_ps_text(data: &[u8]) -> String {
+    let mut text = String::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        match data[pos] {
+            // comment till the end of line
+            b'%' => {
+                pos = data[pos..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map(|p| pos + p)
+                    .unwrap_or(data.len());
+            }
+            b'(' => {
+                let mut depth = 1;
+                let mut string = Vec::new();
+                pos += 1;
+                while pos < data.len() && depth > 0 {
+                    match data[pos] {
+                        b'\\' if pos + 1 < data.len() => {
+                            pos += 1;
+                            match data[pos] {
+                                b'n' => string.push(b'\n'),
+                                b't' => string.push(b'\t'),
+                                b'0'..=b'7' => {
+                                    let end = (pos + 3).min(data.len());
+                                    let digits = data[pos..end]
+                                        .iter()
+                                        .take_while(|b| (b'0'..=b'7').contains(b))
+                                        .count();
+                                    let code = std::str::from_utf8(&data[pos..pos + digits])
+                                        .ok()
+                                        .and_then(|v| u8::from_str_radix(v, 8).ok())
+                                        .unwrap_or(b'?');
+                                    string.push(code);
+                                    pos += digits - 1;
+                                }
+                                b => string.push(b),
+                            }
+                        }
+                        b'(' => {
+                            depth += 1;
+                            string.push(b'(');
+                        }
+                        b')' => {
+                            depth -= 1;
+                            if depth > 0 {
+                                string.push(b')');
+                            }
+                        }
+                        b => string.push(b),
+                    }
+                    pos += 1;
+                }
+                text.push_str(&String::from_utf8_lossy(&string));
+                text.push(' ');
+                continue;
+            }
+            b's' if data[pos..].starts_with(b"showpage") => {
+                text.push_str(PAGE_BREAK);
+                pos += b"showpage".len();
+                continue;
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+
+    text
+}
+
+fn extract_zpl_text(data: &[u8]) -> String {
+    let data = String::from_utf8_lossy(data);
+    let mut text = String::new();
+    let mut origin = String::new();
+    let mut barcode = false;
+
+    for command in data.split(['^', '~']).filter(|c| c.len() >= 2) {
+        let (name, args) = command.split_at(2);
+        match name.to_ascii_uppercase().as_str() {
+            "XA" => text.push_str("--- label ---\n"),
+            "FO" | "FT" => origin = args.trim().to_owned(),
+            "FD" => {
+                if barcode {
+                    text.push_str(&format!("[{}] barcode: {}\n", origin, args.trim_end()));
+                } else {
+                    text.push_str(&format!("[{}] {}\n", origin, args.trim_end()));
+                }
+            }
+            "FS" => barcode = false,
+            name if name.starts_with('B') => barcode = true,
+            _ => {}
+        }
+    }
+
+    text
+}
+
+pub fn extract_text(format: JobFormat, data: &[u8]) -> String {
+    let text = match format {
+        JobFormat::Text => String::from_utf8_lossy(data).into_owned(),
+        JobFormat::PostScript => extract_ps_text(data),
+        JobFormat::Zpl => extract_zpl_text(data),
+        JobFormat::Pdf => "No text can be extracted from PDF data without Ghostscript".to_owned(),
+        JobFormat::Pjl | JobFormat::Pcl | JobFormat::EscP | JobFormat::Unknown => {
+            extract_pcl_text(data)
+        }
+    };
+
+    // the edit control only understands CRLF line endings
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+pub fn read_text(format: JobFormat, path: &Path) -> io::Result<String> {
+    let mut data = Vec::new();
+    fs::File::open(path)?
+        .take(MAX_TEXT_SIZE)
+        .read_to_end(&mut data)?;
+    Ok(extract_text(format, &data))
+}
@@ -0,0 +1,241 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use log::error;
+use windows::{
+    core::PCWSTR,
+    Win32::UI::{
+        Controls::{
+            Dialogs::FR_DOWN,
+            RichEdit::{CHARRANGE, EM_EXSETSEL, EM_FINDTEXTEXW, FINDTEXTEXW},
+            EM_SCROLLCARET, EM_SETCUEBANNER,
+        },
+        Input::KeyboardAndMouse::SetFocus,
+        WindowsAndMessaging::*,
+    },
+};
+
+use crate::{
+    tr,
+    ui::window::{
+        Font, MessageResult, WindowBuilder, WindowEvent, WindowGeometry, WindowMessage,
+        WindowMessageHandler, WindowRef,
+    },
+    utf16z, IDI_MAINICON,
+};
+
+const IDC_FIND_NEXT: u32 = 100;
+
+struct FindState {
+    window: Option<WindowRef>,
+    input: Option<WindowRef>,
+}
+
+// Modeless search window for a rich edit control
+pub struct TextFinder {
+    target: WindowRef,
+    pattern: RwLock<String>,
+    state: Mutex<FindState>,
+}
+
+impl TextFinder {
+    pub fn new(target: WindowRef) -> Arc<TextFinder> {
+        Arc::new(TextFinder {
+            target,
+            pattern: RwLock::new(String::new()),
+            state: Mutex::new(FindState {
+                window: None,
+                input: None,
+            }),
+        })
+    }
+
+    pub fn show(self: &Arc<Self>) {
+        let window = self.state.lock().unwrap().window.clone();
+        if let Some(window) = window {
+            unsafe {
+                let _ = SetForegroundWindow(window.handle());
+            }
+            self.focus_input();
+            return;
+        }
+
+        let geometry = WindowGeometry {
+            width: Some(420),
+            height: Some(90),
+            ..Default::default()
+        };
+
+        let style = WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_VISIBLE;
+
+        match WindowBuilder::window("miniraw_find", None)
+            .geometry(geometry)
+            .title(tr!("Find in log"))
+            .style(style.0)
+            .extended_style(WS_EX_TOOLWINDOW.0)
+            .icon(IDI_MAINICON)
+            .message_handler(self.clone())
+            .build()
+        {
+            Ok(window) => {
+                self.state.lock().unwrap().window = Some(window);
+                self.focus_input();
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    // Searches for the last used pattern after the current selection,
+    // returns false if no pattern has been entered yet
+    pub fn find_next(&self) -> bool {
+        let pattern = self.pattern.read().unwrap().clone();
+        if pattern.is_empty() {
+            return false;
+        }
+
+        let (start, end) = self.target.selection();
+        let from = if end > start { start + 1 } else { end } as i32;
+
+        // search till the end and then wrap around from the beginning
+        let found = self
+            .find_text(&pattern, from, -1)
+            .or_else(|| self.find_text(&pattern, 0, from));
+
+        match found {
+            Some(range) => {
+                self.target.send_message(WindowMessage::new(
+                    self.target.clone(),
+                    EM_EXSETSEL,
+                    0,
+                    &range as *const CHARRANGE as _,
+                ));
+                self.target.send_message(WindowMessage::new(
+                    self.target.clone(),
+                    EM_SCROLLCARET,
+                    0,
+                    0,
+                ));
+            }
+            None => self.notify(&tr!("Cannot find \"{}\"", pattern)),
+        }
+        true
+    }
+
+    fn find_text(&self, pattern: &str, from: i32, to: i32) -> Option<CHARRANGE> {
+        let text = utf16z!(pattern);
+        let mut find = FINDTEXTEXW {
+            chrg: CHARRANGE {
+                cpMin: from,
+                cpMax: to,
+            },
+            lpstrText: PCWSTR(text.as_ptr()),
+            chrgText: CHARRANGE::default(),
+        };
+
+        let result = self.target.send_message(WindowMessage::new(
+            self.target.clone(),
+            EM_FINDTEXTEXW,
+            FR_DOWN.0 as _,
+            &mut find as *mut FINDTEXTEXW as _,
+        ));
+
+        match result {
+            MessageResult::Value(pos) if pos >= 0 => Some(find.chrgText),
+            _ => None,
+        }
+    }
+
+    fn focus_input(&self) {
+        if let Some(input) = self.state.lock().unwrap().input.as_ref() {
+            unsafe {
+                let _ = SetFocus(Some(input.handle()));
+            }
+        }
+    }
+
+    fn create_controls(&self, window: &WindowRef) {
+        let input_style =
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32);
+        let button_style =
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32);
+
+        let input = WindowBuilder::edit_control(window.clone())
+            .geometry(WindowGeometry {
+                x: Some(8),
+                y: Some(12),
+                width: Some(290),
+                height: Some(24),
+            })
+            .style(input_style.0)
+            .extended_style(WS_EX_CLIENTEDGE.0)
+            .font(Font::new(15, "Segoe UI"))
+            .build()
+            .unwrap();
+
+        let cue = utf16z!("IP address, job name, ...");
+        input.send_message(WindowMessage::new(
+            input.clone(),
+            EM_SETCUEBANNER,
+            1,
+            cue.as_ptr() as _,
+        ));
+        let _ = input.set_text(&self.pattern.read().unwrap());
+
+        let _ = WindowBuilder::button(window.clone())
+            .id(IDC_FIND_NEXT)
+            .title(tr!("Find next"))
+            .geometry(WindowGeometry {
+                x: Some(306),
+                y: Some(12),
+                width: Some(90),
+                height: Some(24),
+            })
+            .style(button_style.0)
+            .font(Font::new(15, "Segoe UI"))
+            .build()
+            .unwrap();
+
+        self.state.lock().unwrap().input = Some(input);
+    }
+
+    fn notify(&self, text: &str) {
+        let text = utf16z!(text);
+        let caption = utf16z!(tr!("Find in log"));
+        unsafe {
+            MessageBoxW(
+                Some(self.target.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                MB_ICONINFORMATION,
+            );
+        }
+    }
+}
+
+impl WindowMessageHandler for TextFinder {
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        match message.event() {
+            WindowEvent::Create => {
+                self.create_controls(&message.window);
+                MessageResult::Processed
+            }
+            WindowEvent::Command {
+                id: IDC_FIND_NEXT,
+                code: BN_CLICKED,
+            } => {
+                let input = self.state.lock().unwrap().input.clone();
+                if let Some(input) = input {
+                    *self.pattern.write().unwrap() = input.get_text().unwrap_or_default();
+                }
+                self.find_next();
+                MessageResult::Processed
+            }
+            WindowEvent::Destroy => {
+                let mut state = self.state.lock().unwrap();
+                state.window = None;
+                state.input = None;
+                MessageResult::Processed
+            }
+            _ => MessageResult::Ignored,
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::env;
+
+use log::{error, info};
+use windows::{
+    core::{Error, Result, BSTR, HRESULT},
+    Win32::{
+        Foundation::VARIANT_TRUE,
+        NetworkManagement::WindowsFirewall::{
+            INetFwPolicy2, INetFwRule, INetFwRules, NetFwPolicy2, NetFwRule, NET_FW_ACTION_ALLOW,
+            NET_FW_IP_PROTOCOL_TCP, NET_FW_PROFILE2_ALL, NET_FW_RULE_DIR_IN,
+        },
+        System::Com::{
+            CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED,
+        },
+    },
+};
+
+use crate::shell;
+
+// The elevated copy of the program started with this argument and the comma-separated
+// ports adds the rules and exits
+pub const ADD_RULES_ARGUMENT: &str = "--add-firewall-rules";
+
+// The rules are found by their names, one rule per port
+fn rule_name(port: u16) -> String {
+    format!("MiniRAW NG (TCP {port})")
+}
+
+fn rules() -> Result<INetFwRules> {
+    unsafe {
+        let policy: INetFwPolicy2 = CoCreateInstance(&NetFwPolicy2, None, CLSCTX_INPROC_SERVER)?;
+        policy.Rules()
+    }
+}
+
+pub fn format_ports(ports: &[u16]) -> String {
+    ports
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The ports without a rule of the program, a rule disabled by the user is not
+// added again
+pub fn missing_rules(ports: &[u16]) -> Result<Vec<u16>> {
+    let rules = rules()?;
+    Ok(ports
+        .iter()
+        .copied()
+        .filter(|port| unsafe { rules.Item(&BSTR::from(rule_name(*port))) }.is_err())
+        .collect())
+}
+
+// Allows the incoming TCP connections of the program on the ports in all network
+// profiles, needs the administrator rights
+pub fn add_rules(ports: &[u16]) -> Result<()> {
+    let rules = rules()?;
+    let program = env::current_exe().unwrap_or_default();
+    for port in ports {
+        unsafe {
+            let rule: INetFwRule = CoCreateInstance(&NetFwRule, None, CLSCTX_INPROC_SERVER)?;
+            rule.SetName(&BSTR::from(rule_name(*port)))?;
+            rule.SetDescription(&BSTR::from("Print jobs received by MiniRAW NG"))?;
+            rule.SetApplicationName(&BSTR::from(program.to_string_lossy().as_ref()))?;
+            rule.SetProtocol(NET_FW_IP_PROTOCOL_TCP.0)?;
+            rule.SetLocalPorts(&BSTR::from(port.to_string()))?;
+            rule.SetDirection(NET_FW_RULE_DIR_IN)?;
+            rule.SetAction(NET_FW_ACTION_ALLOW)?;
+            rule.SetProfiles(NET_FW_PROFILE2_ALL.0)?;
+            rule.SetEnabled(VARIANT_TRUE)?;
+            rules.Add(&rule)?;
+        }
+    }
+    Ok(())
+}
+
+// Adds the rules in an elevated copy of the program on a background thread, the
+// result is logged
+pub fn add_rules_elevated(ports: Vec<u16>) {
+    std::thread::spawn(move || {
+        let _ = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+        let arguments = ports.iter().map(u16::to_string).collect::<Vec<_>>();
+        match shell::run_elevated(&format!("{}={}", ADD_RULES_ARGUMENT, arguments.join(","))) {
+            Ok(0) => info!(
+                "Added the firewall rules for port(s) {}",
+                format_ports(&ports)
+            ),
+            // the copy exits with the error code
+            Ok(code) => error!(
+                "Cannot add the firewall rules: {}",
+                Error::from_hresult(HRESULT(code as _))
+            ),
+            Err(e) => error!("Cannot add the firewall rules: {}", e),
+        }
+    });
+}
+
+// The ports given to the elevated copy, returns its exit code
+pub fn add_rules_from_argument(ports: &str) -> i32 {
+    let ports = ports
+        .split(',')
+        .filter_map(|port| port.trim().parse().ok())
+        .collect::<Vec<u16>>();
+    match add_rules(&ports) {
+        Ok(()) => 0,
+        Err(e) => e.code().0,
+    }
+}
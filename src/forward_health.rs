@@ -0,0 +1,453 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+
+use crate::{jobs::ConnectionId, settings::AppSettings, tr, util};
+
+// the subdirectory of the output directory with the jobs of the listeners which
+// only forward, until their target is up
+pub const QUEUE_DIR_NAME: &str = "forward-queue";
+// the queue is kept next to the configuration file across restarts
+const QUEUE_FILE_NAME: &str = "miniraw-forward-queue.txt";
+
+const UEL: &[u8] = b"\x1b%-12345X";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// a target which stops reading the data fails the job rather than holding it
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+// the PJL status ends with a form feed, the rest is ignored
+const MAX_STATUS_SIZE: usize = 4096;
+// how often the settings are looked at while the checks are disabled
+const DISABLED_POLL_INTERVAL: Duration = Duration::from_secs(5);
+// the targets which are no longer configured, e.g. chosen by the script, are checked
+// for a day after their last job
+const UNUSED_TARGET_LIFETIME: Duration = Duration::from_secs(24 * 3600);
+
+// What happens to the jobs for a forward target which is down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardDown {
+    // the jobs are saved and forwarded once the target is up again
+    #[default]
+    Queue,
+    // the connections are closed, so that the clients try again later
+    Refuse,
+    // the jobs are forwarded anyway and the forwarding fails
+    Forward,
+}
+
+impl ForwardDown {
+    pub fn parse(text: &str) -> Option<ForwardDown> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "" | "queue" => Some(ForwardDown::Queue),
+            "refuse" => Some(ForwardDown::Refuse),
+            "forward" => Some(ForwardDown::Forward),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    // not checked yet, the jobs are forwarded
+    Unknown,
+    Up,
+    // with the reason
+    Down(String),
+}
+
+impl Health {
+    pub fn description(&self) -> String {
+        match self {
+            Health::Unknown => tr!("not checked").to_owned(),
+            Health::Up => tr!("up").to_owned(),
+            Health::Down(reason) => tr!("down: {}", reason),
+        }
+    }
+}
+
+struct Target {
+    health: Health,
+    // the last job forwarded to the target
+    used: Instant,
+    // the jobs being forwarded, the target is not probed meanwhile
+    active: u32,
+}
+
+// A saved job waiting for its forward target
+struct QueuedJob {
+    connection: ConnectionId,
+    target: String,
+    path: PathBuf,
+    // saved only to be forwarded
+    delete: bool,
+}
+
+static TARGETS: Mutex<BTreeMap<String, Target>> = Mutex::new(BTreeMap::new());
+static QUEUE: Mutex<VecDeque<QueuedJob>> = Mutex::new(VecDeque::new());
+
+fn queue_path() -> PathBuf {
+    util::app_dir().join(QUEUE_FILE_NAME)
+}
+
+// One line per job: the connection, the target, 1 if the file is deleted and the
+// path separated by tabs
+fn parse_queued_job(line: &str) -> Option<QueuedJob> {
+    let mut fields = line.splitn(4, '\t');
+    Some(QueuedJob {
+        connection: ConnectionId(fields.next()?.parse().ok()?),
+        target: fields.next()?.to_owned(),
+        delete: fields.next()? == "1",
+        path: PathBuf::from(fields.next()?),
+    })
+}
+
+// Called after every change, a temporary file replaces the old one
+fn save_queue(queue: &VecDeque<QueuedJob>) {
+    let path = queue_path();
+    let result = if queue.is_empty() {
+        fs::remove_file(&path).or_else(|e| match e.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })
+    } else {
+        let text = queue
+            .iter()
+            .map(|job| {
+                format!(
+                    "{}\t{}\t{}\t{}\r\n",
+                    job.connection.0,
+                    job.target,
+                    job.delete as u8,
+                    job.path.display()
+                )
+            })
+            .collect::<String>();
+        let temp_path = path.with_extension("txt.tmp");
+        fs::write(&temp_path, text).and_then(|_| fs::rename(&temp_path, &path))
+    };
+    if let Err(e) = result {
+        error!("{}: {}", path.display(), e);
+    }
+}
+
+// Restores the queue of the last run and adds the files left in the queue
+// directories of the listeners which are not in it, e.g. after a crash
+fn load_queue(settings: &AppSettings) {
+    let path = queue_path();
+    let mut queue = QUEUE.lock().unwrap();
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                match parse_queued_job(line) {
+                    Some(job) => queue.push_back(job),
+                    None => warn!("{}: {}", path.display(), line),
+                }
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => error!("{}: {}", path.display(), e),
+    }
+    for config in settings.listener_configs() {
+        if config.forward_target.trim().is_empty() {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(config.output_dir.join(QUEUE_DIR_NAME)) else {
+            continue;
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && !queue.iter().any(|job| job.path == *path))
+            .collect::<Vec<_>>();
+        paths.sort();
+        queue.extend(paths.into_iter().map(|path| QueuedJob {
+            connection: ConnectionId(0),
+            target: config.forward_target.clone(),
+            path,
+            delete: true,
+        }));
+    }
+    if !queue.is_empty() {
+        info!("{} jobs are queued for their forward targets", queue.len());
+        save_queue(&queue);
+    }
+}
+
+// Connects to the target with a timeout, so that a target which does not answer
+// does not hold the thread, the writes time out too
+pub fn connect(target: &str) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in target.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid address: {}", target),
+        )
+    }))
+}
+
+fn set_health(target: &str, health: Health) {
+    let mut targets = TARGETS.lock().unwrap();
+    let entry = targets.entry(target.to_owned()).or_insert(Target {
+        health: Health::Unknown,
+        used: Instant::now(),
+        active: 0,
+    });
+    match (&entry.health, &health) {
+        (Health::Up | Health::Unknown, Health::Down(reason)) => {
+            warn!("The forward target {} is down: {}", target, reason)
+        }
+        (Health::Down(_), Health::Up) => {
+            info!("The forward target {} is up again", target)
+        }
+        _ => {}
+    }
+    entry.health = health;
+}
+
+// The jobs are forwarded to a target which was not checked yet
+pub fn is_down(target: &str) -> bool {
+    TARGETS
+        .lock()
+        .unwrap()
+        .get(target)
+        .is_some_and(|entry| matches!(entry.health, Health::Down(_)))
+}
+
+// The targets with their health and the number of their queued jobs
+pub fn targets() -> Vec<(String, Health, usize)> {
+    let queue = QUEUE.lock().unwrap();
+    TARGETS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(target, entry)| {
+            let queued = queue.iter().filter(|job| job.target == *target).count();
+            (target.clone(), entry.health.clone(), queued)
+        })
+        .collect()
+}
+
+// Marks a connection forwarding a job to the target until it is dropped
+pub struct Forwarding(String);
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        if let Some(entry) = TARGETS.lock().unwrap().get_mut(&self.0) {
+            entry.active = entry.active.saturating_sub(1);
+        }
+    }
+}
+
+// Called when a connection to the target for a job is established
+pub fn connected(target: &str) -> Forwarding {
+    set_health(target, Health::Up);
+    let mut targets = TARGETS.lock().unwrap();
+    let entry = targets.get_mut(target).unwrap();
+    entry.active += 1;
+    entry.used = Instant::now();
+    Forwarding(target.to_owned())
+}
+
+// Called when a job cannot be forwarded to the target
+pub fn failed(target: &str, error: &io::Error) {
+    set_health(target, Health::Down(error.to_string()));
+    if let Some(entry) = TARGETS.lock().unwrap().get_mut(target) {
+        entry.used = Instant::now();
+    }
+}
+
+// The job is forwarded by the monitor once the target is up, the file is deleted
+// then if it was saved only to be forwarded
+pub fn enqueue(connection: ConnectionId, target: String, path: PathBuf, delete: bool) {
+    info!(
+        "[{}] The job is queued until {} is up again",
+        connection, target
+    );
+    let mut queue = QUEUE.lock().unwrap();
+    queue.push_back(QueuedJob {
+        connection,
+        target,
+        path,
+        delete,
+    });
+    save_queue(&queue);
+}
+
+// True while the saved file waits in the queue for its target
+pub fn is_queued(path: &Path) -> bool {
+    QUEUE.lock().unwrap().iter().any(|job| job.path == path)
+}
+
+// Connects to the target and with pjl asks for the status of the printer, which
+// is down if it reports that it is offline
+fn probe(target: &str, pjl: bool) -> Result<(), String> {
+    let mut stream = connect(target).map_err(|e| e.to_string())?;
+    if !pjl {
+        return Ok(());
+    }
+
+    let mut request = UEL.to_vec();
+    request.extend_from_slice(b"@PJL INFO STATUS\r\n");
+    request.extend_from_slice(UEL);
+    stream
+        .set_read_timeout(Some(CONNECT_TIMEOUT))
+        .and_then(|_| stream.write_all(&request))
+        .map_err(|e| e.to_string())?;
+    let mut status = Vec::new();
+    let mut buf = [0; 512];
+    while !status.contains(&b'\x0c') && status.len() < MAX_STATUS_SIZE {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(count) => status.extend_from_slice(&buf[..count]),
+            Err(_) if !status.is_empty() => break,
+            Err(e) => return Err(format!("no PJL status: {}", e)),
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+
+    let status = String::from_utf8_lossy(&status);
+    let value = |name: &str| {
+        status.lines().find_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            key.eq_ignore_ascii_case(name)
+                .then(|| value.trim().trim_matches('"').to_owned())
+        })
+    };
+    match value("ONLINE") {
+        Some(online) if online.eq_ignore_ascii_case("FALSE") => Err(format!(
+            "offline, {}",
+            value("DISPLAY")
+                .or_else(|| value("CODE"))
+                .unwrap_or_default()
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn send_file(job: &QueuedJob) -> io::Result<u64> {
+    let mut file = fs::File::open(&job.path)?;
+    let mut stream = connect(&job.target)?;
+    let _forwarding = connected(&job.target);
+    let size = io::copy(&mut file, &mut stream)?;
+    stream.shutdown(Shutdown::Write)?;
+    Ok(size)
+}
+
+// Forwards the queued jobs of the targets which are up, in the order they came
+fn forward_queued() {
+    loop {
+        let job = {
+            let mut queue = QUEUE.lock().unwrap();
+            match queue.iter().position(|job| !is_down(&job.target)) {
+                Some(index) => queue.remove(index).unwrap(),
+                None => return,
+            }
+        };
+        // the saved queue keeps the job until it is sent, it is sent again after a
+        // crash meanwhile rather than lost
+        match send_file(&job) {
+            Ok(size) => {
+                info!(
+                    "[{}] Forwarded {} bytes to {}",
+                    job.connection, size, job.target
+                );
+                if job.delete {
+                    let _ = fs::remove_file(&job.path);
+                }
+                save_queue(&QUEUE.lock().unwrap());
+            }
+            // a job moved away, e.g. by the scanner or the script, is skipped
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("[{}] {}: {}", job.connection, job.path.display(), e);
+                save_queue(&QUEUE.lock().unwrap());
+            }
+            Err(e) => {
+                failed(&job.target, &e);
+                QUEUE.lock().unwrap().push_front(job);
+            }
+        }
+    }
+}
+
+fn run_monitor(settings: &AppSettings) {
+    loop {
+        let seconds = settings.forward_check_seconds.load(Ordering::SeqCst);
+        if seconds == 0 {
+            // the forwarding is tried for every job again
+            for entry in TARGETS.lock().unwrap().values_mut() {
+                entry.health = Health::Unknown;
+            }
+            forward_queued();
+            thread::sleep(DISABLED_POLL_INTERVAL);
+            continue;
+        }
+
+        let configured = settings
+            .listener_configs()
+            .into_iter()
+            .map(|config| config.forward_target)
+            .filter(|target| !target.trim().is_empty())
+            .collect::<Vec<_>>();
+        let queued = QUEUE
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|job| job.target.clone())
+            .collect::<Vec<_>>();
+        let targets = {
+            let mut targets = TARGETS.lock().unwrap();
+            targets.retain(|target, entry| {
+                configured.contains(target)
+                    || queued.contains(target)
+                    || entry.used.elapsed() < UNUSED_TARGET_LIFETIME
+            });
+            configured
+                .iter()
+                .chain(targets.keys())
+                .chain(&queued)
+                .filter(|target| targets.get(*target).is_none_or(|entry| entry.active == 0))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let pjl = settings.forward_check_pjl.load(Ordering::SeqCst);
+        let mut checked = Vec::new();
+        for target in targets {
+            if !checked.contains(&target) {
+                let health = match probe(&target, pjl) {
+                    Ok(()) => Health::Up,
+                    Err(reason) => Health::Down(reason),
+                };
+                set_health(&target, health);
+                checked.push(target);
+            }
+        }
+        forward_queued();
+        thread::sleep(Duration::from_secs(seconds as u64));
+    }
+}
+
+// Checks the forward targets of the listeners and of the script in the background
+// every forward_check_seconds and forwards the queued jobs
+pub fn start(settings: Arc<AppSettings>) {
+    thread::spawn(move || {
+        load_queue(&settings);
+        run_monitor(&settings)
+    });
+}
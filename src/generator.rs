@@ -0,0 +1,297 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+
+use crate::listener::RAW_PORT;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// the printer may keep the connection open until the job is processed
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_COUNT: u32 = 100_000;
+const MAX_CONCURRENCY: u32 = 64;
+const BUFFER_SIZE: usize = 65536;
+
+const UEL: &[u8] = b"\x1b%-12345X";
+
+// The page description language of the synthetic jobs, only the header and the
+// trailer are real, the rest is filler text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pdl {
+    Text,
+    Pjl,
+    Pcl,
+    PostScript,
+    Pdf,
+}
+
+impl Pdl {
+    fn parse(text: &str) -> Option<Pdl> {
+        match text.to_ascii_lowercase().as_str() {
+            "text" | "raw" => Some(Pdl::Text),
+            "pjl" => Some(Pdl::Pjl),
+            "pcl" => Some(Pdl::Pcl),
+            "ps" | "postscript" => Some(Pdl::PostScript),
+            "pdf" => Some(Pdl::Pdf),
+            _ => None,
+        }
+    }
+
+    fn header(&self) -> Vec<u8> {
+        match self {
+            Pdl::Text => Vec::new(),
+            Pdl::Pjl => [
+                UEL,
+                b"@PJL JOB NAME=\"MiniRAW NG load test\"\r\n@PJL ENTER LANGUAGE=PCL\r\n\x1bE",
+            ]
+            .concat(),
+            Pdl::Pcl => b"\x1bE".to_vec(),
+            Pdl::PostScript => b"%!PS-Adobe-3.0\n".to_vec(),
+            Pdl::Pdf => b"%PDF-1.4\n".to_vec(),
+        }
+    }
+
+    fn trailer(&self) -> Vec<u8> {
+        match self {
+            Pdl::Text => Vec::new(),
+            Pdl::Pjl => [b"\x1bE", UEL, b"@PJL EOJ\r\n", UEL].concat(),
+            Pdl::Pcl => b"\x1bE".to_vec(),
+            Pdl::PostScript | Pdl::Pdf => b"\n%%EOF\n".to_vec(),
+        }
+    }
+}
+
+// "65536", "64k", "10M" or "1G", in bytes
+fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim().to_ascii_lowercase();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text.as_str(), ""),
+    };
+    let unit = match unit {
+        "" | "b" => 1,
+        "k" | "kb" => 1 << 10,
+        "m" | "mb" => 1 << 20,
+        "g" | "gb" => 1 << 30,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+// What the generate command of the control pipe sends
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    pub target: String,
+    size: u64,
+    count: u32,
+    concurrency: u32,
+    pdl: Pdl,
+}
+
+impl LoadOptions {
+    // "[host:port] [size=<bytes>] [count=<jobs>] [concurrency=<connections>]
+    // [pdl=text|pjl|pcl|ps|pdf]", the default target is the main listener
+    pub fn parse(argument: &str, default_target: &str) -> Result<LoadOptions, String> {
+        let mut options = LoadOptions {
+            target: default_target.to_owned(),
+            size: 64 << 10,
+            count: 100,
+            concurrency: 1,
+            pdl: Pdl::Pjl,
+        };
+        for part in argument.split_whitespace() {
+            let invalid = || format!("invalid option: {part}");
+            match part.split_once('=') {
+                Some(("size", value)) => options.size = parse_size(value).ok_or_else(invalid)?,
+                Some(("count", value)) => {
+                    options.count = value
+                        .parse()
+                        .ok()
+                        .filter(|count| (1..=MAX_COUNT).contains(count))
+                        .ok_or_else(invalid)?
+                }
+                Some(("concurrency", value)) => {
+                    options.concurrency = value
+                        .parse()
+                        .ok()
+                        .filter(|count| (1..=MAX_CONCURRENCY).contains(count))
+                        .ok_or_else(invalid)?
+                }
+                Some(("pdl", value)) => options.pdl = Pdl::parse(value).ok_or_else(invalid)?,
+                Some(_) => return Err(invalid()),
+                None => options.target = part.to_owned(),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// Times of the finished jobs, from the connection until the target has closed it
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    jobs: u32,
+    failed: u32,
+    bytes: u64,
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+}
+
+impl LoadReport {
+    fn seconds(&self) -> f64 {
+        self.elapsed.as_secs_f64().max(0.001)
+    }
+
+    pub fn megabits_per_second(&self) -> f64 {
+        self.bytes as f64 * 8.0 / self.seconds() / 1_000_000.0
+    }
+
+    pub fn average_latency(&self) -> Duration {
+        self.latencies
+            .iter()
+            .sum::<Duration>()
+            .checked_div(self.latencies.len() as u32)
+            .unwrap_or_default()
+    }
+
+    pub fn text(&self) -> String {
+        let seconds = self.seconds();
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        let percentile = |p: usize| {
+            latencies
+                .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+                .copied()
+                .unwrap_or_default()
+        };
+        format!(
+            "jobs: {}\nfailed: {}\nbytes: {}\nseconds: {:.3}\nmbit/s: {:.1}\njobs/s: {:.1}\n\
+             latency avg ms: {:.1}\nlatency p50 ms: {:.1}\nlatency p99 ms: {:.1}\n",
+            self.jobs,
+            self.failed,
+            self.bytes,
+            seconds,
+            self.megabits_per_second(),
+            self.jobs as f64 / seconds,
+            self.average_latency().as_secs_f64() * 1000.0,
+            percentile(50).as_secs_f64() * 1000.0,
+            percentile(99).as_secs_f64() * 1000.0,
+        )
+    }
+}
+
+// The same job is sent over every connection, the filler is printable so that the
+// saved jobs can be opened in a text editor
+struct TestJob {
+    header: Vec<u8>,
+    filler: Vec<u8>,
+    filler_size: u64,
+    trailer: Vec<u8>,
+}
+
+impl TestJob {
+    fn new(size: u64, pdl: Pdl) -> TestJob {
+        let header = pdl.header();
+        let trailer = pdl.trailer();
+        TestJob {
+            filler: b"MiniRAW NG load test 0123456789 ABCDEFGHIJKLMNOPQRSTUVWXYZ\r\n"
+                .iter()
+                .copied()
+                .cycle()
+                .take(BUFFER_SIZE)
+                .collect(),
+            filler_size: size.saturating_sub((header.len() + trailer.len()) as u64),
+            header,
+            trailer,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        (self.header.len() + self.trailer.len()) as u64 + self.filler_size
+    }
+
+    fn send(&self, target: &str) -> io::Result<()> {
+        // the port of the RAW printers if it is omitted
+        let address = if target.contains(':') {
+            target.to_socket_addrs()
+        } else {
+            (target, RAW_PORT).to_socket_addrs()
+        }?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, target.to_owned()))?;
+        let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(CLOSE_TIMEOUT))?;
+
+        stream.write_all(&self.header)?;
+        let mut remaining = self.filler_size;
+        while remaining > 0 {
+            let chunk = remaining.min(self.filler.len() as u64) as usize;
+            stream.write_all(&self.filler[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        stream.write_all(&self.trailer)?;
+        stream.shutdown(Shutdown::Write)?;
+
+        // the job is done when the target closes the connection or after the timeout
+        match stream.read_to_end(&mut Vec::new()) {
+            Err(e)
+                if e.kind() != io::ErrorKind::TimedOut && e.kind() != io::ErrorKind::WouldBlock =>
+            {
+                Err(e)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Sends the synthetic jobs over the given number of connections at once and
+// waits for all of them, e.g. to load-test the listener or a printer
+pub fn generate(options: &LoadOptions) -> LoadReport {
+    info!(
+        "Sending {} test job(s) of {} bytes to {}",
+        options.count, options.size, options.target
+    );
+    let job = TestJob::new(options.size, options.pdl);
+    let next = AtomicU32::new(0);
+    let report = Mutex::new(LoadReport::default());
+    let started = Instant::now();
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.concurrency.min(options.count) {
+            scope.spawn(|| {
+                while next.fetch_add(1, Ordering::SeqCst) < options.count {
+                    let job_started = Instant::now();
+                    let result = job.send(&options.target);
+                    let mut report = report.lock().unwrap();
+                    match result {
+                        Ok(()) => {
+                            report.jobs += 1;
+                            report.bytes += job.size();
+                            report.latencies.push(job_started.elapsed());
+                        }
+                        Err(e) => {
+                            report.failed += 1;
+                            warn!("{}: {}", options.target, e);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let mut report = report.into_inner().unwrap();
+    report.elapsed = started.elapsed();
+    info!(
+        "Sent {} test job(s) in {:.1} s, {} failed",
+        report.jobs,
+        report.elapsed.as_secs_f64(),
+        report.failed
+    );
+    report
+}
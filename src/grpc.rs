@@ -0,0 +1,599 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::Read,
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, SyncSender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use log::{debug, error, info, warn};
+
+use crate::{
+    api::ApiContext,
+    http::ConnectionSlots,
+    http2::{self, ResponseStream},
+    jobs::{Job, JobEvent, ListenerState},
+    resend,
+};
+
+const SERVICE: &str = "miniraw.v1.Control";
+const CONTENT_CHUNK_SIZE: usize = 65536;
+// events waiting to be sent to a watcher, it is dropped when the queue is full
+const WATCH_QUEUE_SIZE: usize = 1000;
+// how often the watchers check for the cancellation and the server shutdown
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// longer messages are cut off to fit into the trailers
+const MAX_STATUS_MESSAGE_SIZE: usize = 1024;
+// more connections at once are closed right away, each one has a thread and one
+// more for every open call
+const MAX_CONNECTIONS: usize = 16;
+
+// Status codes of gRPC
+const OK: u32 = 0;
+const CANCELLED: u32 = 1;
+const INVALID_ARGUMENT: u32 = 3;
+const NOT_FOUND: u32 = 5;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const FAILED_PRECONDITION: u32 = 9;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+const UNAVAILABLE: u32 = 14;
+const UNAUTHENTICATED: u32 = 16;
+
+// Wire types of the protocol buffers
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+// The values of ListenerState.State
+const STATE_STARTING: u64 = 0;
+const STATE_LISTENING: u64 = 1;
+const STATE_PAUSED: u64 = 2;
+const STATE_FAILED: u64 = 3;
+
+// A message of miniraw.proto in the protocol buffers encoding, the scalar fields
+// with the default values are omitted like proto3 does
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint((field as u64) << 3 | wire_type as u64);
+    }
+
+    fn uint(mut self, field: u32, value: u64) -> Message {
+        if value != 0 {
+            self.key(field, VARINT);
+            self.varint(value);
+        }
+        self
+    }
+
+    fn bytes(mut self, field: u32, value: &[u8]) -> Message {
+        if !value.is_empty() {
+            self.key(field, LENGTH_DELIMITED);
+            self.varint(value.len() as u64);
+            self.0.extend_from_slice(value);
+        }
+        self
+    }
+
+    fn string(self, field: u32, value: &str) -> Message {
+        self.bytes(field, value.as_bytes())
+    }
+
+    // Always present, even if it is empty
+    fn message(mut self, field: u32, value: Message) -> Message {
+        self.key(field, LENGTH_DELIMITED);
+        self.varint(value.0.len() as u64);
+        self.0.extend_from_slice(&value.0);
+        self
+    }
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// The fields of a request, the last value of a field wins
+struct Fields<'a>(HashMap<u32, Value<'a>>);
+
+impl<'a> Fields<'a> {
+    fn parse(mut data: &'a [u8]) -> Result<Fields<'a>, Status> {
+        let invalid = || Status::new(INVALID_ARGUMENT, "invalid message");
+        let mut fields = HashMap::new();
+        while !data.is_empty() {
+            let key = read_varint(&mut data).ok_or_else(invalid)?;
+            let value = match (key & 7) as u8 {
+                VARINT => Value::Varint(read_varint(&mut data).ok_or_else(invalid)?),
+                LENGTH_DELIMITED => {
+                    let length = read_varint(&mut data).ok_or_else(invalid)? as usize;
+                    if length > data.len() {
+                        return Err(invalid());
+                    }
+                    let (bytes, rest) = data.split_at(length);
+                    data = rest;
+                    Value::Bytes(bytes)
+                }
+                wire_type @ (FIXED64 | FIXED32) => {
+                    let length = if wire_type == FIXED64 { 8 } else { 4 };
+                    data = data.get(length..).ok_or_else(invalid)?;
+                    continue;
+                }
+                _ => return Err(invalid()),
+            };
+            fields.insert((key >> 3) as u32, value);
+        }
+        Ok(Fields(fields))
+    }
+
+    fn uint(&self, field: u32) -> Result<u64, Status> {
+        match self.0.get(&field) {
+            Some(Value::Varint(value)) => Ok(*value),
+            Some(Value::Bytes(_)) => Err(Status::new(INVALID_ARGUMENT, "invalid message")),
+            None => Ok(0),
+        }
+    }
+
+    fn string(&self, field: u32) -> Result<String, Status> {
+        match self.0.get(&field) {
+            Some(Value::Bytes(bytes)) => String::from_utf8(bytes.to_vec())
+                .map_err(|_| Status::new(INVALID_ARGUMENT, "invalid message")),
+            Some(Value::Varint(_)) => Err(Status::new(INVALID_ARGUMENT, "invalid message")),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+fn job_message(job: &Job) -> Message {
+    let time = Message::default()
+        .uint(1, job.time.unix_timestamp() as u64)
+        .uint(2, job.time.nanosecond() as u64);
+    Message::default()
+        .uint(1, job.connection.0 as u64)
+        .message(2, time)
+        .string(3, &job.source.to_string())
+        .uint(4, job.size)
+        .string(5, &job.format.to_string())
+        .string(
+            6,
+            &job.path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+        )
+        .uint(7, job.peak_memory)
+        .uint(8, job.transfer.duration_ms)
+        .uint(9, job.transfer.average_bytes_per_second)
+        .uint(10, job.transfer.peak_bytes_per_second)
+        .uint(11, job.transfer.stalls as u64)
+}
+
+fn listener_message(state: Option<&ListenerState>) -> Message {
+    match state {
+        Some(ListenerState::Listening(port)) => Message::default()
+            .uint(1, STATE_LISTENING)
+            .uint(2, *port as u64),
+        Some(ListenerState::Paused) => Message::default().uint(1, STATE_PAUSED),
+        Some(ListenerState::Failed(e)) => Message::default().uint(1, STATE_FAILED).string(3, e),
+        None => Message::default().uint(1, STATE_STARTING),
+    }
+}
+
+fn event_message(event: &JobEvent) -> Message {
+    match event {
+        JobEvent::Started { source } => {
+            Message::default().message(1, Message::default().string(1, &source.to_string()))
+        }
+        JobEvent::Progress { source, received } => Message::default().message(
+            2,
+            Message::default()
+                .string(1, &source.to_string())
+                .uint(2, *received),
+        ),
+        JobEvent::Completed(job) => Message::default().message(3, job_message(job)),
+        JobEvent::Aborted { source } => {
+            Message::default().message(4, Message::default().string(1, &source.to_string()))
+        }
+        JobEvent::Listener(state) => Message::default().message(5, listener_message(Some(state))),
+    }
+}
+
+// The status of a finished call, sent in the trailers
+struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    fn new(code: u32, message: &str) -> Status {
+        Status {
+            code,
+            message: message.to_owned(),
+        }
+    }
+
+    fn cancelled() -> Status {
+        Status::new(CANCELLED, "the call was cancelled")
+    }
+}
+
+// The grpc-message trailer is percent-encoded UTF-8
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes().take(MAX_STATUS_MESSAGE_SIZE) {
+        if (0x20..0x7f).contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+// The response of a call, the headers are sent with the first message
+struct Call<'a> {
+    response: &'a ResponseStream,
+    started: bool,
+}
+
+impl Call<'_> {
+    fn start(&mut self) -> Result<(), Status> {
+        if !self.started {
+            self.response
+                .send_headers(
+                    &[(":status", "200"), ("content-type", "application/grpc")],
+                    false,
+                )
+                .map_err(|_| Status::cancelled())?;
+            self.started = true;
+        }
+        Ok(())
+    }
+
+    // Length-prefixed, never compressed
+    fn send(&mut self, message: Message) -> Result<(), Status> {
+        self.start()?;
+        let mut data = Vec::with_capacity(5 + message.0.len());
+        data.push(0);
+        data.extend_from_slice(&(message.0.len() as u32).to_be_bytes());
+        data.extend_from_slice(&message.0);
+        self.response
+            .send_data(&data, false)
+            .map_err(|_| Status::cancelled())
+    }
+
+    // A call failing before the first message gets the status in the headers
+    fn finish(self, result: Result<(), Status>) {
+        let status = result.err().unwrap_or(Status::new(OK, ""));
+        let code = status.code.to_string();
+        let message = percent_encode(&status.message);
+        let mut trailers = Vec::new();
+        if !self.started {
+            trailers.extend([(":status", "200"), ("content-type", "application/grpc")]);
+        }
+        trailers.push(("grpc-status", &code));
+        if !message.is_empty() {
+            trailers.push(("grpc-message", &message));
+        }
+        let _ = self.response.send_headers(&trailers, true);
+    }
+}
+
+// The watchers of the job events, the events are queued for every WatchJobs call
+#[derive(Default)]
+pub struct JobWatchers {
+    senders: Mutex<Vec<SyncSender<JobEvent>>>,
+}
+
+impl JobWatchers {
+    pub fn handle_event(&self, event: &JobEvent) {
+        self.senders
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.try_send(event.clone()).is_ok());
+    }
+}
+
+// Shared by the threads of the gRPC server
+struct GrpcContext {
+    api: Arc<ApiContext>,
+    watchers: Arc<JobWatchers>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl GrpcContext {
+    fn handle(&self, request: http2::Request, response: ResponseStream) {
+        let path = request.header(":path").unwrap_or_default().to_owned();
+        let is_grpc = request
+            .header("content-type")
+            .is_some_and(|value| value.starts_with("application/grpc"));
+        if request.header(":method") != Some("POST") || !is_grpc {
+            let _ = response.send_headers(&[(":status", "415")], true);
+            return;
+        }
+
+        let mut call = Call {
+            response: &response,
+            started: false,
+        };
+        let result = self.call(&path, &request, &mut call);
+        debug!(
+            "gRPC {} {}",
+            path,
+            result.as_ref().map(|_| OK).unwrap_or_else(|e| e.code)
+        );
+        call.finish(result);
+    }
+
+    fn call(&self, path: &str, request: &http2::Request, call: &mut Call) -> Result<(), Status> {
+        if !self
+            .api
+            .is_valid_authorization(request.header("authorization"))
+        {
+            return Err(Status::new(UNAUTHENTICATED, "invalid or missing token"));
+        }
+        // one message, the unary and the server streaming calls have no more
+        let message = match request.body.split_first() {
+            None => &[][..],
+            Some((0, rest)) => rest
+                .get(4..)
+                .filter(|message| rest[..4] == (message.len() as u32).to_be_bytes())
+                .ok_or_else(|| Status::new(INVALID_ARGUMENT, "invalid message length"))?,
+            Some(_) => return Err(Status::new(UNIMPLEMENTED, "compression is not supported")),
+        };
+        let fields = Fields::parse(message)?;
+
+        let method = path
+            .strip_prefix('/')
+            .and_then(|path| path.strip_prefix(SERVICE))
+            .and_then(|path| path.strip_prefix('/'))
+            .unwrap_or_default();
+        match method {
+            "ListJobs" => {
+                let since = fields.uint(1)? as u32;
+                let response = self
+                    .api
+                    .history
+                    .jobs_since(since)
+                    .iter()
+                    .fold(Message::default(), |response, job| {
+                        response.message(1, job_message(job))
+                    });
+                call.send(response)
+            }
+            "GetJob" => call.send(job_message(&self.job(&fields)?)),
+            "GetJobContent" => self.job_content(self.job(&fields)?, call),
+            "DeleteJob" => {
+                let job = self.job(&fields)?;
+                self.api
+                    .delete(&job)
+                    .map_err(|e| Status::new(INTERNAL, &e.to_string()))?;
+                call.send(Message::default())
+            }
+            "ResendJob" => {
+                let job = self.job(&fields)?;
+                let Some(path) = job.path else {
+                    return Err(Status::new(FAILED_PRECONDITION, "the job was discarded"));
+                };
+                let mut target = fields.string(2)?;
+                if target.is_empty() {
+                    target = self.api.settings.resend_target.read().unwrap().clone();
+                }
+                if target.is_empty() {
+                    return Err(Status::new(INVALID_ARGUMENT, "no resend target"));
+                }
+                resend::resend_files(vec![path], target, self.api.window.clone());
+                call.send(Message::default())
+            }
+            "PauseListener" | "ResumeListener" => {
+                self.api.pause_listener(method == "PauseListener");
+                call.send(Message::default())
+            }
+            "GetStats" => call.send(self.stats()),
+            "WatchJobs" => self.watch_jobs(call),
+            _ => Err(Status::new(UNIMPLEMENTED, "unknown method")),
+        }
+    }
+
+    fn job(&self, fields: &Fields) -> Result<Job, Status> {
+        let id = fields.uint(1)? as u32;
+        self.api
+            .history
+            .find(id)
+            .ok_or_else(|| Status::new(NOT_FOUND, "no such job"))
+    }
+
+    fn job_content(&self, job: Job, call: &mut Call) -> Result<(), Status> {
+        let Some(path) = job.path else {
+            return Err(Status::new(FAILED_PRECONDITION, "the job was discarded"));
+        };
+        let mut file = fs::File::open(&path).map_err(|e| Status::new(NOT_FOUND, &e.to_string()))?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut buffer = vec![0; CONTENT_CHUNK_SIZE];
+        let mut first = true;
+        loop {
+            let size = file
+                .read(&mut buffer)
+                .map_err(|e| Status::new(INTERNAL, &e.to_string()))?;
+            if size == 0 && !first {
+                return Ok(());
+            }
+            let mut chunk = Message::default().bytes(1, &buffer[..size]);
+            if first {
+                chunk = chunk.string(2, &file_name);
+                first = false;
+            }
+            call.send(chunk)?;
+        }
+    }
+
+    fn stats(&self) -> Message {
+        let jobs = self.api.history.jobs_since(0);
+        let mut formats = BTreeMap::new();
+        for job in jobs.iter() {
+            *formats.entry(job.format.to_string()).or_insert(0u64) += 1;
+        }
+        let stats = Message::default()
+            .uint(1, jobs.len() as u64)
+            .uint(2, jobs.iter().map(|job| job.size).sum())
+            .uint(
+                3,
+                jobs.iter().filter(|job| job.path.is_some()).count() as u64,
+            )
+            .uint(4, self.api.transfers.count() as u64);
+        formats
+            .iter()
+            .fold(stats, |stats, (format, count)| {
+                stats.message(5, Message::default().string(1, format).uint(2, *count))
+            })
+            .message(
+                6,
+                listener_message(self.api.history.listener_state().as_ref()),
+            )
+    }
+
+    fn watch_jobs(&self, call: &mut Call) -> Result<(), Status> {
+        let (sender, receiver) = mpsc::sync_channel(WATCH_QUEUE_SIZE);
+        self.watchers.senders.lock().unwrap().push(sender);
+        call.start()?;
+        loop {
+            match receiver.recv_timeout(WATCH_POLL_INTERVAL) {
+                Ok(event) => call.send(event_message(&event))?,
+                Err(RecvTimeoutError::Timeout) => {
+                    if call.response.is_cancelled() {
+                        return Err(Status::cancelled());
+                    }
+                    if self.stopped.load(Ordering::SeqCst) {
+                        return Err(Status::new(UNAVAILABLE, "the server was stopped"));
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Status::new(
+                        RESOURCE_EXHAUSTED,
+                        "the client does not keep up with the events",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+fn run_grpc_server(address: SocketAddr, context: Arc<GrpcContext>) {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Cannot start the gRPC server on {}: {}", address, e);
+            return;
+        }
+    };
+    info!("Started the gRPC server on {}", address);
+
+    let slots = ConnectionSlots::new(MAX_CONNECTIONS);
+    for stream in listener.incoming() {
+        if context.stopped.load(Ordering::SeqCst) {
+            info!("Stopped the gRPC server on {}", address);
+            return;
+        }
+        match stream {
+            Ok(stream) => {
+                let Some(slot) = slots.take() else {
+                    debug!(
+                        "Rejected connection from {}: the limit of {} connections is reached",
+                        stream
+                            .peer_addr()
+                            .map(|a| a.to_string())
+                            .unwrap_or_default(),
+                        MAX_CONNECTIONS
+                    );
+                    continue;
+                };
+                let context = context.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = http2::serve(stream, move |request, response| {
+                        context.handle(request, response)
+                    }) {
+                        debug!("{}", e);
+                    }
+                    drop(slot);
+                });
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+// The gRPC server thread, replaced by a new one when the address is changed
+pub struct GrpcServer {
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl GrpcServer {
+    pub fn start(
+        address: SocketAddr,
+        api: Arc<ApiContext>,
+        watchers: Arc<JobWatchers>,
+    ) -> GrpcServer {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let context = Arc::new(GrpcContext {
+            api,
+            watchers,
+            stopped: stopped.clone(),
+        });
+        let thread = std::thread::spawn(move || run_grpc_server(address, context));
+        GrpcServer {
+            address,
+            stopped,
+            thread,
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Woken up like RawListener::stop, the open WatchJobs calls end within a second
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if !self.thread.is_finished() {
+            let ip = if self.address.ip().is_unspecified() {
+                IpAddr::V4(Ipv4Addr::LOCALHOST)
+            } else {
+                self.address.ip()
+            };
+            let _ = TcpStream::connect((ip, self.address.port()));
+        }
+        let _ = self.thread.join();
+    }
+}
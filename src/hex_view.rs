@@ -0,0 +1,486 @@
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::error;
+use windows::{
+    core::PCWSTR,
+    Win32::UI::{
+        Controls::{EM_SCROLLCARET, EM_SETCUEBANNER, EM_SETSEL},
+        Input::KeyboardAndMouse::VK_F3,
+        WindowsAndMessaging::*,
+    },
+};
+
+use crate::{
+    tr,
+    ui::{
+        window::{
+            Font, MessageResult, WindowBuilder, WindowEvent, WindowGeometry, WindowMessage,
+            WindowMessageHandler, WindowRef,
+        },
+        Accelerator,
+    },
+    utf16z, IDI_MAINICON,
+};
+
+const BYTES_PER_LINE: u64 = 16;
+const LINE_LENGTH: usize = 78;
+const PAGE_SIZE: u64 = 64 * 1024;
+const SEARCH_CHUNK: usize = 64 * 1024;
+
+const IDC_GO: u32 = 100;
+const IDC_FIND: u32 = 101;
+const IDC_PREV_PAGE: u32 = 102;
+const IDC_NEXT_PAGE: u32 = 103;
+
+const IDM_FIND: u32 = 200;
+const IDM_FIND_NEXT: u32 = 201;
+
+// translated for this window, so the search of the main window is not triggered
+const ACCELERATORS: &[Accelerator] = &[
+    Accelerator {
+        key: b'F' as u16,
+        ctrl: true,
+        command: IDM_FIND,
+    },
+    Accelerator {
+        key: VK_F3.0,
+        ctrl: false,
+        command: IDM_FIND_NEXT,
+    },
+];
+
+// Character position of the given byte inside a page produced by format_page
+fn hex_position(page: u64, offset: u64) -> usize {
+    let line = ((offset - page) / BYTES_PER_LINE) as usize;
+    let column = ((offset - page) % BYTES_PER_LINE) as usize;
+    line * LINE_LENGTH + 10 + column * 3 + (column >= 8) as usize
+}
+
+// Offset, hex and text columns of the data, one line per BYTES_PER_LINE bytes
+pub fn format_page(page: u64, data: &[u8]) -> String {
+    let mut text =
+        String::with_capacity(data.len() / BYTES_PER_LINE as usize * LINE_LENGTH + LINE_LENGTH);
+
+    for (index, line) in data.chunks(BYTES_PER_LINE as usize).enumerate() {
+        text.push_str(&format!("{:08X}  ", page + index as u64 * BYTES_PER_LINE));
+
+        for column in 0..BYTES_PER_LINE as usize {
+            if column == 8 {
+                text.push(' ');
+            }
+            match line.get(column) {
+                Some(byte) => text.push_str(&format!("{byte:02X} ")),
+                None => text.push_str("   "),
+            }
+        }
+
+        text.push(' ');
+        text.extend(line.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        text.push_str("\r\n");
+    }
+    text
+}
+
+// Space-separated byte pairs like "1B 45" are treated as hex, anything else as text
+fn parse_pattern(text: &str) -> Vec<u8> {
+    let tokens = text.split_whitespace().collect::<Vec<_>>();
+
+    let bytes = tokens
+        .iter()
+        .map(|t| {
+            if t.len() == 2 {
+                u8::from_str_radix(t, 16).ok()
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>();
+
+    match bytes {
+        Some(bytes) if !bytes.is_empty() => bytes,
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+fn parse_offset(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u64::from_str_radix(text, 16).ok()
+}
+
+struct HexControls {
+    dump: WindowRef,
+    offset: WindowRef,
+    pattern: WindowRef,
+    buttons: Vec<WindowRef>,
+}
+
+#[derive(Default)]
+struct HexViewState {
+    page: u64,
+    last_match: Option<u64>,
+}
+
+pub struct HexViewer {
+    path: PathBuf,
+    size: u64,
+    state: Mutex<HexViewState>,
+    // released when the window is destroyed, the window keeps this object
+    controls: Mutex<Option<Arc<HexControls>>>,
+}
+
+impl HexViewer {
+    pub fn open(path: &Path) -> io::Result<WindowRef> {
+        let size = fs::metadata(path)?.len();
+
+        let viewer = Arc::new(HexViewer {
+            path: path.to_owned(),
+            size,
+            state: Default::default(),
+            controls: Mutex::new(None),
+        });
+
+        let geometry = WindowGeometry {
+            width: Some(760),
+            height: Some(600),
+            ..Default::default()
+        };
+
+        let title = tr!(
+            "Hex view - {}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+
+        WindowBuilder::window("miniraw_hexview", None)
+            .geometry(geometry)
+            .title(title)
+            .icon(IDI_MAINICON)
+            .accelerators(ACCELERATORS)
+            .message_handler(viewer)
+            .build()
+            .map_err(io::Error::other)
+    }
+
+    fn create_controls(&self, window: &WindowRef) {
+        let dump_style = WS_CHILD
+            | WS_VISIBLE
+            | WS_VSCROLL
+            | WINDOW_STYLE(
+                (ES_LEFT | ES_MULTILINE | ES_AUTOVSCROLL | ES_READONLY | ES_NOHIDESEL) as u32,
+            );
+
+        let input_style =
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32);
+
+        let button_style = WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32);
+
+        let dump = WindowBuilder::edit_control(window.clone())
+            .style(dump_style.0)
+            .extended_style(WS_EX_CLIENTEDGE.0)
+            .font(Font::new(14, "Consolas"))
+            .build()
+            .unwrap();
+
+        let input = |cue: &str| {
+            let edit = WindowBuilder::edit_control(window.clone())
+                .style(input_style.0)
+                .extended_style(WS_EX_CLIENTEDGE.0)
+                .font(Font::new(15, "Segoe UI"))
+                .build()
+                .unwrap();
+            let cue = utf16z!(cue);
+            edit.send_message(WindowMessage::new(
+                edit.clone(),
+                EM_SETCUEBANNER,
+                1,
+                cue.as_ptr() as _,
+            ));
+            edit
+        };
+
+        let offset = input(tr!("Offset (hex)"));
+        let pattern = input(tr!("Text or hex bytes (1B 45)"));
+
+        let buttons = [
+            (IDC_GO, tr!("Go")),
+            (IDC_FIND, tr!("Find next")),
+            (IDC_PREV_PAGE, "<"),
+            (IDC_NEXT_PAGE, ">"),
+        ]
+        .iter()
+        .map(|(id, title)| {
+            WindowBuilder::button(window.clone())
+                .id(*id)
+                .title(title)
+                .style(button_style.0)
+                .font(Font::new(15, "Segoe UI"))
+                .build()
+                .unwrap()
+        })
+        .collect();
+
+        *self.controls.lock().unwrap() = Some(Arc::new(HexControls {
+            dump,
+            offset,
+            pattern,
+            buttons,
+        }));
+    }
+
+    fn controls(&self) -> Option<Arc<HexControls>> {
+        self.controls.lock().unwrap().clone()
+    }
+
+    fn layout(&self, width: i32, height: i32) {
+        let Some(controls) = self.controls() else {
+            return;
+        };
+
+        let row = |x: i32, width: i32| WindowGeometry {
+            x: Some(x),
+            y: Some(6),
+            width: Some(width),
+            height: Some(24),
+        };
+
+        controls.offset.move_window(row(6, 120));
+        controls.buttons[0].move_window(row(132, 50));
+        controls.pattern.move_window(row(194, 220));
+        controls.buttons[1].move_window(row(420, 80));
+        controls.buttons[2].move_window(row(512, 30));
+        controls.buttons[3].move_window(row(548, 30));
+
+        controls.dump.move_window(WindowGeometry {
+            x: Some(6),
+            y: Some(36),
+            width: Some(width - 12),
+            height: Some(height - 42),
+        });
+    }
+
+    fn read_page(&self, page: u64) -> io::Result<Vec<u8>> {
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(page))?;
+
+        let mut data = Vec::new();
+        file.take(PAGE_SIZE).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn show_page(&self, page: u64, highlight: Option<(u64, usize)>) {
+        let Some(controls) = self.controls() else {
+            return;
+        };
+
+        let data = match self.read_page(page) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("{}: {}", self.path.display(), e);
+                return;
+            }
+        };
+
+        self.state.lock().unwrap().page = page;
+
+        let _ = controls.dump.set_text(&format_page(page, &data));
+        let _ = controls.offset.set_text(&format!("{page:X}"));
+
+        if let Some((offset, len)) = highlight {
+            let last = (offset + len.max(1) as u64 - 1).min(page + data.len() as u64 - 1);
+            let start = hex_position(page, offset);
+            let end = hex_position(page, last) + 2;
+
+            controls.dump.send_message(WindowMessage::new(
+                controls.dump.clone(),
+                EM_SETSEL,
+                start,
+                end as _,
+            ));
+            controls.dump.send_message(WindowMessage::new(
+                controls.dump.clone(),
+                EM_SCROLLCARET,
+                0,
+                0,
+            ));
+        }
+    }
+
+    fn go_to_offset(&self) {
+        let Some(controls) = self.controls() else {
+            return;
+        };
+
+        let text = controls.offset.get_text().unwrap_or_default();
+        match parse_offset(&text) {
+            Some(offset) if offset < self.size => {
+                self.state.lock().unwrap().last_match = None;
+                self.show_page(offset - offset % BYTES_PER_LINE, Some((offset, 1)));
+            }
+            _ => self.notify(&tr!(
+                "Offset must be a hex number below {}",
+                format!("{:X}", self.size)
+            )),
+        }
+    }
+
+    fn move_page(&self, forward: bool) {
+        let page = self.state.lock().unwrap().page;
+        let page = if forward {
+            if page + PAGE_SIZE < self.size {
+                page + PAGE_SIZE
+            } else {
+                page
+            }
+        } else {
+            page.saturating_sub(PAGE_SIZE)
+        };
+        self.show_page(page, None);
+    }
+
+    fn search(&self, from: u64, pattern: &[u8]) -> io::Result<Option<u64>> {
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(from))?;
+
+        let mut chunk = vec![0u8; SEARCH_CHUNK];
+        let mut buffer = Vec::new();
+        let mut base = from;
+
+        loop {
+            let size = file.read(&mut chunk)?;
+            if size == 0 {
+                return Ok(None);
+            }
+            buffer.extend_from_slice(&chunk[..size]);
+
+            if let Some(pos) = buffer.windows(pattern.len()).position(|w| w == pattern) {
+                return Ok(Some(base + pos as u64));
+            }
+
+            // keep the tail in case the pattern crosses the chunk boundary
+            let consumed = buffer.len() - (pattern.len() - 1).min(buffer.len());
+            buffer.drain(..consumed);
+            base += consumed as u64;
+        }
+    }
+
+    fn find_next(&self) {
+        let Some(controls) = self.controls() else {
+            return;
+        };
+
+        let pattern = parse_pattern(&controls.pattern.get_text().unwrap_or_default());
+
+        let from = {
+            let state = self.state.lock().unwrap();
+            state.last_match.map(|m| m + 1).unwrap_or(state.page)
+        };
+
+        match self.search(from, &pattern) {
+            Ok(Some(offset)) => {
+                self.state.lock().unwrap().last_match = Some(offset);
+                self.show_page(
+                    offset - offset % BYTES_PER_LINE,
+                    Some((offset, pattern.len())),
+                );
+            }
+            Ok(None) => {
+                self.state.lock().unwrap().last_match = None;
+                self.notify(tr!("Pattern not found"));
+            }
+            Err(e) => error!("{}: {}", self.path.display(), e),
+        }
+    }
+
+    // Ctrl+F selects the pattern to type a new one
+    fn focus_pattern(&self) {
+        let Some(controls) = self.controls() else {
+            return;
+        };
+
+        controls.pattern.set_focus();
+        controls.pattern.send_message(WindowMessage::new(
+            controls.pattern.clone(),
+            EM_SETSEL,
+            0,
+            -1,
+        ));
+    }
+
+    fn notify(&self, text: &str) {
+        let Some(controls) = self.controls() else {
+            return;
+        };
+
+        let text = utf16z!(text);
+        let caption = utf16z!(tr!("Hex view"));
+        unsafe {
+            MessageBoxW(
+                Some(controls.dump.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                MB_ICONINFORMATION,
+            );
+        }
+    }
+}
+
+impl WindowMessageHandler for HexViewer {
+    fn handle_command(&self, _window: &WindowRef, id: u32) -> MessageResult {
+        match id {
+            IDM_FIND => self.focus_pattern(),
+            IDM_FIND_NEXT => self.find_next(),
+            _ => return MessageResult::Ignored,
+        }
+        MessageResult::Processed
+    }
+
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        match message.event() {
+            WindowEvent::Create => {
+                self.create_controls(&message.window);
+                self.show_page(0, None);
+                MessageResult::Processed
+            }
+            WindowEvent::Size { width, height } => {
+                self.layout(width, height);
+                MessageResult::Processed
+            }
+            WindowEvent::Command {
+                id,
+                code: BN_CLICKED,
+            } => {
+                match id {
+                    IDC_GO => self.go_to_offset(),
+                    IDC_FIND => self.find_next(),
+                    IDC_PREV_PAGE => self.move_page(false),
+                    IDC_NEXT_PAGE => self.move_page(true),
+                    _ => return MessageResult::Ignored,
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Destroy => {
+                *self.controls.lock().unwrap() = None;
+                MessageResult::Processed
+            }
+            _ => MessageResult::Ignored,
+        }
+    }
+}
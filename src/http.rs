@@ -0,0 +1,277 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// Requests with a larger header are rejected, the request body is not used
+const MAX_HEADER_SIZE: u64 = 16384;
+// the whole header has to arrive in this time, however slowly its bytes come
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+// a client which does not read the response is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Limits the connections served at once, each of them has a thread of its own
+pub struct ConnectionSlots {
+    max: usize,
+    used: AtomicUsize,
+}
+
+// A connection being served, the slot is free again when it is dropped
+pub struct Slot(Arc<ConnectionSlots>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.used.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ConnectionSlots {
+    pub fn new(max: usize) -> Arc<ConnectionSlots> {
+        Arc::new(ConnectionSlots {
+            max,
+            used: AtomicUsize::new(0),
+        })
+    }
+
+    // None if all slots are used, the connection is to be closed then
+    pub fn take(self: &Arc<Self>) -> Option<Slot> {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < self.max).then_some(used + 1)
+            })
+            .ok()
+            .map(|_| Slot(self.clone()))
+    }
+}
+
+// Reads from the stream until the deadline, the read timeout is what remains of it
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the request header is too slow",
+            ));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+fn invalid_request(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+// Decodes %XX sequences and '+' of a query component, invalid sequences are kept as is
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => match text
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    index += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// An HTTP/1.1 request line with the headers, one request is served per connection
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn read(stream: &TcpStream) -> io::Result<Request> {
+        let reader = DeadlineReader {
+            stream,
+            deadline: Instant::now() + HEADER_TIMEOUT,
+        };
+        let mut reader = BufReader::new(reader.take(MAX_HEADER_SIZE));
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_ascii_whitespace();
+        let (Some(method), Some(target), Some(_version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid_request("invalid request line"));
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let mut request = Request {
+            method: method.to_owned(),
+            path: percent_decode(path),
+            query: query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    (percent_decode(name), percent_decode(value))
+                })
+                .collect(),
+            headers: Vec::new(),
+        };
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid_request("incomplete request header"));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                return Ok(request);
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                request
+                    .headers
+                    .push((name.trim().to_owned(), value.trim().to_owned()));
+            }
+        }
+    }
+
+    // Header names are case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub enum Body {
+    Bytes(Vec<u8>),
+    // sent in chunks, e.g. the contents of a job file
+    File(fs::File, u64),
+}
+
+pub struct Response {
+    pub status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Body,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: Body) -> Response {
+        Response {
+            status,
+            headers: vec![("Content-Type", content_type.to_owned())],
+            body,
+        }
+    }
+
+    pub fn json(status: u16, json: String) -> Response {
+        Response::new(status, "application/json", Body::Bytes(json.into_bytes()))
+    }
+
+    pub fn no_content() -> Response {
+        Response {
+            status: 204,
+            headers: Vec::new(),
+            body: Body::Bytes(Vec::new()),
+        }
+    }
+
+    // {"error": "message"}
+    pub fn error(status: u16, message: &str) -> Response {
+        Response::json(status, format!("{{\"error\":{}}}", json_string(message)))
+    }
+
+    pub fn header(mut self, name: &'static str, value: String) -> Response {
+        self.headers.push((name, value));
+        self
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            204 => "No Content",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+
+    // The connection is closed after the response
+    pub fn write_to(self, stream: &mut TcpStream) -> io::Result<()> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let length = match self.body {
+            Body::Bytes(ref bytes) => bytes.len() as u64,
+            Body::File(_, length) => length,
+        };
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, self.reason());
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!(
+            "Content-Length: {length}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n"
+        ));
+        stream.write_all(head.as_bytes())?;
+
+        match self.body {
+            Body::Bytes(bytes) => stream.write_all(&bytes)?,
+            Body::File(file, length) => {
+                io::copy(&mut file.take(length), stream)?;
+            }
+        }
+        stream.flush()
+    }
+}
+
+// A JSON string literal with the quotes
+pub fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,1368 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    time::Duration,
+};
+
+// HTTP/2 without TLS, the client starts with the preface right away (prior knowledge)
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// Frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// Frame flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+// Settings
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+// Error codes of RST_STREAM and GOAWAY
+const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+const INTERNAL_ERROR: u32 = 0x2;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+
+const DEFAULT_WINDOW_SIZE: i64 = 65535;
+const MAX_WINDOW_SIZE: i64 = 0x7fff_ffff;
+const DEFAULT_FRAME_SIZE: usize = 16384;
+const MAX_FRAME_SIZE: usize = 0xff_ffff;
+// the size of the dynamic table of the decoder, the encoder does not use one
+const HEADER_TABLE_SIZE: usize = 4096;
+// every open stream has a thread of its own
+const MAX_CONCURRENT_STREAMS: usize = 16;
+// larger header blocks and request bodies close the connection or reset the stream
+const MAX_HEADER_BLOCK_SIZE: usize = 65536;
+const MAX_REQUEST_SIZE: usize = 1 << 20;
+const WRITE_TIMEOUT: Duration = Duration::from_secs(60);
+// a connection without frames and without open streams is closed after it, a frame
+// must arrive within it once it is started
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+// The static table of HPACK (RFC 7541, appendix A), index 1 is the first entry
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// The Huffman code of HPACK (RFC 7541, appendix B) with the bit lengths, indexed by
+// the symbol, 256 is EOS
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+// The inner nodes of the Huffman code, a positive child is the index of the next
+// node, a negative one is a symbol (-1 is 0)
+fn huffman_tree() -> &'static [[i32; 2]] {
+    static TREE: OnceLock<Vec<[i32; 2]>> = OnceLock::new();
+    TREE.get_or_init(|| {
+        let mut tree = vec![[0, 0]];
+        for (symbol, &(code, length)) in HUFFMAN_CODES.iter().enumerate() {
+            let mut node = 0;
+            for bit in (0..length).rev() {
+                let branch = (code >> bit & 1) as usize;
+                if bit == 0 {
+                    tree[node][branch] = -(symbol as i32) - 1;
+                } else {
+                    if tree[node][branch] == 0 {
+                        tree.push([0, 0]);
+                        tree[node][branch] = (tree.len() - 1) as i32;
+                    }
+                    node = tree[node][branch] as usize;
+                }
+            }
+        }
+        tree
+    })
+}
+
+// The padding after the last symbol must be the shortest prefix of EOS
+fn huffman_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let tree = huffman_tree();
+    let mut decoded = Vec::with_capacity(data.len() * 8 / 5);
+    let mut node = 0;
+    let mut padding = 0;
+    let mut padding_ones = true;
+    for byte in data {
+        for bit in (0..8).rev() {
+            let branch = (byte >> bit & 1) as usize;
+            padding += 1;
+            padding_ones &= branch == 1;
+            match tree[node][branch] {
+                0 => return None,
+                child if child > 0 => node = child as usize,
+                leaf => {
+                    let symbol = -leaf - 1;
+                    if symbol > u8::MAX as i32 {
+                        return None;
+                    }
+                    decoded.push(symbol as u8);
+                    node = 0;
+                    padding = 0;
+                    padding_ones = true;
+                }
+            }
+        }
+    }
+    (padding < 8 && padding_ones).then_some(decoded)
+}
+
+// An integer with an N-bit prefix, the flags in the first byte are ignored
+fn decode_integer(data: &mut &[u8], prefix_bits: u32) -> Option<usize> {
+    let (&first, rest) = data.split_first()?;
+    *data = rest;
+    let max = (1usize << prefix_bits) - 1;
+    let mut value = first as usize & max;
+    if value < max {
+        return Some(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        if shift > 28 {
+            return None;
+        }
+        value = value.checked_add(((byte & 0x7f) as usize) << shift)?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+}
+
+fn decode_string(data: &mut &[u8]) -> Option<String> {
+    let huffman = *data.first()? & 0x80 != 0;
+    let length = decode_integer(data, 7)?;
+    if length > data.len() {
+        return None;
+    }
+    let (bytes, rest) = data.split_at(length);
+    *data = rest;
+    let bytes = if huffman {
+        huffman_decode(bytes)?
+    } else {
+        bytes.to_vec()
+    };
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn encode_integer(block: &mut Vec<u8>, value: usize, prefix_bits: u32, flags: u8) {
+    let max = (1usize << prefix_bits) - 1;
+    if value < max {
+        block.push(flags | value as u8);
+        return;
+    }
+    block.push(flags | max as u8);
+    let mut value = value - max;
+    while value >= 0x80 {
+        block.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    block.push(value as u8);
+}
+
+// Literal header fields without indexing and without the Huffman code, so the
+// client does not need to keep a table for the responses
+fn encode_headers(headers: &[(&str, &str)]) -> Vec<u8> {
+    let mut block = Vec::new();
+    for (name, value) in headers {
+        block.push(0);
+        for text in [name, value] {
+            encode_integer(&mut block, text.len(), 7, 0);
+            block.extend_from_slice(text.as_bytes());
+        }
+    }
+    block
+}
+
+// The HPACK decoder of the request headers, one per connection
+struct Decoder {
+    // the newest entry is the first one
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Decoder {
+    fn new() -> Decoder {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: HEADER_TABLE_SIZE,
+        }
+    }
+
+    fn entry(&self, index: usize) -> Option<(String, String)> {
+        let (name, value) = match index {
+            0 => return None,
+            1..=61 => STATIC_TABLE[index - 1],
+            _ => {
+                let (name, value) = self.table.get(index - 62)?;
+                (name.as_str(), value.as_str())
+            }
+        };
+        Some((name.to_owned(), value.to_owned()))
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else {
+                break;
+            };
+            self.size -= name.len() + value.len() + 32;
+        }
+    }
+
+    // An entry larger than the table empties it
+    fn insert(&mut self, name: String, value: String) {
+        self.size += name.len() + value.len() + 32;
+        self.table.push_front((name, value));
+        self.evict();
+    }
+
+    // None is a compression error, the connection can't be used anymore
+    fn decode(&mut self, mut block: &[u8]) -> Option<Vec<(String, String)>> {
+        let mut headers = Vec::new();
+        while let Some(&first) = block.first() {
+            if first & 0x80 != 0 {
+                let index = decode_integer(&mut block, 7)?;
+                headers.push(self.entry(index)?);
+            } else if first & 0xe0 == 0x20 {
+                let size = decode_integer(&mut block, 5)?;
+                if size > HEADER_TABLE_SIZE {
+                    return None;
+                }
+                self.max_size = size;
+                self.evict();
+            } else {
+                // with incremental indexing, without indexing or never indexed
+                let indexing = first & 0x40 != 0;
+                let index = decode_integer(&mut block, if indexing { 6 } else { 4 })?;
+                let name = match index {
+                    0 => decode_string(&mut block)?,
+                    _ => self.entry(index)?.0,
+                };
+                let value = decode_string(&mut block)?;
+                if indexing {
+                    self.insert(name.clone(), value.clone());
+                }
+                headers.push((name, value));
+            }
+        }
+        Some(headers)
+    }
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    fn read(reader: &mut impl Read) -> io::Result<Frame> {
+        let mut header = [0u8; 9];
+        reader.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        if length > DEFAULT_FRAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame of {length} bytes"),
+            ));
+        }
+        let mut payload = vec![0; length];
+        reader.read_exact(&mut payload)?;
+        Ok(Frame {
+            kind: header[3],
+            flags: header[4],
+            stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]])
+                & 0x7fff_ffff,
+            payload,
+        })
+    }
+
+    // The payload without the padding, None if the padding is longer than the frame
+    fn unpadded(&self) -> Option<&[u8]> {
+        if self.flags & PADDED == 0 {
+            return Some(&self.payload);
+        }
+        let (&padding, rest) = self.payload.split_first()?;
+        rest.get(..rest.len().checked_sub(padding as usize)?)
+    }
+}
+
+fn write_frame(
+    writer: &mut impl Write,
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(9 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend_from_slice(&stream_id.to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "the stream was closed")
+}
+
+// A request with the pseudo-header fields, e.g. ":path", and the whole body
+#[derive(Debug)]
+pub struct Request {
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// The state shared by the reader of the connection and the threads of the responses
+struct Connection {
+    writer: TcpStream,
+    closed: bool,
+    // the send windows, the streams are removed when the response is complete or
+    // when the client resets them
+    window: i64,
+    streams: HashMap<u32, i64>,
+    initial_window: i64,
+    max_frame_size: usize,
+}
+
+struct Shared {
+    connection: Mutex<Connection>,
+    // signalled when a window is updated and when the connection is closed
+    changed: Condvar,
+}
+
+// The response of a request, the stream is reset if it is dropped before the end
+pub struct ResponseStream {
+    id: u32,
+    shared: Arc<Shared>,
+}
+
+impl ResponseStream {
+    pub fn send_headers(&self, headers: &[(&str, &str)], end_stream: bool) -> io::Result<()> {
+        let block = encode_headers(headers);
+        let mut connection = self.shared.connection.lock().unwrap();
+        if connection.closed || !connection.streams.contains_key(&self.id) {
+            return Err(closed());
+        }
+        let chunks = block.chunks(connection.max_frame_size).collect::<Vec<_>>();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let mut flags = 0;
+            if index == chunks.len() - 1 {
+                flags |= END_HEADERS;
+            }
+            if index == 0 && end_stream {
+                flags |= END_STREAM;
+            }
+            let kind = if index == 0 { HEADERS } else { CONTINUATION };
+            write_frame(&mut connection.writer, kind, flags, self.id, chunk)?;
+        }
+        if end_stream {
+            connection.streams.remove(&self.id);
+        }
+        Ok(())
+    }
+
+    // Waits until the client allows to send more, the data is split into frames
+    pub fn send_data(&self, mut data: &[u8], end_stream: bool) -> io::Result<()> {
+        let mut guard = self.shared.connection.lock().unwrap();
+        loop {
+            let connection = &mut *guard;
+            let window = match connection.streams.get_mut(&self.id) {
+                Some(window) if !connection.closed => window,
+                _ => return Err(closed()),
+            };
+            let available = connection
+                .window
+                .min(*window)
+                .min(connection.max_frame_size as i64);
+            if available <= 0 && !data.is_empty() {
+                guard = self.shared.changed.wait(guard).unwrap();
+                continue;
+            }
+
+            let (chunk, rest) = data.split_at((available.max(0) as usize).min(data.len()));
+            connection.window -= chunk.len() as i64;
+            *window -= chunk.len() as i64;
+            let flags = if rest.is_empty() && end_stream {
+                END_STREAM
+            } else {
+                0
+            };
+            write_frame(&mut connection.writer, DATA, flags, self.id, chunk)?;
+            data = rest;
+            if data.is_empty() {
+                if end_stream {
+                    connection.streams.remove(&self.id);
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    // True if the client has reset the stream or closed the connection
+    pub fn is_cancelled(&self) -> bool {
+        let connection = self.shared.connection.lock().unwrap();
+        connection.closed || !connection.streams.contains_key(&self.id)
+    }
+}
+
+impl Drop for ResponseStream {
+    fn drop(&mut self) {
+        let mut connection = self.shared.connection.lock().unwrap();
+        if connection.streams.remove(&self.id).is_some() && !connection.closed {
+            let _ = write_frame(
+                &mut connection.writer,
+                RST_STREAM,
+                0,
+                self.id,
+                &INTERNAL_ERROR.to_be_bytes(),
+            );
+        }
+    }
+}
+
+type Handler = dyn Fn(Request, ResponseStream) + Send + Sync;
+
+// Reads the frames of a connection, the requests are handled on their own threads
+struct Reader {
+    shared: Arc<Shared>,
+    handler: Arc<Handler>,
+    decoder: Decoder,
+    // the requests whose body is being received
+    requests: HashMap<u32, Request>,
+    // the stream and the flags of a header block continued in CONTINUATION frames
+    header_block: Option<(u32, u8, Vec<u8>)>,
+    last_stream_id: u32,
+}
+
+impl Reader {
+    fn write_frame(&self, kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> io::Result<()> {
+        let mut connection = self.shared.connection.lock().unwrap();
+        write_frame(&mut connection.writer, kind, flags, stream_id, payload)
+    }
+
+    // Err is a connection error with the code of the GOAWAY frame
+    fn process(&mut self, frame: Frame) -> Result<(), u32> {
+        if let Some((stream_id, ..)) = self.header_block {
+            if frame.kind != CONTINUATION || frame.stream_id != stream_id {
+                return Err(PROTOCOL_ERROR);
+            }
+        }
+        match frame.kind {
+            DATA => self.data(&frame),
+            HEADERS => {
+                let mut block = frame.unpadded().ok_or(PROTOCOL_ERROR)?;
+                if frame.flags & PRIORITY != 0 {
+                    block = block.get(5..).ok_or(PROTOCOL_ERROR)?;
+                }
+                if frame.stream_id == 0 {
+                    return Err(PROTOCOL_ERROR);
+                }
+                self.header_block = Some((frame.stream_id, frame.flags, block.to_vec()));
+                self.end_headers(frame.flags)
+            }
+            CONTINUATION => {
+                let Some((_, _, ref mut block)) = self.header_block else {
+                    return Err(PROTOCOL_ERROR);
+                };
+                block.extend_from_slice(&frame.payload);
+                if block.len() > MAX_HEADER_BLOCK_SIZE {
+                    return Err(PROTOCOL_ERROR);
+                }
+                self.end_headers(frame.flags)
+            }
+            RST_STREAM => {
+                self.requests.remove(&frame.stream_id);
+                let mut connection = self.shared.connection.lock().unwrap();
+                connection.streams.remove(&frame.stream_id);
+                self.shared.changed.notify_all();
+                Ok(())
+            }
+            SETTINGS if frame.flags & ACK == 0 => {
+                if frame.stream_id != 0 || !frame.payload.len().is_multiple_of(6) {
+                    return Err(FRAME_SIZE_ERROR);
+                }
+                self.settings(&frame.payload)?;
+                self.write_frame(SETTINGS, ACK, 0, &[])
+                    .map_err(|_| INTERNAL_ERROR)
+            }
+            PING if frame.flags & ACK == 0 => self
+                .write_frame(PING, ACK, 0, &frame.payload)
+                .map_err(|_| INTERNAL_ERROR),
+            WINDOW_UPDATE => {
+                let increment = frame
+                    .payload
+                    .get(..4)
+                    .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) & 0x7fff_ffff)
+                    .ok_or(FRAME_SIZE_ERROR)? as i64;
+                let mut connection = self.shared.connection.lock().unwrap();
+                let window = if frame.stream_id == 0 {
+                    &mut connection.window
+                } else {
+                    match connection.streams.get_mut(&frame.stream_id) {
+                        Some(window) => window,
+                        None => return Ok(()),
+                    }
+                };
+                *window += increment;
+                if *window > MAX_WINDOW_SIZE {
+                    return Err(FLOW_CONTROL_ERROR);
+                }
+                self.shared.changed.notify_all();
+                Ok(())
+            }
+            GOAWAY => Err(NO_ERROR),
+            // PRIORITY, PUSH_PROMISE, the acknowledgements and unknown frames
+            _ => Ok(()),
+        }
+    }
+
+    fn settings(&mut self, payload: &[u8]) -> Result<(), u32> {
+        let mut connection = self.shared.connection.lock().unwrap();
+        for setting in payload.chunks(6) {
+            let id = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match id {
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value as i64 > MAX_WINDOW_SIZE {
+                        return Err(FLOW_CONTROL_ERROR);
+                    }
+                    // the change applies to the windows of the open streams too
+                    let delta = value as i64 - connection.initial_window;
+                    connection.initial_window = value as i64;
+                    for window in connection.streams.values_mut() {
+                        *window += delta;
+                    }
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(DEFAULT_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&(value as usize)) {
+                        return Err(PROTOCOL_ERROR);
+                    }
+                    connection.max_frame_size = value as usize;
+                }
+                _ => {}
+            }
+        }
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    fn end_headers(&mut self, flags: u8) -> Result<(), u32> {
+        if flags & END_HEADERS == 0 {
+            return Ok(());
+        }
+        let Some((stream_id, flags, block)) = self.header_block.take() else {
+            return Ok(());
+        };
+        // every block changes the table, even the ones of the ignored streams
+        let headers = self.decoder.decode(&block).ok_or(COMPRESSION_ERROR)?;
+
+        // the trailers of a request end its body
+        if let Some(request) = self.requests.get_mut(&stream_id) {
+            if flags & END_STREAM == 0 {
+                return Err(PROTOCOL_ERROR);
+            }
+            request.headers.extend(headers);
+            self.dispatch(stream_id);
+            return Ok(());
+        }
+        if stream_id % 2 == 0 || stream_id <= self.last_stream_id {
+            return Err(PROTOCOL_ERROR);
+        }
+        self.last_stream_id = stream_id;
+
+        {
+            let mut connection = self.shared.connection.lock().unwrap();
+            if connection.streams.len() >= MAX_CONCURRENT_STREAMS {
+                return write_frame(
+                    &mut connection.writer,
+                    RST_STREAM,
+                    0,
+                    stream_id,
+                    &REFUSED_STREAM.to_be_bytes(),
+                )
+                .map_err(|_| INTERNAL_ERROR);
+            }
+            let window = connection.initial_window;
+            connection.streams.insert(stream_id, window);
+        }
+        self.requests.insert(
+            stream_id,
+            Request {
+                headers,
+                body: Vec::new(),
+            },
+        );
+        if flags & END_STREAM != 0 {
+            self.dispatch(stream_id);
+        }
+        Ok(())
+    }
+
+    // The whole window is given back right away, the size of the requests is
+    // limited instead
+    fn data(&mut self, frame: &Frame) -> Result<(), u32> {
+        if frame.stream_id == 0 {
+            return Err(PROTOCOL_ERROR);
+        }
+        let data = frame.unpadded().ok_or(PROTOCOL_ERROR)?;
+        if !frame.payload.is_empty() {
+            let increment = (frame.payload.len() as u32).to_be_bytes();
+            self.write_frame(WINDOW_UPDATE, 0, 0, &increment)
+                .map_err(|_| INTERNAL_ERROR)?;
+            if frame.flags & END_STREAM == 0 && self.requests.contains_key(&frame.stream_id) {
+                self.write_frame(WINDOW_UPDATE, 0, frame.stream_id, &increment)
+                    .map_err(|_| INTERNAL_ERROR)?;
+            }
+        }
+
+        let Some(request) = self.requests.get_mut(&frame.stream_id) else {
+            return Ok(());
+        };
+        if request.body.len() + data.len() > MAX_REQUEST_SIZE {
+            self.requests.remove(&frame.stream_id);
+            let mut connection = self.shared.connection.lock().unwrap();
+            connection.streams.remove(&frame.stream_id);
+            return write_frame(
+                &mut connection.writer,
+                RST_STREAM,
+                0,
+                frame.stream_id,
+                &REFUSED_STREAM.to_be_bytes(),
+            )
+            .map_err(|_| INTERNAL_ERROR);
+        }
+        request.body.extend_from_slice(data);
+        if frame.flags & END_STREAM != 0 {
+            self.dispatch(frame.stream_id);
+        }
+        Ok(())
+    }
+
+    fn dispatch(&mut self, stream_id: u32) {
+        let Some(request) = self.requests.remove(&stream_id) else {
+            return;
+        };
+        let response = ResponseStream {
+            id: stream_id,
+            shared: self.shared.clone(),
+        };
+        let handler = self.handler.clone();
+        std::thread::spawn(move || handler(request, response));
+    }
+}
+
+// Serves an HTTP/2 connection until the client closes it or it is idle for
+// IDLE_TIMEOUT, the handler is called on a thread of its own for every request so
+// that long responses do not block the other streams
+pub fn serve<F>(stream: TcpStream, handler: F) -> io::Result<()>
+where
+    F: Fn(Request, ResponseStream) + Send + Sync + 'static,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut preface = [0u8; PREFACE.len()];
+    reader.read_exact(&mut preface)?;
+    if preface != PREFACE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an HTTP/2 connection",
+        ));
+    }
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+
+    let mut writer = stream.try_clone()?;
+    let settings = [
+        (SETTINGS_HEADER_TABLE_SIZE, HEADER_TABLE_SIZE as u32),
+        (SETTINGS_ENABLE_PUSH, 0),
+        (
+            SETTINGS_MAX_CONCURRENT_STREAMS,
+            MAX_CONCURRENT_STREAMS as u32,
+        ),
+    ]
+    .iter()
+    .flat_map(|(id, value)| [&id.to_be_bytes()[..], &value.to_be_bytes()[..]].concat())
+    .collect::<Vec<_>>();
+    write_frame(&mut writer, SETTINGS, 0, 0, &settings)?;
+
+    let mut connection = Reader {
+        shared: Arc::new(Shared {
+            connection: Mutex::new(Connection {
+                writer,
+                closed: false,
+                window: DEFAULT_WINDOW_SIZE,
+                streams: HashMap::new(),
+                initial_window: DEFAULT_WINDOW_SIZE,
+                max_frame_size: DEFAULT_FRAME_SIZE,
+            }),
+            changed: Condvar::new(),
+        }),
+        handler: Arc::new(handler),
+        decoder: Decoder::new(),
+        requests: HashMap::new(),
+        header_block: None,
+        last_stream_id: 0,
+    };
+
+    let result = loop {
+        // the wait for the next frame does not consume anything, so it can go on
+        // while the responses of the open streams are being sent
+        if reader.buffer().is_empty() {
+            match reader.fill_buf() {
+                Ok([]) => break Ok(()),
+                Ok(_) => {}
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    let state = connection.shared.connection.lock().unwrap();
+                    if state.streams.is_empty() {
+                        break Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "the HTTP/2 connection is idle",
+                        ));
+                    }
+                    continue;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => break Err(e),
+            }
+        }
+        let frame = match Frame::read(&mut reader) {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                let _ = connection.write_frame(
+                    GOAWAY,
+                    0,
+                    0,
+                    &[
+                        connection.last_stream_id.to_be_bytes(),
+                        FRAME_SIZE_ERROR.to_be_bytes(),
+                    ]
+                    .concat(),
+                );
+                break Err(e);
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        match connection.process(frame) {
+            Ok(()) => {}
+            Err(NO_ERROR) => break Ok(()),
+            Err(code) => {
+                let _ = connection.write_frame(
+                    GOAWAY,
+                    0,
+                    0,
+                    &[connection.last_stream_id.to_be_bytes(), code.to_be_bytes()].concat(),
+                );
+                break Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("HTTP/2 error {code}"),
+                ));
+            }
+        }
+    };
+
+    // the responses still being sent fail
+    connection.shared.connection.lock().unwrap().closed = true;
+    connection.shared.changed.notify_all();
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let text = text.replace(' ', "");
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    // RFC 7541, C.1
+    #[test]
+    fn integers() {
+        for (value, prefix_bits, encoded) in [(10, 5, "0a"), (1337, 5, "1f9a0a"), (42, 8, "2a")] {
+            let mut block = Vec::new();
+            encode_integer(&mut block, value, prefix_bits, 0);
+            assert_eq!(block, hex(encoded));
+            assert_eq!(decode_integer(&mut &block[..], prefix_bits), Some(value));
+        }
+        // the flags above the prefix are ignored
+        assert_eq!(decode_integer(&mut &[0xea][..], 5), Some(10));
+        // too long for a usize
+        assert_eq!(decode_integer(&mut &hex("1fffffffffffff7f")[..], 5), None);
+        assert_eq!(decode_integer(&mut &hex("1f9a")[..], 5), None);
+    }
+
+    // RFC 7541, C.2
+    #[test]
+    fn literal_fields() {
+        let mut decoder = Decoder::new();
+        let block = hex("400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572");
+        assert_eq!(
+            decoder.decode(&block),
+            Some(headers(&[("custom-key", "custom-header")]))
+        );
+        assert_eq!(decoder.size, 55);
+
+        let mut decoder = Decoder::new();
+        let block = hex("040c 2f73 616d 706c 652f 7061 7468");
+        assert_eq!(
+            decoder.decode(&block),
+            Some(headers(&[(":path", "/sample/path")]))
+        );
+        assert!(decoder.table.is_empty());
+
+        let block = hex("1008 7061 7373 776f 7264 0673 6563 7265 74");
+        assert_eq!(
+            decoder.decode(&block),
+            Some(headers(&[("password", "secret")]))
+        );
+        assert!(decoder.table.is_empty());
+
+        assert_eq!(
+            decoder.decode(&hex("82")),
+            Some(headers(&[(":method", "GET")]))
+        );
+        assert!(decoder.table.is_empty());
+    }
+
+    fn check_requests(blocks: [&str; 3]) {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.decode(&hex(blocks[0])),
+            Some(headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ]))
+        );
+        assert_eq!(decoder.size, 57);
+
+        assert_eq!(
+            decoder.decode(&hex(blocks[1])),
+            Some(headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ]))
+        );
+        assert_eq!(decoder.size, 110);
+
+        assert_eq!(
+            decoder.decode(&hex(blocks[2])),
+            Some(headers(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ]))
+        );
+        assert_eq!(decoder.size, 164);
+        assert_eq!(
+            decoder.table,
+            headers(&[
+                ("custom-key", "custom-value"),
+                ("cache-control", "no-cache"),
+                (":authority", "www.example.com"),
+            ])
+        );
+    }
+
+    // RFC 7541, C.3
+    #[test]
+    fn requests() {
+        check_requests([
+            "8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d",
+            "8286 84be 5808 6e6f 2d63 6163 6865",
+            "8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65",
+        ]);
+    }
+
+    // RFC 7541, C.4
+    #[test]
+    fn requests_with_huffman() {
+        check_requests([
+            "8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff",
+            "8286 84be 5886 a8eb 1064 9cbf",
+            "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+        ]);
+    }
+
+    fn check_responses(blocks: [&str; 3]) {
+        let mut decoder = Decoder::new();
+        decoder.max_size = 256;
+        assert_eq!(
+            decoder.decode(&hex(blocks[0])),
+            Some(headers(&[
+                (":status", "302"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ]))
+        );
+        assert_eq!(decoder.size, 222);
+
+        // the oldest entry is evicted
+        assert_eq!(
+            decoder.decode(&hex(blocks[1])),
+            Some(headers(&[
+                (":status", "307"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ]))
+        );
+        assert_eq!(decoder.size, 222);
+        assert_eq!(decoder.table[0], (":status".into(), "307".into()));
+
+        assert_eq!(
+            decoder.decode(&hex(blocks[2])),
+            Some(headers(&[
+                (":status", "200"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+                ("location", "https://www.example.com"),
+                ("content-encoding", "gzip"),
+                (
+                    "set-cookie",
+                    "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"
+                ),
+            ]))
+        );
+        assert_eq!(decoder.size, 215);
+        assert_eq!(
+            decoder.table,
+            headers(&[
+                (
+                    "set-cookie",
+                    "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"
+                ),
+                ("content-encoding", "gzip"),
+                ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+            ])
+        );
+    }
+
+    // RFC 7541, C.5
+    #[test]
+    fn responses() {
+        check_responses([
+            "4803 3330 3258 0770 7269 7661 7465 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 \
+             3133 2032 303a 3133 3a32 3120 474d 546e 1768 7474 7073 3a2f 2f77 7777 2e65 \
+             7861 6d70 6c65 2e63 6f6d",
+            "4803 3330 37c1 c0bf",
+            "88c1 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 2032 303a 3133 3a32 3220 \
+             474d 54c0 5a04 677a 6970 7738 666f 6f3d 4153 444a 4b48 514b 425a 584f 5157 \
+             454f 5049 5541 5851 5745 4f49 553b 206d 6178 2d61 6765 3d33 3630 303b 2076 \
+             6572 7369 6f6e 3d31",
+        ]);
+    }
+
+    // RFC 7541, C.6
+    #[test]
+    fn responses_with_huffman() {
+        check_responses([
+            "4882 6402 5885 aec3 771a 4b61 96d0 7abe 9410 54d4 44a8 2005 9504 0b81 66e0 \
+             82a6 2d1b ff6e 919d 29ad 1718 63c7 8f0b 97c8 e9ae 82ae 43d3",
+            "4883 640e ffc1 c0bf",
+            "88c1 6196 d07a be94 1054 d444 a820 0595 040b 8166 e084 a62d 1bff c05a 839b \
+             d9ab 77ad 94e7 821d d7f2 e6c7 b335 dfdf cd5b 3960 d5af 2708 7f36 72c1 ab27 \
+             0fb5 291f 9587 3160 65c0 03ed 4ee5 b106 3d50 07",
+        ]);
+    }
+
+    #[test]
+    fn invalid_blocks() {
+        let mut decoder = Decoder::new();
+        // index 0 and an index past the tables
+        assert_eq!(decoder.decode(&hex("80")), None);
+        assert_eq!(decoder.decode(&hex("be")), None);
+        // a string longer than the block
+        assert_eq!(decoder.decode(&hex("4005 6162")), None);
+        // a table size above the one sent in the settings
+        assert_eq!(decoder.decode(&hex("3fe2 1f")), None);
+        // the padding is longer than 7 bits or is not the prefix of EOS
+        assert_eq!(huffman_decode(&hex("1fff")), None);
+        assert_eq!(huffman_decode(&hex("f1e3 c2e5 f23a 6ba0 ab90 f4fe")), None);
+        assert_eq!(
+            huffman_decode(&hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff")),
+            Some(b"www.example.com".to_vec())
+        );
+    }
+
+    #[test]
+    fn encoded_headers_are_decoded() {
+        let response = [(":status", "200"), ("content-type", "application/grpc")];
+        let block = encode_headers(&response);
+        assert_eq!(Decoder::new().decode(&block), Some(headers(&response)));
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    ffi::c_void,
+    fs::{self, File},
+    io::{self, Write},
+    mem,
+    path::Path,
+    ptr, slice,
+};
+
+use windows::{
+    core::{Error, Result, PCWSTR},
+    Win32::Networking::WinHttp::*,
+};
+
+use crate::utf16z;
+
+const USER_AGENT: &str = concat!("MiniRAW NG/", env!("CARGO_PKG_VERSION"));
+// resolve, connect, send and receive timeouts in milliseconds
+const TIMEOUT: i32 = 15000;
+// the rest of a larger response body is not read by send
+const MAX_RESPONSE_SIZE: usize = 1 << 20;
+
+// WinHTTP handle closed when dropped
+struct Handle(*mut c_void);
+
+impl Handle {
+    fn new(handle: *mut c_void) -> Result<Handle> {
+        if handle.is_null() {
+            Err(Error::from_win32())
+        } else {
+            Ok(Handle(handle))
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = WinHttpCloseHandle(self.0);
+        }
+    }
+}
+
+pub struct HttpResponse {
+    pub status: u32,
+    pub body: Vec<u8>,
+}
+
+// Sends a request and waits for the response. WinHTTP takes care of HTTPS, the
+// redirects and the proxy configured in Windows.
+pub fn send(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<HttpResponse> {
+    let mut response = Vec::new();
+    let status = request(method, url, headers, body, |data| {
+        response.extend_from_slice(data);
+        response.len() < MAX_RESPONSE_SIZE
+    })?;
+    Ok(HttpResponse {
+        status,
+        body: response,
+    })
+}
+
+// Saves the response body of a GET request to the file, which is removed if the
+// download fails
+pub fn download(url: &str, path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    let mut write_error = None;
+    let result = request("GET", url, &[], &[], |data| match file.write_all(data) {
+        Ok(()) => true,
+        Err(e) => {
+            write_error = Some(e);
+            false
+        }
+    });
+    drop(file);
+
+    let result = match (result, write_error) {
+        (_, Some(e)) => Err(e),
+        (Err(e), None) => Err(e.into()),
+        (Ok(status), None) if !(200..300).contains(&status) => {
+            Err(io::Error::other(format!("HTTP status {status}")))
+        }
+        (Ok(_), None) => Ok(()),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+// Returns the status, the response body is passed to the callback in chunks until
+// it returns false
+fn request<F>(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    mut on_data: F,
+) -> Result<u32>
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    let url = url.encode_utf16().collect::<Vec<_>>();
+    // the lengths without the pointers make WinHttpCrackUrl point into the url
+    let mut components = URL_COMPONENTS {
+        dwStructSize: mem::size_of::<URL_COMPONENTS>() as _,
+        dwSchemeLength: u32::MAX,
+        dwHostNameLength: u32::MAX,
+        dwUrlPathLength: u32::MAX,
+        dwExtraInfoLength: u32::MAX,
+        ..Default::default()
+    };
+
+    unsafe {
+        WinHttpCrackUrl(&url, 0, &mut components)?;
+        let host = slice::from_raw_parts(
+            components.lpszHostName.0,
+            components.dwHostNameLength as usize,
+        );
+        // the path is followed by the query in the url
+        let path = slice::from_raw_parts(
+            components.lpszUrlPath.0,
+            (components.dwUrlPathLength + components.dwExtraInfoLength) as usize,
+        );
+        let host = host.iter().copied().chain([0]).collect::<Vec<_>>();
+        let path = path.iter().copied().chain([0]).collect::<Vec<_>>();
+
+        let agent = utf16z!(USER_AGENT);
+        let session = Handle::new(WinHttpOpen(
+            PCWSTR(agent.as_ptr()),
+            WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY,
+            PCWSTR::null(),
+            PCWSTR::null(),
+            0,
+        ))?;
+        WinHttpSetTimeouts(session.0, TIMEOUT, TIMEOUT, TIMEOUT, TIMEOUT)?;
+
+        let connection = Handle::new(WinHttpConnect(
+            session.0,
+            PCWSTR(host.as_ptr()),
+            components.nPort,
+            0,
+        ))?;
+
+        let method = utf16z!(method);
+        let flags = if components.nScheme == WINHTTP_INTERNET_SCHEME_HTTPS {
+            WINHTTP_FLAG_SECURE
+        } else {
+            WINHTTP_OPEN_REQUEST_FLAGS(0)
+        };
+        let request = Handle::new(WinHttpOpenRequest(
+            connection.0,
+            PCWSTR(method.as_ptr()),
+            PCWSTR(path.as_ptr()),
+            PCWSTR::null(),
+            PCWSTR::null(),
+            ptr::null(),
+            flags,
+        ))?;
+
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\r\n"))
+            .collect::<String>()
+            .encode_utf16()
+            .collect::<Vec<_>>();
+        WinHttpSendRequest(
+            request.0,
+            (!headers.is_empty()).then_some(&headers),
+            (!body.is_empty()).then_some(body.as_ptr() as _),
+            body.len() as _,
+            body.len() as _,
+            0,
+        )?;
+        WinHttpReceiveResponse(request.0, ptr::null_mut())?;
+
+        let mut status = 0u32;
+        let mut size = mem::size_of::<u32>() as u32;
+        WinHttpQueryHeaders(
+            request.0,
+            WINHTTP_QUERY_STATUS_CODE | WINHTTP_QUERY_FLAG_NUMBER,
+            PCWSTR::null(),
+            Some(&mut status as *mut u32 as _),
+            &mut size,
+            ptr::null_mut(),
+        )?;
+
+        let mut buffer = [0u8; 8192];
+        loop {
+            let mut read = 0;
+            WinHttpReadData(
+                request.0,
+                buffer.as_mut_ptr() as _,
+                buffer.len() as _,
+                &mut read,
+            )?;
+            if read == 0 || !on_data(&buffer[..read as usize]) {
+                break;
+            }
+        }
+
+        Ok(status)
+    }
+}
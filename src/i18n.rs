@@ -0,0 +1,709 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        OnceLock,
+    },
+};
+
+use windows::Win32::Globalization::GetUserDefaultUILanguage;
+
+// Translates a string literal into the current language, the remaining arguments
+// replace the "{}" placeholders of the translated string in order. Only the text
+// shown in the UI is translated, the log records are written in English.
+#[macro_export]
+macro_rules! tr {
+    ($text: expr) => {
+        $crate::i18n::translate($text)
+    };
+    ($text: expr, $($arg: expr),+ $(,)?) => {
+        $crate::i18n::format(
+            $crate::i18n::translate($text),
+            &[$(&$arg as &dyn std::fmt::Display),+],
+        )
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    German,
+    Russian,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::English, Language::German, Language::Russian];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::Russian => "ru",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Language> {
+        Self::ALL.into_iter().find(|l| l.code() == code)
+    }
+
+    // Native name, so that the language can be found without reading the current one
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::German => "Deutsch",
+            Language::Russian => "Русский",
+        }
+    }
+
+    // Language of the Windows UI, English if it is not supported
+    pub fn system() -> Language {
+        const LANG_GERMAN: u16 = 0x07;
+        const LANG_RUSSIAN: u16 = 0x19;
+
+        match unsafe { GetUserDefaultUILanguage() } & 0x3ff {
+            LANG_GERMAN => Language::German,
+            LANG_RUSSIAN => Language::Russian,
+            _ => Language::English,
+        }
+    }
+}
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+pub fn language() -> Language {
+    Language::ALL[LANGUAGE.load(Ordering::SeqCst) as usize]
+}
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::SeqCst);
+}
+
+pub fn translate(text: &'static str) -> &'static str {
+    static ROWS: OnceLock<HashMap<&str, &[&str; 3]>> = OnceLock::new();
+    let rows = ROWS.get_or_init(|| STRINGS.iter().map(|row| (row[0], row)).collect());
+    rows.get(text)
+        .map(|row| row[language() as usize])
+        .unwrap_or(text)
+}
+
+pub fn format(template: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut parts = template.split("{}");
+    let mut result = parts.next().unwrap_or_default().to_owned();
+    for (index, part) in parts.enumerate() {
+        if let Some(arg) = args.get(index) {
+            let _ = write!(result, "{}", arg);
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+// English, German and Russian, the English text is the lookup key
+const STRINGS: &[[&str; 3]] = &[
+    // menus
+    ["&File", "&Datei", "&Файл"],
+    [
+        "&Open containing folder\tCtrl+O",
+        "&Ordner öffnen\tCtrl+O",
+        "&Открыть папку\tCtrl+O",
+    ],
+    [
+        "&Save log\tCtrl+S",
+        "Protokoll &speichern\tCtrl+S",
+        "&Сохранить журнал\tCtrl+S",
+    ],
+    [
+        "Save log &as...",
+        "Protokoll speichern &unter...",
+        "Сохранить журнал &как...",
+    ],
+    [
+        "Next pa&ge\tCtrl+Tab",
+        "Nächste &Seite\tCtrl+Tab",
+        "Следующая &страница\tCtrl+Tab",
+    ],
+    [
+        "Next pa&ne\tF6",
+        "Nächster &Bereich\tF6",
+        "Следующая &область\tF6",
+    ],
+    ["&Zoom", "&Zoom", "&Масштаб"],
+    [
+        "Zoom &in\tCtrl++",
+        "&Vergrößern\tCtrl++",
+        "&Увеличить\tCtrl++",
+    ],
+    [
+        "Zoom &out\tCtrl+-",
+        "Ver&kleinern\tCtrl+-",
+        "У&меньшить\tCtrl+-",
+    ],
+    [
+        "&Reset zoom\tCtrl+0",
+        "&Zurücksetzen\tCtrl+0",
+        "&Сбросить масштаб\tCtrl+0",
+    ],
+    ["Listener status", "Listener-Status", "Состояние приёма"],
+    ["Pages", "Seiten", "Страницы"],
+    ["Throughput graph", "Durchsatzdiagramm", "График пропускной способности"],
+    ["Statistics summary", "Statistikübersicht", "Сводка статистики"],
+    [
+        "&Pause listener",
+        "Listener &anhalten",
+        "&Приостановить приём",
+    ],
+    ["E&xit", "&Beenden", "В&ыход"],
+    ["&Edit", "&Bearbeiten", "&Правка"],
+    ["&Copy log", "Protokoll &kopieren", "&Копировать журнал"],
+    [
+        "C&lear log\tCtrl+L",
+        "Protokoll &leeren\tCtrl+L",
+        "&Очистить журнал\tCtrl+L",
+    ],
+    [
+        "&Find...\tCtrl+F",
+        "&Suchen...\tCtrl+F",
+        "&Найти...\tCtrl+F",
+    ],
+    ["Find &next\tF3", "&Weitersuchen\tF3", "Найти &далее\tF3"],
+    ["&View", "&Ansicht", "&Вид"],
+    [
+        "&Preview selected job",
+        "&Vorschau des gewählten Auftrags",
+        "&Предпросмотр выбранного задания",
+    ],
+    [
+        "&Hex view of selected job",
+        "&Hex-Ansicht des gewählten Auftrags",
+        "&Шестнадцатеричный просмотр задания",
+    ],
+    ["&Font...", "Schrift&art...", "&Шрифт..."],
+    [
+        "&Auto-scroll log",
+        "&Automatisch scrollen",
+        "&Автопрокрутка журнала",
+    ],
+    [
+        "&Pause log output",
+        "Protokollausgabe &anhalten",
+        "П&риостановить вывод журнала",
+    ],
+    ["&Log filter", "Protokoll&filter", "&Фильтр журнала"],
+    ["&Errors", "&Fehler", "О&шибки"],
+    ["&Warnings", "&Warnungen", "&Предупреждения"],
+    ["&Information", "&Informationen", "&Информация"],
+    ["&Debug", "&Debug", "&Отладка"],
+    ["&Trace", "&Ablaufverfolgung", "&Трассировка"],
+    ["&Listener", "&Listener", "П&риёмник"],
+    ["&Storage", "&Speicherung", "&Хранение"],
+    ["&User interface", "&Benutzeroberfläche", "Инт&ерфейс"],
+    ["&Tools", "E&xtras", "&Сервис"],
+    [
+        "&Settings...\tCtrl+,",
+        "&Einstellungen...\tCtrl+,",
+        "&Настройки...\tCtrl+,",
+    ],
+    [
+        "&Discard received files",
+        "Empfangene Dateien &verwerfen",
+        "&Не сохранять полученные файлы",
+    ],
+    [
+        "&Notify about received jobs",
+        "Über empfangene Aufträge &benachrichtigen",
+        "&Уведомлять о полученных заданиях",
+    ],
+    [
+        "&Flash taskbar button on new jobs",
+        "&Taskleiste bei neuen Aufträgen blinken lassen",
+        "&Мигать на панели задач при новых заданиях",
+    ],
+    [
+        "&Export settings...",
+        "Einstellungen e&xportieren...",
+        "&Экспорт настроек...",
+    ],
+    [
+        "&Import settings...",
+        "Einstellungen &importieren...",
+        "&Импорт настроек...",
+    ],
+    [
+        "&Reset settings...",
+        "Einstellungen &zurücksetzen...",
+        "С&бросить настройки...",
+    ],
+    ["&Presets", "Vor&lagen", "&Предустановки"],
+    [
+        "&Save preset...",
+        "Vorlage &speichern...",
+        "&Сохранить предустановку...",
+    ],
+    ["&Language", "&Sprache", "&Язык"],
+    ["&Help", "&Hilfe", "&Справка"],
+    [
+        "&Collect diagnostics...",
+        "&Diagnosedaten sammeln...",
+        "&Собрать диагностику...",
+    ],
+    [
+        "Check for &updates",
+        "Nach &Updates suchen",
+        "Проверить &обновления",
+    ],
+    ["&About", "&Über", "&О программе"],
+    // dialogs and notifications
+    ["Discarded job", "Verworfener Auftrag", "Удалённое задание"],
+    [
+        "{}\nFrom {}, {} bytes, {}",
+        "{}\nVon {}, {} Bytes, {}",
+        "{}\nОт {}, {} байт, {}",
+    ],
+    [
+        "Print job received",
+        "Druckauftrag empfangen",
+        "Получено задание печати",
+    ],
+    [
+        "Re-ingest {} file(s) through the listener?\n\n\
+         A printer to resend the files to can be configured in the settings.",
+        "{} Datei(en) über den Listener erneut einlesen?\n\n\
+         Ein Drucker zum erneuten Senden kann in den Einstellungen konfiguriert werden.",
+        "Повторно принять {} файл(ов) через приёмник?\n\n\
+         Принтер для повторной отправки можно указать в настройках.",
+    ],
+    [
+        "Resend {} file(s) to the printer {}?\n\n\
+         Yes - send to the printer\nNo - re-ingest through the listener",
+        "{} Datei(en) erneut an den Drucker {} senden?\n\n\
+         Ja - an den Drucker senden\nNein - über den Listener erneut einlesen",
+        "Отправить {} файл(ов) на принтер {}?\n\n\
+         Да - отправить на принтер\nНет - повторно принять через приёмник",
+    ],
+    [
+        "Resend dropped files",
+        "Abgelegte Dateien erneut senden",
+        "Повторная отправка файлов",
+    ],
+    [
+        "MiniRAW NG {}\nSimple print job listener by Dmitry Pankratov",
+        "MiniRAW NG {}\nEinfacher Empfänger für Druckaufträge von Dmitry Pankratov",
+        "MiniRAW NG {}\nПростой приёмник заданий печати, автор Dmitry Pankratov",
+    ],
+    [
+        "About MiniRAW NG",
+        "Über MiniRAW NG",
+        "О программе MiniRAW NG",
+    ],
+    // settings window
+    ["Settings", "Einstellungen", "Настройки"],
+    [
+        "Discard received files",
+        "Empfangene Dateien verwerfen",
+        "Не сохранять полученные файлы",
+    ],
+    [
+        "Auto-scroll log",
+        "Protokoll automatisch scrollen",
+        "Автопрокрутка журнала",
+    ],
+    [
+        "Notify about received jobs",
+        "Über empfangene Aufträge benachrichtigen",
+        "Уведомлять о полученных заданиях",
+    ],
+    [
+        "Flash taskbar button on new jobs",
+        "Taskleiste bei neuen Aufträgen blinken lassen",
+        "Мигать на панели задач при новых заданиях",
+    ],
+    [
+        "Start minimized to the tray",
+        "Minimiert im Infobereich starten",
+        "Запускать свёрнутым в трей",
+    ],
+    [
+        "Run at Windows startup",
+        "Beim Windows-Start ausführen",
+        "Запускать при старте Windows",
+    ],
+    ["Log font...", "Protokollschrift...", "Шрифт журнала..."],
+    ["Log level: {}", "Protokollstufe: {}", "Уровень журнала: {}"],
+    [
+        "Resend printer (host:port)",
+        "Drucker zum erneuten Senden (Host:Port)",
+        "Принтер для повторной отправки (хост:порт)",
+    ],
+    // dialogs
+    ["Save log as", "Protokoll speichern unter", "Сохранить журнал как"],
+    ["Log files", "Protokolldateien", "Файлы журнала"],
+    [
+        "Collect diagnostics",
+        "Diagnosedaten sammeln",
+        "Собрать диагностику",
+    ],
+    ["ZIP archives", "ZIP-Archive", "Архивы ZIP"],
+    ["All files", "Alle Dateien", "Все файлы"],
+    ["OK", "OK", "ОК"],
+    ["Cancel", "Abbrechen", "Отмена"],
+    // settings tooltips
+    [
+        "Jobs are logged but not saved to disk",
+        "Aufträge werden protokolliert, aber nicht gespeichert",
+        "Задания записываются в журнал, но не сохраняются на диск",
+    ],
+    [
+        "Keep the newest log lines visible",
+        "Die neuesten Protokollzeilen sichtbar halten",
+        "Показывать последние строки журнала",
+    ],
+    [
+        "Show a tray notification for every received job",
+        "Für jeden empfangenen Auftrag eine Benachrichtigung anzeigen",
+        "Показывать уведомление о каждом полученном задании",
+    ],
+    [
+        "Flash the taskbar button when a job arrives while the window is inactive",
+        "Die Taskleistenschaltfläche blinken lassen, wenn ein Auftrag bei inaktivem Fenster eintrifft",
+        "Мигать кнопкой на панели задач, если задание получено при неактивном окне",
+    ],
+    [
+        "Hide the window to the notification area when the program starts",
+        "Das Fenster beim Programmstart im Infobereich ausblenden",
+        "Скрывать окно в область уведомлений при запуске программы",
+    ],
+    [
+        "Start the program minimized when you sign in to Windows",
+        "Das Programm bei der Windows-Anmeldung minimiert starten",
+        "Запускать программу свёрнутой при входе в Windows",
+    ],
+    [
+        "Font of the log window",
+        "Schriftart des Protokollfensters",
+        "Шрифт окна журнала",
+    ],
+    [
+        "Add firewall rules",
+        "Firewall-Regeln hinzufügen",
+        "Добавить правила брандмауэра",
+    ],
+    [
+        "Allow the incoming connections on the listening ports in the Windows Firewall",
+        "Eingehende Verbindungen an den Empfangsports in der Windows-Firewall zulassen",
+        "Разрешить входящие подключения на прослушиваемые порты в брандмауэре Windows",
+    ],
+    [
+        "Least severe messages written to the log",
+        "Niedrigste Stufe der protokollierten Meldungen",
+        "Минимальный уровень сообщений, записываемых в журнал",
+    ],
+    [
+        "Printer to which dropped files and jobs are resent",
+        "Drucker, an den abgelegte Dateien und Aufträge erneut gesendet werden",
+        "Принтер, на который повторно отправляются задания и перетащенные файлы",
+    ],
+    // job list columns
+    ["Time", "Zeit", "Время"],
+    ["Source", "Quelle", "Источник"],
+    ["Size", "Größe", "Размер"],
+    ["Format", "Format", "Формат"],
+    ["File", "Datei", "Файл"],
+    ["Connection", "Verbindung", "Соединение"],
+    ["(discarded)", "(verworfen)", "(удалено)"],
+    // main window pages
+    ["Log", "Protokoll", "Журнал"],
+    ["Jobs", "Aufträge", "Задания"],
+    ["Statistics", "Statistik", "Статистика"],
+    [
+        "Received jobs: {}",
+        "Empfangene Aufträge: {}",
+        "Получено заданий: {}",
+    ],
+    [
+        "Saved jobs: {}",
+        "Gespeicherte Aufträge: {}",
+        "Сохранено заданий: {}",
+    ],
+    [
+        "Discarded jobs: {}",
+        "Verworfene Aufträge: {}",
+        "Удалено заданий: {}",
+    ],
+    [
+        "Total size: {} bytes",
+        "Gesamtgröße: {} Bytes",
+        "Общий объём: {} байт",
+    ],
+    [
+        "Average job size: {} bytes",
+        "Durchschnittliche Auftragsgröße: {} Bytes",
+        "Средний размер задания: {} байт",
+    ],
+    [
+        "Largest job: {} bytes",
+        "Größter Auftrag: {} Bytes",
+        "Самое большое задание: {} байт",
+    ],
+    ["Bytes", "Bytes", "Байт"],
+    ["Jobs per day", "Aufträge pro Tag", "Задания по дням"],
+    ["Jobs per hour", "Aufträge pro Stunde", "Задания по часам"],
+    [
+        "Top source hosts",
+        "Häufigste Quellen",
+        "Основные источники",
+    ],
+    [
+        "Throughput: {}, peak: {}",
+        "Durchsatz: {}, Spitze: {}",
+        "Скорость: {}, пик: {}",
+    ],
+    // status bar
+    [
+        "Starting listener...",
+        "Listener wird gestartet...",
+        "Запуск приёмника...",
+    ],
+    [
+        "Listening on port {}",
+        "Empfang auf Port {}",
+        "Приём на порту {}",
+    ],
+    [
+        "Listener paused",
+        "Listener angehalten",
+        "Приём приостановлен",
+    ],
+    ["Listener failed", "Listener-Fehler", "Ошибка приёмника"],
+    // tray icon tooltip
+    ["Jobs today: {}", "Aufträge heute: {}", "Заданий сегодня: {}"],
+    [
+        "Last job: {} from {}",
+        "Letzter Auftrag: {} von {}",
+        "Последнее задание: {} от {}",
+    ],
+    // find, hex view and preview windows
+    ["Find in log", "Im Protokoll suchen", "Поиск в журнале"],
+    ["Find next", "Weitersuchen", "Найти далее"],
+    [
+        "Cannot find \"{}\"",
+        "\"{}\" wurde nicht gefunden",
+        "Не удалось найти \"{}\"",
+    ],
+    ["Hex view", "Hex-Ansicht", "Шестнадцатеричный просмотр"],
+    [
+        "Hex view - {}",
+        "Hex-Ansicht - {}",
+        "Шестнадцатеричный просмотр - {}",
+    ],
+    ["Offset (hex)", "Offset (hex)", "Смещение (hex)"],
+    [
+        "Text or hex bytes (1B 45)",
+        "Text oder Hex-Bytes (1B 45)",
+        "Текст или hex-байты (1B 45)",
+    ],
+    ["Go", "Gehe zu", "Перейти"],
+    [
+        "Offset must be a hex number below {}",
+        "Der Offset muss eine Hex-Zahl kleiner als {} sein",
+        "Смещение должно быть шестнадцатеричным числом меньше {}",
+    ],
+    [
+        "Pattern not found",
+        "Muster nicht gefunden",
+        "Образец не найден",
+    ],
+    [
+        "Preview - {} ({})",
+        "Vorschau - {} ({})",
+        "Предпросмотр - {} ({})",
+    ],
+    [
+        "Rendering the first page with Ghostscript...",
+        "Die erste Seite wird mit Ghostscript gerendert...",
+        "Отрисовка первой страницы с помощью Ghostscript...",
+    ],
+    // file dialogs and messages
+    ["Settings files", "Einstellungsdateien", "Файлы настроек"],
+    [
+        "Export settings",
+        "Einstellungen exportieren",
+        "Экспорт настроек",
+    ],
+    [
+        "Import settings",
+        "Einstellungen importieren",
+        "Импорт настроек",
+    ],
+    [
+        "Change the following settings?\n\n{}",
+        "Folgende Einstellungen ändern?\n\n{}",
+        "Изменить следующие настройки?\n\n{}",
+    ],
+    ["Save preset", "Vorlage speichern", "Сохранение предустановки"],
+    ["New preset", "Neue Vorlage", "Новая предустановка"],
+    ["Reset settings", "Einstellungen zurücksetzen", "Сброс настроек"],
+    [
+        "Reset all settings to the defaults?\n\n\
+         The current settings are saved to a backup file next to the configuration file.",
+        "Alle Einstellungen auf die Standardwerte zurücksetzen?\n\n\
+         Die aktuellen Einstellungen werden in einer Sicherungsdatei neben der Konfigurationsdatei gespeichert.",
+        "Сбросить все настройки к значениям по умолчанию?\n\n\
+         Текущие настройки будут сохранены в резервный файл рядом с файлом конфигурации.",
+    ],
+    [
+        "The Windows Firewall may block the print jobs on port(s) {}.\n\n\
+         Add the inbound firewall rules for MiniRAW NG now?\n\n\
+         Choose No to not ask again, the rules can still be added in the settings.",
+        "Die Windows-Firewall blockiert möglicherweise die Druckaufträge an Port(s) {}.\n\n\
+         Jetzt eingehende Firewall-Regeln für MiniRAW NG hinzufügen?\n\n\
+         Wählen Sie Nein, um nicht mehr zu fragen, die Regeln können weiterhin in den Einstellungen hinzugefügt werden.",
+        "Брандмауэр Windows может блокировать задания печати на портах {}.\n\n\
+         Добавить входящие правила брандмауэра для MiniRAW NG сейчас?\n\n\
+         Выберите «Нет», чтобы больше не спрашивать, правила можно добавить в настройках.",
+    ],
+    [
+        "Firewall rules",
+        "Firewall-Regeln",
+        "Правила брандмауэра",
+    ],
+    [
+        "Check for updates",
+        "Nach Updates suchen",
+        "Проверка обновлений",
+    ],
+    [
+        "MiniRAW NG {} is up to date",
+        "MiniRAW NG {} ist aktuell",
+        "MiniRAW NG {} — последняя версия",
+    ],
+    [
+        "Cannot check for updates: {}",
+        "Suche nach Updates fehlgeschlagen: {}",
+        "Не удалось проверить обновления: {}",
+    ],
+    [
+        "MiniRAW NG {} is available, the installer is downloaded to {}",
+        "MiniRAW NG {} ist verfügbar, das Installationsprogramm wurde nach {} heruntergeladen",
+        "Доступна версия MiniRAW NG {}, установщик загружен в {}",
+    ],
+    [
+        "MiniRAW NG {} is available at {}",
+        "MiniRAW NG {} ist verfügbar unter {}",
+        "Доступна версия MiniRAW NG {}: {}",
+    ],
+    [
+        "Update available",
+        "Update verfügbar",
+        "Доступно обновление",
+    ],
+    [
+        "Show the installer?",
+        "Installationsprogramm anzeigen?",
+        "Показать установщик?",
+    ],
+    [
+        "Open the release page?",
+        "Seite der Version öffnen?",
+        "Открыть страницу выпуска?",
+    ],
+    [
+        "{} job(s) are still being received.\n\n\
+         Yes - exit now, the files of these jobs will be incomplete\n\
+         No - stop accepting connections and exit when the jobs are finished",
+        "{} Auftrag/Aufträge werden noch empfangen.\n\n\
+         Ja - jetzt beenden, die Dateien dieser Aufträge bleiben unvollständig\n\
+         Nein - keine Verbindungen mehr annehmen und nach Abschluss der Aufträge beenden",
+        "Ещё принимается заданий: {}.\n\n\
+         Да - выйти сейчас, файлы этих заданий останутся неполными\n\
+         Нет - прекратить приём подключений и выйти после завершения заданий",
+    ],
+    ["Exit MiniRAW NG", "MiniRAW NG beenden", "Выход из MiniRAW NG"],
+    [
+        "MiniRAW NG has stopped because of an internal error.\n\n\
+         A crash report was saved to {}, please attach the files {} and {} \
+         to a bug report.",
+        "MiniRAW NG wurde wegen eines internen Fehlers beendet.\n\n\
+         Ein Absturzbericht wurde in {} gespeichert, bitte fügen Sie die Dateien {} und {} \
+         einem Fehlerbericht bei.",
+        "MiniRAW NG остановлена из-за внутренней ошибки.\n\n\
+         Отчёт о сбое сохранён в {}, приложите файлы {} и {} \
+         к сообщению об ошибке.",
+    ],
+    [
+        "MiniRAW NG has stopped because of an internal error.\n\n\
+         The crash report cannot be saved to {}: {}\n\n{}",
+        "MiniRAW NG wurde wegen eines internen Fehlers beendet.\n\n\
+         Der Absturzbericht kann nicht in {} gespeichert werden: {}\n\n{}",
+        "MiniRAW NG остановлена из-за внутренней ошибки.\n\n\
+         Не удалось сохранить отчёт о сбое в {}: {}\n\n{}",
+    ],
+    ["Receiving jobs", "Aufträge werden empfangen", "Приём заданий"],
+    ["Recent jobs", "Letzte Aufträge", "Последние задания"],
+    ["Open output folder", "Ausgabeordner öffnen", "Открыть папку вывода"],
+    [
+        "Pause or resume listener",
+        "Listener anhalten oder fortsetzen",
+        "Приостановить или возобновить приём",
+    ],
+    [
+        "&Capture printer",
+        "&Drucker umleiten",
+        "&Перехват принтера",
+    ],
+    [
+        "&Restore all printers",
+        "Alle Drucker &wiederherstellen",
+        "&Восстановить все принтеры",
+    ],
+    [
+        "MiniRAW NG &printer",
+        "MiniRAW NG-&Drucker",
+        "&Принтер MiniRAW NG",
+    ],
+    [
+        "Run &self-test",
+        "&Selbsttest ausführen",
+        "Запустить &самотестирование",
+    ],
+    [
+        "Errors",
+        "Fehler",
+        "Ошибки",
+    ],
+    [
+        "not checked",
+        "nicht geprüft",
+        "не проверялся",
+    ],
+    [
+        "up",
+        "erreichbar",
+        "доступен",
+    ],
+    [
+        "down: {}",
+        "nicht erreichbar: {}",
+        "недоступен: {}",
+    ],
+    [
+        "Forward targets",
+        "Weiterleitungsziele",
+        "Цели пересылки",
+    ],
+    [
+        "Queued",
+        "Wartend",
+        "В очереди",
+    ],
+    [
+        "State",
+        "Zustand",
+        "Состояние",
+    ],
+    [
+        "The menus will be shown in the new language after MiniRAW NG is restarted.",
+        "Die Menüs werden nach einem Neustart von MiniRAW NG in der neuen Sprache angezeigt.",
+        "Меню будут показаны на новом языке после перезапуска MiniRAW NG.",
+    ],
+];
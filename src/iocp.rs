@@ -0,0 +1,391 @@
+use std::{
+    alloc::{self, Layout},
+    fs::{self, File},
+    io::{self, Write},
+    mem,
+    net::TcpStream,
+    os::windows::{
+        fs::OpenOptionsExt,
+        io::{AsRawHandle, AsRawSocket},
+    },
+    path::Path,
+    ptr::{self, NonNull},
+    time::Duration,
+};
+
+use windows::{
+    core::{HRESULT, PSTR},
+    Win32::{
+        Foundation::{CloseHandle, ERROR_IO_PENDING, HANDLE, WAIT_TIMEOUT},
+        Networking::WinSock::{WSAGetLastError, WSARecv, SOCKET, WSABUF, WSA_IO_PENDING},
+        Storage::FileSystem::{
+            FileEndOfFileInfo, SetFileInformationByHandle, WriteFile, FILE_END_OF_FILE_INFO,
+            FILE_FLAG_NO_BUFFERING, FILE_FLAG_OVERLAPPED,
+        },
+        System::{
+            Threading::INFINITE,
+            IO::{CancelIoEx, CreateIoCompletionPort, GetQueuedCompletionStatus, OVERLAPPED},
+        },
+    },
+};
+
+// the unbuffered writes need addresses, offsets and sizes which are multiples of the
+// sector size, the page size covers the 512 and 4096 byte sectors
+const ALIGNMENT: usize = 4096;
+// the receives posted on a socket at a time, the kernel fills them while the data of
+// the previous one is written
+const RECEIVE_BUFFERS: usize = 4;
+const RECEIVE_BUFFER_SIZE: usize = 256 * 1024;
+// the writes in flight for a file
+const WRITE_BUFFERS: usize = 4;
+const WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+
+// A zeroed heap buffer at an address aligned for the unbuffered I/O
+struct AlignedBuffer {
+    data: NonNull<u8>,
+    layout: Layout,
+}
+
+// only the thread of the connection uses the buffer
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    fn new(size: usize) -> AlignedBuffer {
+        let layout = Layout::from_size_align(size, ALIGNMENT).unwrap();
+        let data = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        AlignedBuffer { data, layout }
+    }
+
+    fn len(&self) -> usize {
+        self.layout.size()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr(), self.len()) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.data.as_ptr(), self.len()) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.data.as_ptr(), self.layout) };
+    }
+}
+
+// The completion port returns the pointer to the OVERLAPPED of the operation
+struct Operation {
+    overlapped: OVERLAPPED,
+    pending: bool,
+    result: Option<io::Result<u32>>,
+}
+
+// The operations of one file or socket and the completion port they are reported to.
+// The operations of a port can complete in any order, a completion which is not
+// waited for is kept in its operation.
+struct Operations {
+    port: HANDLE,
+    handle: HANDLE,
+    // never resized, the kernel keeps pointers to the OVERLAPPEDs
+    operations: Vec<Operation>,
+}
+
+// only the thread of the connection uses the port
+unsafe impl Send for Operations {}
+
+impl Operations {
+    fn new(handle: HANDLE, count: usize) -> io::Result<Operations> {
+        let port = unsafe { CreateIoCompletionPort(handle, None, 0, 1) }?;
+        let operations = (0..count)
+            .map(|_| Operation {
+                overlapped: OVERLAPPED::default(),
+                pending: false,
+                result: None,
+            })
+            .collect();
+        Ok(Operations {
+            port,
+            handle,
+            operations,
+        })
+    }
+
+    fn is_pending(&self, index: usize) -> bool {
+        self.operations[index].pending
+    }
+
+    // The OVERLAPPED for the next operation at the file offset
+    fn start(&mut self, index: usize, offset: u64) -> *mut OVERLAPPED {
+        let operation = &mut self.operations[index];
+        operation.overlapped = OVERLAPPED::default();
+        operation.overlapped.Anonymous.Anonymous.Offset = offset as u32;
+        operation.overlapped.Anonymous.Anonymous.OffsetHigh = (offset >> 32) as u32;
+        operation.pending = true;
+        operation.result = None;
+        &mut operation.overlapped
+    }
+
+    // An operation which failed right away is not reported to the port, the ones
+    // completed right away are
+    fn failed(&mut self, index: usize, error: io::Error) {
+        let operation = &mut self.operations[index];
+        operation.pending = false;
+        operation.result = Some(Err(error));
+    }
+
+    // The number of bytes transferred by the operation
+    fn wait(&mut self, index: usize) -> io::Result<u32> {
+        self.wait_timeout(index, None)
+    }
+
+    // Fails with TimedOut if the operation is not completed in time, it is still
+    // pending then and can be waited for again
+    fn wait_timeout(&mut self, index: usize, timeout: Option<Duration>) -> io::Result<u32> {
+        let timeout = timeout.map_or(INFINITE, |timeout| {
+            timeout.as_millis().min(INFINITE as u128 - 1) as u32
+        });
+        while self.operations[index].pending {
+            let mut bytes = 0;
+            let mut key = 0;
+            let mut overlapped = ptr::null_mut();
+            let result = unsafe {
+                GetQueuedCompletionStatus(self.port, &mut bytes, &mut key, &mut overlapped, timeout)
+            };
+            if overlapped.is_null() {
+                if matches!(&result, Err(e) if e.code() == HRESULT::from_win32(WAIT_TIMEOUT.0)) {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                return Err(result
+                    .err()
+                    .map(io::Error::from)
+                    .unwrap_or_else(|| io::Error::other("empty completion packet")));
+            }
+            if let Some(operation) = self
+                .operations
+                .iter_mut()
+                .find(|operation| ptr::eq(&operation.overlapped, overlapped))
+            {
+                operation.pending = false;
+                operation.result = Some(result.map(|()| bytes).map_err(io::Error::from));
+            }
+        }
+        self.operations[index].result.take().unwrap_or(Ok(0))
+    }
+}
+
+impl Drop for Operations {
+    // The kernel must not write into the buffers after they are freed
+    fn drop(&mut self) {
+        if (0..self.operations.len()).any(|index| self.is_pending(index)) {
+            let _ = unsafe { CancelIoEx(self.handle, None) };
+            for index in 0..self.operations.len() {
+                // a cancelled operation is still reported, it stays pending only if
+                // the port failed
+                if self.is_pending(index) && self.wait(index).is_err() && self.is_pending(index) {
+                    break;
+                }
+            }
+        }
+        let _ = unsafe { CloseHandle(self.port) };
+    }
+}
+
+// Receives the data of a socket with several overlapped receives posted at a time.
+// The receives of a TCP socket are filled in the order they were posted, so the
+// buffers are returned in that order.
+pub struct OverlappedReceiver<'a> {
+    // dropped before the buffers and the socket
+    operations: Operations,
+    buffers: Vec<AlignedBuffer>,
+    socket: &'a TcpStream,
+    // the buffer with the oldest receive
+    next: usize,
+    // the buffer returned by the last call of receive, posted again on the next one
+    returned: Option<usize>,
+    finished: bool,
+}
+
+impl<'a> OverlappedReceiver<'a> {
+    // The socket can only be associated with one completion port, so a stream is
+    // received by one receiver
+    pub fn new(socket: &'a TcpStream) -> io::Result<OverlappedReceiver<'a>> {
+        let operations = Operations::new(HANDLE(socket.as_raw_socket() as _), RECEIVE_BUFFERS)?;
+        let mut receiver = OverlappedReceiver {
+            operations,
+            buffers: (0..RECEIVE_BUFFERS)
+                .map(|_| AlignedBuffer::new(RECEIVE_BUFFER_SIZE))
+                .collect(),
+            socket,
+            next: 0,
+            returned: None,
+            finished: false,
+        };
+        for index in 0..RECEIVE_BUFFERS {
+            receiver.post(index);
+        }
+        Ok(receiver)
+    }
+
+    fn post(&mut self, index: usize) {
+        let buffer = &mut self.buffers[index];
+        let buffers = [WSABUF {
+            len: buffer.len() as u32,
+            buf: PSTR(buffer.as_mut_slice().as_mut_ptr()),
+        }];
+        let overlapped = self.operations.start(index, 0);
+        let mut flags = 0;
+        let result = unsafe {
+            WSARecv(
+                SOCKET(self.socket.as_raw_socket() as _),
+                &buffers,
+                None,
+                &mut flags,
+                Some(overlapped),
+                None,
+            )
+        };
+        if result != 0 {
+            let error = unsafe { WSAGetLastError() };
+            if error != WSA_IO_PENDING {
+                self.operations
+                    .failed(index, io::Error::from_raw_os_error(error.0));
+            }
+        }
+    }
+
+    // The next received data, None at the end of the stream. Fails with TimedOut if
+    // nothing is received in time, the receive can be repeated then.
+    pub fn receive(&mut self, timeout: Option<Duration>) -> io::Result<Option<&[u8]>> {
+        if let Some(index) = self.returned.take() {
+            self.post(index);
+        }
+        if self.finished {
+            return Ok(None);
+        }
+        let index = self.next;
+        let count = self.operations.wait_timeout(index, timeout)? as usize;
+        if count == 0 {
+            self.finished = true;
+            return Ok(None);
+        }
+        self.next = (index + 1) % RECEIVE_BUFFERS;
+        self.returned = Some(index);
+        Ok(Some(&self.buffers[index].as_slice()[..count]))
+    }
+}
+
+// Writes a new file without the file cache in large aligned blocks with several
+// writes in flight, the size of the file is set to the written data when it is
+// finished. The data is written in the order it is given.
+pub struct UnbufferedFile {
+    // dropped before the buffers and the file
+    operations: Operations,
+    buffers: Vec<AlignedBuffer>,
+    file: File,
+    // the buffer being filled
+    current: usize,
+    filled: usize,
+    // the offset of the current buffer in the file
+    offset: u64,
+}
+
+impl UnbufferedFile {
+    // Fails like OpenOptions::create_new if the file exists
+    pub fn create_new(path: &Path) -> io::Result<UnbufferedFile> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(FILE_FLAG_OVERLAPPED.0 | FILE_FLAG_NO_BUFFERING.0)
+            .open(path)?;
+        let operations = Operations::new(HANDLE(file.as_raw_handle()), WRITE_BUFFERS)?;
+        Ok(UnbufferedFile {
+            operations,
+            buffers: (0..WRITE_BUFFERS)
+                .map(|_| AlignedBuffer::new(WRITE_BUFFER_SIZE))
+                .collect(),
+            file,
+            current: 0,
+            filled: 0,
+            offset: 0,
+        })
+    }
+
+    // Starts the write of the current buffer, the size is rounded up to the alignment
+    fn submit(&mut self) -> io::Result<()> {
+        let index = self.current;
+        let size = self.filled.next_multiple_of(ALIGNMENT);
+        let overlapped = self.operations.start(index, self.offset);
+        let data = &self.buffers[index].as_slice()[..size];
+        if let Err(e) = unsafe {
+            WriteFile(
+                HANDLE(self.file.as_raw_handle()),
+                Some(data),
+                None,
+                Some(overlapped),
+            )
+        } {
+            if e.code() != ERROR_IO_PENDING.to_hresult() {
+                self.operations.failed(index, e.into());
+                self.operations.wait(index)?;
+            }
+        }
+        self.offset += self.filled as u64;
+        self.current = (index + 1) % WRITE_BUFFERS;
+        self.filled = 0;
+        // the next buffer can be filled once its previous write is done
+        if self.operations.is_pending(self.current) {
+            self.operations.wait(self.current)?;
+        }
+        Ok(())
+    }
+
+    // Writes the rest of the data and cuts the file to the size of the data, the
+    // file is incomplete if it is dropped without this
+    pub fn finish(mut self) -> io::Result<()> {
+        let size = self.offset + self.filled as u64;
+        if self.filled > 0 {
+            // the padding of the last block is cut off below
+            let padding = self.filled.next_multiple_of(ALIGNMENT);
+            self.buffers[self.current].as_mut_slice()[self.filled..padding].fill(0);
+            self.submit()?;
+        }
+        for index in 0..WRITE_BUFFERS {
+            if self.operations.is_pending(index) {
+                self.operations.wait(index)?;
+            }
+        }
+        let info = FILE_END_OF_FILE_INFO {
+            EndOfFile: size as i64,
+        };
+        unsafe {
+            SetFileInformationByHandle(
+                HANDLE(self.file.as_raw_handle()),
+                FileEndOfFileInfo,
+                &info as *const _ as _,
+                mem::size_of::<FILE_END_OF_FILE_INFO>() as u32,
+            )
+        }?;
+        Ok(())
+    }
+}
+
+impl Write for UnbufferedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let buffer = &mut self.buffers[self.current].as_mut_slice()[self.filled..];
+        let count = buf.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&buf[..count]);
+        self.filled += count;
+        if self.filled == WRITE_BUFFER_SIZE {
+            self.submit()?;
+        }
+        Ok(count)
+    }
+
+    // The data is on the disk when finish returns
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use log::{error, warn};
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::jobs::{ConnectionId, Job, JobFormat, TransferStats};
+
+// next to the configuration file
+pub const INDEX_FILE_NAME: &str = "miniraw-jobs.txt";
+// the oldest jobs are dropped when the index is loaded with more
+pub const MAX_JOBS: usize = 100_000;
+// the jobs read for the shown rows, the cache is cleared when it is full
+const CACHE_SIZE: usize = 1024;
+// the first byte of a removed record is replaced with it
+const REMOVED: u8 = b'#';
+const HEADER: &str = "# time\toffset\tconnection\tsource\tsize\tformat\tpeak_memory\t\
+    duration_ms\taverage_bytes_per_second\tpeak_bytes_per_second\tstalls\tpath\r\n";
+
+// One line per job with the fields separated by tabs, the path is the last field
+// and empty for a discarded job
+fn format_record(job: &Job) -> String {
+    let transfer = &job.transfer;
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\r\n",
+        job.time.unix_timestamp(),
+        job.time.offset().whole_seconds(),
+        job.connection.0,
+        job.source,
+        job.size,
+        job.format,
+        job.peak_memory,
+        transfer.duration_ms,
+        transfer.average_bytes_per_second,
+        transfer.peak_bytes_per_second,
+        transfer.stalls,
+        job.path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default()
+    )
+}
+
+fn parse_record(line: &str) -> Option<Job> {
+    let mut fields = line.trim_end_matches(['\r', '\n']).splitn(12, '\t');
+    let mut next = || fields.next();
+    let timestamp = next()?.parse().ok()?;
+    let offset = UtcOffset::from_whole_seconds(next()?.parse().ok()?).ok()?;
+    let job = Job {
+        time: OffsetDateTime::from_unix_timestamp(timestamp)
+            .ok()?
+            .to_offset(offset),
+        connection: ConnectionId(next()?.parse().ok()?),
+        source: next()?.parse().ok()?,
+        size: next()?.parse().ok()?,
+        format: JobFormat::parse(next()?)?,
+        peak_memory: next()?.parse().ok()?,
+        transfer: TransferStats {
+            duration_ms: next()?.parse().ok()?,
+            average_bytes_per_second: next()?.parse().ok()?,
+            peak_bytes_per_second: next()?.parse().ok()?,
+            stalls: next()?.parse().ok()?,
+        },
+        path: Some(next()?)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from),
+    };
+    Some(job)
+}
+
+// The start and the length of each record in the file, in the order of the jobs
+fn scan_records(data: &[u8]) -> Vec<(u64, usize)> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    for line in data.split_inclusive(|&byte| byte == b'\n') {
+        if line.first() != Some(&REMOVED) && line.ends_with(b"\n") {
+            records.push((offset as u64, line.len()));
+        }
+        offset += line.len();
+    }
+    records
+}
+
+enum Record {
+    Stored { offset: u64, length: usize },
+    // kept in the memory when it could not be written
+    Memory(Box<Job>),
+}
+
+struct State {
+    file: Option<File>,
+    records: Vec<Record>,
+    cache: HashMap<u64, Job>,
+    // a write error is logged once
+    write_failed: bool,
+}
+
+// The jobs of this and the earlier sessions in a text file, one line per job. Only
+// the positions of the lines are kept in the memory and the jobs are read when
+// they are shown, so that a long history costs neither memory nor time on start.
+pub struct JobIndex {
+    path: PathBuf,
+    state: Mutex<State>,
+    // the jobs of the earlier sessions come first
+    loaded: usize,
+}
+
+impl JobIndex {
+    // The invalid lines are skipped, the file is rewritten without the oldest jobs
+    // if it has more than MAX_JOBS
+    pub fn load(path: PathBuf) -> JobIndex {
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                error!("{}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        let mut records = scan_records(&data);
+        if records.len() > MAX_JOBS {
+            let mut text = HEADER.as_bytes().to_vec();
+            for (offset, length) in &records[records.len() - MAX_JOBS..] {
+                text.extend_from_slice(&data[*offset as usize..*offset as usize + length]);
+            }
+            let temp_path = path.with_extension("txt.tmp");
+            let result = fs::write(&temp_path, &text).and_then(|_| fs::rename(&temp_path, &path));
+            match result {
+                Ok(()) => records = scan_records(&text),
+                Err(e) => {
+                    let _ = fs::remove_file(&temp_path);
+                    error!("{}: {}", path.display(), e);
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .and_then(|mut file| {
+                if file.metadata()?.len() == 0 {
+                    file.write_all(HEADER.as_bytes())?;
+                }
+                Ok(file)
+            })
+            .inspect_err(|e| error!("{}: {}", path.display(), e))
+            .ok();
+        let loaded = records.len();
+        JobIndex {
+            path,
+            state: Mutex::new(State {
+                file,
+                records: records
+                    .into_iter()
+                    .map(|(offset, length)| Record::Stored { offset, length })
+                    .collect(),
+                cache: HashMap::new(),
+                write_failed: false,
+            }),
+            loaded,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.state.lock().unwrap().records.len()
+    }
+
+    // Returns the number of the jobs
+    pub fn append(&self, job: &Job) -> usize {
+        let record = format_record(job);
+        let mut state = self.state.lock().unwrap();
+        let result = match state.file.as_mut() {
+            Some(file) => file.seek(SeekFrom::End(0)).and_then(|offset| {
+                file.write_all(record.as_bytes())?;
+                Ok(offset)
+            }),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        let record = match result {
+            Ok(offset) => Record::Stored {
+                offset,
+                length: record.len(),
+            },
+            Err(e) => {
+                if !state.write_failed && state.file.is_some() {
+                    error!("{}: {}", self.path.display(), e);
+                }
+                state.write_failed = true;
+                Record::Memory(Box::new(job.clone()))
+            }
+        };
+        state.records.push(record);
+        state.records.len()
+    }
+
+    // None if the row does not exist or its line is damaged
+    pub fn get(&self, row: usize) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        let (offset, length) = match state.records.get(row)? {
+            Record::Memory(job) => return Some(job.as_ref().clone()),
+            Record::Stored { offset, length } => (*offset, *length),
+        };
+        if let Some(job) = state.cache.get(&offset) {
+            return Some(job.clone());
+        }
+
+        let mut line = vec![0; length];
+        let file = state.file.as_mut()?;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut line))
+            .inspect_err(|e| warn!("{}: {}", self.path.display(), e))
+            .ok()?;
+        let job = parse_record(&String::from_utf8_lossy(&line))?;
+        if state.cache.len() >= CACHE_SIZE {
+            state.cache.clear();
+        }
+        state.cache.insert(offset, job.clone());
+        Some(job)
+    }
+
+    // Removes the job of this session with the connection, its line is marked as
+    // removed. Returns the number of the jobs if it was found.
+    pub fn remove(&self, connection: ConnectionId) -> Option<usize> {
+        let row = (self.loaded..self.count()).rev().find(|row| {
+            self.get(*row)
+                .is_some_and(|job| job.connection == connection)
+        })?;
+
+        let mut state = self.state.lock().unwrap();
+        if let Record::Stored { offset, .. } = state.records.remove(row) {
+            state.cache.remove(&offset);
+            // the appending handle cannot write in the middle of the file
+            let result = OpenOptions::new()
+                .write(true)
+                .open(&self.path)
+                .and_then(|mut file| {
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.write_all(&[REMOVED])
+                });
+            if let Err(e) = result {
+                warn!("Cannot remove the job from {}: {}", self.path.display(), e);
+            }
+        }
+        Some(state.records.len())
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use crate::{
+    job_index::{JobIndex, MAX_JOBS},
+    jobs::{ConnectionId, Job},
+    tr,
+    ui::{
+        list_view::{ColumnAlign, ListView},
+        window::WindowRef,
+    },
+};
+
+const COLUMNS: &[(&str, i32, ColumnAlign)] = &[
+    ("Time", 150, ColumnAlign::Left),
+    ("Source", 150, ColumnAlign::Left),
+    ("Size", 90, ColumnAlign::Right),
+    ("Format", 90, ColumnAlign::Left),
+    ("File", 200, ColumnAlign::Left),
+    ("Connection", 80, ColumnAlign::Right),
+];
+
+// The rows are not stored in the list view, the view is created with LVS_OWNERDATA
+// and the cells are formatted from the job index when they are shown, so the jobs
+// of the earlier sessions are listed right away and the number of jobs does not
+// affect the cost of adding one
+pub struct JobList {
+    view: ListView,
+    index: Arc<JobIndex>,
+    // the latest MAX_JOBS of this session, for the statistics and the diagnostics
+    session: RwLock<VecDeque<Job>>,
+    // the list view is only posted to while it is held, so that a thread waiting
+    // for it does not block the UI thread
+    insert_lock: Mutex<()>,
+}
+
+impl JobList {
+    pub fn new(view: WindowRef, index: JobIndex) -> JobList {
+        let view = ListView::new(view);
+        for (title, width, align) in COLUMNS {
+            view.add_column(tr!(title), *width, *align);
+        }
+
+        let index = Arc::new(index);
+        let source = index.clone();
+        view.set_data_source(move |row, column| {
+            source
+                .get(row)
+                .map(|job| Self::column(&job, column))
+                .unwrap_or_default()
+        });
+        let count = index.count();
+        view.set_row_count(count);
+        if count > 0 {
+            view.ensure_visible(count - 1);
+        }
+
+        JobList {
+            view,
+            index,
+            session: Default::default(),
+            insert_lock: Default::default(),
+        }
+    }
+
+    pub fn view(&self) -> &ListView {
+        &self.view
+    }
+
+    fn column(job: &Job, column: usize) -> String {
+        match column {
+            0 => {
+                let (hour, minute, second) = job.time.to_hms();
+                format!(
+                    "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    job.time.year(),
+                    job.time.month() as u8,
+                    job.time.day(),
+                    hour,
+                    minute,
+                    second
+                )
+            }
+            1 => job.source.to_string(),
+            2 => job.size.to_string(),
+            3 => job.format.to_string(),
+            4 => job
+                .path
+                .as_ref()
+                .and_then(|p| p.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| tr!("(discarded)").to_owned()),
+            5 => job.connection.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    pub fn add_job(&self, job: &Job) {
+        // keeps the rows in the same order as the jobs when called from several listener threads
+        let _guard = self.insert_lock.lock().unwrap();
+
+        {
+            let mut session = self.session.write().unwrap();
+            if session.len() == MAX_JOBS {
+                session.pop_front();
+            }
+            session.push_back(job.clone());
+        }
+        let count = self.index.append(job);
+
+        // posted under the lock so that the counts reach the list view in order
+        self.view.post_row_count(count);
+        self.view.post_ensure_visible(count - 1);
+    }
+
+    // Only the jobs of this session are found by their connection
+    pub fn remove_job(&self, connection: ConnectionId) {
+        let _guard = self.insert_lock.lock().unwrap();
+
+        self.session
+            .write()
+            .unwrap()
+            .retain(|job| job.connection != connection);
+        if let Some(count) = self.index.remove(connection) {
+            // posted after the counts of the jobs added before
+            self.view.post_row_count(count);
+        }
+    }
+
+    pub fn jobs(&self) -> Vec<Job> {
+        self.session.read().unwrap().iter().cloned().collect()
+    }
+
+    pub fn latest_saved_job(&self) -> Option<Job> {
+        self.session
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|job| job.path.is_some())
+            .cloned()
+    }
+
+    pub fn selected_job(&self) -> Option<Job> {
+        self.index.get(self.view.selected_row()?)
+    }
+}
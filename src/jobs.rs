@@ -0,0 +1,216 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+};
+
+use time::OffsetDateTime;
+
+const UEL: &[u8] = b"\x1b%-12345X";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobFormat {
+    Pjl,
+    Pcl,
+    PostScript,
+    Pdf,
+    Zpl,
+    EscP,
+    Text,
+    Unknown,
+}
+
+impl JobFormat {
+    pub const ALL: [JobFormat; 8] = [
+        JobFormat::Pjl,
+        JobFormat::Pcl,
+        JobFormat::PostScript,
+        JobFormat::Pdf,
+        JobFormat::Zpl,
+        JobFormat::EscP,
+        JobFormat::Text,
+        JobFormat::Unknown,
+    ];
+
+    // The name shown by Display
+    pub fn parse(name: &str) -> Option<JobFormat> {
+        JobFormat::ALL
+            .into_iter()
+            .find(|format| format.to_string() == name)
+    }
+
+    pub fn detect(head: &[u8]) -> JobFormat {
+        let mut data = head;
+        let mut pjl = false;
+
+        // skip the PJL job header to find out the actual page description language
+        while let Some(rest) = data.strip_prefix(UEL) {
+            pjl = true;
+            data = rest;
+            while data.starts_with(b"@PJL") {
+                match data.iter().position(|&b| b == b'\n') {
+                    Some(pos) => data = &data[pos + 1..],
+                    None => return JobFormat::Pjl,
+                }
+            }
+        }
+
+        let data = data.trim_ascii_start();
+
+        if data.starts_with(b"%!PS") || data.starts_with(b"\x04%!PS") {
+            JobFormat::PostScript
+        } else if data.starts_with(b"%PDF-") {
+            JobFormat::Pdf
+        } else if data.starts_with(b"^XA") || data.starts_with(b"~") {
+            JobFormat::Zpl
+        } else if data.starts_with(b"\x1bE")
+            || data.starts_with(b"\x1b&")
+            || data.starts_with(b"\x1b*")
+        {
+            JobFormat::Pcl
+        } else if data.starts_with(b"\x1b@") {
+            JobFormat::EscP
+        } else if pjl {
+            JobFormat::Pjl
+        } else if !data.is_empty()
+            && data
+                .iter()
+                .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        {
+            JobFormat::Text
+        } else {
+            JobFormat::Unknown
+        }
+    }
+}
+
+impl fmt::Display for JobFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            JobFormat::Pjl => "PJL",
+            JobFormat::Pcl => "PCL",
+            JobFormat::PostScript => "PostScript",
+            JobFormat::Pdf => "PDF",
+            JobFormat::Zpl => "ZPL",
+            JobFormat::EscP => "ESC/P",
+            JobFormat::Text => "Text",
+            JobFormat::Unknown => "Unknown",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// Number of an accepted connection, the log lines of the connection are prefixed
+// with it so that concurrent jobs can be told apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionId(pub u32);
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// How the data of a job came over the network, to tell a slow client or network
+// from a slow printer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    // from the connection to the end of the data
+    pub duration_ms: u64,
+    pub average_bytes_per_second: u64,
+    // the fastest second of the transfer
+    pub peak_bytes_per_second: u64,
+    // the waits for the data of at least STALL_SECONDS, including the one for the
+    // first data
+    pub stalls: u32,
+}
+
+impl TransferStats {
+    pub const STALL_SECONDS: u64 = 2;
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub connection: ConnectionId,
+    pub time: OffsetDateTime,
+    pub source: SocketAddr,
+    pub size: u64,
+    pub format: JobFormat,
+    pub path: Option<PathBuf>,
+    // the most heap memory the connection needed to receive and check the job
+    pub peak_memory: u64,
+    pub transfer: TransferStats,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenerState {
+    // accepting connections on the port
+    Listening(u16),
+    // bound, but incoming connections are rejected
+    Paused,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    // a connection was accepted and the job is being received
+    Started { source: SocketAddr },
+    // published every PROGRESS_STEP bytes while a job is being received
+    Progress { source: SocketAddr, received: u64 },
+    Completed(Job),
+    // the connection failed or delivered no data
+    Aborted { source: SocketAddr },
+    Listener(ListenerState),
+}
+
+// Connections which are still receiving data
+#[derive(Default)]
+pub struct ActiveTransfers {
+    sources: Mutex<HashSet<SocketAddr>>,
+}
+
+impl ActiveTransfers {
+    pub fn handle_event(&self, event: &JobEvent) {
+        let mut sources = self.sources.lock().unwrap();
+        match event {
+            JobEvent::Started { source } => {
+                sources.insert(*source);
+            }
+            JobEvent::Completed(job) => {
+                sources.remove(&job.source);
+            }
+            JobEvent::Aborted { source } => {
+                sources.remove(source);
+            }
+            JobEvent::Progress { .. } | JobEvent::Listener(_) => {}
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.sources.lock().unwrap().len()
+    }
+}
+
+type JobEventHandler = Box<dyn Fn(&JobEvent) + Send + Sync + 'static>;
+
+#[derive(Default)]
+pub struct JobEventBus {
+    handlers: RwLock<Vec<JobEventHandler>>,
+}
+
+impl JobEventBus {
+    pub fn subscribe<F>(&self, handler: F)
+    where
+        F: Fn(&JobEvent) + Send + Sync + 'static,
+    {
+        self.handlers.write().unwrap().push(Box::new(handler));
+    }
+
+    pub fn publish(&self, event: JobEvent) {
+        for handler in self.handlers.read().unwrap().iter() {
+            handler(&event);
+        }
+    }
+}
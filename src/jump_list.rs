@@ -0,0 +1,133 @@
+use std::{env, path::PathBuf};
+
+use log::warn;
+use windows::{
+    core::{Interface, HSTRING},
+    Win32::{
+        Storage::EnhancedStorage::PKEY_Title,
+        System::Com::{CoCreateInstance, StructuredStorage::PROPVARIANT, CLSCTX_INPROC_SERVER},
+        UI::Shell::{
+            Common::{IObjectArray, IObjectCollection},
+            DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW,
+            PropertiesSystem::IPropertyStore,
+            ShellLink,
+        },
+    },
+};
+
+use crate::{
+    jobs::Job,
+    tr,
+    ui::window::{user_message, WindowRef},
+};
+
+// Posted to the main window when the list of recent jobs has changed
+pub const WM_UPDATE_JUMP_LIST: u32 = user_message(5);
+
+const MAX_RECENT_JOBS: usize = 10;
+
+// Jump list entry which starts the program with the given arguments
+pub struct JumpTask {
+    pub title: &'static str,
+    pub arguments: &'static str,
+}
+
+fn shell_link(
+    path: &str,
+    arguments: &str,
+    title: &str,
+    description: &str,
+) -> windows::core::Result<IShellLinkW> {
+    unsafe {
+        let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+        link.SetPath(&HSTRING::from(path))?;
+        link.SetArguments(&HSTRING::from(arguments))?;
+        link.SetDescription(&HSTRING::from(description))?;
+
+        // the text shown in the jump list is the title property of the link
+        let store = link.cast::<IPropertyStore>()?;
+        store.SetValue(&PKEY_Title, &PROPVARIANT::from(title))?;
+        store.Commit()?;
+        Ok(link)
+    }
+}
+
+// Links to the most recent saved jobs, each one opens Explorer with the job selected
+fn recent_jobs(jobs: &[Job]) -> windows::core::Result<IObjectCollection> {
+    let explorer = PathBuf::from(env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".into()))
+        .join("explorer.exe");
+
+    unsafe {
+        let collection: IObjectCollection =
+            CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+
+        let saved = jobs
+            .iter()
+            .rev()
+            .filter_map(|job| {
+                job.path
+                    .as_ref()
+                    .filter(|path| path.exists())
+                    .map(|p| (job, p))
+            })
+            .take(MAX_RECENT_JOBS);
+
+        for (job, path) in saved {
+            let link = shell_link(
+                &explorer.to_string_lossy(),
+                &format!("/select,\"{}\"", path.display()),
+                &path.file_name().unwrap_or_default().to_string_lossy(),
+                &format!("{}, {}", job.source, job.format),
+            )?;
+            collection.AddObject(&link)?;
+        }
+        Ok(collection)
+    }
+}
+
+fn build(tasks: &[JumpTask], jobs: &[Job]) -> windows::core::Result<()> {
+    let exe = env::current_exe().unwrap_or_default();
+
+    unsafe {
+        let list: ICustomDestinationList =
+            CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+        let mut slots = 0u32;
+        let _removed: IObjectArray = list.BeginList(&mut slots)?;
+
+        // the category is rejected when it contains a job removed from the list by
+        // the user, the tasks are still shown then
+        let recent = recent_jobs(jobs)?;
+        if recent.cast::<IObjectArray>()?.GetCount()? > 0 {
+            let _ = list.AppendCategory(&HSTRING::from(tr!("Recent jobs")), &recent);
+        }
+
+        let collection: IObjectCollection =
+            CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+        for task in tasks {
+            let link = shell_link(
+                &exe.to_string_lossy(),
+                task.arguments,
+                task.title,
+                task.title,
+            )?;
+            collection.AddObject(&link)?;
+        }
+        list.AddUserTasks(&collection)?;
+
+        list.CommitList()
+    }
+}
+
+// Replaces the jump list of the taskbar button, must be called on the UI thread
+pub fn update(tasks: &[JumpTask], jobs: &[Job]) {
+    if let Err(e) = build(tasks, jobs) {
+        warn!("Cannot update the jump list: {}", e);
+    }
+}
+
+// Called from the listener threads, the list is rebuilt on the UI thread
+pub fn request_update(window: &WindowRef) {
+    if let Err(e) = window.post_user_message(WM_UPDATE_JUMP_LIST, 0, 0) {
+        warn!("Cannot update the jump list: {}", e);
+    }
+}
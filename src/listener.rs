@@ -1,86 +1,1106 @@
 use std::{
-    env, fs, io,
-    net::{Ipv4Addr, TcpListener, TcpStream},
-    path::PathBuf,
+    fs,
+    io::{self, BufWriter, Read, Write},
+    net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
     },
-    time,
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use log::{error, info, warn};
+use log::{debug, error, info, log_enabled, warn, Level};
+use time::OffsetDateTime;
 
-fn new_filename_from_timestamp() -> io::Result<(fs::File, PathBuf)> {
-    let timestamp = time::SystemTime::now()
-        .duration_since(time::UNIX_EPOCH)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
-        .as_secs();
+use crate::{
+    admission::{Admission, ConnectionLimits, Ticket, TransferGuard},
+    forward_health::{self, ForwardDown, Forwarding},
+    hex_view,
+    iocp::{OverlappedReceiver, UnbufferedFile},
+    jobs::{ConnectionId, Job, JobEvent, JobEventBus, JobFormat, ListenerState, TransferStats},
+    logger::STORAGE_TARGET,
+    mapped_file::MappedFile,
+    memory::MemoryMeter,
+    plugins::PluginHost,
+    priority,
+    scanner::Scanner,
+    schedule::{OffHours, Schedule},
+    script::{JobRoute, ScriptHooks},
+    util,
+};
+
+// the default port, can be changed in the settings
+pub const RAW_PORT: u16 = 9100;
+// the seconds since 1970 with the extension used by the print spoolers
+pub const DEFAULT_NAME_TEMPLATE: &str = "{timestamp}.spl";
+
+const HEAD_SIZE: usize = 512;
+// the buffer of the blocking receives and of the buffered job files
+const RECEIVE_BUFFER_SIZE: usize = 64 * 1024;
+// upper limit of the hexdump logged at the debug level
+const MAX_HEXDUMP_SIZE: usize = 4096;
+const PROGRESS_STEP: u64 = 1024 * 1024;
+// waiting for the connection which wakes up a stopped listener
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+static NEXT_CONNECTION: AtomicU32 = AtomicU32::new(1);
+// The sockets of the jobs being received, so that they can be closed when the
+// computer resumes from sleep
+static OPEN_CONNECTIONS: Mutex<Vec<(ConnectionId, TcpStream)>> = Mutex::new(Vec::new());
+
+// Measures the transfer of a job as its data is received
+struct TransferMeter {
+    started: Instant,
+    last_data: Instant,
+    bytes: u64,
+    // the second the peak rate is measured in
+    second_start: Instant,
+    second_bytes: u64,
+    peak_bytes_per_second: u64,
+    stalls: u32,
+}
+
+impl TransferMeter {
+    fn new() -> Self {
+        let now = Instant::now();
+        TransferMeter {
+            started: now,
+            last_data: now,
+            bytes: 0,
+            second_start: now,
+            second_bytes: 0,
+            peak_bytes_per_second: 0,
+            stalls: 0,
+        }
+    }
+
+    fn record(&mut self, received: usize) {
+        let now = Instant::now();
+        if now - self.last_data >= Duration::from_secs(TransferStats::STALL_SECONDS) {
+            self.stalls += 1;
+        }
+        self.last_data = now;
+        self.bytes += received as u64;
+        let elapsed = now - self.second_start;
+        if elapsed >= Duration::from_secs(1) {
+            let rate = self.second_bytes * 1000 / elapsed.as_millis() as u64;
+            self.peak_bytes_per_second = self.peak_bytes_per_second.max(rate);
+            self.second_start = now;
+            self.second_bytes = 0;
+        }
+        self.second_bytes += received as u64;
+    }
+
+    // The transfer ends with the last data, not when the client closes the connection
+    fn stats(&self) -> TransferStats {
+        let duration_ms = (self.last_data - self.started).as_millis() as u64;
+        let average = (self.bytes * 1000)
+            .checked_div(duration_ms)
+            .unwrap_or(self.bytes);
+        TransferStats {
+            duration_ms,
+            average_bytes_per_second: average,
+            // a job sent within a second has no full second to measure
+            peak_bytes_per_second: self.peak_bytes_per_second.max(average),
+            stalls: self.stalls,
+        }
+    }
+}
+
+// Passes the data through while keeping the first bytes for format detection
+// and the hexdump
+struct HeadWriter<W> {
+    inner: W,
+    head: Vec<u8>,
+    size: usize,
+}
+
+impl<W: Write> HeadWriter<W> {
+    fn new(inner: W, size: usize) -> Self {
+        HeadWriter {
+            inner,
+            head: Vec::with_capacity(size),
+            size,
+        }
+    }
+}
+
+impl<W: Write> Write for HeadWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let room = self.size - self.head.len();
+        self.head.extend_from_slice(&buf[..written.min(room)]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Publishes progress events while the job data is being written
+struct ProgressWriter<W> {
+    inner: W,
+    source: SocketAddr,
+    events: Arc<JobEventBus>,
+    received: u64,
+}
+
+impl<W: Write> ProgressWriter<W> {
+    fn new(inner: W, source: SocketAddr, events: Arc<JobEventBus>) -> Self {
+        ProgressWriter {
+            inner,
+            source,
+            events,
+            received: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let step = self.received / PROGRESS_STEP;
+        self.received += written as u64;
+        if self.received / PROGRESS_STEP > step {
+            self.events.publish(JobEvent::Progress {
+                source: self.source,
+                received: self.received,
+            });
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Sends a copy of the job to another printer while it is being received. The job is
+// still received when the printer cannot be reached or fails.
+struct ForwardWriter<W> {
+    inner: W,
+    stream: Option<TcpStream>,
+    // the health of the target is updated as the job is forwarded
+    forwarding: Option<Forwarding>,
+    connection: ConnectionId,
+    target: String,
+    forwarded: u64,
+}
+
+impl<W: Write> ForwardWriter<W> {
+    fn new(inner: W, target: &str, connection: ConnectionId) -> Self {
+        let (stream, forwarding) = if target.is_empty() {
+            (None, None)
+        } else {
+            match forward_health::connect(target) {
+                Ok(stream) => (Some(stream), Some(forward_health::connected(target))),
+                Err(e) => {
+                    warn!("[{}] {}: {}", connection, target, e);
+                    forward_health::failed(target, &e);
+                    (None, None)
+                }
+            }
+        };
+        ForwardWriter {
+            inner,
+            stream,
+            forwarding,
+            connection,
+            target: target.to_owned(),
+            forwarded: 0,
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Write);
+            info!(
+                "[{}] Forwarded {} bytes to {}",
+                self.connection, self.forwarded, self.target
+            );
+        }
+    }
+}
+
+impl<W: Write> Write for ForwardWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(ref mut stream) = self.stream {
+            match stream.write_all(&buf[..written]) {
+                Ok(()) => self.forwarded += written as u64,
+                Err(e) => {
+                    warn!("[{}] {}: {}", self.connection, self.target, e);
+                    forward_health::failed(&self.target, &e);
+                    self.stream = None;
+                    self.forwarding = None;
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Characters which are not allowed in file names are replaced with '_'
+pub fn safe_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+// Expands the placeholders of a job file name template: {timestamp} (seconds since
+// 1970), {date}, {time}, {port}, {source} (the client address) and {connection},
+// see safe_file_name for the characters which are replaced.
+fn job_file_name(
+    template: &str,
+    time: OffsetDateTime,
+    source: SocketAddr,
+    port: u16,
+    connection: ConnectionId,
+) -> String {
+    let (hour, minute, second) = time.to_hms();
+    let name = template
+        .replace("{timestamp}", &time.unix_timestamp().to_string())
+        .replace(
+            "{date}",
+            &format!(
+                "{}-{:02}-{:02}",
+                time.year(),
+                time.month() as u8,
+                time.day()
+            ),
+        )
+        .replace("{time}", &format!("{hour:02}{minute:02}{second:02}"))
+        .replace("{port}", &port.to_string())
+        .replace("{source}", &source.ip().to_string())
+        .replace("{connection}", &connection.0.to_string());
+
+    safe_file_name(&name)
+}
+
+// How the jobs are written into their files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    // blocking receives and plain buffered writes through the page cache
+    #[default]
+    Buffered,
+    // overlapped receives and writes bypassing the page cache, see UnbufferedFile,
+    // it needs about 5 MB of aligned buffers per connection
+    Unbuffered,
+    // copied into the mapped file and flushed periodically, see MappedFile
+    Mapped,
+}
+
+impl WriteMode {
+    pub fn parse(text: &str) -> Option<WriteMode> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "" | "buffered" => Some(WriteMode::Buffered),
+            "unbuffered" => Some(WriteMode::Unbuffered),
+            "mapped" => Some(WriteMode::Mapped),
+            _ => None,
+        }
+    }
+}
+
+// The file of a job written the way chosen in the settings
+enum JobFile {
+    Buffered(BufWriter<fs::File>),
+    Unbuffered(UnbufferedFile),
+    Mapped(MappedFile),
+}
+
+impl JobFile {
+    fn create_new(path: &Path, mode: WriteMode) -> io::Result<JobFile> {
+        Ok(match mode {
+            WriteMode::Buffered => JobFile::Buffered(BufWriter::with_capacity(
+                RECEIVE_BUFFER_SIZE,
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(path)?,
+            )),
+            WriteMode::Unbuffered => JobFile::Unbuffered(UnbufferedFile::create_new(path)?),
+            WriteMode::Mapped => JobFile::Mapped(MappedFile::create_new(path)?),
+        })
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            JobFile::Buffered(mut file) => file.flush(),
+            JobFile::Unbuffered(file) => file.finish(),
+            JobFile::Mapped(file) => file.finish(),
+        }
+    }
+}
+
+impl Write for JobFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            JobFile::Buffered(file) => file.write(buf),
+            JobFile::Unbuffered(file) => file.write(buf),
+            JobFile::Mapped(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            JobFile::Buffered(file) => file.flush(),
+            JobFile::Unbuffered(file) => file.flush(),
+            JobFile::Mapped(file) => file.flush(),
+        }
+    }
+}
+
+// A number is added before the extension if the file already exists
+fn create_job_file(dir: &Path, name: &str, mode: WriteMode) -> io::Result<(JobFile, PathBuf)> {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (name, String::new()),
+    };
 
     let mut suffix = 0;
 
     loop {
         let filename = if suffix == 0 {
-            format!("{timestamp}.spl")
+            format!("{stem}{extension}")
         } else {
-            format!("{timestamp}-{suffix}.spl")
+            format!("{stem}-{suffix}{extension}")
         };
 
-        let filepath = env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.to_owned()))
-            .unwrap_or_default()
-            .join(filename);
+        let filepath = dir.join(filename);
 
-        match fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(&filepath)
-        {
+        match JobFile::create_new(&filepath, mode) {
             Ok(writer) => break Ok((writer, filepath)),
             Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
                 suffix += 1;
             }
             Err(e) => {
-                error!("{}", e);
+                error!(target: STORAGE_TARGET, "{}", e);
                 return Err(e);
             }
         }
     }
 }
 
-fn handle_request(mut stream: TcpStream, discard_flag: Arc<AtomicBool>) -> io::Result<()> {
-    info!("Incoming connection from {}", stream.peer_addr()?);
+// A job whose connection was closed by close_connections is incomplete
+fn ensure_open(connection: ConnectionId) -> io::Result<()> {
+    if OPEN_CONNECTIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(id, _)| *id == connection)
+    {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "The connection was closed after resuming from sleep",
+        ))
+    }
+}
+
+// Closes the connections of the jobs being received, their clients may be gone
+// while the computer was asleep and the reads would never return. The jobs are
+// aborted, returns their number.
+pub fn close_connections() -> usize {
+    let connections = std::mem::take(&mut *OPEN_CONNECTIONS.lock().unwrap());
+    for (connection, stream) in &connections {
+        info!(
+            "[{}] Closing the connection interrupted by sleep",
+            connection
+        );
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+    connections.len()
+}
+
+// The first HEAD_SIZE bytes of the job or less if the connection is closed or the
+// deadline of the job is passed before
+fn receive_head(
+    stream: &mut TcpStream,
+    guard: &mut TransferGuard,
+    meter: &mut TransferMeter,
+) -> io::Result<Vec<u8>> {
+    let mut head = vec![0; HEAD_SIZE];
+    let mut size = 0;
+    while size < HEAD_SIZE && !guard.is_past_deadline() {
+        stream.set_read_timeout(guard.wait_time())?;
+        match stream.read(&mut head[size..]) {
+            Ok(0) => break,
+            Ok(count) => {
+                size += count;
+                meter.record(count);
+                guard.update(count)?;
+            }
+            // the timeout is reported as WouldBlock on some systems
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                guard.update(0)?
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    stream.set_read_timeout(None)?;
+    head.truncate(size);
+    Ok(head)
+}
+
+// Writes the first bytes and then the rest of the job as it is received until the
+// end of the stream or the deadline of the job, returns the size of the job. The
+// overlapped receives are only used with the unbuffered writes.
+fn receive_data<W: Write>(
+    first: &[u8],
+    stream: &TcpStream,
+    mode: WriteMode,
+    guard: &mut TransferGuard,
+    meter: &mut TransferMeter,
+    target: &mut W,
+) -> io::Result<u64> {
+    target.write_all(first)?;
+    let size = first.len() as u64;
+    if mode == WriteMode::Unbuffered {
+        receive_overlapped(size, stream, guard, meter, target)
+    } else {
+        receive_blocking(size, stream, guard, meter, target)
+    }
+}
 
-    if discard_flag.load(Ordering::SeqCst) {
-        let bytes = io::copy(&mut stream, &mut io::sink())?;
-        info!("Discarded {} bytes", bytes);
-    } else if let Ok((mut target, filepath)) = new_filename_from_timestamp() {
-        let bytes = io::copy(&mut stream, &mut target)?;
+fn receive_blocking<W: Write>(
+    mut size: u64,
+    mut stream: &TcpStream,
+    guard: &mut TransferGuard,
+    meter: &mut TransferMeter,
+    target: &mut W,
+) -> io::Result<u64> {
+    let mut buffer = vec![0; RECEIVE_BUFFER_SIZE];
+    while !guard.is_past_deadline() {
+        stream.set_read_timeout(guard.wait_time())?;
+        match stream.read(&mut buffer) {
+            Ok(0) => return Ok(size),
+            Ok(count) => {
+                meter.record(count);
+                guard.update(count)?;
+                target.write_all(&buffer[..count])?;
+                size += count as u64;
+            }
+            // the timeout is reported as WouldBlock on some systems
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                guard.update(0)?
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    guard.check_deadline()?;
+    Ok(size)
+}
+
+fn receive_overlapped<W: Write>(
+    mut size: u64,
+    stream: &TcpStream,
+    guard: &mut TransferGuard,
+    meter: &mut TransferMeter,
+    target: &mut W,
+) -> io::Result<u64> {
+    let mut receiver = OverlappedReceiver::new(stream)?;
+    while !guard.is_past_deadline() {
+        match receiver.receive(guard.wait_time()) {
+            Ok(Some(data)) => {
+                meter.record(data.len());
+                guard.update(data.len())?;
+                target.write_all(data)?;
+                size += data.len() as u64;
+            }
+            Ok(None) => return Ok(size),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => guard.update(0)?,
+            Err(e) => return Err(e),
+        }
+    }
+    guard.check_deadline()?;
+    Ok(size)
+}
+
+// The first bytes of the job are received before the file is created, so that the
+// on_job_header hook of the script can change where the job goes
+#[allow(clippy::too_many_arguments)]
+fn receive_job(
+    mut stream: TcpStream,
+    connection: ConnectionId,
+    source: SocketAddr,
+    config: &ListenerConfig,
+    ticket: &Ticket,
+    discard_flag: Arc<AtomicBool>,
+    hexdump_size: usize,
+    hooks: &ScriptHooks,
+    events: Arc<JobEventBus>,
+) -> io::Result<Option<Job>> {
+    let time = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    let head_size = HEAD_SIZE.max(hexdump_size);
+    let mut route = JobRoute {
+        file_name: job_file_name(&config.name_template, time, source, config.port, connection),
+        output_dir: config.output_dir.clone(),
+        forward_target: config.forward_target.clone(),
+        discard: config
+            .discard
+            .unwrap_or_else(|| discard_flag.load(Ordering::SeqCst)),
+    };
+    // the rejected connections are closed by run_raw_listener
+    if !config.schedule.is_open(time) {
+        route.discard = true;
+        if config.off_hours == OffHours::Discard {
+            route.forward_target.clear();
+        }
+        info!(
+            target: STORAGE_TARGET,
+            "[{}] Received outside the schedule, the job is not saved",
+            connection
+        );
+    }
+
+    let mut guard = TransferGuard::new(ticket.admission());
+    let mut meter = TransferMeter::new();
+    let mut first = receive_head(&mut stream, &mut guard, &mut meter)?;
+    if !config.job_secret.is_empty() && !strip_job_secret(&config.job_secret, &mut first) {
+        warn!(
+            target: STORAGE_TARGET,
+            "[{}] Dropped the job from {} without the job secret",
+            connection,
+            source
+        );
+        return Ok(None);
+    }
+    if !hooks.on_job_header(connection, source, config.port, &first, &mut route) {
+        info!(target: STORAGE_TARGET, "[{}] Job rejected by the script", connection);
+        return Ok(None);
+    }
+    // the jobs for a forward target which is down are refused or saved and queued
+    let mut queued_target = None;
+    if forward_health::is_down(&route.forward_target) {
+        match config.forward_down {
+            ForwardDown::Refuse => {
+                info!(
+                    target: STORAGE_TARGET,
+                    "[{}] Job refused, the forward target {} is down",
+                    connection,
+                    route.forward_target
+                );
+                return Ok(None);
+            }
+            ForwardDown::Queue => queued_target = Some(std::mem::take(&mut route.forward_target)),
+            ForwardDown::Forward => {}
+        }
+    }
+    // a job which is only forwarded is kept in the queue directory until then
+    let queue_only = queued_target.is_some() && route.discard;
+    if queue_only {
+        route.discard = false;
+        route.output_dir = route.output_dir.join(forward_health::QUEUE_DIR_NAME);
+        // a failure is logged when the file cannot be created
+        let _ = fs::create_dir_all(&route.output_dir);
+    }
+    let (size, head, path) = if route.discard {
+        let mut target = ProgressWriter::new(
+            HeadWriter::new(
+                ForwardWriter::new(io::sink(), &route.forward_target, connection),
+                head_size,
+            ),
+            source,
+            events,
+        );
+        let bytes = receive_data(
+            &first,
+            &stream,
+            config.write_mode,
+            &mut guard,
+            &mut meter,
+            &mut target,
+        )?;
+        target.inner.inner.finish();
+        ensure_open(connection)?;
+        info!(target: STORAGE_TARGET, "[{}] Discarded {} bytes", connection, bytes);
+        (bytes, target.inner.head, None)
+    } else if let Ok((target, filepath)) = create_job_file(
+        &route.output_dir,
+        &safe_file_name(&route.file_name),
+        config.write_mode,
+    ) {
+        let target = ForwardWriter::new(target, &route.forward_target, connection);
+        let mut target = ProgressWriter::new(HeadWriter::new(target, head_size), source, events);
+        let received = receive_data(
+            &first,
+            &stream,
+            config.write_mode,
+            &mut guard,
+            &mut meter,
+            &mut target,
+        );
+        target.inner.inner.finish();
+        let ProgressWriter {
+            inner: HeadWriter { inner, head, .. },
+            ..
+        } = target;
+        // the data received before a failure is kept
+        inner.inner.finish()?;
+        let bytes = received?;
+        ensure_open(connection)?;
         if bytes > 0 {
             info!(
-                "Saved {} bytes into {}",
+                target: STORAGE_TARGET,
+                "[{}] Saved {} bytes into {}",
+                connection,
                 bytes,
                 filepath.file_name().unwrap().to_string_lossy()
             );
+            (bytes, head, Some(filepath))
         } else {
-            warn!("Ignored empty file");
+            warn!(target: STORAGE_TARGET, "[{}] Ignored empty file", connection);
             let _ = fs::remove_file(filepath);
+            return Ok(None);
+        }
+    } else {
+        return Ok(None);
+    };
+    let path = match (path, queued_target) {
+        (Some(path), Some(target)) => {
+            forward_health::enqueue(connection, target, path.clone(), queue_only);
+            (!queue_only).then_some(path)
+        }
+        (path, _) => path,
+    };
+
+    if guard.is_past_deadline() {
+        warn!(
+            target: STORAGE_TARGET,
+            "[{}] The job has taken longer than {} minutes, it is cut off",
+            connection,
+            config.limits.max_job_minutes
+        );
+    }
+
+    if size > 0 {
+        let transfer = meter.stats();
+        debug!(
+            "[{}] Received in {} ms, {} bytes/s on average, {} bytes/s at most, {} stalls",
+            connection,
+            transfer.duration_ms,
+            transfer.average_bytes_per_second,
+            transfer.peak_bytes_per_second,
+            transfer.stalls
+        );
+    }
+
+    if size > 0 && hexdump_size > 0 {
+        let dump = &head[..head.len().min(hexdump_size)];
+        debug!(
+            "[{}] First {} bytes:\r\n{}",
+            connection,
+            dump.len(),
+            hex_view::format_page(0, dump).trim_end()
+        );
+    }
+
+    Ok((size > 0).then(|| Job {
+        connection,
+        time,
+        source,
+        size,
+        format: JobFormat::detect(&head),
+        path,
+        peak_memory: 0,
+        transfer: meter.stats(),
+    }))
+}
+
+// Removes the shared secret from the first bytes of the job, either the secret
+// itself at the start of the stream, optionally followed by a line break, or an
+// "@PJL COMMENT <secret>" line in the PJL header, which a driver can add without a
+// change of the data. False if the job has neither.
+fn strip_job_secret(secret: &str, head: &mut Vec<u8>) -> bool {
+    let secret = secret.as_bytes();
+    if head
+        .get(..secret.len())
+        .is_some_and(|prefix| util::constant_time_eq(prefix, secret))
+    {
+        let rest = &head[secret.len()..];
+        let line_break = if rest.starts_with(b"\r\n") {
+            2
+        } else {
+            rest.starts_with(b"\n") as usize
+        };
+        head.drain(..secret.len() + line_break);
+        return true;
+    }
+    let mut offset = 0;
+    for line in head.split_inclusive(|&byte| byte == b'\n') {
+        // the UEL of the job before the comment is kept
+        if let Some(start) = line
+            .windows(4)
+            .position(|bytes| bytes.eq_ignore_ascii_case(b"@PJL"))
+        {
+            let command = String::from_utf8_lossy(&line[start..]);
+            let comment = command
+                .get(..12)
+                .filter(|command| command.eq_ignore_ascii_case("@PJL COMMENT"))
+                .map(|_| command[12..].trim().trim_matches('"'));
+            if comment.is_some_and(|comment| util::constant_time_eq(comment.as_bytes(), secret)) {
+                head.drain(offset + start..offset + line.len());
+                return true;
+            }
+        }
+        offset += line.len();
+    }
+    false
+}
+
+// False if the job failed the scan and was moved into the quarantine
+fn scan_job(scanner: &Scanner, job: &Job) -> bool {
+    let Some(ref path) = job.path else {
+        return true;
+    };
+    if !scanner.is_enabled() {
+        return true;
+    }
+    let Err(reason) = scanner.scan(path) else {
+        return true;
+    };
+    match scanner.quarantine(path) {
+        Ok(target) => warn!(
+            target: STORAGE_TARGET,
+            "[{}] Quarantined the job into {}: {}",
+            job.connection,
+            target.display(),
+            reason
+        ),
+        Err(e) => {
+            warn!(
+                target: STORAGE_TARGET,
+                "[{}] Cannot quarantine the job, deleting it: {}: {}",
+                job.connection,
+                reason,
+                e
+            );
+            let _ = fs::remove_file(path);
         }
     }
+    false
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_request(
+    stream: TcpStream,
+    connection: ConnectionId,
+    config: &ListenerConfig,
+    ticket: Ticket,
+    discard_flag: Arc<AtomicBool>,
+    hexdump_size: usize,
+    hooks: &ScriptHooks,
+    plugins: &PluginHost,
+    events: Arc<JobEventBus>,
+) -> io::Result<()> {
+    let meter = MemoryMeter::start();
+    let source = stream.peer_addr()?;
+
+    if !hooks.on_connection(source, config.port) {
+        info!(
+            "[{}] Connection from {} rejected by the script",
+            connection, source
+        );
+        return Ok(());
+    }
+
+    info!(
+        "[{}] Incoming connection from {} on port {}",
+        connection, source, config.port
+    );
+    OPEN_CONNECTIONS
+        .lock()
+        .unwrap()
+        .push((connection, stream.try_clone()?));
+    events.publish(JobEvent::Started { source });
+
+    let result = receive_job(
+        stream,
+        connection,
+        source,
+        config,
+        &ticket,
+        discard_flag,
+        hexdump_size,
+        hooks,
+        events.clone(),
+    );
+    OPEN_CONNECTIONS
+        .lock()
+        .unwrap()
+        .retain(|(id, _)| *id != connection);
+
+    match result {
+        Ok(Some(job)) if !scan_job(&config.scanner, &job) => {
+            events.publish(JobEvent::Aborted { source });
+        }
+        Ok(Some(mut job)) => {
+            if hooks.on_job_complete(&mut job, config.port) {
+                plugins.handle_job(&mut job);
+                job.peak_memory = meter.peak();
+                ticket.job_completed();
+                events.publish(JobEvent::Completed(job));
+            } else {
+                info!(target: STORAGE_TARGET, "[{}] Job deleted by the script", connection);
+                events.publish(JobEvent::Aborted { source });
+            }
+        }
+        Ok(None) => events.publish(JobEvent::Aborted { source }),
+        Err(e) => {
+            warn!("[{}] {}", connection, e);
+            events.publish(JobEvent::Aborted { source });
+            return Err(e);
+        }
+    }
+
     Ok(())
 }
 
-pub fn start_raw_listener(discard_flag: Arc<AtomicBool>) -> io::Result<()> {
-    let listener = TcpListener::bind((Ipv4Addr::new(0, 0, 0, 0), 9100))?;
-    info!("Started listener on port 9100");
+// The port of a listener and how the jobs received on it are stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerConfig {
+    // a local address or 0.0.0.0 for all addresses
+    pub address: IpAddr,
+    pub port: u16,
+    pub output_dir: PathBuf,
+    // file name of the jobs, see job_file_name
+    pub name_template: String,
+    // host:port the jobs are forwarded to while being received, empty to not forward
+    pub forward_target: String,
+    // the discard flag of the settings is used if None
+    pub discard: Option<bool>,
+    // the jobs received outside the schedule are handled according to off_hours
+    pub schedule: Schedule,
+    pub off_hours: OffHours,
+    // the saved jobs are scanned before the script sees them if it is enabled
+    pub scanner: Scanner,
+    // the jobs have to start with it, see strip_job_secret, empty to accept all jobs
+    pub job_secret: String,
+    // protect the listener from the clients which connect and send nothing or
+    // send very slowly, see the admission module
+    pub limits: ConnectionLimits,
+    pub write_mode: WriteMode,
+    // what happens to the jobs when the forward target is down
+    pub forward_down: ForwardDown,
+    // the state of the main listener is shown in the UI, the others are only logged
+    pub primary: bool,
+}
+
+// Connections accepted while paused are closed right away, the state changes
+// caused by pausing are published by the owner of the flag. The first hexdump_size
+// bytes of each job are logged at the debug level. The hooks of the script and the
+// plugins are called for each connection. The connections above the limits are
+// closed right away as well.
+#[allow(clippy::too_many_arguments)]
+fn run_raw_listener(
+    config: Arc<ListenerConfig>,
+    discard_flag: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    hexdump_size: Arc<AtomicU32>,
+    stopped: Arc<AtomicBool>,
+    hooks: Arc<ScriptHooks>,
+    plugins: Arc<PluginHost>,
+    events: Arc<JobEventBus>,
+) -> io::Result<()> {
+    let port = config.port;
+    let admission = Admission::new(config.limits);
+    let publish = |state| {
+        if config.primary {
+            events.publish(JobEvent::Listener(state));
+        }
+    };
+
+    let listener = match TcpListener::bind((config.address, port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            publish(ListenerState::Failed(e.to_string()));
+            return Err(e);
+        }
+    };
+    info!("Started listener on port {}", port);
+    publish(if paused.load(Ordering::SeqCst) {
+        ListenerState::Paused
+    } else {
+        ListenerState::Listening(port)
+    });
+
+    loop {
+        let (stream, source) = match listener.accept() {
+            Ok(_) if stopped.load(Ordering::SeqCst) => {
+                info!("Stopped listener on port {}", port);
+                return Ok(());
+            }
+            Ok((stream, source)) => (stream, source),
+            Err(e) => {
+                publish(ListenerState::Failed(e.to_string()));
+                return Err(e);
+            }
+        };
+
+        let connection = ConnectionId(NEXT_CONNECTION.fetch_add(1, Ordering::SeqCst));
 
-    while let Ok((stream, _)) = listener.accept() {
+        if paused.load(Ordering::SeqCst) {
+            info!(
+                "[{}] Rejected connection from {} while paused",
+                connection, source
+            );
+            continue;
+        }
+
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        if !config.schedule.is_open(now) && !config.off_hours.accepts(&config.forward_target) {
+            info!(
+                "[{}] Rejected connection from {} outside the schedule",
+                connection, source
+            );
+            continue;
+        }
+
+        if config.forward_down == ForwardDown::Refuse
+            && forward_health::is_down(&config.forward_target)
+        {
+            info!(
+                "[{}] Rejected connection from {}, the forward target {} is down",
+                connection, source, config.forward_target
+            );
+            continue;
+        }
+
+        // logged at the debug level only, there can be many of them in a flood
+        let ticket = match admission.admit(source.ip()) {
+            Ok(ticket) => ticket,
+            Err(reason) => {
+                debug!(
+                    "[{}] Rejected connection from {}: {}",
+                    connection, source, reason
+                );
+                continue;
+            }
+        };
+
+        let config = config.clone();
         let discard_flag = discard_flag.clone();
+        let hooks = hooks.clone();
+        let plugins = plugins.clone();
+        let events = events.clone();
+        let hexdump_size = if log_enabled!(Level::Debug) {
+            (hexdump_size.load(Ordering::SeqCst) as usize).min(MAX_HEXDUMP_SIZE)
+        } else {
+            0
+        };
 
         std::thread::spawn(move || {
-            let _ = handle_request(stream, discard_flag);
+            priority::pin_worker_thread();
+            let _ = handle_request(
+                stream,
+                connection,
+                &config,
+                ticket,
+                discard_flag,
+                hexdump_size,
+                &hooks,
+                &plugins,
+                events,
+            );
         });
     }
-    Ok(())
+}
+
+// A listener thread, replaced by a new one when its configuration is changed.
+// The jobs being received are finished by their own threads.
+pub struct RawListener {
+    config: Arc<ListenerConfig>,
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl RawListener {
+    pub fn start(
+        config: ListenerConfig,
+        discard_flag: Arc<AtomicBool>,
+        paused: Arc<AtomicBool>,
+        hexdump_size: Arc<AtomicU32>,
+        hooks: Arc<ScriptHooks>,
+        plugins: Arc<PluginHost>,
+        events: Arc<JobEventBus>,
+    ) -> RawListener {
+        let config = Arc::new(config);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let config = config.clone();
+            let stopped = stopped.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = run_raw_listener(
+                    config,
+                    discard_flag,
+                    paused,
+                    hexdump_size,
+                    stopped,
+                    hooks,
+                    plugins,
+                    events,
+                ) {
+                    error!("{}", e);
+                }
+            })
+        };
+
+        RawListener {
+            config,
+            stopped,
+            thread,
+        }
+    }
+
+    pub fn config(&self) -> &ListenerConfig {
+        &self.config
+    }
+
+    // False when the listener has failed
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    // The blocking accept is woken up by a connection to the listener itself.
+    // Returns when the port is closed, so a new listener can bind it. A listener
+    // whose address is gone cannot be woken up and is left to finish on its own.
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if !self.thread.is_finished() {
+            let address = if self.config.address.is_unspecified() {
+                Ipv4Addr::LOCALHOST.into()
+            } else {
+                self.config.address
+            };
+            let address = SocketAddr::new(address, self.config.port);
+            if TcpStream::connect_timeout(&address, STOP_TIMEOUT).is_err() {
+                return;
+            }
+        }
+        let _ = self.thread.join();
+    }
 }
@@ -0,0 +1,165 @@
+use std::sync::Mutex;
+
+use windows::Win32::{
+    Foundation::{COLORREF, RECT},
+    Graphics::Gdi::{
+        CreateSolidBrush, DeleteObject, DrawTextW, FillRect, SetBkMode, SetTextColor, DT_CENTER,
+        DT_SINGLELINE, DT_VCENTER, TRANSPARENT,
+    },
+    UI::Controls::{DRAWITEMSTRUCT, SBT_OWNERDRAW, SB_GETRECT, SB_SETPARTS, SB_SETTEXTW},
+};
+
+use crate::{
+    jobs::{JobEvent, ListenerState},
+    tr,
+    ui::window::{
+        MessageResult, ProgressBar, WindowError, WindowGeometry, WindowMessage, WindowRef,
+    },
+    utf16z,
+    util::rgb,
+};
+
+const STATE_PART_WIDTH: i32 = 200;
+const PROGRESS_PART_WIDTH: i32 = 160;
+
+// Shows the listener state in the first part of the status bar and the failure
+// reason in the second one, the first part is owner-drawn with the state color.
+// The last part holds a progress bar which is shown while files are resent.
+pub struct ListenerStatus {
+    status_bar: WindowRef,
+    // None until the listener thread reports its state
+    state: Mutex<Option<ListenerState>>,
+    progress: ProgressBar,
+}
+
+impl ListenerStatus {
+    pub fn new(status_bar: WindowRef) -> Result<ListenerStatus, WindowError> {
+        let progress = ProgressBar::new(status_bar.clone())?;
+        let status = ListenerStatus {
+            status_bar,
+            state: Mutex::new(None),
+            progress,
+        };
+
+        status.update_parts();
+        status.update();
+        Ok(status)
+    }
+
+    // Must be called after the status bar is resized, the width of the middle
+    // part depends on the width of the bar
+    pub fn update_parts(&self) {
+        let width = self.status_bar.client_size().0;
+        let parts = [
+            STATE_PART_WIDTH,
+            (width - PROGRESS_PART_WIDTH).max(STATE_PART_WIDTH),
+            -1,
+        ];
+        self.send(SB_SETPARTS, parts.len(), parts.as_ptr() as _);
+
+        let mut rect = RECT::default();
+        self.send(SB_GETRECT, parts.len() - 1, &mut rect as *mut RECT as _);
+        self.progress.window().move_window(WindowGeometry {
+            x: Some(rect.left + 2),
+            y: Some(rect.top + 2),
+            width: Some((rect.right - rect.left - 4).max(0)),
+            height: Some((rect.bottom - rect.top - 4).max(0)),
+        });
+    }
+
+    // Shows the progress in percent, None hides the progress bar
+    pub fn set_progress(&self, percent: Option<u32>) {
+        match percent {
+            Some(percent) => {
+                self.progress.set_position(percent);
+                self.progress.window().set_visible(true);
+            }
+            None => self.progress.window().set_visible(false),
+        }
+    }
+
+    // None until the listener thread reports its state
+    pub fn state(&self) -> Option<ListenerState> {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn status_bar(&self) -> &WindowRef {
+        &self.status_bar
+    }
+
+    fn send(&self, msg: u32, wparam: usize, lparam: isize) -> isize {
+        match self.status_bar.send_message(WindowMessage::new(
+            self.status_bar.clone(),
+            msg,
+            wparam,
+            lparam,
+        )) {
+            MessageResult::Value(value) => value,
+            _ => 0,
+        }
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        let JobEvent::Listener(new_state) = event else {
+            return;
+        };
+
+        {
+            let mut state = self.state.lock().unwrap();
+            // pausing or resuming does not bring a failed listener back, listening
+            // again after a rebind or a restart does
+            if matches!(*state, Some(ListenerState::Failed(_)))
+                && !matches!(new_state, ListenerState::Listening(_))
+            {
+                return;
+            }
+            *state = Some(new_state.clone());
+        }
+        self.update();
+    }
+
+    fn update(&self) {
+        let details = match *self.state.lock().unwrap() {
+            Some(ListenerState::Failed(ref e)) => e.clone(),
+            _ => String::new(),
+        };
+
+        // setting the owner-drawn part repaints it with the current state
+        self.send(SB_SETTEXTW, SBT_OWNERDRAW as _, 0);
+        let text = utf16z!(details);
+        self.send(SB_SETTEXTW, 1, text.as_ptr() as _);
+    }
+
+    fn label(&self) -> (String, COLORREF) {
+        match *self.state.lock().unwrap() {
+            None => (tr!("Starting listener...").to_owned(), rgb(128, 128, 128)),
+            Some(ListenerState::Listening(port)) => {
+                (tr!("Listening on port {}", port), rgb(0, 128, 0))
+            }
+            Some(ListenerState::Paused) => (tr!("Listener paused").to_owned(), rgb(176, 128, 0)),
+            Some(ListenerState::Failed(_)) => (tr!("Listener failed").to_owned(), rgb(200, 0, 0)),
+        }
+    }
+
+    // Called by the parent window on WM_DRAWITEM sent by the status bar
+    pub fn draw(&self, item: &DRAWITEMSTRUCT) {
+        let (text, color) = self.label();
+        let mut text = text.encode_utf16().collect::<Vec<_>>();
+        let mut rect = item.rcItem;
+
+        unsafe {
+            let brush = CreateSolidBrush(color);
+            FillRect(item.hDC, &rect, brush);
+            let _ = DeleteObject(brush.into());
+
+            SetBkMode(item.hDC, TRANSPARENT);
+            SetTextColor(item.hDC, rgb(255, 255, 255));
+            DrawTextW(
+                item.hDC,
+                &mut text,
+                &mut rect,
+                DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use log::LevelFilter;
+
+const CRATE_NAME: &str = "miniraw";
+
+// Log levels per module in the RUST_LOG syntax, e.g. "warn,listener=debug,ui=trace".
+// A bare level sets the default one, module paths may omit the crate name.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn new(default: LevelFilter) -> LogFilter {
+        LogFilter {
+            default,
+            modules: Vec::new(),
+        }
+    }
+
+    // Adds the directives of the spec, invalid ones are ignored. A later directive
+    // for the same module replaces the earlier one.
+    pub fn apply(&mut self, spec: &str) {
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let Ok(level) = level.trim().parse() else {
+                        continue;
+                    };
+                    let module = module.trim();
+                    let module =
+                        if module == CRATE_NAME || module.starts_with(&format!("{CRATE_NAME}::")) {
+                            module.to_owned()
+                        } else {
+                            format!("{CRATE_NAME}::{module}")
+                        };
+                    self.modules.retain(|(m, _)| *m != module);
+                    self.modules.push((module, level));
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        self.default = level;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set_default(&mut self, level: LevelFilter) {
+        self.default = level;
+    }
+
+    // The most verbose level of all modules, records above it are not even created
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+
+    // The level of the longest module path which the target belongs to
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
@@ -1,48 +1,610 @@
-use log::{LevelFilter, Metadata, Record};
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use log::{Level, LevelFilter, Metadata, Record};
 use time::OffsetDateTime;
+use windows::Win32::{
+    Foundation::COLORREF,
+    Graphics::Gdi::InvalidateRect,
+    UI::{
+        Controls::{
+            RichEdit::{
+                CFE_AUTOCOLOR, CFE_EFFECTS, CFM_COLOR, CHARFORMATW, EM_EXLIMITTEXT,
+                EM_SETCHARFORMAT, SCF_SELECTION,
+            },
+            EM_GETFIRSTVISIBLELINE, EM_GETLINECOUNT, EM_LINEINDEX, EM_LINESCROLL, EM_REPLACESEL,
+            EM_SETSEL,
+        },
+        WindowsAndMessaging::{SB_BOTTOM, WM_SETREDRAW, WM_VSCROLL},
+    },
+};
+
+use crate::{
+    log_filter::LogFilter,
+    ui::window::{user_message, MessageResult, WindowError, WindowMessage, WindowRef},
+    utf16z,
+    util::rgb,
+};
+
+fn level_color(level: Level) -> Option<COLORREF> {
+    match level {
+        Level::Error => Some(rgb(200, 0, 0)),
+        Level::Warn => Some(rgb(176, 128, 0)),
+        Level::Info => None,
+        Level::Debug | Level::Trace => Some(rgb(128, 128, 128)),
+    }
+}
+
+// Records which are not yet formatted, the new ones are dropped when the queue is full
+const QUEUE_SIZE: usize = 4096;
+
+// Records kept in memory when the number of log lines is not limited
+const MAX_RECORDS: usize = 100_000;
+
+// The number of repeats of a message is written when a different message arrives
+// or when no message arrives for this long
+const REPEAT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Sent to the log window to show the records added to the buffer
+const WM_LOG_FLUSH: u32 = user_message(7);
+
+// Log target of the messages about the job files, their module is the listener
+pub const STORAGE_TARGET: &str = "miniraw::storage";
+
+// Part of the application a record comes from, the log view can hide each one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Listener,
+    Storage,
+    Ui,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 3] = [Subsystem::Listener, Subsystem::Storage, Subsystem::Ui];
+
+    fn of(target: &str) -> Subsystem {
+        let in_module = |module: &str| {
+            target
+                .strip_prefix(module)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        };
+
+        if in_module(STORAGE_TARGET) {
+            Subsystem::Storage
+        } else if in_module("miniraw::listener") || in_module("miniraw::resend") {
+            Subsystem::Listener
+        } else {
+            Subsystem::Ui
+        }
+    }
+}
+
+// Records shown in the log window, the hidden ones are kept in the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewFilter {
+    pub level: LevelFilter,
+    pub hidden: [bool; Subsystem::ALL.len()],
+}
+
+impl Default for ViewFilter {
+    fn default() -> Self {
+        ViewFilter {
+            level: LevelFilter::Trace,
+            hidden: [false; Subsystem::ALL.len()],
+        }
+    }
+}
 
-use crate::ui::window::WindowRef;
+impl ViewFilter {
+    pub fn is_hidden(&self, subsystem: Subsystem) -> bool {
+        self.hidden[subsystem as usize]
+    }
+
+    pub fn set_hidden(&mut self, subsystem: Subsystem, hidden: bool) {
+        self.hidden[subsystem as usize] = hidden;
+    }
+
+    fn matches(&self, record: &LogRecord) -> bool {
+        record.level <= self.level && !self.is_hidden(record.subsystem)
+    }
+}
 
-pub struct WindowLogger(WindowRef);
+struct LogEntry {
+    level: Level,
+    subsystem: Subsystem,
+    time: OffsetDateTime,
+    text: String,
+}
+
+// The words of the message with the ones containing digits, such as the connection
+// ids, the addresses and the sizes, replaced, so the messages of a flood from many
+// peers have the same key
+fn message_key(text: &str) -> impl Iterator<Item = &str> {
+    text.split_whitespace().map(|word| {
+        if word.bytes().any(|b| b.is_ascii_digit()) {
+            "#"
+        } else {
+            word
+        }
+    })
+}
+
+impl LogEntry {
+    fn is_repeated_by(&self, other: &LogEntry) -> bool {
+        self.level == other.level
+            && self.subsystem == other.subsystem
+            && message_key(&self.text).eq(message_key(&other.text))
+    }
+}
+
+#[derive(Clone)]
+struct LogRecord {
+    level: Level,
+    subsystem: Subsystem,
+    line: String,
+}
+
+// Ring buffer of the formatted records, the window is rendered from it
+#[derive(Default)]
+struct LogBuffer {
+    records: VecDeque<LogRecord>,
+    // number of records at the end which are not yet written to the window
+    pending: usize,
+    // number of records added since the start, including the removed ones
+    total: u64,
+}
+
+// The records are formatted on a dedicated thread, so the threads which log never
+// wait for the UI thread. All formatted records are kept in the buffer and the
+// window shows the ones which pass the view filter, the window is only changed
+// on the UI thread.
+pub struct WindowLogger {
+    window: WindowRef,
+    sender: SyncSender<LogEntry>,
+    dropped: AtomicU64,
+    filter: RwLock<LogFilter>,
+    view_filter: Mutex<ViewFilter>,
+    buffer: Mutex<LogBuffer>,
+    auto_scroll: Arc<AtomicBool>,
+    // the oldest lines are removed when the log grows over this count
+    max_lines: Option<usize>,
+    // the pending records are written when the output is resumed
+    paused: AtomicBool,
+}
 
 impl WindowLogger {
-    pub fn init(win: WindowRef, level: LevelFilter) {
-        let _ = log::set_boxed_logger(Box::new(WindowLogger(win)));
-        log::set_max_level(level);
+    // The window must be a rich edit control
+    pub fn init(
+        win: WindowRef,
+        filter: LogFilter,
+        auto_scroll: Arc<AtomicBool>,
+        max_lines: Option<usize>,
+    ) -> Result<&'static WindowLogger, WindowError> {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+        let logger: &'static WindowLogger = Box::leak(Box::new(WindowLogger {
+            window: win,
+            sender,
+            dropped: AtomicU64::new(0),
+            filter: RwLock::new(filter.clone()),
+            view_filter: Default::default(),
+            buffer: Default::default(),
+            auto_scroll,
+            max_lines,
+            paused: AtomicBool::new(false),
+        }));
+
+        // the default limit of 32K characters is too low for a log
+        logger.send(EM_EXLIMITTEXT, 0, i32::MAX as _);
+
+        logger.window.subclass(|message| {
+            if message.msg == WM_LOG_FLUSH {
+                logger.flush_pending();
+                MessageResult::Processed
+            } else {
+                MessageResult::Ignored
+            }
+        })?;
+
+        std::thread::spawn(move || logger.write_entries(receiver));
+
+        let _ = log::set_logger(logger);
+        log::set_max_level(filter.max_level());
+        Ok(logger)
+    }
+
+    // Changes the level of the modules which are not configured separately
+    pub fn set_level(&self, level: LevelFilter) {
+        let mut filter = self.filter.write().unwrap();
+        filter.set_default(level);
+        log::set_max_level(filter.max_level());
+    }
+
+    pub fn set_filter(&self, filter: LogFilter) {
+        log::set_max_level(filter.max_level());
+        *self.filter.write().unwrap() = filter;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    // The records are kept in the buffer while paused and written on resume.
+    // Must be called on the UI thread.
+    pub fn set_paused(&self, flag: bool) {
+        self.paused.store(flag, Ordering::SeqCst);
+        if !flag {
+            self.flush_pending();
+        }
+    }
+
+    pub fn view_filter(&self) -> ViewFilter {
+        *self.view_filter.lock().unwrap()
+    }
+
+    // Fills the window again with the buffered records which pass the filter.
+    // Must be called on the UI thread.
+    pub fn set_view_filter(&self, view_filter: ViewFilter) {
+        *self.view_filter.lock().unwrap() = view_filter;
+
+        let records = {
+            let buffer = self.buffer.lock().unwrap();
+            let shown = buffer.records.len() - buffer.pending;
+            buffer
+                .records
+                .iter()
+                .take(shown)
+                .filter(|record| view_filter.matches(record))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        self.send(WM_SETREDRAW, 0, 0);
+        let _ = self.window.set_text("");
+        for record in records {
+            self.append(record.level, &record.line);
+        }
+        self.send(WM_SETREDRAW, 1, 0);
+        unsafe {
+            let _ = InvalidateRect(Some(self.window.handle()), None, true);
+        }
+    }
+
+    // All buffered records regardless of the view filter
+    pub fn text(&self) -> String {
+        let buffer = self.buffer.lock().unwrap();
+        buffer
+            .records
+            .iter()
+            .map(|record| record.line.as_str())
+            .collect()
+    }
+
+    // Like text, but None instead of waiting for the buffer, used for the crash reports
+    pub fn try_text(&self) -> Option<String> {
+        let buffer = self.buffer.try_lock().ok()?;
+        Some(
+            buffer
+                .records
+                .iter()
+                .map(|record| record.line.as_str())
+                .collect(),
+        )
+    }
+
+    // The records which are shown in the window
+    pub fn shown_text(&self) -> String {
+        let view_filter = self.view_filter();
+        let buffer = self.buffer.lock().unwrap();
+        let shown = buffer.records.len() - buffer.pending;
+        buffer
+            .records
+            .iter()
+            .take(shown)
+            .filter(|record| view_filter.matches(record))
+            .map(|record| record.line.as_str())
+            .collect()
+    }
+
+    // The lines added after the first `since` records and the total number of records,
+    // which is passed as `since` to get the next lines
+    pub fn tail(&self, since: u64) -> (Vec<String>, u64) {
+        let buffer = self.buffer.lock().unwrap();
+        let first = buffer.total - buffer.records.len() as u64;
+        let lines = buffer
+            .records
+            .iter()
+            .skip(since.saturating_sub(first) as usize)
+            .map(|record| record.line.trim_end().to_owned())
+            .collect();
+        (lines, buffer.total)
+    }
+
+    // Removes the records from the buffer and the window. Must be called on the UI thread.
+    pub fn clear(&self) {
+        {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.records.clear();
+            buffer.pending = 0;
+        }
+        let _ = self.window.set_text("");
+    }
+
+    // Runs until the sender is gone, which never happens for the global logger.
+    // The records received together are written to the window at once. Consecutive
+    // records which differ only in numbers are written once followed by the number
+    // of repeats.
+    fn write_entries(&self, receiver: Receiver<LogEntry>) {
+        let mut last: Option<LogEntry> = None;
+        let mut repeated = 0;
+
+        loop {
+            let entry = if repeated > 0 {
+                receiver.recv_timeout(REPEAT_TIMEOUT)
+            } else {
+                receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            };
+
+            let entries = match entry {
+                Ok(entry) => [entry]
+                    .into_iter()
+                    .chain(receiver.try_iter())
+                    .collect::<Vec<_>>(),
+                // nothing arrived after the repeats
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(ref last) = last {
+                        self.push_repeated(last, mem::take(&mut repeated));
+                    }
+                    Vec::new()
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            for entry in entries {
+                if let Some(last) = last.as_mut().filter(|last| last.is_repeated_by(&entry)) {
+                    last.time = entry.time;
+                    repeated += 1;
+                    continue;
+                }
+                if let Some(ref last) = last {
+                    self.push_repeated(last, mem::take(&mut repeated));
+                }
+                self.push(&entry);
+                last = Some(entry);
+            }
+
+            let dropped = self.dropped.swap(0, Ordering::SeqCst);
+            if dropped > 0 {
+                self.push(&LogEntry {
+                    level: Level::Warn,
+                    subsystem: Subsystem::Ui,
+                    time: OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc()),
+                    text: format!("{} log messages were dropped", dropped),
+                });
+            }
+
+            self.send(WM_LOG_FLUSH, 0, 0);
+        }
+    }
+
+    fn push_repeated(&self, last: &LogEntry, repeated: u64) {
+        if repeated > 0 {
+            self.push(&LogEntry {
+                level: last.level,
+                subsystem: last.subsystem,
+                time: last.time,
+                text: format!("Last message repeated {} times", repeated),
+            });
+        }
+    }
+
+    // Adds the record to the buffer, the oldest records above the capacity are removed
+    fn push(&self, entry: &LogEntry) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.records.push_back(LogRecord {
+            level: entry.level,
+            subsystem: entry.subsystem,
+            line: Self::format(entry),
+        });
+        buffer.pending += 1;
+        buffer.total += 1;
+
+        let capacity = self.max_lines.unwrap_or(MAX_RECORDS);
+        while buffer.records.len() > capacity {
+            buffer.records.pop_front();
+        }
+        buffer.pending = buffer.pending.min(buffer.records.len());
+    }
+
+    // Writes the pending records which pass the view filter, called on the UI thread
+    fn flush_pending(&self) {
+        if self.is_paused() {
+            return;
+        }
+
+        let view_filter = self.view_filter();
+        let records = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let shown = buffer.records.len() - buffer.pending;
+            buffer.pending = 0;
+            buffer
+                .records
+                .iter()
+                .skip(shown)
+                .filter(|record| view_filter.matches(record))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        for record in records {
+            self.append(record.level, &record.line);
+        }
+    }
+
+    fn format(entry: &LogEntry) -> String {
+        let time = entry.time;
+        let (hour, minute, second, nano) = time.to_hms_nano();
+
+        format!(
+            "[{}] {}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} {}\r\n",
+            entry.level,
+            time.year(),
+            time.month() as u8 + 1,
+            time.day(),
+            hour,
+            minute,
+            second,
+            nano / 1_000_000,
+            entry.text
+        )
     }
 
     fn is_our_path(&self, path: &Option<&str>) -> bool {
         path.iter().any(|p| p.starts_with("miniraw"))
     }
+
+    fn send(&self, msg: u32, wparam: usize, lparam: isize) -> isize {
+        match self
+            .window
+            .send_message(WindowMessage::new(self.window.clone(), msg, wparam, lparam))
+        {
+            MessageResult::Value(value) => value,
+            _ => 0,
+        }
+    }
+
+    // Removes the lines above the limit from the top of the control and returns the
+    // number of removed lines and characters. The log is trimmed once it exceeds the
+    // limit by a tenth so that the removal does not happen on every append.
+    fn trim(&self) -> (isize, usize) {
+        let Some(max_lines) = self.max_lines else {
+            return (0, 0);
+        };
+
+        // the text ends with a line break followed by an empty line
+        let lines = (self.send(EM_GETLINECOUNT, 0, 0) - 1).max(0) as usize;
+        if lines <= max_lines + max_lines / 10 {
+            return (0, 0);
+        }
+
+        let excess = lines - max_lines;
+        let chars = self.send(EM_LINEINDEX, excess, 0).max(0) as usize;
+        let empty = [0u16];
+        self.send(EM_SETSEL, 0, chars as _);
+        self.send(EM_REPLACESEL, 0, empty.as_ptr() as _);
+        (excess as isize, chars)
+    }
+
+    // Appends the line at the end of the control with the level color, then either
+    // follows the output or restores the selection and the first visible line
+    fn append(&self, level: Level, line: &str) {
+        let (start, end) = self.window.selection();
+        let first_line = self.send(EM_GETFIRSTVISIBLELINE, 0, 0);
+
+        let color = level_color(level);
+        let format = CHARFORMATW {
+            cbSize: mem::size_of::<CHARFORMATW>() as _,
+            dwMask: CFM_COLOR,
+            dwEffects: if color.is_some() {
+                CFE_EFFECTS(0)
+            } else {
+                CFE_AUTOCOLOR
+            },
+            crTextColor: color.unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let text = utf16z!(line);
+        self.send(EM_SETSEL, usize::MAX, -1);
+        self.send(
+            EM_SETCHARFORMAT,
+            SCF_SELECTION as _,
+            &format as *const CHARFORMATW as _,
+        );
+        self.send(EM_REPLACESEL, 0, text.as_ptr() as _);
+
+        let (removed_lines, removed_chars) = self.trim();
+
+        if self.auto_scroll.load(Ordering::SeqCst) {
+            self.send(WM_VSCROLL, SB_BOTTOM.0 as _, 0);
+        } else {
+            // the selection and the position follow the text which was not removed
+            self.send(
+                EM_SETSEL,
+                start.saturating_sub(removed_chars),
+                end.saturating_sub(removed_chars) as _,
+            );
+            let current_line = self.send(EM_GETFIRSTVISIBLELINE, 0, 0);
+            self.send(
+                EM_LINESCROLL,
+                0,
+                (first_line - removed_lines).max(0) - current_line,
+            );
+        }
+    }
 }
 
 impl log::Log for WindowLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= log::max_level()
+        metadata.level() <= self.filter.read().unwrap().level(metadata.target())
     }
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) && self.is_our_path(&record.module_path()) {
-            let old_text = self.0.get_text().unwrap_or_default();
-            let time = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
-            let (hour, minute, second, nano) = time.to_hms_nano();
-
-            let msg = format!(
-                "{}[{}] {}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} {}\r\n",
-                old_text,
-                record.level(),
-                time.year(),
-                time.month() as u8 + 1,
-                time.day(),
-                hour,
-                minute,
-                second,
-                nano / 1_000_000,
-                record.args()
-            );
-            let _ = self.0.set_text(&msg);
+            let entry = LogEntry {
+                level: record.level(),
+                subsystem: Subsystem::of(record.target()),
+                time: OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc()),
+                text: record.args().to_string(),
+            };
+            if let Err(TrySendError::Full(_)) = self.sender.try_send(entry) {
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+            }
         }
     }
 
     fn flush(&self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(text: &str) -> LogEntry {
+        LogEntry {
+            level: Level::Info,
+            subsystem: Subsystem::Listener,
+            time: OffsetDateTime::UNIX_EPOCH,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn repeated_by_other_connection() {
+        let first = entry("[17] Rejected connection from 192.168.1.20:50123 while paused");
+        assert!(first.is_repeated_by(&entry(
+            "[18] Rejected connection from 192.168.1.21:50124 while paused"
+        )));
+        assert!(first.is_repeated_by(&entry(
+            "[1024] Rejected connection from [fe80::1]:61000 while paused"
+        )));
+        assert!(entry("[3] Ignored empty file").is_repeated_by(&entry("[4] Ignored empty file")));
+    }
+
+    #[test]
+    fn not_repeated_by_other_message() {
+        let first = entry("[17] Rejected connection from 192.168.1.20:50123 while paused");
+        assert!(!first.is_repeated_by(&entry("[18] Ignored empty file")));
+        assert!(!first.is_repeated_by(&LogEntry {
+            level: Level::Warn,
+            ..entry("[18] Rejected connection from 192.168.1.21:50124 while paused")
+        }));
+    }
+}
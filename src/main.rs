@@ -1,54 +1,411 @@
 #![windows_subsystem = "windows"]
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use log::{error, info, LevelFilter};
+use log::{error, info, warn, LevelFilter};
 use windows::{
     core::PCWSTR,
     Win32::{
-        System::Registry::{
-            RegCloseKey, RegCreateKeyW, RegOpenKeyW, RegQueryValueExW, RegSetKeyValueW, HKEY,
-            HKEY_CURRENT_USER, REG_DWORD,
+        Foundation::{E_ACCESSDENIED, POINT},
+        System::Com::{CoInitializeEx, COINIT_APARTMENTTHREADED},
+        UI::{
+            Controls::{
+                DRAWITEMSTRUCT, LVS_OWNERDATA, LVS_REPORT, LVS_SHOWSELALWAYS, LVS_SINGLESEL,
+                SBARS_SIZEGRIP, TCN_SELCHANGE,
+            },
+            Input::KeyboardAndMouse::{
+                VK_ADD, VK_F3, VK_F6, VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PLUS, VK_SUBTRACT, VK_TAB,
+            },
+            WindowsAndMessaging::*,
         },
-        UI::WindowsAndMessaging::*,
     },
 };
 
-use crate::ui::{
-    window::{
-        Font, MessageResult, WindowBuilder, WindowError, WindowGeometry, WindowMessage,
-        WindowMessageHandler, WindowRef,
+use crate::{
+    accounting::Accountant,
+    api::{ApiContext, ApiServer, JobHistory, WM_API_JOB_DELETED, WM_API_PAUSE_LISTENER},
+    audit::AuditLog,
+    cli::Arguments,
+    control::{start_control_server, ControlContext, WM_CONTROL_ROTATE_LOG},
+    diagnostics::Diagnostics,
+    email::EmailNotifier,
+    find::TextFinder,
+    grpc::{GrpcServer, JobWatchers},
+    hex_view::HexViewer,
+    i18n::Language,
+    job_index::{JobIndex, INDEX_FILE_NAME},
+    job_list::JobList,
+    jobs::{ActiveTransfers, ConnectionId, Job, JobEvent, JobEventBus, ListenerState},
+    jump_list::{JumpTask, WM_UPDATE_JUMP_LIST},
+    listener::{close_connections, RawListener},
+    listener_status::ListenerStatus,
+    log_filter::LogFilter,
+    logger::{Subsystem, WindowLogger},
+    memory::MeteredAllocator,
+    mqtt::MqttPublisher,
+    network::{is_local_address, NetworkMonitor, WM_NETWORK_CHANGED},
+    plugins::PluginHost,
+    preview::PreviewWindow,
+    resend::WM_RESEND_PROGRESS,
+    script::ScriptHooks,
+    settings::{AppSettings, DEFAULT_LOG_FONT_HEIGHT},
+    settings_window::SettingsWindow,
+    splitter::{Splitter, WM_SPLITTER_MOVED},
+    spooler::{LocalPrinter, SpoolerCommand},
+    statistics::StatisticsPage,
+    statistics_history::{StatisticsHistory, HISTORY_FILE_NAME},
+    taskbar::{Overlay, TaskbarOverlay, TaskbarProgress, WM_TASKBAR_OVERLAY, WM_TASKBAR_PROGRESS},
+    telemetry::TelemetryExporter,
+    toml::Document,
+    tray_status::TrayStatus,
+    ui::{
+        layout::{Dock, Layout, Margins},
+        list_view::ListViewEvent,
+        win32::{post_to_instance, TrayEvent, TrayIcon},
+        window::{
+            user_message, Menu, MessageResult, TabControl, WindowBuilder, WindowError, WindowEvent,
+            WindowGeometry, WindowHandle, WindowMessage, WindowMessageHandler, WindowRef,
+        },
+        Accelerator, MessageLoop,
     },
-    MessageLoop,
+    update::{UpdateCheck, UpdateChecker, WM_UPDATE_CHECKED},
+    webhook::WebhookNotifier,
 };
 
+pub mod accounting;
+pub mod admission;
+pub mod api;
+pub mod audit;
+pub mod benchmark;
+pub mod cli;
+mod control;
+mod crash;
+pub mod diagnostics;
+pub mod email;
+pub mod extract;
+pub mod find;
+mod firewall;
+pub mod forward_health;
+pub mod generator;
+pub mod grpc;
+pub mod hex_view;
+pub mod http;
+pub mod http2;
+pub mod http_client;
+pub mod i18n;
+pub mod iocp;
+pub mod job_index;
+pub mod job_list;
+pub mod jobs;
+pub mod jump_list;
 pub mod listener;
+pub mod listener_status;
+pub mod log_filter;
 pub mod logger;
+pub mod mapped_file;
+pub mod memory;
+pub mod mqtt;
+mod network;
+pub mod plugins;
+pub mod preview;
+pub mod priority;
+pub mod redact;
+pub mod resend;
+pub mod scanner;
+pub mod schedule;
+pub mod script;
+pub mod settings;
+pub mod settings_window;
+pub mod shell;
+pub mod splitter;
+mod spooler;
+pub mod statistics;
+pub mod statistics_history;
+pub mod taskbar;
+pub mod telemetry;
+pub mod throughput;
+pub mod toml;
+pub mod tray_status;
 pub mod ui;
-pub mod util;
+pub mod update;
+mod util;
+pub mod webhook;
+pub mod zip;
+
+// the jobs report the memory their connections needed
+#[global_allocator]
+static ALLOCATOR: MeteredAllocator = MeteredAllocator;
 
 const IDI_MAINICON: u32 = 1000;
 const IDM_DISCARD_FILES: u32 = 1001;
-const REG_KEY_NAME: &str = "Software\\MiniRAW NG";
-const REG_VALUE_NAME: &str = "discard";
+const IDM_PREVIEW_JOB: u32 = 1002;
+const IDM_HEX_VIEW: u32 = 1003;
+const IDM_EXIT: u32 = 1004;
+const IDM_ABOUT: u32 = 1005;
+const IDM_FONT: u32 = 1006;
+const IDM_COPY_LOG: u32 = 1007;
+const IDM_FIND: u32 = 1008;
+const IDM_FIND_NEXT: u32 = 1009;
+const IDM_AUTO_SCROLL: u32 = 1010;
+const IDM_PAUSE_LOG: u32 = 1011;
+const IDM_CLEAR_LOG: u32 = 1012;
+const IDM_SAVE_LOG: u32 = 1013;
+const IDM_SETTINGS: u32 = 1014;
+const IDM_NOTIFY_JOBS: u32 = 1015;
+const IDM_FLASH_JOBS: u32 = 1016;
+const IDM_OPEN_FOLDER: u32 = 1017;
+const IDM_START_MINIMIZED: u32 = 1018;
+const IDM_RUN_AT_STARTUP: u32 = 1019;
+// one command per language starting from this one
+const IDM_LANGUAGE: u32 = 1020;
+const IDM_PAUSE_LISTENER: u32 = 1023;
+const IDM_SAVE_LOG_AS: u32 = 1024;
+const IDM_NEXT_PAGE: u32 = 1025;
+const IDM_NEXT_PANE: u32 = 1026;
+const IDM_ZOOM_IN: u32 = 1027;
+const IDM_ZOOM_OUT: u32 = 1028;
+const IDM_ZOOM_RESET: u32 = 1029;
+// one command per log view level and subsystem starting from these ones
+const IDM_LOG_VIEW_LEVEL: u32 = 1030;
+const IDM_LOG_VIEW_SUBSYSTEM: u32 = 1035;
+const IDM_COLLECT_DIAGNOSTICS: u32 = 1038;
+const IDM_EXPORT_SETTINGS: u32 = 1039;
+const IDM_IMPORT_SETTINGS: u32 = 1040;
+const IDM_RESET_SETTINGS: u32 = 1041;
+const IDM_SAVE_PRESET: u32 = 1042;
+const IDM_CHECK_UPDATES: u32 = 1043;
+const IDM_FIREWALL_RULES: u32 = 1044;
+const IDM_RELEASE_PRINTERS: u32 = 1045;
+const IDM_VIRTUAL_PRINTER: u32 = 1046;
+const IDM_SELF_TEST: u32 = 1047;
+// one item per preset, up to MAX_PRESETS
+const IDM_PRESET: u32 = 1100;
+const MAX_PRESETS: usize = 100;
+// one item per local printer, up to MAX_PRINTERS
+const IDM_CAPTURE_PRINTER: u32 = 1200;
+const MAX_PRINTERS: usize = 100;
+
+const LOG_VIEW_LEVELS: [(LevelFilter, &str); 5] = [
+    (LevelFilter::Error, "&Errors"),
+    (LevelFilter::Warn, "&Warnings"),
+    (LevelFilter::Info, "&Information"),
+    (LevelFilter::Debug, "&Debug"),
+    (LevelFilter::Trace, "&Trace"),
+];
+
+const LOG_VIEW_SUBSYSTEMS: [(Subsystem, &str); 3] = [
+    (Subsystem::Listener, "&Listener"),
+    (Subsystem::Storage, "&Storage"),
+    (Subsystem::Ui, "&User interface"),
+];
+
+// Step and limits of the log font height in pixels
+const ZOOM_STEP: i32 = 2;
+const MIN_LOG_FONT_HEIGHT: u32 = 8;
+const MAX_LOG_FONT_HEIGHT: u32 = 72;
+
+// Callback message of the tray icon, lparam holds the mouse or notification event
+const WM_TRAY_ICON: u32 = user_message(2);
+const TRAY_ICON_ID: u32 = 1;
+
+// Posted when settings were changed by a reload or an import
+const WM_SETTINGS_CHANGED: u32 = user_message(8);
+// Posted on start to offer the missing firewall rules once the window is shown
+const WM_CHECK_FIREWALL: u32 = user_message(13);
+// The configuration file is checked for changes made by other programs
+const SETTINGS_TIMER_ID: usize = 1;
+const SETTINGS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// The automatic update check is done when its interval has passed
+const UPDATE_TIMER_ID: usize = 2;
+const UPDATE_TIMER_INTERVAL: Duration = Duration::from_secs(3600);
+// The listeners are bound again once the network changes have settled
+const NETWORK_TIMER_ID: usize = 3;
+const NETWORK_SETTLE_DELAY: Duration = Duration::from_secs(2);
+// The statistics history is saved when it has changed, and on exit
+const STATISTICS_TIMER_ID: usize = 4;
+const STATISTICS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+const PAGE_LOG: usize = 0;
+const PAGE_JOBS: usize = 1;
+const PAGE_STATISTICS: usize = 2;
+const PAGE_COUNT: usize = 3;
+
+const SPLITTER_SIZE: i32 = 6;
+
+const WINDOW_CLASS: &str = "miniraw";
+
+// Command line arguments of the jump list tasks, they are passed to the running
+// instance as the menu commands
+const COMMAND_ARGUMENTS: &[(&str, u32)] = &[
+    ("--open-folder", IDM_OPEN_FOLDER),
+    ("--pause-listener", IDM_PAUSE_LISTENER),
+];
+
+const ACCELERATORS: &[Accelerator] = &[
+    Accelerator {
+        key: b'F' as u16,
+        ctrl: true,
+        command: IDM_FIND,
+    },
+    Accelerator {
+        key: VK_F3.0,
+        ctrl: false,
+        command: IDM_FIND_NEXT,
+    },
+    Accelerator {
+        key: b'L' as u16,
+        ctrl: true,
+        command: IDM_CLEAR_LOG,
+    },
+    Accelerator {
+        key: b'S' as u16,
+        ctrl: true,
+        command: IDM_SAVE_LOG,
+    },
+    Accelerator {
+        key: b'O' as u16,
+        ctrl: true,
+        command: IDM_OPEN_FOLDER,
+    },
+    Accelerator {
+        key: VK_OEM_COMMA.0,
+        ctrl: true,
+        command: IDM_SETTINGS,
+    },
+    Accelerator {
+        key: VK_TAB.0,
+        ctrl: true,
+        command: IDM_NEXT_PAGE,
+    },
+    Accelerator {
+        key: VK_F6.0,
+        ctrl: false,
+        command: IDM_NEXT_PANE,
+    },
+    Accelerator {
+        key: VK_OEM_PLUS.0,
+        ctrl: true,
+        command: IDM_ZOOM_IN,
+    },
+    Accelerator {
+        key: VK_ADD.0,
+        ctrl: true,
+        command: IDM_ZOOM_IN,
+    },
+    Accelerator {
+        key: VK_OEM_MINUS.0,
+        ctrl: true,
+        command: IDM_ZOOM_OUT,
+    },
+    Accelerator {
+        key: VK_SUBTRACT.0,
+        ctrl: true,
+        command: IDM_ZOOM_OUT,
+    },
+    Accelerator {
+        key: b'0' as u16,
+        ctrl: true,
+        command: IDM_ZOOM_RESET,
+    },
+];
 
 struct MainWindow {
-    discard_flag: Arc<AtomicBool>,
+    settings: Arc<AppSettings>,
+    job_events: Arc<JobEventBus>,
+    job_list: OnceLock<Arc<JobList>>,
+    finder: OnceLock<Arc<TextFinder>>,
+    logger: OnceLock<&'static WindowLogger>,
+    settings_window: OnceLock<Arc<SettingsWindow>>,
+    notified_job: Arc<Mutex<Option<PathBuf>>>,
+    listener_status: OnceLock<Arc<ListenerStatus>>,
+    // not persisted, the listener always starts accepting connections
+    listener_paused: Arc<AtomicBool>,
+    tabs: OnceLock<TabControl>,
+    statistics: OnceLock<Arc<StatisticsPage>>,
+    splitter: OnceLock<WindowRef>,
+    tray_icon: OnceLock<Arc<TrayIcon>>,
+    tray_status: OnceLock<Arc<TrayStatus>>,
+    update_checker: OnceLock<Arc<UpdateChecker>>,
+    network_monitor: OnceLock<NetworkMonitor>,
+    script_hooks: Arc<ScriptHooks>,
+    plugins: Arc<PluginHost>,
+    transfers: Arc<ActiveTransfers>,
+    // the window is closed when the last active transfer is finished
+    exit_pending: Arc<AtomicBool>,
+    listeners: Mutex<Vec<RawListener>>,
+    // the jobs of this session served by the API
+    job_history: Arc<JobHistory>,
+    api_server: Mutex<Option<ApiServer>>,
+    // the WatchJobs calls of the gRPC API
+    job_watchers: Arc<JobWatchers>,
+    grpc_server: Mutex<Option<GrpcServer>>,
+    // anchored a last time on exit
+    audit_log: OnceLock<Arc<AuditLog>>,
+    // settings overridden in the environment or on the command line and the ignored
+    // arguments and variables, logged on start
+    overrides: Vec<String>,
+    argument_errors: Vec<String>,
+    environment_errors: Vec<String>,
+    // the backup of the settings reset with --reset-config
+    reset_backup: Option<Result<PathBuf, String>>,
+    // the preset files of the last shown presets menu, indexed by the item id
+    presets: Mutex<Vec<PathBuf>>,
+    // the printers of the last shown capture menu, indexed by the item id
+    printers: Mutex<Vec<LocalPrinter>>,
 }
 
 impl MainWindow {
-    fn new() -> Self {
-        let window = MainWindow {
-            discard_flag: Arc::new(AtomicBool::new(false)),
-        };
-        window.load_discard_flag();
-        window
+    fn new(arguments: &Arguments) -> Self {
+        let settings = AppSettings::load(arguments.config.as_deref());
+        let reset_backup = arguments
+            .reset_config
+            .then(|| settings.reset().map_err(|e| e.to_string()));
+        let (overrides, environment_errors) = settings.apply_overrides(arguments);
+        MainWindow {
+            settings: Arc::new(settings),
+            job_events: Default::default(),
+            job_list: OnceLock::new(),
+            finder: OnceLock::new(),
+            logger: OnceLock::new(),
+            settings_window: OnceLock::new(),
+            notified_job: Default::default(),
+            listener_status: OnceLock::new(),
+            listener_paused: Default::default(),
+            tabs: OnceLock::new(),
+            statistics: OnceLock::new(),
+            splitter: OnceLock::new(),
+            tray_icon: OnceLock::new(),
+            tray_status: OnceLock::new(),
+            update_checker: OnceLock::new(),
+            network_monitor: OnceLock::new(),
+            script_hooks: Default::default(),
+            plugins: Default::default(),
+            transfers: Default::default(),
+            exit_pending: Default::default(),
+            listeners: Default::default(),
+            job_history: Default::default(),
+            api_server: Default::default(),
+            job_watchers: Default::default(),
+            grpc_server: Default::default(),
+            audit_log: OnceLock::new(),
+            overrides,
+            argument_errors: arguments.errors.clone(),
+            environment_errors,
+            reset_backup,
+            presets: Mutex::new(Vec::new()),
+            printers: Mutex::new(Vec::new()),
+        }
     }
 
-    pub fn create<T>(title: T) -> Result<WindowRef, WindowError>
+    pub fn create<T>(title: T, arguments: &Arguments) -> Result<WindowRef, WindowError>
     where
         T: AsRef<str>,
     {
@@ -58,16 +415,139 @@ impl MainWindow {
             ..Default::default()
         };
 
-        let main_window = Arc::new(MainWindow::new());
+        let main_window = Arc::new(MainWindow::new(arguments));
+        let minimized =
+            arguments.minimized || main_window.settings.start_minimized.load(Ordering::SeqCst);
+
+        let language = Language::from_code(&main_window.settings.language.read().unwrap())
+            .unwrap_or_else(Language::system);
+        i18n::set_language(language);
+
+        let language_menu = Language::ALL
+            .into_iter()
+            .fold(Menu::new(tr!("&Language")), |menu, l| {
+                menu.item(IDM_LANGUAGE + l as u32, l.name(), l == language)
+            });
+
+        // all records are shown until the filter is changed
+        let log_view_menu = LOG_VIEW_LEVELS.iter().enumerate().fold(
+            Menu::new(tr!("&Log filter")),
+            |menu, (index, (level, title))| {
+                menu.item(
+                    IDM_LOG_VIEW_LEVEL + index as u32,
+                    tr!(title),
+                    *level == LevelFilter::Trace,
+                )
+            },
+        );
+        let log_view_menu = LOG_VIEW_SUBSYSTEMS.iter().enumerate().fold(
+            log_view_menu.separator(),
+            |menu, (index, (_, title))| {
+                menu.item(IDM_LOG_VIEW_SUBSYSTEM + index as u32, tr!(title), true)
+            },
+        );
 
-        let win = WindowBuilder::window("miniraw", None)
+        let win = WindowBuilder::window(WINDOW_CLASS, None)
             .geometry(geometry)
             .title(title.as_ref())
             .icon(IDI_MAINICON)
-            .sys_menu_item(
-                IDM_DISCARD_FILES,
-                "Discard received files",
-                main_window.discard_flag.load(Ordering::SeqCst),
+            .extended_style(WS_EX_ACCEPTFILES.0)
+            .visible(!minimized)
+            .accelerators(ACCELERATORS)
+            .menu(
+                Menu::new(tr!("&File"))
+                    .item(
+                        IDM_OPEN_FOLDER,
+                        tr!("&Open containing folder\tCtrl+O"),
+                        false,
+                    )
+                    .item(IDM_SAVE_LOG, tr!("&Save log\tCtrl+S"), false)
+                    .item(IDM_SAVE_LOG_AS, tr!("Save log &as..."), false)
+                    .item(IDM_PAUSE_LISTENER, tr!("&Pause listener"), false)
+                    .separator()
+                    .item(IDM_EXIT, tr!("E&xit"), false),
+            )
+            .menu(
+                Menu::new(tr!("&Edit"))
+                    .item(IDM_COPY_LOG, tr!("&Copy log"), false)
+                    .item(IDM_CLEAR_LOG, tr!("C&lear log\tCtrl+L"), false)
+                    .separator()
+                    .item(IDM_FIND, tr!("&Find...\tCtrl+F"), false)
+                    .item(IDM_FIND_NEXT, tr!("Find &next\tF3"), false),
+            )
+            .menu(
+                Menu::new(tr!("&View"))
+                    .item(IDM_PREVIEW_JOB, tr!("&Preview selected job"), false)
+                    .item(IDM_HEX_VIEW, tr!("&Hex view of selected job"), false)
+                    .separator()
+                    .item(IDM_NEXT_PAGE, tr!("Next pa&ge\tCtrl+Tab"), false)
+                    .item(IDM_NEXT_PANE, tr!("Next pa&ne\tF6"), false)
+                    .separator()
+                    .item(IDM_FONT, tr!("&Font..."), false)
+                    .submenu(
+                        Menu::new(tr!("&Zoom"))
+                            .item(IDM_ZOOM_IN, tr!("Zoom &in\tCtrl++"), false)
+                            .item(IDM_ZOOM_OUT, tr!("Zoom &out\tCtrl+-"), false)
+                            .item(IDM_ZOOM_RESET, tr!("&Reset zoom\tCtrl+0"), false),
+                    )
+                    .item(
+                        IDM_AUTO_SCROLL,
+                        tr!("&Auto-scroll log"),
+                        main_window.settings.auto_scroll.load(Ordering::SeqCst),
+                    )
+                    .item(IDM_PAUSE_LOG, tr!("&Pause log output"), false)
+                    .submenu(log_view_menu),
+            )
+            .menu(
+                Menu::new(tr!("&Tools"))
+                    .item(IDM_SETTINGS, tr!("&Settings...\tCtrl+,"), false)
+                    .item(IDM_EXPORT_SETTINGS, tr!("&Export settings..."), false)
+                    .item(IDM_IMPORT_SETTINGS, tr!("&Import settings..."), false)
+                    .item(IDM_RESET_SETTINGS, tr!("&Reset settings..."), false)
+                    .submenu(Menu::new(tr!("&Presets")).item(
+                        IDM_SAVE_PRESET,
+                        tr!("&Save preset..."),
+                        false,
+                    ))
+                    .submenu(Menu::new(tr!("&Capture printer")).item(
+                        IDM_RELEASE_PRINTERS,
+                        tr!("&Restore all printers"),
+                        false,
+                    ))
+                    .item(
+                        IDM_VIRTUAL_PRINTER,
+                        tr!("MiniRAW NG &printer"),
+                        spooler::has_virtual_printer(),
+                    )
+                    .separator()
+                    .item(
+                        IDM_DISCARD_FILES,
+                        tr!("&Discard received files"),
+                        main_window.settings.discard.load(Ordering::SeqCst),
+                    )
+                    .item(
+                        IDM_NOTIFY_JOBS,
+                        tr!("&Notify about received jobs"),
+                        main_window.settings.notify_jobs.load(Ordering::SeqCst),
+                    )
+                    .item(
+                        IDM_FLASH_JOBS,
+                        tr!("&Flash taskbar button on new jobs"),
+                        main_window.settings.flash_jobs.load(Ordering::SeqCst),
+                    ),
+            )
+            .menu(language_menu)
+            .menu(
+                Menu::new(tr!("&Help"))
+                    .item(
+                        IDM_COLLECT_DIAGNOSTICS,
+                        tr!("&Collect diagnostics..."),
+                        false,
+                    )
+                    .item(IDM_SELF_TEST, tr!("Run &self-test"), false)
+                    .item(IDM_CHECK_UPDATES, tr!("Check for &updates"), false)
+                    .separator()
+                    .item(IDM_ABOUT, tr!("&About"), false),
             )
             .message_handler(main_window)
             .build()?;
@@ -75,123 +555,1994 @@ impl MainWindow {
         Ok(win)
     }
 
-    fn load_discard_flag(&self) {
+    // Flips the persistent option and its menu check mark, returns the new value
+    // Options enforced by the policy keep their value
+    fn toggle_option(&self, window: &WindowRef, id: u32, option: &AtomicBool) -> bool {
+        if self.settings.is_flag_enforced(option) {
+            let flag = option.load(Ordering::SeqCst);
+            window.check_menu_item(id, flag);
+            warn!("The option is enforced by the administrator");
+            return flag;
+        }
+
+        let flag = !option.load(Ordering::SeqCst);
+        option.store(flag, Ordering::SeqCst);
+        window.check_menu_item(id, flag);
+        self.settings.store();
+        flag
+    }
+
+    // Shows the controls of the selected tab, the log is also used by the find commands
+    fn select_page(&self, window: &WindowRef, page: usize) {
+        let Some(tabs) = self.tabs.get() else {
+            return;
+        };
+        tabs.select(page);
+
+        // the Jobs page shows the log below the job list
+        let children = window.children();
+        children[0].set_visible(page == PAGE_LOG || page == PAGE_JOBS);
+        children[1].set_visible(page == PAGE_JOBS);
+
+        if let Some(splitter) = self.splitter.get() {
+            splitter.set_visible(page == PAGE_JOBS);
+        }
+
+        if let Some(statistics) = self.statistics.get() {
+            if page == PAGE_STATISTICS {
+                self.refresh_statistics();
+            }
+            statistics.set_visible(page == PAGE_STATISTICS);
+        }
+
+        self.layout(window);
+    }
+
+    // Moves the keyboard focus to the next visible control of the window in the
+    // order they are shown, the same way F6 cycles the panes of Explorer
+    fn focus_next_pane(&self, window: &WindowRef) {
+        let children = window.children();
+        let mut panes = Vec::new();
+        if let Some(tabs) = self.tabs.get() {
+            panes.push(tabs.window().clone());
+        }
+        panes.push(children[1].clone());
+        panes.push(children[0].clone());
+        if let Some(statistics) = self.statistics.get() {
+            panes.push(statistics.view().clone());
+        }
+        panes.retain(|pane| pane.is_visible());
+
+        let next = panes
+            .iter()
+            .position(|pane| pane.has_focus())
+            .map_or(0, |index| (index + 1) % panes.len());
+        if let Some(pane) = panes.get(next) {
+            pane.set_focus();
+        }
+    }
+
+    // Right click actions of the job list and the log, the items send the same
+    // commands as the main menu
+    fn context_menu(&self, window: &WindowRef, from: WindowHandle) -> Option<Menu> {
+        let children = window.children();
+        if from == children[1].handle() {
+            Some(
+                Menu::new("")
+                    .item(IDM_PREVIEW_JOB, tr!("&Preview selected job"), false)
+                    .item(IDM_HEX_VIEW, tr!("&Hex view of selected job"), false)
+                    .separator()
+                    .item(
+                        IDM_OPEN_FOLDER,
+                        tr!("&Open containing folder\tCtrl+O"),
+                        false,
+                    ),
+            )
+        } else if from == children[0].handle() {
+            Some(
+                Menu::new("")
+                    .item(IDM_COPY_LOG, tr!("&Copy log"), false)
+                    .item(IDM_CLEAR_LOG, tr!("C&lear log\tCtrl+L"), false)
+                    .separator()
+                    .item(IDM_FIND, tr!("&Find...\tCtrl+F"), false)
+                    .item(IDM_SAVE_LOG_AS, tr!("Save log &as..."), false),
+            )
+        } else {
+            None
+        }
+    }
+
+    // The level from the settings with the per-module levels from the settings
+    // and then from MINIRAW_LOG or RUST_LOG, the environment takes precedence
+    fn log_filter(&self) -> LogFilter {
+        let mut filter = LogFilter::new(*self.settings.log_level.read().unwrap());
+        filter.apply(&self.settings.log_modules.read().unwrap());
+        if let Ok(spec) = std::env::var("MINIRAW_LOG").or_else(|_| std::env::var("RUST_LOG")) {
+            filter.apply(&spec);
+        }
+        filter
+    }
+
+    // The splitter position is saved when it is moved, the rest on exit
+    fn save_ui_state(&self) {
+        if let Some(tabs) = self.tabs.get() {
+            self.settings
+                .active_page
+                .store(tabs.selected() as u32, Ordering::SeqCst);
+        }
+        if let Some(job_list) = self.job_list.get() {
+            *self.settings.job_column_widths.write().unwrap() = job_list.view().column_widths();
+            *self.settings.job_column_order.write().unwrap() = job_list.view().column_order();
+        }
+        self.settings.store();
+    }
+
+    fn update_jump_list(&self) {
+        let tasks = [
+            JumpTask {
+                title: tr!("Open output folder"),
+                arguments: COMMAND_ARGUMENTS[0].0,
+            },
+            JumpTask {
+                title: tr!("Pause or resume listener"),
+                arguments: COMMAND_ARGUMENTS[1].0,
+            },
+        ];
+        let jobs = self
+            .job_list
+            .get()
+            .map(|list| list.jobs())
+            .unwrap_or_default();
+        jump_list::update(&tasks, &jobs);
+    }
+
+    // Height of the area below the tab strip shared by all pages
+    fn page_height(&self, window: &WindowRef) -> i32 {
+        let Some(layout) = window.layout() else {
+            return 0;
+        };
+        let (width, height) = window.client_size();
+        let area = layout.area(width, height);
+        let strip_height = self
+            .tabs
+            .get()
+            .map(|tabs| tabs.strip_height(area.width.unwrap_or(0)))
+            .unwrap_or_default();
+        area.height.unwrap_or(0) - strip_height
+    }
+
+    // Sizes of the docked children which depend on the window size and the settings,
+    // the children are moved by the layout after WM_SIZE
+    fn update_docks(&self, window: &WindowRef) {
+        let Some(layout) = window.layout() else {
+            return;
+        };
+
+        if let Some(status) = self.listener_status.get() {
+            let status_bar = status.status_bar();
+            status_bar.send_message(WindowMessage::new(status_bar.clone(), WM_SIZE, 0, 0));
+            status.update_parts();
+            layout.set_margins(Margins {
+                bottom: 6 + status_bar.size().1,
+                ..Margins::all(6)
+            });
+        }
+
+        let page_height = self.page_height(window);
+        if let Some(tabs) = self.tabs.get() {
+            let (width, height) = window.client_size();
+            let area_width = layout.area(width, height).width.unwrap_or(0);
+            layout.set_dock(tabs.window(), Dock::Top(tabs.strip_height(area_width)));
+        }
+
+        let ratio = self.settings.split_ratio.load(Ordering::SeqCst) as i32;
+        layout.set_dock(&window.children()[1], Dock::Top(page_height * ratio / 1000));
+    }
+
+    fn layout(&self, window: &WindowRef) {
+        self.update_docks(window);
+        window.update_layout();
+    }
+
+    fn save_statistics(&self) {
+        if let Some(statistics) = self.statistics.get() {
+            statistics.history().save();
+        }
+    }
+
+    fn refresh_statistics(&self) {
+        if let (Some(statistics), Some(list)) = (self.statistics.get(), self.job_list.get()) {
+            statistics.update(&list.jobs());
+        }
+    }
+
+    // The menus are built once, so they are translated on the next start, which the
+    // user is told in the new language
+    fn select_language(&self, window: &WindowRef, language: Language) {
+        for l in Language::ALL {
+            window.check_menu_item(IDM_LANGUAGE + l as u32, l == language);
+        }
+        i18n::set_language(language);
+        *self.settings.language.write().unwrap() = language.code().to_owned();
+        self.settings.store();
+        info!("Language: {}", language.name());
+
+        let text = utf16z!(tr!(
+            "The menus will be shown in the new language after MiniRAW NG is restarted."
+        ));
+        let caption = utf16z!(tr!("&Language").replace('&', ""));
         unsafe {
-            let mut hkey = HKEY::default();
-            let key_name = utf16z!(REG_KEY_NAME);
-            let value_name = utf16z!(REG_VALUE_NAME);
-            if RegOpenKeyW(HKEY_CURRENT_USER, PCWSTR(key_name.as_ptr()), &mut hkey).is_ok() {
-                let mut data = [0u8; 4];
-                let mut size = data.len() as u32;
-                if RegQueryValueExW(
-                    hkey,
-                    PCWSTR(value_name.as_ptr()),
-                    None,
-                    None,
-                    Some(data.as_mut_ptr()),
-                    Some(&mut size),
-                )
-                .is_ok()
-                {
-                    self.discard_flag
-                        .store(u32::from_ne_bytes(data) != 0, Ordering::SeqCst);
+            MessageBoxW(
+                Some(window.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                MB_OK | MB_ICONINFORMATION,
+            );
+        }
+    }
+
+    fn notify_job(tray_icon: &TrayIcon, job: &Job) {
+        let name = match job.path {
+            Some(ref path) => path.file_name().unwrap_or_default().to_string_lossy(),
+            None => tr!("Discarded job").into(),
+        };
+        let text = tr!(
+            "{}\nFrom {}, {} bytes, {}",
+            name,
+            job.source.ip(),
+            job.size,
+            job.format
+        );
+        tray_icon.show_notification(tr!("Print job received"), &text);
+    }
+
+    fn open_notified_job(&self, window: &WindowRef) {
+        let Some(path) = self.notified_job.lock().unwrap().clone() else {
+            return;
+        };
+
+        if let Err(e) = shell::open_file(window, &path) {
+            warn!("{}", e);
+        }
+    }
+
+    // Shows the selected or the most recent saved job in Explorer
+    fn open_job_folder(&self, window: &WindowRef) {
+        let path = self
+            .job_list
+            .get()
+            .and_then(|list| list.selected_job().or_else(|| list.latest_saved_job()))
+            .and_then(|job| job.path)
+            .filter(|path| path.exists())
+            .unwrap_or_else(|| self.settings.output_dir());
+
+        if let Err(e) = shell::show_in_folder(window, &path) {
+            error!("{}", e);
+        }
+    }
+
+    // Dropped files are sent either to the configured printer or back to our own listener
+    fn resend_dropped_files(&self, window: &WindowRef, files: Vec<PathBuf>) {
+        let files = files
+            .into_iter()
+            .filter(|f| f.is_file())
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            return;
+        }
+
+        let local = format!("127.0.0.1:{}", self.settings.port());
+        let printer = self.settings.resend_target.read().unwrap().clone();
+
+        let (text, style) = if printer.is_empty() {
+            (
+                tr!(
+                    "Re-ingest {} file(s) through the listener?\n\n\
+                     A printer to resend the files to can be configured in the settings.",
+                    files.len()
+                ),
+                MB_OKCANCEL,
+            )
+        } else {
+            (
+                tr!(
+                    "Resend {} file(s) to the printer {}?\n\n\
+                     Yes - send to the printer\nNo - re-ingest through the listener",
+                    files.len(),
+                    printer
+                ),
+                MB_YESNOCANCEL,
+            )
+        };
+
+        let text = utf16z!(text);
+        let caption = utf16z!(tr!("Resend dropped files"));
+        let answer = unsafe {
+            MessageBoxW(
+                Some(window.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                style | MB_ICONQUESTION,
+            )
+        };
+
+        match answer {
+            IDYES => resend::resend_files(files, printer, window.clone()),
+            IDOK | IDNO => resend::resend_files(files, local, window.clone()),
+            _ => {}
+        }
+    }
+
+    fn hex_view_selected_job(&self) {
+        match self.job_list.get().and_then(|list| list.selected_job()) {
+            Some(Job {
+                path: Some(path), ..
+            }) => {
+                if let Err(e) = HexViewer::open(&path) {
+                    error!("{}: {}", path.display(), e);
                 }
-                let _ = RegCloseKey(hkey);
             }
+            Some(_) => warn!("Discarded jobs cannot be viewed"),
+            None => {}
         }
     }
 
-    fn store_discard_flag(&self) {
-        unsafe {
-            let mut hkey = HKEY::default();
-            let key_name = utf16z!(REG_KEY_NAME);
-            let value_name = utf16z!(REG_VALUE_NAME);
-            let rc = RegCreateKeyW(HKEY_CURRENT_USER, PCWSTR(key_name.as_ptr()), &mut hkey);
-            if rc.is_ok() {
-                let data = (self.discard_flag.load(Ordering::SeqCst) as u32).to_ne_bytes();
-                let _ = RegSetKeyValueW(
-                    hkey,
-                    PCWSTR::null(),
-                    PCWSTR(value_name.as_ptr()),
-                    REG_DWORD.0,
-                    Some(data.as_ptr() as _),
-                    data.len() as _,
-                );
-                let _ = RegCloseKey(hkey);
+    fn choose_log_font(&self, window: &WindowRef) {
+        let current = self.settings.log_font.read().unwrap().clone();
+        if let Some(font) = window.choose_font(&current) {
+            info!("Log font: {} {}px", font.face, font.height);
+            window.children()[0].set_font(&font);
+            *self.settings.log_font.write().unwrap() = font;
+            self.settings.store();
+        }
+    }
+
+    // Shows the log records up to the level with the given index in LOG_VIEW_LEVELS
+    fn set_log_view_level(&self, window: &WindowRef, index: usize) {
+        if let Some(logger) = self.logger.get() {
+            let mut view_filter = logger.view_filter();
+            view_filter.level = LOG_VIEW_LEVELS[index].0;
+            logger.set_view_filter(view_filter);
+            for item in 0..LOG_VIEW_LEVELS.len() {
+                window.check_menu_item(IDM_LOG_VIEW_LEVEL + item as u32, item == index);
             }
         }
     }
-}
 
-impl WindowMessageHandler for MainWindow {
-    fn handle_message(&self, message: WindowMessage) -> MessageResult {
-        match message.msg {
-            WM_SYSCOMMAND if message.wparam == IDM_DISCARD_FILES as _ => {
-                let flag = !self.discard_flag.load(Ordering::SeqCst);
-                info!("Discard received files: {}", flag);
-                self.discard_flag.store(flag, Ordering::SeqCst);
-                message.window.check_sys_menu_item(IDM_DISCARD_FILES, flag);
-                self.store_discard_flag();
-                MessageResult::Processed
+    fn toggle_log_view_subsystem(&self, window: &WindowRef, index: usize) {
+        if let Some(logger) = self.logger.get() {
+            let subsystem = LOG_VIEW_SUBSYSTEMS[index].0;
+            let mut view_filter = logger.view_filter();
+            let hidden = !view_filter.is_hidden(subsystem);
+            view_filter.set_hidden(subsystem, hidden);
+            logger.set_view_filter(view_filter);
+            window.check_menu_item(IDM_LOG_VIEW_SUBSYSTEM + index as u32, !hidden);
+        }
+    }
+
+    // Changes the height of the log font by the number of steps, 0 restores the default
+    fn zoom_log(&self, window: &WindowRef, steps: i32) {
+        let font = {
+            let mut font = self.settings.log_font.write().unwrap();
+            let height = if steps == 0 {
+                DEFAULT_LOG_FONT_HEIGHT
+            } else {
+                (font.height as i32 + steps * ZOOM_STEP)
+                    .clamp(MIN_LOG_FONT_HEIGHT as i32, MAX_LOG_FONT_HEIGHT as i32)
+                    as u32
+            };
+            if height == font.height {
+                return;
             }
-            WM_CREATE => {
-                let edit_style = WS_CHILD
-                    | WS_VISIBLE
-                    | WS_VSCROLL
-                    | WINDOW_STYLE((ES_LEFT | ES_MULTILINE | ES_AUTOVSCROLL | ES_READONLY) as u32);
+            font.height = height;
+            font.clone()
+        };
+        window.children()[0].set_font(&font);
+        self.settings.store();
+    }
 
-                let font = Font::new(14, "Consolas");
+    // Copies the selected part of the log or the whole log if nothing is selected
+    fn copy_log(&self, window: &WindowRef) {
+        let log = &window.children()[0];
+        let (start, end) = log.selection();
 
-                let edit = WindowBuilder::edit_control(message.window)
-                    .style(edit_style.0)
-                    .extended_style(WS_EX_CLIENTEDGE.0)
-                    .font(font)
-                    .build()
-                    .unwrap();
+        if start < end {
+            log.send_message(WindowMessage::new(log.clone(), WM_COPY, 0, 0));
+        } else if let Some(logger) = self.logger.get() {
+            if let Err(e) = log.copy_to_clipboard(&logger.shown_text()) {
+                error!("{}", e);
+            }
+        }
+    }
 
-                logger::WindowLogger::init(edit, LevelFilter::Info);
+    fn default_log_path() -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        util::app_dir().join(format!("miniraw-{timestamp}.log"))
+    }
 
-                info!(
-                    ">>> MiniRAW NG {} by Dmitry Pankratov",
-                    env!("CARGO_PKG_VERSION")
-                );
+    fn save_log(&self) {
+        self.write_log(&Self::default_log_path());
+    }
 
-                info!(
-                    "Discard received files: {}",
-                    self.discard_flag.load(Ordering::SeqCst)
-                );
+    // Saves the log like save_log and starts over with an empty one
+    fn rotate_log(&self) {
+        let Some(logger) = self.logger.get() else {
+            return;
+        };
+        let path = Self::default_log_path();
+        match fs::write(&path, logger.text()) {
+            Ok(()) => {
+                logger.clear();
+                info!("Log saved to {}", path.display());
+            }
+            Err(e) => error!("{}: {}", path.display(), e),
+        }
+    }
 
-                let flag = self.discard_flag.clone();
+    fn save_log_as(&self, window: &WindowRef) {
+        let filters = [(tr!("Log files"), "*.log"), (tr!("All files"), "*.*")];
+        if let Some(path) =
+            window.save_file(tr!("Save log as"), &filters, &Self::default_log_path())
+        {
+            self.write_log(&path);
+        }
+    }
 
-                std::thread::spawn(|| {
-                    if let Err(e) = listener::start_raw_listener(flag) {
-                        error!("{}", e);
-                    }
-                });
+    // Writes all buffered records, including the ones hidden by the view filter
+    fn write_log(&self, path: &Path) {
+        let text = self
+            .logger
+            .get()
+            .map(|logger| logger.text())
+            .unwrap_or_default();
+        match fs::write(path, text) {
+            Ok(()) => info!("Log saved to {}", path.display()),
+            Err(e) => error!("{}: {}", path.display(), e),
+        }
+    }
 
-                MessageResult::Processed
+    fn set_listener_paused(&self, window: &WindowRef, flag: bool) {
+        self.listener_paused.store(flag, Ordering::SeqCst);
+        window.check_menu_item(IDM_PAUSE_LISTENER, flag);
+        self.job_events.publish(JobEvent::Listener(if flag {
+            ListenerState::Paused
+        } else {
+            ListenerState::Listening(self.settings.port())
+        }));
+        info!("Pause listener: {}", flag);
+    }
+
+    // Asks whether to exit while jobs are being received, the files of the interrupted
+    // jobs would be left incomplete. Returns true if the window can be closed now.
+    fn confirm_exit(&self, window: &WindowRef) -> bool {
+        let active = self.transfers.count();
+        if active == 0 {
+            return true;
+        }
+
+        let text = utf16z!(tr!(
+            "{} job(s) are still being received.\n\n\
+             Yes - exit now, the files of these jobs will be incomplete\n\
+             No - stop accepting connections and exit when the jobs are finished",
+            active
+        ));
+        let caption = utf16z!(tr!("Exit MiniRAW NG"));
+        let answer = unsafe {
+            MessageBoxW(
+                Some(window.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                MB_YESNOCANCEL | MB_ICONWARNING | MB_DEFBUTTON2,
+            )
+        };
+
+        match answer {
+            IDYES => true,
+            IDNO => {
+                self.exit_pending.store(true, Ordering::SeqCst);
+                self.set_listener_paused(window, true);
+                info!("Exiting when the current jobs are finished");
+                // the last job may have been finished while the question was shown
+                self.transfers.count() == 0
             }
-            WM_SIZE => {
-                let gm = WindowGeometry {
-                    x: Some(6),
-                    y: Some(6),
-                    width: Some(((message.lparam as u32) & 0xffff) as i32 - 12),
-                    height: Some(((message.lparam as u32) >> 16) as i32 - 12),
-                };
-                message.window.children()[0].move_window(gm);
+            _ => false,
+        }
+    }
 
-                MessageResult::Processed
+    fn export_settings(&self, window: &WindowRef) {
+        let initial = util::app_dir().join("miniraw-settings.toml");
+        let filters = [(tr!("Settings files"), "*.toml"), (tr!("All files"), "*.*")];
+        if let Some(path) = window.save_file(tr!("Export settings"), &filters, &initial) {
+            match self.settings.export(&path) {
+                Ok(()) => info!("Settings exported to {}", path.display()),
+                Err(e) => error!("{}: {}", path.display(), e),
             }
-            WM_DESTROY => {
-                MessageLoop::quit();
-                MessageResult::Processed
+        }
+    }
+
+    fn read_settings_file(path: &Path) -> Option<Document> {
+        let result = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Document::parse(&text).map_err(|e| e.to_string()));
+        match result {
+            Ok(document) => Some(document),
+            Err(e) => {
+                error!("{}: {}", path.display(), e);
+                None
             }
-            _ => MessageResult::Ignored,
         }
     }
-}
 
-fn main() {
-    let _ = MainWindow::create(format!("MiniRAW NG {}", env!("CARGO_PKG_VERSION")));
+    // One item per preset followed by a separator, the presets which match the current
+    // settings are checked
+    fn presets_menu(&self) -> Menu {
+        let presets = self
+            .settings
+            .presets()
+            .into_iter()
+            .take(MAX_PRESETS)
+            .collect::<Vec<_>>();
+        let menu = presets
+            .iter()
+            .enumerate()
+            .fold(Menu::new(""), |menu, (index, path)| {
+                let active = fs::read_to_string(path)
+                    .ok()
+                    .and_then(|text| Document::parse(&text).ok())
+                    .and_then(|document| self.settings.changes(&document).ok())
+                    .is_some_and(|changes| changes.is_empty());
+                let name = settings::preset_name(path).replace('&', "&&");
+                menu.item(IDM_PRESET + index as u32, name, active)
+            });
+        let menu = if presets.is_empty() {
+            menu
+        } else {
+            menu.separator()
+        };
+        *self.presets.lock().unwrap() = presets;
+        menu
+    }
+
+    // One item per local printer followed by a separator, the printers which print to
+    // MiniRAW NG are checked
+    fn printers_menu(&self) -> Menu {
+        let printers = match spooler::local_printers() {
+            Ok(printers) => printers
+                .into_iter()
+                .filter(|printer| printer.name != spooler::VIRTUAL_PRINTER_NAME)
+                .take(MAX_PRINTERS)
+                .collect(),
+            Err(e) => {
+                warn!("Cannot read the printers: {}", e);
+                Vec::new()
+            }
+        };
+        let menu = printers
+            .iter()
+            .enumerate()
+            .fold(Menu::new(""), |menu, (index, printer)| {
+                menu.item(
+                    IDM_CAPTURE_PRINTER + index as u32,
+                    printer.name.replace('&', "&&"),
+                    printer.captured,
+                )
+            });
+        let menu = if printers.is_empty() {
+            menu
+        } else {
+            menu.separator()
+        };
+        *self.printers.lock().unwrap() = printers;
+        menu
+    }
+
+    // A printer is pointed to the main listener through a Standard TCP/IP port on
+    // this computer, a captured printer gets its own port back
+    fn toggle_printer_capture(&self, index: usize) {
+        let Some(printer) = self.printers.lock().unwrap().get(index).cloned() else {
+            return;
+        };
+        let command = if printer.captured {
+            SpoolerCommand::Release {
+                printer: printer.name,
+            }
+        } else {
+            SpoolerCommand::Capture {
+                printer: printer.name,
+                port: self.settings.port.load(Ordering::SeqCst) as u16,
+            }
+        };
+        spooler::execute(vec![command]);
+    }
+
+    // The MiniRAW NG printer is added for the main listener or removed
+    fn toggle_virtual_printer(&self) {
+        let command = if spooler::has_virtual_printer() {
+            SpoolerCommand::RemovePrinter
+        } else {
+            SpoolerCommand::AddPrinter {
+                port: self.settings.port.load(Ordering::SeqCst) as u16,
+                driver: self
+                    .settings
+                    .printer_driver
+                    .read()
+                    .unwrap()
+                    .trim()
+                    .to_owned(),
+            }
+        };
+        spooler::execute(vec![command]);
+    }
+
+    fn release_printers(&self) {
+        let commands = self
+            .printers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|printer| printer.captured)
+            .map(|printer| SpoolerCommand::Release {
+                printer: printer.name.clone(),
+            })
+            .collect::<Vec<_>>();
+        if commands.is_empty() {
+            info!("No printer prints to MiniRAW NG");
+        } else {
+            spooler::execute(commands);
+        }
+    }
+
+    fn show_tray_menu(&self, window: &WindowRef) {
+        let menu = self
+            .presets_menu()
+            .item(IDM_SAVE_PRESET, tr!("&Save preset..."), false)
+            .separator()
+            .item(IDM_EXIT, tr!("E&xit"), false);
+        let mut point = POINT::default();
+        let position = unsafe { GetCursorPos(&mut point) }
+            .ok()
+            .map(|_| (point.x, point.y));
+        if let Err(e) = window.show_context_menu(&menu, position) {
+            error!("{}", e);
+        }
+    }
+
+    // The whole preset is applied at once, the listeners are restarted by the change
+    // notification if the port, the output directory or the profiles change
+    fn apply_preset(&self, index: usize) {
+        let Some(path) = self.presets.lock().unwrap().get(index).cloned() else {
+            return;
+        };
+        let name = settings::preset_name(&path);
+        let Some(document) = Self::read_settings_file(&path) else {
+            return;
+        };
+
+        match self.settings.changes(&document) {
+            Ok(changes) if changes.is_empty() => {
+                info!("The preset {} is already active", name);
+            }
+            Ok(changes) => {
+                self.settings.apply_changes(&changes);
+                info!("Switched to the preset {}", name);
+            }
+            Err(errors) => {
+                for entry in errors {
+                    error!("Invalid settings entry in {}: {}", path.display(), entry);
+                }
+            }
+        }
+    }
+
+    fn save_preset(&self, window: &WindowRef) {
+        let dir = self.settings.presets_dir();
+        if let Err(e) = fs::create_dir_all(&dir) {
+            error!("{}: {}", dir.display(), e);
+            return;
+        }
+        let initial = dir.join(format!("{}.toml", tr!("New preset")));
+        let filters = [(tr!("Settings files"), "*.toml")];
+        if let Some(path) = window.save_file(tr!("Save preset"), &filters, &initial) {
+            match self.settings.save_preset(&path) {
+                Ok(()) => info!(
+                    "Preset {} saved to {}",
+                    settings::preset_name(&path),
+                    path.display()
+                ),
+                Err(e) => error!("{}: {}", path.display(), e),
+            }
+        }
+    }
+
+    // Nothing is changed if any entry of the file is invalid, otherwise the changed
+    // settings are listed and applied after a confirmation
+    fn import_settings(&self, window: &WindowRef) {
+        let filters = [(tr!("Settings files"), "*.toml"), (tr!("All files"), "*.*")];
+        let Some(path) = window.open_file(tr!("Import settings"), &filters) else {
+            return;
+        };
+
+        let Some(document) = Self::read_settings_file(&path) else {
+            return;
+        };
+
+        let changes = match self.settings.changes(&document) {
+            Ok(changes) => changes,
+            Err(errors) => {
+                for entry in errors {
+                    error!("Invalid settings entry in {}: {}", path.display(), entry);
+                }
+                return;
+            }
+        };
+
+        if changes.is_empty() {
+            info!(
+                "The settings in {} are the same as the current ones",
+                path.display()
+            );
+            return;
+        }
+
+        let summary = changes
+            .iter()
+            .map(|change| format!("{}: {} -> {}", change.name, change.old, change.new))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let text = utf16z!(tr!("Change the following settings?\n\n{}", summary));
+        let caption = utf16z!(tr!("Import settings"));
+        let answer = unsafe {
+            MessageBoxW(
+                Some(window.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                MB_OKCANCEL | MB_ICONQUESTION,
+            )
+        };
+        if answer != IDOK {
+            return;
+        }
+
+        self.settings.apply_changes(&changes);
+        info!(
+            "Imported {} setting(s) from {}",
+            changes.len(),
+            path.display()
+        );
+    }
+
+    fn reset_settings(&self, window: &WindowRef) {
+        let text = utf16z!(tr!(
+            "Reset all settings to the defaults?\n\n\
+             The current settings are saved to a backup file next to the configuration file."
+        ));
+        let caption = utf16z!(tr!("Reset settings"));
+        let answer = unsafe {
+            MessageBoxW(
+                Some(window.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                MB_OKCANCEL | MB_ICONWARNING | MB_DEFBUTTON2,
+            )
+        };
+        if answer != IDOK {
+            return;
+        }
+
+        match self.settings.reset() {
+            Ok(backup) => info!(
+                "Settings reset, the backup is saved to {}",
+                backup.display()
+            ),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    // Starts the listeners of the main port and of the profiles. The running
+    // listeners whose configuration was changed are stopped first, so their ports
+    // can be bound again.
+    fn update_listeners(&self) {
+        if self.settings.listen_address().is_none() {
+            error!(
+                "Invalid listen address: {}, listening on all addresses",
+                self.settings.listen_address.read().unwrap()
+            );
+        }
+        if let Err(e) = self.settings.schedule() {
+            error!("{}, accepting the jobs at any time", e);
+        }
+        if self.settings.off_hours().is_none() {
+            error!(
+                "Invalid off_hours value: {}, rejecting the jobs outside the schedule",
+                self.settings.off_hours.read().unwrap()
+            );
+        }
+        let configs = self.settings.listener_configs();
+        let mut listeners = self.listeners.lock().unwrap();
+
+        let (running, changed) = listeners
+            .drain(..)
+            .partition::<Vec<_>, _>(|listener| configs.contains(listener.config()));
+        for listener in changed {
+            listener.stop();
+        }
+        *listeners = running;
+
+        for config in configs {
+            if !listeners
+                .iter()
+                .any(|listener| *listener.config() == config)
+            {
+                listeners.push(RawListener::start(
+                    config,
+                    self.settings.discard.clone(),
+                    self.listener_paused.clone(),
+                    self.settings.hexdump_size.clone(),
+                    self.script_hooks.clone(),
+                    self.plugins.clone(),
+                    self.job_events.clone(),
+                ));
+            }
+        }
+    }
+
+    // The sockets may be dead after the computer was asleep: the listeners are bound
+    // again and the connections of the interrupted jobs are closed
+    fn restart_listeners(&self) {
+        for listener in self.listeners.lock().unwrap().drain(..) {
+            listener.stop();
+        }
+        let closed = close_connections();
+        if closed > 0 {
+            warn!("Aborted {} job(s) interrupted by sleep", closed);
+        }
+        self.update_listeners();
+    }
+
+    // The listeners bound to a specific address are started again when the address
+    // is back, e.g. after a DHCP renewal or when a VPN is connected. A listener which
+    // could not bind its address on start is started when the address appears.
+    fn rebind_listeners(&self) {
+        let Some(monitor) = self.network_monitor.get() else {
+            return;
+        };
+
+        let mut listeners = self.listeners.lock().unwrap();
+        let mut addresses = listeners
+            .iter()
+            .map(|listener| listener.config().address)
+            .filter(|address| !address.is_unspecified())
+            .collect::<Vec<_>>();
+        addresses.dedup();
+
+        let changes = monitor.changes(&addresses);
+        for (address, available) in &changes {
+            if *available {
+                info!("The address {} is available again", address);
+            } else {
+                warn!("The address {} is no longer available", address);
+            }
+        }
+
+        let (stale, running) = listeners.drain(..).partition::<Vec<_>, _>(|listener| {
+            let address = listener.config().address;
+            changes.contains(&(address, true))
+                || !address.is_unspecified() && !listener.is_running() && is_local_address(address)
+        });
+        *listeners = running;
+        drop(listeners);
+
+        if !stale.is_empty() {
+            for listener in stale {
+                info!(
+                    "Binding the listener on {}:{} again",
+                    listener.config().address,
+                    listener.config().port
+                );
+                listener.stop();
+            }
+            self.update_listeners();
+        }
+    }
+
+    // Applies the settings changed by a reload or an import, the language, the log
+    // size limit and the window layout are applied after a restart
+    fn apply_settings(&self, window: &WindowRef) {
+        for (id, option) in [
+            (IDM_DISCARD_FILES, &self.settings.discard),
+            (IDM_NOTIFY_JOBS, &self.settings.notify_jobs),
+            (IDM_FLASH_JOBS, &self.settings.flash_jobs),
+            (IDM_AUTO_SCROLL, &self.settings.auto_scroll),
+        ] {
+            window.check_menu_item(id, option.load(Ordering::SeqCst));
+        }
+        window.children()[0].set_font(&self.settings.log_font.read().unwrap());
+        if let Some(logger) = self.logger.get() {
+            logger.set_filter(self.log_filter());
+        }
+        if let Some(settings_window) = self.settings_window.get() {
+            settings_window.refresh();
+        }
+        priority::apply(&self.settings);
+        self.update_listeners();
+        self.update_api_server(window);
+        self.update_grpc_server(window);
+    }
+
+    // Restarts the API server when its address or port is changed, port 0 stops it
+    fn update_api_server(&self, window: &WindowRef) {
+        let address = self.settings.api_address();
+        if self.settings.api_port.load(Ordering::SeqCst) != 0 && address.is_none() {
+            error!(
+                "Invalid API address: {}",
+                self.settings.api_address.read().unwrap()
+            );
+        }
+
+        let mut api_server = self.api_server.lock().unwrap();
+        if api_server.as_ref().map(ApiServer::address) == address {
+            return;
+        }
+        if let Some(api_server) = api_server.take() {
+            api_server.stop();
+        }
+        if let Some(address) = address {
+            // generated before the first request
+            self.settings.api_token();
+            *api_server = Some(ApiServer::start(address, self.api_context(window)));
+        }
+    }
+
+    fn api_context(&self, window: &WindowRef) -> Arc<ApiContext> {
+        Arc::new(ApiContext {
+            settings: self.settings.clone(),
+            history: self.job_history.clone(),
+            transfers: self.transfers.clone(),
+            paused: self.listener_paused.clone(),
+            window: window.clone(),
+            logger: self.logger.get().copied(),
+        })
+    }
+
+    // Restarts the gRPC server like the API server, it uses the API address and token
+    fn update_grpc_server(&self, window: &WindowRef) {
+        let address = self.settings.grpc_address();
+        let mut grpc_server = self.grpc_server.lock().unwrap();
+        if grpc_server.as_ref().map(GrpcServer::address) == address {
+            return;
+        }
+        if let Some(grpc_server) = grpc_server.take() {
+            grpc_server.stop();
+        }
+        if let Some(address) = address {
+            self.settings.api_token();
+            *grpc_server = Some(GrpcServer::start(
+                address,
+                self.api_context(window),
+                self.job_watchers.clone(),
+            ));
+        }
+    }
+
+    // Saves the log, the settings, the listener state and the recent jobs into a ZIP
+    // file which can be attached to a bug report
+    fn collect_diagnostics(&self, window: &WindowRef) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let initial = util::app_dir().join(format!("miniraw-diagnostics-{timestamp}.zip"));
+        let filters = [(tr!("ZIP archives"), "*.zip"), (tr!("All files"), "*.*")];
+        let Some(path) = window.save_file(tr!("Collect diagnostics"), &filters, &initial) else {
+            return;
+        };
+
+        let mut listener = vec![
+            format!(
+                "State: {:?}",
+                self.listener_status.get().and_then(|status| status.state())
+            ),
+            format!("Paused: {}", self.listener_paused.load(Ordering::SeqCst)),
+            format!("Active transfers: {}", self.transfers.count()),
+        ];
+        listener.extend(
+            self.listeners
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|listener| format!("{:?}", listener.config())),
+        );
+
+        let diagnostics = Diagnostics::new()
+            .file(
+                "log.txt",
+                self.logger
+                    .get()
+                    .map(|logger| logger.text())
+                    .unwrap_or_default(),
+            )
+            .file("settings.txt", self.settings.to_text())
+            .file("listener.txt", listener.join("\r\n"))
+            .jobs(
+                &self
+                    .job_list
+                    .get()
+                    .map(|list| list.jobs())
+                    .unwrap_or_default(),
+            );
+
+        match diagnostics.write(&path) {
+            Ok(()) => info!("Diagnostics saved to {}", path.display()),
+            Err(e) => error!("{}: {}", path.display(), e),
+        }
+    }
+
+    fn show_about(&self, window: &WindowRef) {
+        let text = utf16z!(tr!(
+            "MiniRAW NG {}\nSimple print job listener by Dmitry Pankratov",
+            env!("CARGO_PKG_VERSION")
+        ));
+        let caption = utf16z!(tr!("About MiniRAW NG"));
+        unsafe {
+            MessageBoxW(
+                Some(window.handle()),
+                PCWSTR(text.as_ptr()),
+                PCWSTR(caption.as_ptr()),
+                MB_ICONINFORMATION,
+            );
+        }
+    }
+
+    // Adds the missing inbound rules for the listening ports, on start the user is
+    // asked first and can turn off the question. The rules are added by an elevated
+    // copy of the program if the user is not an administrator.
+    fn add_firewall_rules(&self, window: &WindowRef, on_start: bool) {
+        let mut ports = self
+            .settings
+            .listener_configs()
+            .iter()
+            .map(|config| config.port)
+            .collect::<Vec<_>>();
+        ports.sort();
+        ports.dedup();
+
+        let missing = match firewall::missing_rules(&ports) {
+            Ok(missing) => missing,
+            Err(e) => {
+                warn!("Cannot read the firewall rules: {}", e);
+                return;
+            }
+        };
+        if missing.is_empty() {
+            if !on_start {
+                info!(
+                    "The firewall rules for port(s) {} already exist",
+                    firewall::format_ports(&ports)
+                );
+            }
+            return;
+        }
+
+        if on_start {
+            let text = utf16z!(tr!(
+                "The Windows Firewall may block the print jobs on port(s) {}.\n\n\
+                 Add the inbound firewall rules for MiniRAW NG now?\n\n\
+                 Choose No to not ask again, the rules can still be added in the settings.",
+                firewall::format_ports(&missing)
+            ));
+            let caption = utf16z!(tr!("Firewall rules"));
+            let answer = unsafe {
+                MessageBoxW(
+                    Some(window.handle()),
+                    PCWSTR(text.as_ptr()),
+                    PCWSTR(caption.as_ptr()),
+                    MB_YESNOCANCEL | MB_ICONQUESTION,
+                )
+            };
+            if answer == IDNO {
+                self.settings.check_firewall.store(false, Ordering::SeqCst);
+                self.settings.store();
+            }
+            if answer != IDYES {
+                return;
+            }
+        }
+
+        match firewall::add_rules(&missing) {
+            Ok(()) => info!(
+                "Added the firewall rules for port(s) {}",
+                firewall::format_ports(&missing)
+            ),
+            Err(e) if e.code() == E_ACCESSDENIED => firewall::add_rules_elevated(missing),
+            Err(e) => error!("Cannot add the firewall rules: {}", e),
+        }
+    }
+
+    fn check_updates_if_due(&self) {
+        if let Some(checker) = self.update_checker.get() {
+            if checker.is_due() {
+                checker.start(false);
+            }
+        }
+    }
+
+    // The result of the automatic check is logged and a newer version is shown in a
+    // tray notification, the result of the check from the Help menu in a message box
+    fn show_update_check(&self, window: &WindowRef, manual: bool) {
+        let Some(result) = self.update_checker.get().and_then(|c| c.take_result()) else {
+            return;
+        };
+
+        let (text, icon) = match result {
+            UpdateCheck::UpToDate => {
+                let text = tr!("MiniRAW NG {} is up to date", env!("CARGO_PKG_VERSION"));
+                info!("{}", text);
+                (text, MB_ICONINFORMATION)
+            }
+            UpdateCheck::Failed(e) => {
+                let text = tr!("Cannot check for updates: {}", e);
+                warn!("{}", text);
+                (text, MB_ICONWARNING)
+            }
+            UpdateCheck::Available(release) => {
+                let text = match release.installer {
+                    Some(ref path) => tr!(
+                        "MiniRAW NG {} is available, the installer is downloaded to {}",
+                        release.version,
+                        path.display()
+                    ),
+                    None => tr!(
+                        "MiniRAW NG {} is available at {}",
+                        release.version,
+                        release.page_url
+                    ),
+                };
+                info!("{}", text);
+
+                if !manual {
+                    *self.notified_job.lock().unwrap() = None;
+                    if let Some(tray_icon) = self.tray_icon.get() {
+                        tray_icon.show_notification(tr!("Update available"), &text);
+                    }
+                    return;
+                }
+
+                let question = match release.installer {
+                    Some(_) => tr!("Show the installer?"),
+                    None => tr!("Open the release page?"),
+                };
+                let text = utf16z!(format!("{text}\n\n{question}"));
+                let caption = utf16z!(tr!("Check for updates"));
+                let answer = unsafe {
+                    MessageBoxW(
+                        Some(window.handle()),
+                        PCWSTR(text.as_ptr()),
+                        PCWSTR(caption.as_ptr()),
+                        MB_YESNO | MB_ICONQUESTION,
+                    )
+                };
+                if answer == IDYES {
+                    let result = match release.installer {
+                        Some(ref path) => shell::show_in_folder(window, path),
+                        None => shell::open_url(window, &release.page_url),
+                    };
+                    if let Err(e) = result {
+                        warn!("{}", e);
+                    }
+                }
+                return;
+            }
+        };
+
+        if manual {
+            let text = utf16z!(text);
+            let caption = utf16z!(tr!("Check for updates"));
+            unsafe {
+                MessageBoxW(
+                    Some(window.handle()),
+                    PCWSTR(text.as_ptr()),
+                    PCWSTR(caption.as_ptr()),
+                    icon,
+                );
+            }
+        }
+    }
+
+    fn preview_selected_job(&self) {
+        if let Some(job) = self.job_list.get().and_then(|list| list.selected_job()) {
+            if let Err(e) = PreviewWindow::open(&job, self.settings.redactor()) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+impl WindowMessageHandler for MainWindow {
+    fn handle_timer(&self, window: &WindowRef, id: usize) -> MessageResult {
+        if id == SETTINGS_TIMER_ID {
+            self.settings.reload();
+            // the script is loaded again when it is edited
+            self.script_hooks.load(&self.settings.script_path());
+            self.plugins.load(&self.settings.plugin_names());
+        } else if id == UPDATE_TIMER_ID {
+            self.check_updates_if_due();
+        } else if id == NETWORK_TIMER_ID {
+            window.kill_timer(NETWORK_TIMER_ID);
+            self.rebind_listeners();
+        } else if id == STATISTICS_TIMER_ID {
+            self.save_statistics();
+        }
+        MessageResult::Processed
+    }
+
+    fn handle_command(&self, window: &WindowRef, id: u32) -> MessageResult {
+        match id {
+            IDM_DISCARD_FILES => {
+                let flag = self.toggle_option(window, IDM_DISCARD_FILES, &self.settings.discard);
+                info!("Discard received files: {}", flag);
+            }
+            IDM_PAUSE_LISTENER => {
+                let flag = !self.listener_paused.load(Ordering::SeqCst);
+                self.set_listener_paused(window, flag);
+            }
+            IDM_AUTO_SCROLL => {
+                self.toggle_option(window, IDM_AUTO_SCROLL, &self.settings.auto_scroll);
+            }
+            IDM_NOTIFY_JOBS => {
+                self.toggle_option(window, IDM_NOTIFY_JOBS, &self.settings.notify_jobs);
+            }
+            IDM_START_MINIMIZED => {
+                self.toggle_option(window, IDM_START_MINIMIZED, &self.settings.start_minimized);
+            }
+            IDM_RUN_AT_STARTUP => {
+                let flag = !settings::is_run_at_startup();
+                settings::set_run_at_startup(flag);
+                info!("Run at Windows startup: {}", flag);
+            }
+            id if (IDM_LANGUAGE..IDM_LANGUAGE + Language::ALL.len() as u32).contains(&id) => {
+                self.select_language(window, Language::ALL[(id - IDM_LANGUAGE) as usize]);
+            }
+            IDM_FLASH_JOBS => {
+                self.toggle_option(window, IDM_FLASH_JOBS, &self.settings.flash_jobs);
+            }
+            IDM_PAUSE_LOG => {
+                if let Some(logger) = self.logger.get() {
+                    let flag = !logger.is_paused();
+                    logger.set_paused(flag);
+                    window.check_menu_item(IDM_PAUSE_LOG, flag);
+                }
+            }
+            IDM_NEXT_PAGE => {
+                if let Some(tabs) = self.tabs.get() {
+                    self.select_page(window, (tabs.selected() + 1) % PAGE_COUNT);
+                }
+            }
+            IDM_NEXT_PANE => self.focus_next_pane(window),
+            IDM_PREVIEW_JOB => self.preview_selected_job(),
+            IDM_HEX_VIEW => self.hex_view_selected_job(),
+            IDM_EXIT => {
+                window.send_message(WindowMessage::new(window.clone(), WM_CLOSE, 0, 0));
+            }
+            IDM_ABOUT => self.show_about(window),
+            IDM_COLLECT_DIAGNOSTICS => self.collect_diagnostics(window),
+            IDM_SELF_TEST => {
+                // the result is logged by the test
+                let settings = self.settings.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = benchmark::run(&settings, "") {
+                        error!("The self-test has failed: {}", e);
+                    }
+                });
+            }
+            IDM_FIREWALL_RULES => self.add_firewall_rules(window, false),
+            IDM_RELEASE_PRINTERS => self.release_printers(),
+            IDM_VIRTUAL_PRINTER => self.toggle_virtual_printer(),
+            id if (IDM_CAPTURE_PRINTER..IDM_CAPTURE_PRINTER + MAX_PRINTERS as u32)
+                .contains(&id) =>
+            {
+                self.toggle_printer_capture((id - IDM_CAPTURE_PRINTER) as usize);
+            }
+            IDM_CHECK_UPDATES => {
+                if let Some(checker) = self.update_checker.get() {
+                    checker.start(true);
+                }
+            }
+            IDM_EXPORT_SETTINGS => self.export_settings(window),
+            IDM_IMPORT_SETTINGS => self.import_settings(window),
+            IDM_RESET_SETTINGS => self.reset_settings(window),
+            IDM_SAVE_PRESET => self.save_preset(window),
+            id if (IDM_PRESET..IDM_PRESET + MAX_PRESETS as u32).contains(&id) => {
+                self.apply_preset((id - IDM_PRESET) as usize);
+            }
+            IDM_FONT => self.choose_log_font(window),
+            IDM_ZOOM_IN => self.zoom_log(window, 1),
+            IDM_ZOOM_OUT => self.zoom_log(window, -1),
+            IDM_ZOOM_RESET => self.zoom_log(window, 0),
+            id if (IDM_LOG_VIEW_LEVEL..IDM_LOG_VIEW_LEVEL + LOG_VIEW_LEVELS.len() as u32)
+                .contains(&id) =>
+            {
+                self.set_log_view_level(window, (id - IDM_LOG_VIEW_LEVEL) as usize);
+            }
+            id if (IDM_LOG_VIEW_SUBSYSTEM
+                ..IDM_LOG_VIEW_SUBSYSTEM + LOG_VIEW_SUBSYSTEMS.len() as u32)
+                .contains(&id) =>
+            {
+                self.toggle_log_view_subsystem(window, (id - IDM_LOG_VIEW_SUBSYSTEM) as usize);
+            }
+            IDM_COPY_LOG => self.copy_log(window),
+            IDM_CLEAR_LOG => {
+                if let Some(logger) = self.logger.get() {
+                    logger.clear();
+                }
+            }
+            IDM_SAVE_LOG => self.save_log(),
+            IDM_SAVE_LOG_AS => self.save_log_as(window),
+            IDM_OPEN_FOLDER => self.open_job_folder(window),
+            IDM_SETTINGS => {
+                if let Some(settings_window) = self.settings_window.get() {
+                    settings_window.show();
+                }
+            }
+            IDM_FIND => {
+                self.select_page(window, PAGE_LOG);
+                if let Some(finder) = self.finder.get() {
+                    finder.show();
+                }
+            }
+            IDM_FIND_NEXT => {
+                self.select_page(window, PAGE_LOG);
+                if let Some(finder) = self.finder.get() {
+                    if !finder.find_next() {
+                        finder.show();
+                    }
+                }
+            }
+            _ => return MessageResult::Ignored,
+        }
+        MessageResult::Processed
+    }
+
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        match message.event() {
+            WindowEvent::Create => {
+                let edit_style = WS_CHILD
+                    | WS_VISIBLE
+                    | WS_VSCROLL
+                    | WINDOW_STYLE(
+                        (ES_LEFT | ES_MULTILINE | ES_AUTOVSCROLL | ES_READONLY | ES_NOHIDESEL)
+                            as u32,
+                    );
+
+                let font = self.settings.log_font.read().unwrap().clone();
+
+                let edit = WindowBuilder::rich_edit_control(message.window.clone())
+                    .style(edit_style.0)
+                    .extended_style(WS_EX_CLIENTEDGE.0)
+                    .font(font)
+                    .build()
+                    .unwrap();
+
+                let _ = edit.set_accessible_name(tr!("Log"));
+
+                // the rich edit zooms its text on Ctrl+wheel by itself, the font size
+                // is changed instead so that it is kept
+                let window = message.window.clone();
+                let result = edit.subclass(move |message| match message.event() {
+                    WindowEvent::MouseWheel { delta, ctrl: true } => {
+                        let id = if delta > 0 { IDM_ZOOM_IN } else { IDM_ZOOM_OUT };
+                        let _ = window.post_message(WM_COMMAND, id as usize, 0);
+                        MessageResult::Processed
+                    }
+                    _ => MessageResult::Ignored,
+                });
+                if let Err(e) = result {
+                    error!("{}", e);
+                }
+                let _ = self.finder.set(TextFinder::new(edit.clone()));
+                let logger = WindowLogger::init(
+                    edit,
+                    self.log_filter(),
+                    self.settings.auto_scroll.clone(),
+                    match self.settings.log_max_lines.load(Ordering::SeqCst) {
+                        0 => None,
+                        max_lines => Some(max_lines as usize),
+                    },
+                )
+                .unwrap();
+                let _ = self.logger.set(logger);
+                crash::set_logger(logger);
+
+                let _ = self.settings_window.set(SettingsWindow::new(
+                    Arc::downgrade(&message.window),
+                    self.settings.clone(),
+                    logger,
+                ));
+
+                let list_style = WS_CHILD
+                    | WS_VISIBLE
+                    | WINDOW_STYLE(LVS_REPORT | LVS_SHOWSELALWAYS | LVS_SINGLESEL | LVS_OWNERDATA);
+
+                let list_view = WindowBuilder::list_view(message.window.clone())
+                    .style(list_style.0)
+                    .extended_style(WS_EX_CLIENTEDGE.0)
+                    .build()
+                    .unwrap();
+                let _ = list_view.set_accessible_name(tr!("Jobs"));
+
+                let job_list = self.job_list.get_or_init(|| {
+                    let index = JobIndex::load(util::app_dir().join(INDEX_FILE_NAME));
+                    Arc::new(JobList::new(list_view, index))
+                });
+                let job_list = job_list.clone();
+
+                self.job_events.subscribe(move |event| {
+                    if let JobEvent::Completed(job) = event {
+                        job_list.add_job(job);
+                    }
+                });
+
+                let status_style = WS_CHILD | WS_VISIBLE | WINDOW_STYLE(SBARS_SIZEGRIP);
+
+                let status_bar = WindowBuilder::status_bar(message.window.clone())
+                    .style(status_style.0)
+                    .build()
+                    .unwrap();
+                let _ = status_bar.set_accessible_name(tr!("Listener status"));
+
+                let listener_status = self
+                    .listener_status
+                    .get_or_init(|| Arc::new(ListenerStatus::new(status_bar).unwrap()))
+                    .clone();
+                self.job_events
+                    .subscribe(move |event| listener_status.handle_event(event));
+
+                let tabs = TabControl::new(
+                    message.window.clone(),
+                    &[tr!("Log"), tr!("Jobs"), tr!("Statistics")],
+                )
+                .unwrap();
+                let _ = tabs.window().set_accessible_name(tr!("Pages"));
+                let _ = self.tabs.set(tabs);
+
+                let history = Arc::new(StatisticsHistory::load(
+                    util::app_dir().join(HISTORY_FILE_NAME),
+                ));
+                let statistics = self
+                    .statistics
+                    .get_or_init(|| {
+                        Arc::new(
+                            StatisticsPage::new(message.window.clone(), history.clone()).unwrap(),
+                        )
+                    })
+                    .clone();
+                if let Err(e) = message
+                    .window
+                    .set_timer(STATISTICS_TIMER_ID, STATISTICS_SAVE_INTERVAL)
+                {
+                    error!("{}", e);
+                }
+
+                // counted before the page below is updated
+                self.job_events
+                    .subscribe(move |event| history.handle_event(event));
+
+                let graph = statistics.graph().clone();
+                self.job_events
+                    .subscribe(move |event| graph.handle_event(event));
+
+                let job_list = self.job_list.get().unwrap().clone();
+                let page = statistics.clone();
+                self.job_events.subscribe(move |event| {
+                    if matches!(event, JobEvent::Completed(_)) && page.is_visible() {
+                        page.update(&job_list.jobs());
+                    }
+                });
+
+                let window = message.window.clone();
+                self.job_events.subscribe(move |event| {
+                    if let JobEvent::Completed(Job { path: Some(_), .. }) = event {
+                        jump_list::request_update(&window);
+                    }
+                });
+                self.update_jump_list();
+
+                let job_history = self.job_history.clone();
+                self.job_events
+                    .subscribe(move |event| job_history.handle_event(event));
+
+                let job_watchers = self.job_watchers.clone();
+                self.job_events
+                    .subscribe(move |event| job_watchers.handle_event(event));
+
+                let webhook = WebhookNotifier::new(self.settings.clone());
+                self.job_events
+                    .subscribe(move |event| webhook.handle_event(event));
+
+                let email = EmailNotifier::new(self.settings.clone());
+                self.job_events
+                    .subscribe(move |event| email.handle_event(event));
+
+                let mqtt = MqttPublisher::new(self.settings.clone());
+                self.job_events
+                    .subscribe(move |event| mqtt.handle_event(event));
+
+                let telemetry = TelemetryExporter::new(self.settings.clone());
+                self.job_events
+                    .subscribe(move |event| telemetry.handle_event(event));
+
+                let accountant = Accountant::new(self.settings.clone());
+                self.job_events
+                    .subscribe(move |event| accountant.handle_event(event));
+
+                let audit_log = Arc::new(AuditLog::new(self.settings.clone()));
+                let _ = self.audit_log.set(audit_log.clone());
+                self.job_events
+                    .subscribe(move |event| audit_log.handle_event(event));
+
+                let plugins = self.plugins.clone();
+                self.job_events
+                    .subscribe(move |event| plugins.handle_event(event));
+
+                let transfers = self.transfers.clone();
+                let exit_pending = self.exit_pending.clone();
+                let window = message.window.clone();
+                self.job_events.subscribe(move |event| {
+                    transfers.handle_event(event);
+                    if exit_pending.load(Ordering::SeqCst) && transfers.count() == 0 {
+                        let _ = window.post_message(WM_CLOSE, 0, 0);
+                    }
+                });
+
+                let splitter = Splitter::create(message.window.clone()).unwrap();
+
+                // the pages share the area below the tabs, only the controls of the
+                // selected page are visible and take part in the layout
+                let children = message.window.children();
+                let layout = Layout::new(Margins::all(6));
+                layout.add(self.tabs.get().unwrap().window().clone(), Dock::Top(0), 0);
+                layout.add(children[1].clone(), Dock::Top(0), 0);
+                layout.add(splitter.clone(), Dock::Top(SPLITTER_SIZE), 0);
+                statistics.add_to_layout(&layout);
+                layout.add(children[0].clone(), Dock::Fill, 0);
+                message.window.set_layout(layout);
+
+                let _ = self.splitter.set(splitter);
+
+                if let Some(job_list) = self.job_list.get() {
+                    let view = job_list.view();
+                    let widths = self.settings.job_column_widths.read().unwrap();
+                    for (column, width) in widths.iter().enumerate().take(view.column_count()) {
+                        view.set_column_width(column, *width);
+                    }
+                    view.set_column_order(&self.settings.job_column_order.read().unwrap());
+                }
+
+                let page = self.settings.active_page.load(Ordering::SeqCst) as usize;
+                self.select_page(&message.window, page.min(PAGE_COUNT - 1));
+
+                match TrayIcon::add(
+                    message.window.clone(),
+                    TRAY_ICON_ID,
+                    WM_TRAY_ICON,
+                    IDI_MAINICON,
+                    &format!("MiniRAW NG {}", env!("CARGO_PKG_VERSION")),
+                ) {
+                    Ok(tray_icon) => {
+                        let tray_icon = Arc::new(tray_icon);
+                        let tray_status = Arc::new(TrayStatus::new(tray_icon.clone()));
+                        let _ = self.tray_icon.set(tray_icon);
+                        let _ = self.tray_status.set(tray_status.clone());
+                        self.job_events
+                            .subscribe(move |event| tray_status.handle_event(event));
+                    }
+                    Err(e) => error!("{}", e),
+                }
+
+                let window = message.window.clone();
+                let tray_icon = self.tray_icon.get().cloned();
+                let settings = self.settings.clone();
+                let notified_job = self.notified_job.clone();
+                self.job_events.subscribe(move |event| {
+                    if let JobEvent::Completed(job) = event {
+                        if settings.notify_jobs.load(Ordering::SeqCst) {
+                            *notified_job.lock().unwrap() = job.path.clone();
+                            if let Some(ref tray_icon) = tray_icon {
+                                MainWindow::notify_job(tray_icon, job);
+                            }
+                        }
+                        if settings.flash_jobs.load(Ordering::SeqCst) {
+                            window.flash_taskbar();
+                        }
+                    }
+                });
+
+                let taskbar = TaskbarProgress::new(message.window.clone());
+                self.job_events
+                    .subscribe(move |event| taskbar.handle_event(event));
+
+                let overlay = TaskbarOverlay::new(message.window.clone());
+                self.job_events
+                    .subscribe(move |event| overlay.handle_event(event));
+
+                info!(
+                    ">>> MiniRAW NG {} by Dmitry Pankratov",
+                    env!("CARGO_PKG_VERSION")
+                );
+
+                match self.reset_backup {
+                    Some(Ok(ref backup)) => info!(
+                        "Settings reset, the backup is saved to {}",
+                        backup.display()
+                    ),
+                    Some(Err(ref e)) => error!("{}", e),
+                    None => {}
+                }
+                for e in self.settings.config_errors.read().unwrap().iter() {
+                    warn!(
+                        "Ignored configuration entry in {}: {}",
+                        self.settings.config_path().display(),
+                        e
+                    );
+                }
+                for arg in &self.argument_errors {
+                    warn!("Ignored command line argument: {}", arg);
+                }
+                for variable in &self.environment_errors {
+                    warn!("Ignored environment variable: {}", variable);
+                }
+                for entry in &self.overrides {
+                    info!("Overridden for this run: {}", entry);
+                }
+                for entry in self.settings.enforced() {
+                    info!("Enforced by the administrator: {}", entry);
+                }
+
+                info!(
+                    "Discard received files: {}",
+                    self.settings.discard.load(Ordering::SeqCst)
+                );
+
+                self.script_hooks.load(&self.settings.script_path());
+                self.plugins.load(&self.settings.plugin_names());
+                priority::apply(&self.settings);
+                self.update_listeners();
+                forward_health::start(self.settings.clone());
+                match NetworkMonitor::start(message.window.clone()) {
+                    Ok(monitor) => {
+                        let _ = self.network_monitor.set(monitor);
+                    }
+                    Err(e) => warn!("Cannot watch the network changes: {}", e),
+                }
+                self.update_api_server(&message.window);
+                self.update_grpc_server(&message.window);
+                if self.settings.check_firewall.load(Ordering::SeqCst) {
+                    let _ = message.window.post_user_message(WM_CHECK_FIREWALL, 0, 0);
+                }
+                start_control_server(ControlContext {
+                    settings: self.settings.clone(),
+                    history: self.job_history.clone(),
+                    transfers: self.transfers.clone(),
+                    paused: self.listener_paused.clone(),
+                    window: message.window.clone(),
+                });
+
+                let window = message.window.clone();
+                self.settings.subscribe(move |names| {
+                    info!("Settings changed: {}", names.join(", "));
+                    let _ = window.post_user_message(WM_SETTINGS_CHANGED, 0, 0);
+                });
+                if let Err(e) = message
+                    .window
+                    .set_timer(SETTINGS_TIMER_ID, SETTINGS_CHECK_INTERVAL)
+                {
+                    error!("{}", e);
+                }
+
+                let _ = self.update_checker.set(Arc::new(UpdateChecker::new(
+                    self.settings.clone(),
+                    message.window.clone(),
+                )));
+                self.check_updates_if_due();
+                if let Err(e) = message
+                    .window
+                    .set_timer(UPDATE_TIMER_ID, UPDATE_TIMER_INTERVAL)
+                {
+                    error!("{}", e);
+                }
+
+                MessageResult::Processed
+            }
+            WindowEvent::Notify { from, code } => {
+                let list_event = self
+                    .job_list
+                    .get()
+                    .and_then(|list| list.view().notification(&message));
+
+                let from_tabs = self
+                    .tabs
+                    .get()
+                    .is_some_and(|tabs| tabs.window().handle() == from);
+
+                if let Some(ListViewEvent::ItemActivated(_)) = list_event {
+                    self.hex_view_selected_job();
+                    MessageResult::Processed
+                } else if from_tabs && code == TCN_SELCHANGE {
+                    if let Some(tabs) = self.tabs.get() {
+                        self.select_page(&message.window, tabs.selected());
+                    }
+                    MessageResult::Processed
+                } else {
+                    MessageResult::Ignored
+                }
+            }
+            WindowEvent::Other(WM_DRAWITEM) if message.is_received() => {
+                let item = unsafe { &*(message.lparam as *const DRAWITEMSTRUCT) };
+                match self.listener_status.get() {
+                    Some(status) if status.status_bar().handle() == item.hwndItem => {
+                        status.draw(item);
+                        MessageResult::Value(1)
+                    }
+                    _ => MessageResult::Ignored,
+                }
+            }
+            WindowEvent::Size { .. } => {
+                self.update_docks(&message.window);
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_SPLITTER_MOVED,
+                wparam,
+                lparam,
+            } => {
+                let page_height = self.page_height(&message.window);
+                if page_height > 0 {
+                    let ratio = self.settings.split_ratio.load(Ordering::SeqCst) as i32;
+                    let list_height = page_height * ratio / 1000 + lparam as i32;
+                    let ratio = (list_height * 1000 / page_height).clamp(100, 900);
+                    self.settings
+                        .split_ratio
+                        .store(ratio as u32, Ordering::SeqCst);
+                    self.layout(&message.window);
+
+                    if wparam != 0 {
+                        self.settings.store();
+                    }
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_TASKBAR_PROGRESS,
+                wparam,
+                ..
+            } => {
+                TaskbarProgress::show(&message.window, wparam != 0);
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_TASKBAR_OVERLAY,
+                wparam,
+                ..
+            } => {
+                TaskbarOverlay::show(&message.window, Overlay::from_wparam(wparam));
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_UPDATE_JUMP_LIST,
+                ..
+            } => {
+                self.update_jump_list();
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_API_PAUSE_LISTENER,
+                wparam,
+                ..
+            } => {
+                self.set_listener_paused(&message.window, wparam != 0);
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_API_JOB_DELETED,
+                wparam,
+                ..
+            } => {
+                if let Some(job_list) = self.job_list.get() {
+                    job_list.remove_job(ConnectionId(wparam as u32));
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_NETWORK_CHANGED,
+                ..
+            } => {
+                // one change often comes with several notifications
+                if let Err(e) = message
+                    .window
+                    .set_timer(NETWORK_TIMER_ID, NETWORK_SETTLE_DELAY)
+                {
+                    error!("{}", e);
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_CHECK_FIREWALL,
+                ..
+            } => {
+                self.add_firewall_rules(&message.window, true);
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_UPDATE_CHECKED,
+                wparam,
+                ..
+            } => {
+                self.show_update_check(&message.window, wparam != 0);
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_CONTROL_ROTATE_LOG,
+                ..
+            } => {
+                self.rotate_log();
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_SETTINGS_CHANGED,
+                ..
+            } => {
+                self.apply_settings(&message.window);
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_RESEND_PROGRESS,
+                wparam,
+                lparam,
+            } => {
+                if let Some(status) = self.listener_status.get() {
+                    status.set_progress((lparam != 0).then_some(wparam as u32));
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::ContextMenu { from, position } => {
+                match self.context_menu(&message.window, from) {
+                    Some(menu) => {
+                        if let Err(e) = message.window.show_context_menu(&menu, position) {
+                            error!("{}", e);
+                        }
+                        MessageResult::Processed
+                    }
+                    None => MessageResult::Ignored,
+                }
+            }
+            WindowEvent::Other(WM_INITMENUPOPUP) => {
+                // the presets may have been added or changed since the menu was shown
+                let menu = self.presets_menu();
+                if let Err(e) = message.window.replace_menu_entries(IDM_SAVE_PRESET, &menu) {
+                    error!("{}", e);
+                }
+                // and the printers or their ports
+                let menu = self.printers_menu();
+                if let Err(e) = message
+                    .window
+                    .replace_menu_entries(IDM_RELEASE_PRINTERS, &menu)
+                {
+                    error!("{}", e);
+                }
+                message
+                    .window
+                    .check_menu_item(IDM_VIRTUAL_PRINTER, spooler::has_virtual_printer());
+                MessageResult::Ignored
+            }
+            WindowEvent::Other(WM_POWERBROADCAST) => {
+                match message.wparam as u32 {
+                    PBT_APMSUSPEND => info!("The computer is going to sleep"),
+                    // sent for every resume, also when nobody is at the computer
+                    PBT_APMRESUMEAUTOMATIC => {
+                        info!("The computer has resumed from sleep");
+                        self.restart_listeners();
+                    }
+                    _ => {}
+                }
+                MessageResult::Value(1)
+            }
+            WindowEvent::Other(WM_DROPFILES) => {
+                let files = message.dropped_files();
+                self.resend_dropped_files(&message.window, files);
+                MessageResult::Processed
+            }
+            WindowEvent::Custom {
+                msg: WM_TRAY_ICON, ..
+            } => {
+                match self.tray_icon.get().and_then(|t| t.event(&message)) {
+                    Some(TrayEvent::NotificationClicked) => self.open_notified_job(&message.window),
+                    Some(TrayEvent::Click | TrayEvent::DoubleClick) => message.window.show(),
+                    Some(TrayEvent::RightClick) => self.show_tray_menu(&message.window),
+                    Some(TrayEvent::Hover) => {
+                        if let Some(tray_status) = self.tray_status.get() {
+                            tray_status.refresh();
+                        }
+                    }
+                    _ => {}
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Close => {
+                if self.confirm_exit(&message.window) {
+                    // closed by the default handler
+                    MessageResult::Ignored
+                } else {
+                    MessageResult::Processed
+                }
+            }
+            WindowEvent::Destroy => {
+                self.save_ui_state();
+                self.save_statistics();
+                if let Some(audit_log) = self.audit_log.get() {
+                    audit_log.close();
+                }
+                if let Some(tray_icon) = self.tray_icon.get() {
+                    tray_icon.remove();
+                }
+                MessageLoop::quit();
+                MessageResult::Processed
+            }
+            _ => MessageResult::Ignored,
+        }
+    }
+}
+
+fn main() {
+    crash::install();
+
+    // the taskbar progress is driven through COM from the UI thread
+    let _ = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+
+    // the elevated copy started to add the firewall rules
+    let firewall_ports = std::env::args().find_map(|arg| {
+        arg.strip_prefix(firewall::ADD_RULES_ARGUMENT)
+            .and_then(|ports| ports.strip_prefix('='))
+            .map(str::to_owned)
+    });
+    if let Some(ports) = firewall_ports {
+        std::process::exit(firewall::add_rules_from_argument(&ports));
+    }
+
+    // the elevated copy started to change the printer ports
+    let spooler_commands = std::env::args().find_map(|arg| {
+        arg.strip_prefix(spooler::SPOOLER_ARGUMENT)
+            .and_then(|commands| commands.strip_prefix('='))
+            .map(str::to_owned)
+    });
+    if let Some(commands) = spooler_commands {
+        std::process::exit(spooler::execute_from_argument(&commands));
+    }
+
+    // a jump list task started while the application is running is handed over
+    // to it, otherwise the application is started as usual
+    let command = std::env::args().find_map(|arg| {
+        COMMAND_ARGUMENTS
+            .iter()
+            .find(|(name, _)| *name == arg)
+            .map(|(_, id)| *id)
+    });
+    if let Some(id) = command {
+        if post_to_instance(WINDOW_CLASS, WM_COMMAND, id as usize, 0) {
+            return;
+        }
+    }
+
+    let arguments = Arguments::parse(
+        std::env::args()
+            .skip(1)
+            .filter(|arg| !COMMAND_ARGUMENTS.iter().any(|(name, _)| name == arg)),
+    );
+
+    let _window = MainWindow::create(
+        format!("MiniRAW NG {}", env!("CARGO_PKG_VERSION")),
+        &arguments,
+    );
     MessageLoop::default().run();
 }
@@ -0,0 +1,165 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    os::windows::io::AsRawHandle,
+    path::Path,
+};
+
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        System::Memory::{
+            CreateFileMappingW, FlushViewOfFile, MapViewOfFile, UnmapViewOfFile, FILE_MAP_WRITE,
+            MEMORY_MAPPED_VIEW_ADDRESS, PAGE_READWRITE,
+        },
+    },
+};
+
+// the part of the file mapped at a time, a multiple of the allocation granularity
+const VIEW_SIZE: usize = 64 * 1024 * 1024;
+// the written pages are handed to the disk after this much data, so that the dirty
+// pages of a large job do not pile up in the page cache
+const FLUSH_SIZE: usize = 16 * 1024 * 1024;
+
+// A part of the file mapped into the memory
+struct View {
+    mapping: HANDLE,
+    address: MEMORY_MAPPED_VIEW_ADDRESS,
+}
+
+impl View {
+    fn data(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.address.Value as *mut u8, VIEW_SIZE) }
+    }
+
+    fn flush(&self, start: usize, end: usize) -> io::Result<()> {
+        if end > start {
+            unsafe {
+                FlushViewOfFile(
+                    self.address.Value.cast::<u8>().add(start).cast(),
+                    end - start,
+                )
+            }?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for View {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = UnmapViewOfFile(self.address);
+            let _ = CloseHandle(self.mapping);
+        }
+    }
+}
+
+// Writes a new file through the page cache by copying the data into the mapped
+// views of the file, which needs no system call per write. The file grows by a view
+// at a time and is cut to the size of the data when it is finished. An alternative
+// to UnbufferedFile, faster on some storage for very large jobs.
+pub struct MappedFile {
+    // dropped before the file
+    view: Option<View>,
+    file: File,
+    // the offset of the view in the file
+    offset: u64,
+    filled: usize,
+    flushed: usize,
+}
+
+impl MappedFile {
+    // Fails like OpenOptions::create_new if the file exists
+    pub fn create_new(path: &Path) -> io::Result<MappedFile> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        Ok(MappedFile {
+            view: None,
+            file,
+            offset: 0,
+            filled: 0,
+            flushed: 0,
+        })
+    }
+
+    // Extends the file to the end of the next view and maps it
+    fn map(&mut self) -> io::Result<&mut View> {
+        if self.view.is_none() {
+            let size = self.offset + VIEW_SIZE as u64;
+            let mapping = unsafe {
+                CreateFileMappingW(
+                    HANDLE(self.file.as_raw_handle()),
+                    None,
+                    PAGE_READWRITE,
+                    (size >> 32) as u32,
+                    size as u32,
+                    PCWSTR::null(),
+                )
+            }?;
+            let address = unsafe {
+                MapViewOfFile(
+                    mapping,
+                    FILE_MAP_WRITE,
+                    (self.offset >> 32) as u32,
+                    self.offset as u32,
+                    VIEW_SIZE,
+                )
+            };
+            if address.Value.is_null() {
+                let error = io::Error::last_os_error();
+                let _ = unsafe { CloseHandle(mapping) };
+                return Err(error);
+            }
+            self.filled = 0;
+            self.flushed = 0;
+            self.view = Some(View { mapping, address });
+        }
+        Ok(self.view.as_mut().unwrap())
+    }
+
+    // Flushes and unmaps the current view
+    fn unmap(&mut self) -> io::Result<()> {
+        if let Some(view) = self.view.take() {
+            view.flush(self.flushed, self.filled)?;
+            self.offset += self.filled as u64;
+            self.filled = 0;
+            self.flushed = 0;
+        }
+        Ok(())
+    }
+
+    // Flushes the rest of the data and cuts the file to the size of the data, the
+    // file is incomplete if it is dropped without this
+    pub fn finish(mut self) -> io::Result<()> {
+        self.unmap()?;
+        self.file.set_len(self.offset)
+    }
+}
+
+impl Write for MappedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let filled = self.filled;
+        let view = self.map()?;
+        let count = buf.len().min(VIEW_SIZE - filled);
+        view.data()[filled..filled + count].copy_from_slice(&buf[..count]);
+        self.filled += count;
+        if self.filled == VIEW_SIZE {
+            self.unmap()?;
+        } else if self.filled - self.flushed >= FLUSH_SIZE {
+            if let Some(view) = &self.view {
+                view.flush(self.flushed, self.filled)?;
+            }
+            self.flushed = self.filled;
+        }
+        Ok(count)
+    }
+
+    // The data is handed to the disk when finish returns
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
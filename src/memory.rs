@@ -0,0 +1,81 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    marker::PhantomData,
+};
+
+thread_local! {
+    // the allocated and the peak bytes while the thread is measured
+    static METER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+fn record(allocated: usize, freed: usize) {
+    let _ = METER.try_with(|meter| {
+        if let Some((current, peak)) = meter.get() {
+            let current = (current + allocated).saturating_sub(freed);
+            meter.set(Some((current, peak.max(current))));
+        }
+    });
+}
+
+// The system allocator counting the heap memory of the threads with a MemoryMeter,
+// which costs a thread-local lookup per allocation
+pub struct MeteredAllocator;
+
+unsafe impl GlobalAlloc for MeteredAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let data = unsafe { System.alloc(layout) };
+        if !data.is_null() {
+            record(layout.size(), 0);
+        }
+        data
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let data = unsafe { System.alloc_zeroed(layout) };
+        if !data.is_null() {
+            record(layout.size(), 0);
+        }
+        data
+    }
+
+    unsafe fn dealloc(&self, data: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(data, layout) };
+        record(0, layout.size());
+    }
+
+    unsafe fn realloc(&self, data: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_data = unsafe { System.realloc(data, layout, new_size) };
+        if !new_data.is_null() {
+            record(new_size, layout.size());
+        }
+        new_data
+    }
+}
+
+// Measures the peak of the heap memory allocated by the current thread until it is
+// dropped. The memory freed by other threads, e.g. of a job passed to them, is not
+// subtracted, so the peak is an upper bound.
+pub struct MemoryMeter {
+    // the meter belongs to the thread
+    _thread: PhantomData<*const ()>,
+}
+
+impl MemoryMeter {
+    pub fn start() -> MemoryMeter {
+        METER.with(|meter| meter.set(Some((0, 0))));
+        MemoryMeter {
+            _thread: PhantomData,
+        }
+    }
+
+    pub fn peak(&self) -> u64 {
+        METER.with(|meter| meter.get().map_or(0, |(_, peak)| peak as u64))
+    }
+}
+
+impl Drop for MemoryMeter {
+    fn drop(&mut self) {
+        METER.with(|meter| meter.set(None));
+    }
+}
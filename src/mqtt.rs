@@ -0,0 +1,322 @@
+use std::{
+    env,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::{
+    api::job_json,
+    http::json_string,
+    jobs::{JobEvent, ListenerState},
+    settings::AppSettings,
+};
+
+const MQTT_PORT: u16 = 1883;
+const TIMEOUT: Duration = Duration::from_secs(15);
+// the broker drops the connection after 1.5 times the keep alive without packets
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+// messages waiting to be published, newer ones are dropped while the queue is full
+const QUEUE_SIZE: usize = 100;
+
+// Packet types of MQTT 3.1.1 in the high nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PUBREC: u8 = 0x50;
+// PUBREL has the reserved flags 0010
+const PUBREL: u8 = 0x62;
+const PUBCOMP: u8 = 0x70;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+const DISCONNECT: u8 = 0xe0;
+
+struct Message {
+    // appended to the topic prefix
+    topic: &'static str,
+    payload: String,
+    // the broker keeps the last retained message of the topic for new subscribers
+    retain: bool,
+}
+
+fn push_string(packet: &mut Vec<u8>, text: &str) {
+    packet.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    packet.extend_from_slice(text.as_bytes());
+}
+
+// A minimal MQTT 3.1.1 client without authentication and TLS, meant for the broker
+// of the local network
+struct MqttSession {
+    stream: TcpStream,
+    next_packet_id: u16,
+}
+
+impl MqttSession {
+    fn connect(broker: &str, client_id: &str) -> io::Result<MqttSession> {
+        let address = if broker.contains(':') {
+            broker.to_socket_addrs()
+        } else {
+            (broker, MQTT_PORT).to_socket_addrs()
+        }?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, broker.to_owned()))?;
+
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut session = MqttSession {
+            stream,
+            next_packet_id: 1,
+        };
+
+        let mut body = Vec::new();
+        push_string(&mut body, "MQTT");
+        // protocol level 4 with a clean session
+        body.extend_from_slice(&[4, 0x02]);
+        body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+        push_string(&mut body, client_id);
+        session.send(CONNECT, &body)?;
+
+        let reply = session.expect(CONNACK)?;
+        match reply.get(1) {
+            Some(0) => Ok(session),
+            code => Err(io::Error::other(format!(
+                "connection refused with code {}",
+                code.copied().unwrap_or_default()
+            ))),
+        }
+    }
+
+    fn send(&mut self, header: u8, body: &[u8]) -> io::Result<()> {
+        let mut packet = vec![header];
+        // the remaining length is a variable length integer, 7 bits per byte
+        let mut length = body.len();
+        loop {
+            let byte = (length % 128) as u8;
+            length /= 128;
+            if length > 0 {
+                packet.push(byte | 0x80);
+            } else {
+                packet.push(byte);
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet)
+    }
+
+    // Reads the next packet and fails if its type is not the expected one, returns
+    // the body
+    fn expect(&mut self, packet_type: u8) -> io::Result<Vec<u8>> {
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte)?;
+        let header = byte[0];
+
+        let mut length = 0usize;
+        for shift in (0..28).step_by(7) {
+            let mut next = [0u8];
+            self.stream.read_exact(&mut next)?;
+            length |= ((next[0] & 0x7f) as usize) << shift;
+            if next[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        self.stream.read_exact(&mut body)?;
+
+        if header & 0xf0 == packet_type & 0xf0 {
+            Ok(body)
+        } else {
+            Err(io::Error::other(format!(
+                "unexpected packet 0x{header:02x}"
+            )))
+        }
+    }
+
+    // QoS 1 waits for the acknowledgement, QoS 2 also for the completion
+    fn publish(&mut self, topic: &str, payload: &[u8], qos: u8, retain: bool) -> io::Result<()> {
+        let packet_id = self.next_packet_id;
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+        push_string(&mut body, topic);
+        if qos > 0 {
+            body.extend_from_slice(&packet_id.to_be_bytes());
+        }
+        body.extend_from_slice(payload);
+        self.send(PUBLISH | qos << 1 | retain as u8, &body)?;
+
+        match qos {
+            1 => self.expect(PUBACK).map(|_| ()),
+            2 => {
+                self.expect(PUBREC)?;
+                self.send(PUBREL, &packet_id.to_be_bytes())?;
+                self.expect(PUBCOMP).map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn ping(&mut self) -> io::Result<()> {
+        self.send(PINGREQ, &[])?;
+        self.expect(PINGRESP).map(|_| ())
+    }
+}
+
+impl Drop for MqttSession {
+    fn drop(&mut self) {
+        let _ = self.send(DISCONNECT, &[]);
+    }
+}
+
+// Publishes the job and listener events to the mqtt_broker under mqtt_topic_prefix
+// with mqtt_qos: <prefix>/job/completed, <prefix>/job/aborted and the retained
+// <prefix>/listener with the state of the main listener. The payloads are JSON
+// like the webhook notifications. The connection is kept open by a background
+// thread and opened again after an error.
+pub struct MqttPublisher {
+    settings: Arc<AppSettings>,
+    sender: SyncSender<Message>,
+}
+
+impl MqttPublisher {
+    pub fn new(settings: Arc<AppSettings>) -> MqttPublisher {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+        {
+            let settings = settings.clone();
+            std::thread::spawn(move || Self::publish_messages(&settings, receiver));
+        }
+        MqttPublisher { settings, sender }
+    }
+
+    fn publish_messages(settings: &AppSettings, receiver: Receiver<Message>) {
+        let client_id = format!(
+            "miniraw-{}",
+            env::var("COMPUTERNAME")
+                .unwrap_or_default()
+                .to_ascii_lowercase()
+        );
+        // the broker the session is connected to
+        let mut session: Option<(String, MqttSession)> = None;
+
+        loop {
+            let message = match receiver.recv_timeout(KEEP_ALIVE / 2) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some((broker, mut connected)) = session.take() {
+                        match connected.ping() {
+                            Ok(()) => session = Some((broker, connected)),
+                            Err(e) => warn!("MQTT broker {}: {}", broker, e),
+                        }
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            let broker = settings.mqtt_broker.read().unwrap().trim().to_owned();
+            if broker.is_empty() {
+                session = None;
+                continue;
+            }
+            let topic = format!(
+                "{}/{}",
+                settings
+                    .mqtt_topic_prefix
+                    .read()
+                    .unwrap()
+                    .trim_end_matches('/'),
+                message.topic
+            );
+            let qos = settings.mqtt_qos.load(Ordering::SeqCst) as u8;
+
+            // the message is sent again once on a new connection if the broker has
+            // closed the old one
+            for _ in 0..2 {
+                let mut connected = match session.take() {
+                    Some((connected_broker, connected)) if connected_broker == broker => connected,
+                    _ => match MqttSession::connect(&broker, &client_id) {
+                        Ok(connected) => {
+                            info!("Connected to the MQTT broker {}", broker);
+                            connected
+                        }
+                        Err(e) => {
+                            warn!("Cannot connect to the MQTT broker {}: {}", broker, e);
+                            break;
+                        }
+                    },
+                };
+                match connected.publish(&topic, message.payload.as_bytes(), qos, message.retain) {
+                    Ok(()) => {
+                        session = Some((broker.clone(), connected));
+                        break;
+                    }
+                    Err(e) => warn!("MQTT broker {}: {}", broker, e),
+                }
+            }
+        }
+    }
+
+    fn publish(&self, topic: &'static str, payload: String, retain: bool) {
+        let message = Message {
+            topic,
+            payload,
+            retain,
+        };
+        if self.sender.try_send(message).is_err() {
+            warn!("Too many MQTT messages, one was dropped");
+        }
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        if self.settings.mqtt_broker.read().unwrap().trim().is_empty() {
+            return;
+        }
+
+        let host = json_string(&env::var("COMPUTERNAME").unwrap_or_default());
+        match event {
+            JobEvent::Completed(job) => self.publish(
+                "job/completed",
+                format!("{{\"host\":{},\"job\":{}}}", host, job_json(job)),
+                false,
+            ),
+            JobEvent::Aborted { source } => self.publish(
+                "job/aborted",
+                format!(
+                    "{{\"host\":{},\"source\":{}}}",
+                    host,
+                    json_string(&source.to_string())
+                ),
+                false,
+            ),
+            JobEvent::Listener(state) => {
+                let (name, details) = match state {
+                    ListenerState::Listening(port) => ("listening", format!(",\"port\":{port}")),
+                    ListenerState::Paused => ("paused", String::new()),
+                    ListenerState::Failed(error) => {
+                        ("failed", format!(",\"error\":{}", json_string(error)))
+                    }
+                };
+                self.publish(
+                    "listener",
+                    format!(
+                        "{{\"host\":{},\"state\":{}{}}}",
+                        host,
+                        json_string(name),
+                        details
+                    ),
+                    true,
+                );
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    net::{IpAddr, UdpSocket},
+    sync::Mutex,
+};
+
+use windows::{
+    core::Error,
+    Win32::{
+        Foundation::HANDLE,
+        NetworkManagement::IpHelper::{
+            CancelMibChangeNotify2, NotifyUnicastIpAddressChange, MIB_NOTIFICATION_TYPE,
+            MIB_UNICASTIPADDRESS_ROW,
+        },
+        Networking::WinSock::AF_UNSPEC,
+    },
+};
+
+use crate::ui::window::{user_message, WindowRef};
+
+// Posted to the main window when a local IP address was added, removed or changed
+pub const WM_NETWORK_CHANGED: u32 = user_message(14);
+
+// An address is available if a socket can be bound to it
+pub fn is_local_address(address: IpAddr) -> bool {
+    UdpSocket::bind((address, 0)).is_ok()
+}
+
+unsafe extern "system" fn address_changed(
+    context: *const c_void,
+    _row: *const MIB_UNICASTIPADDRESS_ROW,
+    _notification: MIB_NOTIFICATION_TYPE,
+) {
+    let window = unsafe { &*(context as *const WindowRef) };
+    let _ = window.post_user_message(WM_NETWORK_CHANGED, 0, 0);
+}
+
+// Watches the local IP addresses, e.g. for DHCP renewals and VPN connections, and
+// keeps track of the addresses the listeners are bound to
+pub struct NetworkMonitor {
+    handle: HANDLE,
+    // passed to the callback, freed after the notifications are cancelled
+    window: *mut WindowRef,
+    available: Mutex<HashMap<IpAddr, bool>>,
+}
+
+// The handle and the window are only used to cancel the notifications
+unsafe impl Send for NetworkMonitor {}
+unsafe impl Sync for NetworkMonitor {}
+
+impl NetworkMonitor {
+    pub fn start(window: WindowRef) -> Result<NetworkMonitor, Error> {
+        let window = Box::into_raw(Box::new(window));
+        let mut handle = HANDLE::default();
+        let result = unsafe {
+            NotifyUnicastIpAddressChange(
+                AF_UNSPEC,
+                Some(address_changed),
+                Some(window as *const c_void),
+                false,
+                &mut handle,
+            )
+        };
+        if let Err(e) = result.ok() {
+            drop(unsafe { Box::from_raw(window) });
+            return Err(e);
+        }
+
+        Ok(NetworkMonitor {
+            handle,
+            window,
+            available: Default::default(),
+        })
+    }
+
+    // The addresses whose availability has changed since the last call with their
+    // new state, an address seen for the first time was bound when the listeners
+    // were started and is reported only if it is gone
+    pub fn changes(&self, addresses: &[IpAddr]) -> Vec<(IpAddr, bool)> {
+        let mut available = self.available.lock().unwrap();
+        addresses
+            .iter()
+            .filter_map(|address| {
+                let now = is_local_address(*address);
+                let before = available.insert(*address, now).unwrap_or(true);
+                (before != now).then_some((*address, now))
+            })
+            .collect()
+    }
+}
+
+impl Drop for NetworkMonitor {
+    fn drop(&mut self) {
+        unsafe {
+            // waits for a running callback
+            let _ = CancelMibChangeNotify2(self.handle);
+            drop(Box::from_raw(self.window));
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    env,
+    ffi::{c_char, CStr, CString},
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use log::{info, warn};
+use windows::{
+    core::{PCSTR, PCWSTR},
+    Win32::{
+        Foundation::{FreeLibrary, HMODULE},
+        System::LibraryLoader::{GetProcAddress, LoadLibraryW},
+    },
+};
+
+use crate::{
+    api::job_json,
+    forward_health,
+    http::json_string,
+    jobs::{Job, JobEvent, ListenerState},
+    logger::STORAGE_TARGET,
+    utf16z, util,
+};
+
+// next to the executable
+const PLUGINS_DIR_NAME: &str = "plugins";
+
+// Plugins built for another version of the interface are not loaded
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+// The functions exported by a plugin DLL, all strings are UTF-8 and zero-terminated
+// and are only valid during the call:
+//
+// uint32_t miniraw_plugin_version(void), required, returns PLUGIN_API_VERSION
+// const char *miniraw_plugin_name(void), optional, the name shown in the log
+// int32_t miniraw_process_job(const char *path, const char *job_json), job processor
+// called for each saved job, e.g. to convert or to stamp the file
+// int32_t miniraw_store_job(const char *path, const char *job_json), storage backend
+// called after the processors in place of the local storage, e.g. to upload the
+// file, which is deleted when all backends have stored it
+// void miniraw_notify(const char *event_json), notification sink called with the
+// event in JSON like the webhook for each job and listener failure
+//
+// A non-zero result of the job functions is logged as an error of the plugin.
+type VersionFn = unsafe extern "C" fn() -> u32;
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type JobFn = unsafe extern "C" fn(*const c_char, *const c_char) -> i32;
+type NotifyFn = unsafe extern "C" fn(*const c_char);
+
+struct Plugin {
+    name: String,
+    process_job: Option<JobFn>,
+    store_job: Option<JobFn>,
+    notify: Option<NotifyFn>,
+}
+
+unsafe fn symbol<T: Copy>(module: HMODULE, name: &CStr) -> Option<T> {
+    let address = unsafe { GetProcAddress(module, PCSTR(name.as_ptr() as _)) }?;
+    Some(unsafe { mem::transmute_copy(&address) })
+}
+
+fn c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+impl Plugin {
+    // The module stays loaded for the lifetime of the program
+    fn load(path: &Path) -> Result<Plugin, String> {
+        let file_name = utf16z!(path.to_string_lossy());
+        let module =
+            unsafe { LoadLibraryW(PCWSTR(file_name.as_ptr())) }.map_err(|e| e.to_string())?;
+
+        let version = unsafe { symbol::<VersionFn>(module, c"miniraw_plugin_version") }
+            .map(|version| unsafe { version() });
+        if version != Some(PLUGIN_API_VERSION) {
+            unsafe {
+                let _ = FreeLibrary(module);
+            }
+            return Err(format!(
+                "not a plugin for version {} of the interface",
+                PLUGIN_API_VERSION
+            ));
+        }
+
+        let name = unsafe { symbol::<NameFn>(module, c"miniraw_plugin_name") }
+            .map(|name| unsafe { name() })
+            .filter(|name| !name.is_null())
+            .map(|name| {
+                unsafe { CStr::from_ptr(name) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            });
+
+        unsafe {
+            Ok(Plugin {
+                name,
+                process_job: symbol(module, c"miniraw_process_job"),
+                store_job: symbol(module, c"miniraw_store_job"),
+                notify: symbol(module, c"miniraw_notify"),
+            })
+        }
+    }
+
+    fn kinds(&self) -> String {
+        [
+            (self.process_job.is_some(), "processor"),
+            (self.store_job.is_some(), "storage"),
+            (self.notify.is_some(), "notifications"),
+        ]
+        .iter()
+        .filter(|(present, _)| *present)
+        .map(|(_, kind)| *kind)
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+// The path of a DLL listed in the plugins setting, only the file names of the
+// plugins directory are allowed
+fn plugin_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let path = Path::new(name);
+    let is_file_name = path.file_name().is_some_and(|file_name| file_name == name)
+        && !name.contains(['/', '\\', ':']);
+    if !is_file_name
+        || !path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("dll"))
+    {
+        return Err("not a DLL file name in the plugins directory".to_owned());
+    }
+    Ok(dir.join(name))
+}
+
+#[derive(Default)]
+struct LoadedPlugins {
+    names: Vec<String>,
+    plugins: Vec<Plugin>,
+}
+
+// The DLLs of the plugins directory listed in the plugins setting, in the order of
+// the list. No DLL is loaded unless it is listed, so that a file dropped into the
+// directory does not run with the program. The plugins are called on the thread of
+// the job, a slow plugin delays the end of the connection.
+#[derive(Default)]
+pub struct PluginHost {
+    loaded: RwLock<LoadedPlugins>,
+}
+
+impl PluginHost {
+    // Loads the plugins again when the list was changed, the jobs being received
+    // keep the plugins they have started with
+    pub fn load(&self, names: &[String]) {
+        if self.loaded.read().unwrap().names == names {
+            return;
+        }
+
+        let dir = util::app_dir().join(PLUGINS_DIR_NAME);
+        let plugins = names
+            .iter()
+            .filter_map(
+                |name| match plugin_path(&dir, name).and_then(|path| Plugin::load(&path)) {
+                    Ok(plugin) => {
+                        info!("Loaded plugin {} ({})", plugin.name, plugin.kinds());
+                        Some(plugin)
+                    }
+                    Err(e) => {
+                        warn!("Cannot load plugin {}: {}", name, e);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        *self.loaded.write().unwrap() = LoadedPlugins {
+            names: names.to_vec(),
+            plugins,
+        };
+    }
+
+    // True if the function succeeded
+    fn call_job_function(plugin: &Plugin, function: JobFn, path: &Path, json: &str) -> bool {
+        let c_path = c_string(&path.to_string_lossy());
+        let c_json = c_string(json);
+        let result = unsafe { function(c_path.as_ptr(), c_json.as_ptr()) };
+        if result != 0 {
+            warn!(
+                "Plugin {} failed with code {} for {}",
+                plugin.name,
+                result,
+                path.display()
+            );
+        }
+        result == 0
+    }
+
+    // Called for a saved job before it is published. The processors are called
+    // first, then the storage backends, the local file is deleted when all of them
+    // have stored it unless it waits to be forwarded. A failed backend leaves the
+    // file in place so that the job is not lost.
+    pub fn handle_job(&self, job: &mut Job) {
+        let Some(ref path) = job.path else {
+            return;
+        };
+        let loaded = self.loaded.read().unwrap();
+        if loaded.plugins.is_empty() {
+            return;
+        }
+
+        let json = job_json(job);
+        for plugin in &loaded.plugins {
+            if let Some(function) = plugin.process_job {
+                Self::call_job_function(plugin, function, path, &json);
+            }
+        }
+
+        let mut backends = loaded
+            .plugins
+            .iter()
+            .filter_map(|plugin| Some((plugin, plugin.store_job?)))
+            .peekable();
+        if backends.peek().is_none() {
+            return;
+        }
+        let stored = backends.fold(true, |stored, (plugin, function)| {
+            Self::call_job_function(plugin, function, path, &json) && stored
+        });
+        if !stored || forward_health::is_queued(path) {
+            return;
+        }
+        match fs::remove_file(path) {
+            Ok(()) => {
+                info!(
+                    target: STORAGE_TARGET,
+                    "[{}] Stored by the plugins, deleted {}",
+                    job.connection,
+                    path.display()
+                );
+                job.path = None;
+            }
+            Err(e) => warn!(
+                target: STORAGE_TARGET,
+                "[{}] Cannot delete the job stored by the plugins: {}: {}",
+                job.connection,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    fn notify(&self, name: &str, fields: String) {
+        let payload = c_string(&format!(
+            "{{\"event\":{},\"host\":{},{}}}",
+            json_string(name),
+            json_string(&env::var("COMPUTERNAME").unwrap_or_default()),
+            fields
+        ));
+        let loaded = self.loaded.read().unwrap();
+        for notify in loaded.plugins.iter().filter_map(|plugin| plugin.notify) {
+            unsafe { notify(payload.as_ptr()) };
+        }
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        if self.loaded.read().unwrap().plugins.is_empty() {
+            return;
+        }
+
+        match event {
+            JobEvent::Completed(job) => {
+                self.notify("job_completed", format!("\"job\":{}", job_json(job)))
+            }
+            JobEvent::Aborted { source } => self.notify(
+                "job_aborted",
+                format!("\"source\":{}", json_string(&source.to_string())),
+            ),
+            JobEvent::Listener(ListenerState::Failed(error)) => self.notify(
+                "listener_failed",
+                format!("\"error\":{}", json_string(error)),
+            ),
+            _ => {}
+        }
+    }
+}
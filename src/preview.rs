@@ -0,0 +1,341 @@
+use std::{
+    env, fs, io,
+    os::windows::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use log::{error, warn};
+use windows::Win32::{Foundation::RECT, Graphics::Gdi::*, UI::WindowsAndMessaging::*};
+
+use crate::{
+    extract,
+    jobs::{Job, JobFormat},
+    redact::Redactor,
+    tr,
+    ui::window::{
+        Font, MessageResult, WindowBuilder, WindowEvent, WindowGeometry, WindowMessage,
+        WindowMessageHandler, WindowRef,
+    },
+    IDI_MAINICON,
+};
+
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+const GHOSTSCRIPT_NAMES: &[&str] = &["gswin64c.exe", "gswin32c.exe", "gs.exe"];
+// the size of BITMAPFILEHEADER, BITMAPINFOHEADER follows it
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40;
+
+// makes the names of the rendered pages unique, so the previews of the jobs with
+// the same name can be rendered at the same time
+static NEXT_PAGE: AtomicU32 = AtomicU32::new(1);
+
+fn find_ghostscript() -> Option<PathBuf> {
+    let in_path = env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .flat_map(|dir| GHOSTSCRIPT_NAMES.iter().map(move |name| dir.join(name)))
+            .find(|p| p.is_file())
+    });
+
+    // default installation layout is C:\Program Files\gs\gs<version>\bin
+    in_path.or_else(|| {
+        let root = PathBuf::from(env::var_os("ProgramFiles")?).join("gs");
+        let mut versions = fs::read_dir(root)
+            .ok()?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect::<Vec<_>>();
+        versions.sort();
+        versions.iter().rev().find_map(|dir| {
+            GHOSTSCRIPT_NAMES
+                .iter()
+                .map(|name| dir.join("bin").join(name))
+                .find(|p| p.is_file())
+        })
+    })
+}
+
+struct Bitmap {
+    data: Vec<u8>,
+    bits_offset: usize,
+    width: i32,
+    height: i32,
+}
+
+impl Bitmap {
+    // Only the uncompressed 24 and 32-bit bitmaps of Ghostscript are accepted, the
+    // file must hold all the rows, so that StretchDIBits does not read past it
+    fn load(path: &Path) -> io::Result<Bitmap> {
+        let data = fs::read(path)?;
+        let field = |offset: usize, size: usize| {
+            data.get(offset..offset + size).map(|bytes| {
+                let mut value = [0; 4];
+                value[..size].copy_from_slice(bytes);
+                u32::from_le_bytes(value)
+            })
+        };
+
+        let bitmap = || {
+            if !data.starts_with(b"BM") {
+                return None;
+            }
+            let bits_offset = field(10, 4)? as usize;
+            let header_size = field(14, 4)? as usize;
+            let width = field(18, 4)? as i32;
+            let height = (field(22, 4)? as i32).checked_abs()?;
+            let bits_per_pixel = field(28, 2)?;
+            let compression = field(30, 4)?;
+            if header_size < INFO_HEADER_SIZE
+                || bits_offset < FILE_HEADER_SIZE + header_size
+                || width <= 0
+                || height == 0
+                || !matches!(bits_per_pixel, 24 | 32)
+                || compression != BI_RGB.0
+            {
+                return None;
+            }
+            // the rows are padded to 4 bytes
+            let stride = (width as u64 * bits_per_pixel as u64).div_ceil(32) * 4;
+            if bits_offset as u64 + stride * height as u64 > data.len() as u64 {
+                return None;
+            }
+            Some((bits_offset, width, height))
+        };
+
+        match bitmap() {
+            Some((bits_offset, width, height)) => Ok(Bitmap {
+                bits_offset,
+                width,
+                height,
+                data,
+            }),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid bitmap file",
+            )),
+        }
+    }
+}
+
+fn render_first_page(ghostscript: &Path, job_path: &Path) -> io::Result<Bitmap> {
+    let output = env::temp_dir().join(format!(
+        "miniraw-{}-{}-{}.bmp",
+        process::id(),
+        NEXT_PAGE.fetch_add(1, Ordering::SeqCst),
+        job_path.file_stem().unwrap_or_default().to_string_lossy()
+    ));
+
+    let status = Command::new(ghostscript)
+        .args([
+            "-q",
+            "-dSAFER",
+            "-dBATCH",
+            "-dNOPAUSE",
+            "-sDEVICE=bmp16m",
+            "-r96",
+            "-dFirstPage=1",
+            "-dLastPage=1",
+        ])
+        .arg(format!("-sOutputFile={}", output.display()))
+        .arg(job_path)
+        .creation_flags(CREATE_NO_WINDOW)
+        .status()?;
+
+    let result = if status.success() {
+        Bitmap::load(&output)
+    } else {
+        Err(io::Error::other(format!("Ghostscript failed: {status}")))
+    };
+
+    let _ = fs::remove_file(output);
+    result
+}
+
+pub struct PreviewWindow {
+    job: Job,
+    redactor: Redactor,
+    // released when the window is destroyed, the window keeps this object
+    text_view: Mutex<Option<WindowRef>>,
+    bitmap: Mutex<Option<Bitmap>>,
+}
+
+impl PreviewWindow {
+    // The text is redacted if the redactor is enabled, the pages are not rendered
+    // then because they would show the data as it is
+    pub fn open(job: &Job, redactor: Redactor) -> io::Result<WindowRef> {
+        let Some(path) = job.path.clone() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Discarded jobs cannot be previewed",
+            ));
+        };
+
+        let preview = Arc::new(PreviewWindow {
+            job: job.clone(),
+            redactor,
+            text_view: Mutex::new(None),
+            bitmap: Mutex::new(None),
+        });
+
+        let geometry = WindowGeometry {
+            width: Some(700),
+            height: Some(800),
+            ..Default::default()
+        };
+
+        let title = tr!(
+            "Preview - {} ({})",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            job.format
+        );
+
+        let window = WindowBuilder::window("miniraw_preview", None)
+            .geometry(geometry)
+            .title(title)
+            .icon(IDI_MAINICON)
+            .message_handler(preview.clone())
+            .build()
+            .map_err(io::Error::other)?;
+
+        let ghostscript = match job.format {
+            _ if preview.redactor.is_enabled() => None,
+            JobFormat::PostScript | JobFormat::Pdf => find_ghostscript(),
+            _ => None,
+        };
+
+        match ghostscript {
+            Some(ghostscript) => {
+                preview.set_text(tr!("Rendering the first page with Ghostscript..."));
+                let window = window.clone();
+                std::thread::spawn(move || match render_first_page(&ghostscript, &path) {
+                    Ok(bitmap) => preview.show_bitmap(&window, bitmap),
+                    Err(e) => {
+                        warn!("{}: {}", path.display(), e);
+                        preview.show_text(&path);
+                    }
+                });
+            }
+            None => preview.show_text(&path),
+        }
+
+        Ok(window)
+    }
+
+    fn text_view(&self) -> Option<WindowRef> {
+        self.text_view.lock().unwrap().clone()
+    }
+
+    fn set_text(&self, text: &str) {
+        if let Some(view) = self.text_view() {
+            let _ = view.set_text(text);
+        }
+    }
+
+    fn show_text(&self, path: &Path) {
+        match extract::read_text(self.job.format, path) {
+            Ok(text) => self.set_text(&self.redactor.redact(&text)),
+            Err(e) => {
+                error!("{}: {}", path.display(), e);
+                self.set_text(&e.to_string());
+            }
+        }
+    }
+
+    fn show_bitmap(&self, window: &WindowRef, bitmap: Bitmap) {
+        *self.bitmap.lock().unwrap() = Some(bitmap);
+        unsafe {
+            if let Some(view) = self.text_view() {
+                let _ = ShowWindow(view.handle(), SW_HIDE);
+            }
+            let _ = InvalidateRect(Some(window.handle()), None, true);
+        }
+    }
+
+    fn paint(&self, window: &WindowRef) {
+        unsafe {
+            let hwnd = window.handle();
+            let mut ps = PAINTSTRUCT::default();
+            let hdc = BeginPaint(hwnd, &mut ps);
+
+            let mut rect = RECT::default();
+            let _ = GetClientRect(hwnd, &mut rect);
+            FillRect(hdc, &rect, GetSysColorBrush(COLOR_APPWORKSPACE));
+
+            if let Some(bitmap) = self.bitmap.lock().unwrap().as_ref() {
+                // fit the page into the window keeping the aspect ratio
+                let scale = f64::min(
+                    rect.right as f64 / bitmap.width as f64,
+                    rect.bottom as f64 / bitmap.height as f64,
+                );
+                let width = (bitmap.width as f64 * scale) as i32;
+                let height = (bitmap.height as f64 * scale) as i32;
+
+                SetStretchBltMode(hdc, HALFTONE);
+                StretchDIBits(
+                    hdc,
+                    (rect.right - width) / 2,
+                    (rect.bottom - height) / 2,
+                    width,
+                    height,
+                    0,
+                    0,
+                    bitmap.width,
+                    bitmap.height,
+                    Some(bitmap.data[bitmap.bits_offset..].as_ptr() as _),
+                    bitmap.data[FILE_HEADER_SIZE..].as_ptr() as *const BITMAPINFO,
+                    DIB_RGB_COLORS,
+                    SRCCOPY,
+                );
+            }
+
+            let _ = EndPaint(hwnd, &ps);
+        }
+    }
+}
+
+impl WindowMessageHandler for PreviewWindow {
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        match message.event() {
+            WindowEvent::Create => {
+                let style = WS_CHILD
+                    | WS_VISIBLE
+                    | WS_VSCROLL
+                    | WINDOW_STYLE((ES_LEFT | ES_MULTILINE | ES_AUTOVSCROLL | ES_READONLY) as u32);
+
+                let view = WindowBuilder::edit_control(message.window)
+                    .style(style.0)
+                    .extended_style(WS_EX_CLIENTEDGE.0)
+                    .font(Font::new(14, "Consolas"))
+                    .build()
+                    .unwrap();
+
+                *self.text_view.lock().unwrap() = Some(view);
+                MessageResult::Processed
+            }
+            WindowEvent::Size { width, height } => {
+                if let Some(view) = self.text_view() {
+                    view.move_window(WindowGeometry {
+                        x: Some(6),
+                        y: Some(6),
+                        width: Some(width - 12),
+                        height: Some(height - 12),
+                    });
+                }
+                let _ = unsafe { InvalidateRect(Some(message.window.handle()), None, true) };
+                MessageResult::Processed
+            }
+            WindowEvent::Other(WM_PAINT) => {
+                self.paint(&message.window);
+                MessageResult::Processed
+            }
+            WindowEvent::Destroy => {
+                *self.text_view.lock().unwrap() = None;
+                MessageResult::Processed
+            }
+            _ => MessageResult::Ignored,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{error, warn};
+use windows::Win32::System::Threading::{
+    GetCurrentProcess, GetCurrentThread, GetProcessAffinityMask, SetPriorityClass,
+    SetThreadAffinityMask, ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS,
+    HIGH_PRIORITY_CLASS, IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS, PROCESS_CREATION_FLAGS,
+};
+
+use crate::settings::AppSettings;
+
+// the cores the threads receiving the jobs run on, 0 for all cores of the process
+static WORKER_AFFINITY: AtomicUsize = AtomicUsize::new(0);
+
+// The priority class of the process, lower than normal leaves the CPU to the print
+// server running on the same computer during bulk captures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProcessPriority {
+    Idle,
+    BelowNormal,
+    #[default]
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl ProcessPriority {
+    pub fn parse(text: &str) -> Option<ProcessPriority> {
+        match text
+            .trim()
+            .to_ascii_lowercase()
+            .replace([' ', '-'], "_")
+            .as_str()
+        {
+            "idle" => Some(ProcessPriority::Idle),
+            "below_normal" => Some(ProcessPriority::BelowNormal),
+            "" | "normal" => Some(ProcessPriority::Normal),
+            "above_normal" => Some(ProcessPriority::AboveNormal),
+            "high" => Some(ProcessPriority::High),
+            _ => None,
+        }
+    }
+
+    fn class(&self) -> PROCESS_CREATION_FLAGS {
+        match self {
+            ProcessPriority::Idle => IDLE_PRIORITY_CLASS,
+            ProcessPriority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            ProcessPriority::Normal => NORMAL_PRIORITY_CLASS,
+            ProcessPriority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+            ProcessPriority::High => HIGH_PRIORITY_CLASS,
+        }
+    }
+}
+
+// The mask of a comma-separated list of core numbers and ranges, e.g. "0-3,6",
+// 0 for an empty list. Only the cores of the first processor group can be used.
+pub fn parse_cores(text: &str) -> Option<usize> {
+    let mut mask = 0usize;
+    for item in text
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let (first, last): (u32, u32) = match item.split_once('-') {
+            Some((first, last)) => (first.trim().parse().ok()?, last.trim().parse().ok()?),
+            None => {
+                let core = item.parse().ok()?;
+                (core, core)
+            }
+        };
+        if first > last || last >= usize::BITS {
+            return None;
+        }
+        for core in first..=last {
+            mask |= 1 << core;
+        }
+    }
+    Some(mask)
+}
+
+// Sets the priority of the process and the cores of the worker threads started from
+// now on, the invalid values are logged and leave the current ones
+pub fn apply(settings: &AppSettings) {
+    match settings.process_priority() {
+        Some(priority) => {
+            if let Err(e) = unsafe { SetPriorityClass(GetCurrentProcess(), priority.class()) } {
+                error!("Cannot set the process priority: {}", e);
+            }
+        }
+        None => error!(
+            "Invalid process priority: {}",
+            settings.process_priority.read().unwrap()
+        ),
+    }
+
+    let Some(mask) = settings.worker_cores() else {
+        error!(
+            "Invalid worker cores: {}",
+            settings.worker_cores.read().unwrap()
+        );
+        return;
+    };
+    let mut process_mask = 0;
+    let mut system_mask = 0;
+    let available =
+        unsafe { GetProcessAffinityMask(GetCurrentProcess(), &mut process_mask, &mut system_mask) }
+            .map_or(usize::MAX, |()| process_mask);
+    if mask & available != mask {
+        warn!(
+            "Some of the worker cores are not available: {}",
+            settings.worker_cores.read().unwrap()
+        );
+    }
+    // the threads run on all cores rather than on none
+    WORKER_AFFINITY.store(mask & available, Ordering::SeqCst);
+}
+
+// Called by the threads receiving the jobs when they start
+pub fn pin_worker_thread() {
+    let mask = WORKER_AFFINITY.load(Ordering::SeqCst);
+    if mask != 0 {
+        unsafe { SetThreadAffinityMask(GetCurrentThread(), mask) };
+    }
+}
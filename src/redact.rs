@@ -0,0 +1,172 @@
+// The character the redacted letters and digits are replaced with, the separators
+// are kept so that the layout of the text does not change
+const MASK: char = '*';
+const MIN_CARD_DIGITS: usize = 13;
+const MAX_CARD_DIGITS: usize = 19;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    // 13 to 19 digits, optionally in groups separated by spaces or dashes, which pass
+    // the Luhn check
+    Card,
+    // a local part, @ and a domain with a dot
+    Email,
+    // # is a digit, @ is a letter and the other characters match themselves
+    // ignoring the case, e.g. ###-##-#### for US social security numbers
+    Mask(Vec<char>),
+}
+
+fn is_separator(c: char) -> bool {
+    c == ' ' || c == '-'
+}
+
+fn luhn(digits: &[u32]) -> bool {
+    let sum = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, &digit)| match (index % 2, digit * 2) {
+            (0, _) => digit,
+            (_, double) if double > 9 => double - 9,
+            (_, double) => double,
+        })
+        .sum::<u32>();
+    sum.is_multiple_of(10)
+}
+
+// The end of the longest card number starting at the text
+fn match_card(text: &[char]) -> Option<usize> {
+    let mut digits = Vec::new();
+    // the end of the text after each digit
+    let mut ends = Vec::new();
+    let mut pos = 0;
+    while digits.len() < MAX_CARD_DIGITS {
+        match text.get(pos).and_then(|c| c.to_digit(10)) {
+            Some(digit) => {
+                digits.push(digit);
+                pos += 1;
+                ends.push(pos);
+            }
+            None => break,
+        }
+        if text.get(pos).copied().is_some_and(is_separator)
+            && text.get(pos + 1).is_some_and(char::is_ascii_digit)
+        {
+            pos += 1;
+        }
+    }
+    (MIN_CARD_DIGITS..=digits.len())
+        .rev()
+        .map(|count| (count, ends[count - 1]))
+        .find(|&(count, end)| {
+            !text.get(end).is_some_and(char::is_ascii_digit) && luhn(&digits[..count])
+        })
+        .map(|(_, end)| end)
+}
+
+fn match_email(text: &[char]) -> Option<usize> {
+    let is_local = |c: &char| c.is_alphanumeric() || "._%+-".contains(*c);
+    let is_domain = |c: &char| c.is_alphanumeric() || ".-".contains(*c);
+    let at = text.iter().position(|c| !is_local(c))?;
+    if at == 0 || text[at] != '@' {
+        return None;
+    }
+    let domain = text[at + 1..]
+        .iter()
+        .take_while(|c| is_domain(c))
+        .collect::<String>();
+    // a sentence may end right after the address
+    let domain = domain.trim_end_matches(['.', '-']);
+    let (_, top_level) = domain.rsplit_once('.')?;
+    (top_level.chars().count() >= 2 && top_level.chars().all(char::is_alphabetic))
+        .then(|| at + 1 + domain.chars().count())
+}
+
+fn match_mask(mask: &[char], text: &[char]) -> Option<usize> {
+    if mask.len() > text.len() {
+        return None;
+    }
+    mask.iter()
+        .zip(text)
+        .all(|(m, c)| match m {
+            '#' => c.is_ascii_digit(),
+            '@' => c.is_alphabetic(),
+            m => m.to_lowercase().eq(c.to_lowercase()),
+        })
+        .then_some(mask.len())
+}
+
+impl Pattern {
+    fn parse(text: &str) -> Option<Pattern> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "" => None,
+            "card" => Some(Pattern::Card),
+            "email" => Some(Pattern::Email),
+            "ssn" => Some(Pattern::Mask("###-##-####".chars().collect())),
+            _ => Some(Pattern::Mask(text.trim().chars().collect())),
+        }
+    }
+
+    // The end of the match at the start of the text
+    fn match_at(&self, text: &[char]) -> Option<usize> {
+        match self {
+            Pattern::Card => match_card(text),
+            Pattern::Email => match_email(text),
+            Pattern::Mask(mask) => match_mask(mask, text),
+        }
+    }
+}
+
+// Masks sensitive data in the text shown in the previews or exported over the API,
+// the saved jobs are never changed. The patterns are a comma-separated list of
+// "card", "ssn", "email" and masks, see Pattern.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Redactor {
+    patterns: Vec<Pattern>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &str) -> Redactor {
+        Redactor {
+            patterns: patterns.split(',').filter_map(Pattern::parse).collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.patterns.is_empty()
+    }
+
+    // Only whole words are matched, a number inside a longer number is left alone
+    pub fn redact(&self, text: &str) -> String {
+        if self.patterns.is_empty() {
+            return text.to_owned();
+        }
+        let mut chars = text.chars().collect::<Vec<_>>();
+        let mut pos = 0;
+        while pos < chars.len() {
+            let at_boundary = pos == 0 || !chars[pos - 1].is_alphanumeric();
+            let end = at_boundary
+                .then(|| {
+                    self.patterns.iter().find_map(|pattern| {
+                        pattern.match_at(&chars[pos..]).filter(|&length| {
+                            length > 0
+                                && !chars.get(pos + length).is_some_and(|c| c.is_alphanumeric())
+                        })
+                    })
+                })
+                .flatten();
+            match end {
+                Some(length) => {
+                    for c in &mut chars[pos..pos + length] {
+                        if c.is_alphanumeric() {
+                            *c = MASK;
+                        }
+                    }
+                    pos += length;
+                }
+                None => pos += 1,
+            }
+        }
+        chars.into_iter().collect()
+    }
+}
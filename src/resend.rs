@@ -0,0 +1,95 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    path::{Path, PathBuf},
+};
+
+use log::{error, info, warn};
+
+use crate::ui::window::{user_message, WindowRef};
+
+// Posted to the main window while the files are being sent, wparam is the percentage
+// of all bytes sent and lparam is 0 once the last file is done
+pub const WM_RESEND_PROGRESS: u32 = user_message(4);
+
+// Reports the overall progress of a resend when the percentage changes
+struct Progress {
+    window: WindowRef,
+    total: u64,
+    sent: u64,
+    percent: Option<usize>,
+}
+
+impl Progress {
+    fn post(&mut self, active: bool) {
+        let percent = (self.sent * 100)
+            .checked_div(self.total)
+            .unwrap_or(100)
+            .min(100) as usize;
+        if self.percent != Some(percent) || !active {
+            self.percent = Some(percent);
+            if let Err(e) =
+                self.window
+                    .post_user_message(WM_RESEND_PROGRESS, percent, active as isize)
+            {
+                warn!("Cannot update the resend progress: {}", e);
+            }
+        }
+    }
+
+    fn add(&mut self, bytes: u64) {
+        self.sent += bytes;
+        self.post(true);
+    }
+}
+
+fn send_file(path: &Path, target: &str, progress: &mut Progress) -> io::Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let mut stream = TcpStream::connect(target)?;
+    let mut buffer = [0u8; 65536];
+    let mut bytes = 0;
+    loop {
+        let size = file.read(&mut buffer)?;
+        if size == 0 {
+            break;
+        }
+        stream.write_all(&buffer[..size])?;
+        bytes += size as u64;
+        progress.add(size as u64);
+    }
+    stream.shutdown(Shutdown::Write)?;
+    Ok(bytes)
+}
+
+// Sends the files one by one to a RAW printer port in the background,
+// the progress is posted to the window with WM_RESEND_PROGRESS
+pub fn resend_files(paths: Vec<PathBuf>, target: String, window: WindowRef) {
+    std::thread::spawn(move || {
+        let mut progress = Progress {
+            window,
+            total: paths
+                .iter()
+                .filter_map(|path| fs::metadata(path).ok())
+                .map(|meta| meta.len())
+                .sum(),
+            sent: 0,
+            percent: None,
+        };
+        progress.post(true);
+
+        for path in paths {
+            match send_file(&path, &target, &mut progress) {
+                Ok(bytes) => info!(
+                    "Sent {} bytes from {} to {}",
+                    bytes,
+                    path.file_name().unwrap_or_default().to_string_lossy(),
+                    target
+                ),
+                Err(e) => error!("{}: {}", path.display(), e),
+            }
+        }
+
+        progress.post(false);
+    });
+}
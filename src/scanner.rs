@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::windows::process::CommandExt,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+const ICAP_PORT: u16 = 1344;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// the server answers after it has scanned the whole job
+const SCAN_TIMEOUT: Duration = Duration::from_secs(120);
+const CHUNK_SIZE: usize = 65536;
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+fn invalid_url(url: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid ICAP URL: {}", url),
+    )
+}
+
+// The file name in the URL of the encapsulated HTTP request
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+// The threat reported by the common ICAP servers, e.g.
+// "X-Infection-Found: Type=0; Resolution=2; Threat=Eicar-Test-Signature;"
+fn threat_name(name: &str, value: &str) -> Option<String> {
+    match name.to_ascii_lowercase().as_str() {
+        "x-infection-found" => value
+            .split(';')
+            .find_map(|part| part.trim().strip_prefix("Threat="))
+            .map(str::to_owned),
+        "x-virus-id" | "x-violations-found" => Some(value.trim().to_owned()),
+        _ => None,
+    }
+}
+
+// Sends the job as the body of an HTTP response in a RESPMOD request (RFC 3507),
+// 204 is a clean job and 200 is a job the server would have changed
+fn icap_scan(url: &str, path: &Path) -> io::Result<Option<String>> {
+    let rest = url
+        .strip_prefix("icap://")
+        .ok_or_else(|| invalid_url(url))?;
+    let authority = rest.split('/').next().unwrap_or_default();
+    let address = if authority.contains(':') {
+        authority.to_socket_addrs()
+    } else {
+        (authority, ICAP_PORT).to_socket_addrs()
+    }?
+    .next()
+    .ok_or_else(|| invalid_url(url))?;
+
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let request_header = format!(
+        "GET /{} HTTP/1.1\r\nHost: miniraw\r\n\r\n",
+        percent_encode(&name)
+    );
+    let response_header = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {size}\r\n\r\n"
+    );
+    let icap_header = format!(
+        "RESPMOD {} ICAP/1.0\r\nHost: {}\r\nAllow: 204\r\nEncapsulated: req-hdr=0, res-hdr={}, res-body={}\r\n\r\n",
+        url,
+        authority,
+        request_header.len(),
+        request_header.len() + response_header.len()
+    );
+
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(SCAN_TIMEOUT))?;
+    stream.set_write_timeout(Some(SCAN_TIMEOUT))?;
+    stream.write_all(icap_header.as_bytes())?;
+    stream.write_all(request_header.as_bytes())?;
+    stream.write_all(response_header.as_bytes())?;
+    // the body is chunked like in HTTP/1.1
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        stream.write_all(format!("{count:x}\r\n").as_bytes())?;
+        stream.write_all(&buffer[..count])?;
+        stream.write_all(b"\r\n")?;
+    }
+    stream.write_all(b"0\r\n\r\n")?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    let mut threat = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            threat = threat.or_else(|| threat_name(name.trim(), value));
+        }
+    }
+    match status.split_whitespace().nth(1) {
+        Some("204") => Ok(None),
+        Some("200") => {
+            Ok(Some(threat.unwrap_or_else(|| {
+                "the job was blocked by the ICAP server".to_owned()
+            })))
+        }
+        _ => Err(io::Error::other(format!(
+            "The ICAP server returned {}",
+            status.trim()
+        ))),
+    }
+}
+
+// A command line with {path} for the quoted path of the job, the path is appended
+// if it has none. An exit code other than 0 is a threat.
+fn command_scan(command: &str, path: &Path) -> io::Result<Option<String>> {
+    let command = command.trim();
+    let (program, arguments) = match command.strip_prefix('"') {
+        Some(rest) => rest.split_once('"').unwrap_or((rest, "")),
+        None => command.split_once(' ').unwrap_or((command, "")),
+    };
+    let quoted = format!("\"{}\"", path.display());
+    let arguments = if arguments.contains("{path}") {
+        arguments.replace("{path}", &quoted)
+    } else {
+        format!("{arguments} {quoted}")
+    };
+    let status = Command::new(program)
+        .raw_arg(arguments.trim())
+        .creation_flags(CREATE_NO_WINDOW)
+        .status()?;
+    Ok((!status.success()).then(|| format!("the scanner command exited with {}", status)))
+}
+
+// How the received jobs are checked for malware before they are handed over to
+// the script and the notifications, e.g. weaponized PostScript from an untrusted
+// network. The jobs are checked after they have been received, the forwarded
+// data is not held back.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scanner {
+    // icap://host[:port]/service, empty to not use an ICAP server
+    pub icap_url: String,
+    // command line of a local scanner, empty to not run one
+    pub command: String,
+    // where the jobs failing the scan are moved to
+    pub quarantine_dir: PathBuf,
+}
+
+impl Scanner {
+    pub fn is_enabled(&self) -> bool {
+        !self.icap_url.trim().is_empty() || !self.command.trim().is_empty()
+    }
+
+    fn verdict(result: io::Result<Option<String>>) -> Result<(), String> {
+        match result {
+            Ok(None) => Ok(()),
+            Ok(Some(threat)) => Err(threat),
+            Err(e) => Err(format!("the scan failed: {}", e)),
+        }
+    }
+
+    // Err with the reason if the job has a threat or if it can't be scanned, the
+    // command runs first
+    pub fn scan(&self, path: &Path) -> Result<(), String> {
+        if !self.command.trim().is_empty() {
+            Self::verdict(command_scan(&self.command, path))?;
+        }
+        if !self.icap_url.trim().is_empty() {
+            Self::verdict(icap_scan(self.icap_url.trim(), path))?;
+        }
+        Ok(())
+    }
+
+    // Moves the job into the quarantine directory, a number is added to the name
+    // if a file with the same name is there
+    pub fn quarantine(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.quarantine_dir)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut target = self.quarantine_dir.join(&*name);
+        let mut number = 1;
+        while target.exists() {
+            target = self.quarantine_dir.join(format!("{number}-{name}"));
+            number += 1;
+        }
+        // the quarantine may be on another volume
+        if fs::rename(path, &target).is_err() {
+            fs::copy(path, &target)?;
+            fs::remove_file(path)?;
+        }
+        Ok(target)
+    }
+}
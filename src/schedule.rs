@@ -0,0 +1,137 @@
+use time::OffsetDateTime;
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+// What the listeners do with the jobs outside the schedule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OffHours {
+    // the connections are closed right away
+    #[default]
+    Reject,
+    // the jobs are received but neither saved nor forwarded
+    Discard,
+    // the jobs are only forwarded, listeners without a forward target reject them
+    Forward,
+}
+
+impl OffHours {
+    pub fn parse(text: &str) -> Option<OffHours> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "" | "reject" => Some(OffHours::Reject),
+            "discard" => Some(OffHours::Discard),
+            "forward" => Some(OffHours::Forward),
+            _ => None,
+        }
+    }
+
+    // False if the connections are closed outside the schedule
+    pub fn accepts(&self, forward_target: &str) -> bool {
+        match self {
+            OffHours::Reject => false,
+            OffHours::Discard => true,
+            OffHours::Forward => !forward_target.trim().is_empty(),
+        }
+    }
+}
+
+// Days of the week with the hours, a period ending before or at its start ends on
+// the next day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Period {
+    // bit 0 is Monday
+    days: u8,
+    // minutes since midnight
+    start: u16,
+    end: u16,
+}
+
+// The first three letters of the English name are enough
+fn parse_day(text: &str) -> Option<u32> {
+    let text = text.trim().to_ascii_lowercase();
+    let prefix = text.get(..3)?;
+    DAY_NAMES
+        .iter()
+        .position(|name| *name == prefix)
+        .map(|day| day as u32)
+}
+
+// "Mon", "Mon-Fri" or a list of them separated by commas, a range may wrap around
+// the end of the week, e.g. "Sat-Mon"
+fn parse_days(text: &str) -> Option<u8> {
+    text.split(',').try_fold(0u8, |days, part| {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+            None => {
+                let day = parse_day(part)?;
+                (day, day)
+            }
+        };
+        let count = (last + 7 - first) % 7 + 1;
+        Some((0..count).fold(days, |days, offset| days | 1 << ((first + offset) % 7)))
+    })
+}
+
+// "HH:MM", 24:00 is the end of the day
+fn parse_time(text: &str) -> Option<u16> {
+    let (hours, minutes) = text.trim().split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u16>().ok()?, minutes.parse::<u16>().ok()?);
+    let time = hours.checked_mul(60)?.checked_add(minutes)?;
+    (minutes < 60 && time <= MINUTES_PER_DAY).then_some(time)
+}
+
+impl Period {
+    // "[days] HH:MM-HH:MM", every day if the days are omitted
+    fn parse(text: &str) -> Option<Period> {
+        let (days, hours) = match text.trim().rsplit_once(char::is_whitespace) {
+            Some((days, hours)) => (parse_days(days)?, hours),
+            None => (0x7f, text.trim()),
+        };
+        let (start, end) = hours.split_once('-')?;
+        Some(Period {
+            days,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+
+    fn contains(&self, day: u32, minute: u16) -> bool {
+        let has_day = |day: u32| self.days & 1 << day != 0;
+        if self.start < self.end {
+            has_day(day) && (self.start..self.end).contains(&minute)
+        } else {
+            (has_day(day) && minute >= self.start) || (has_day((day + 6) % 7) && minute < self.end)
+        }
+    }
+}
+
+// The hours the listeners accept the jobs, e.g. "Mon-Fri 08:00-18:00; Sat 09:00-13:00",
+// the periods are separated by semicolons. An empty schedule is always open.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    periods: Vec<Period>,
+}
+
+impl Schedule {
+    pub fn parse(text: &str) -> Result<Schedule, String> {
+        let periods = text
+            .split(';')
+            .filter(|period| !period.trim().is_empty())
+            .map(|period| {
+                Period::parse(period)
+                    .ok_or_else(|| format!("Invalid schedule entry: {}", period.trim()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Schedule { periods })
+    }
+
+    pub fn is_open(&self, time: OffsetDateTime) -> bool {
+        let day = time.weekday().number_days_from_monday() as u32;
+        let minute = time.hour() as u16 * 60 + time.minute() as u16;
+        self.periods.is_empty()
+            || self
+                .periods
+                .iter()
+                .any(|period| period.contains(day, minute))
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::SystemTime,
+};
+
+use log::{info, warn};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+use crate::{
+    jobs::{ConnectionId, Job, JobFormat},
+    listener::safe_file_name,
+};
+
+// Names of the functions called by the listeners, a script may define any of them
+const ON_CONNECTION: &str = "on_connection";
+const ON_JOB_HEADER: &str = "on_job_header";
+const ON_JOB_COMPLETE: &str = "on_job_complete";
+
+// a runaway script is stopped after this number of operations
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+// Where a job is saved, may be changed by on_job_header before the file is created
+#[derive(Debug, Clone)]
+pub struct JobRoute {
+    pub file_name: String,
+    pub output_dir: PathBuf,
+    pub forward_target: String,
+    pub discard: bool,
+}
+
+struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    ast: AST,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn string_value(map: &Map, key: &str) -> Option<String> {
+    map.get(key)
+        .and_then(|value| value.clone().into_string().ok())
+}
+
+// The Rhai script of the script_path setting with the hooks of the job pipeline:
+//
+// on_connection(info) is called with #{source, port} when a client connects,
+// returning false closes the connection.
+// on_job_header(job) is called with #{connection, source, port, format, head,
+// file_name, output_dir, forward_target, discard} once the first bytes of the job
+// are received, returning false rejects the job and returning a map with any of
+// file_name, output_dir, forward_target and discard changes where the job goes.
+// on_job_complete(job) is called with #{connection, source, port, format, size,
+// path} when the job is saved, returning false deletes the file and returning a map
+// with file_name renames it.
+//
+// Errors of the script are logged and the job is handled as without the script.
+pub struct ScriptHooks {
+    engine: Engine,
+    script: RwLock<Option<Script>>,
+}
+
+impl Default for ScriptHooks {
+    fn default() -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        // print and debug of the script go to the log
+        engine.on_print(|text| info!("{}", text));
+        engine.on_debug(|text, _, _| info!("{}", text));
+        ScriptHooks {
+            engine,
+            script: Default::default(),
+        }
+    }
+}
+
+impl ScriptHooks {
+    // Compiles the script again when its path or the file was changed, an empty
+    // path removes the hooks
+    pub fn load(&self, path: &Path) {
+        let modified = modified(path);
+        {
+            let script = self.script.read().unwrap();
+            let loaded = script
+                .as_ref()
+                .map(|script| (script.path.as_path(), script.modified));
+            if loaded == Some((path, modified)) || (loaded.is_none() && path == Path::new("")) {
+                return;
+            }
+        }
+
+        let mut script = self.script.write().unwrap();
+        if path == Path::new("") {
+            *script = None;
+            return;
+        }
+        match self.engine.compile_file(path.to_owned()) {
+            Ok(ast) => {
+                info!("Loaded the script {}", path.display());
+                *script = Some(Script {
+                    path: path.to_owned(),
+                    modified,
+                    ast,
+                });
+            }
+            Err(e) => {
+                warn!("Cannot load the script {}: {}", path.display(), e);
+                // not compiled again until the file is changed
+                *script = Some(Script {
+                    path: path.to_owned(),
+                    modified,
+                    ast: AST::empty(),
+                });
+            }
+        }
+    }
+
+    // The result of the hook, None if the script has no such function or it failed
+    fn call(&self, name: &str, argument: Map) -> Option<Dynamic> {
+        let script = self.script.read().unwrap();
+        let script = script.as_ref()?;
+        if !script
+            .ast
+            .iter_functions()
+            .any(|function| function.name == name)
+        {
+            return None;
+        }
+        match self.engine.call_fn::<Dynamic>(
+            &mut Scope::new(),
+            &script.ast,
+            name,
+            (Dynamic::from(argument),),
+        ) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("Script error in {}: {}", name, e);
+                None
+            }
+        }
+    }
+
+    // False if the connection is rejected by the script
+    pub fn on_connection(&self, source: SocketAddr, port: u16) -> bool {
+        let mut info = Map::new();
+        info.insert("source".into(), source.to_string().into());
+        info.insert("port".into(), (port as i64).into());
+        self.call(ON_CONNECTION, info)
+            .and_then(|result| result.as_bool().ok())
+            .unwrap_or(true)
+    }
+
+    // False if the job is rejected by the script, the route is updated with the
+    // values returned by it
+    pub fn on_job_header(
+        &self,
+        connection: ConnectionId,
+        source: SocketAddr,
+        port: u16,
+        head: &[u8],
+        route: &mut JobRoute,
+    ) -> bool {
+        let mut job = Map::new();
+        job.insert("connection".into(), (connection.0 as i64).into());
+        job.insert("source".into(), source.to_string().into());
+        job.insert("port".into(), (port as i64).into());
+        job.insert("format".into(), JobFormat::detect(head).to_string().into());
+        job.insert("head".into(), Dynamic::from_blob(head.to_vec()));
+        job.insert("file_name".into(), route.file_name.clone().into());
+        job.insert(
+            "output_dir".into(),
+            route.output_dir.display().to_string().into(),
+        );
+        job.insert("forward_target".into(), route.forward_target.clone().into());
+        job.insert("discard".into(), route.discard.into());
+
+        let Some(result) = self.call(ON_JOB_HEADER, job) else {
+            return true;
+        };
+        if let Ok(accepted) = result.as_bool() {
+            return accepted;
+        }
+        if let Some(changes) = result.try_cast::<Map>() {
+            if let Some(file_name) = string_value(&changes, "file_name") {
+                route.file_name = file_name;
+            }
+            if let Some(output_dir) = string_value(&changes, "output_dir") {
+                route.output_dir = PathBuf::from(output_dir);
+            }
+            if let Some(forward_target) = string_value(&changes, "forward_target") {
+                route.forward_target = forward_target;
+            }
+            if let Some(discard) = changes
+                .get("discard")
+                .and_then(|value| value.as_bool().ok())
+            {
+                route.discard = discard;
+            }
+        }
+        true
+    }
+
+    // False if the script rejects the job, its file is deleted then. A file renamed by
+    // the script keeps its directory.
+    pub fn on_job_complete(&self, job: &mut Job, port: u16) -> bool {
+        let mut info = Map::new();
+        info.insert("connection".into(), (job.connection.0 as i64).into());
+        info.insert("source".into(), job.source.to_string().into());
+        info.insert("port".into(), (port as i64).into());
+        info.insert("format".into(), job.format.to_string().into());
+        info.insert("size".into(), (job.size as i64).into());
+        info.insert(
+            "path".into(),
+            job.path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
+                .into(),
+        );
+
+        let Some(result) = self.call(ON_JOB_COMPLETE, info) else {
+            return true;
+        };
+        if result.as_bool().ok() == Some(false) {
+            if let Some(ref path) = job.path {
+                let _ = fs::remove_file(path);
+            }
+            return false;
+        }
+
+        let file_name = result
+            .try_cast::<Map>()
+            .and_then(|changes| string_value(&changes, "file_name"));
+        if let (Some(file_name), Some(path)) = (file_name, job.path.as_mut()) {
+            let file_name = safe_file_name(&file_name);
+            let new_path = path.with_file_name(&file_name);
+            match fs::rename(&*path, &new_path) {
+                Ok(()) => {
+                    info!(
+                        "[{}] Renamed by the script to {}",
+                        job.connection, file_name
+                    );
+                    *path = new_path;
+                }
+                Err(e) => warn!("[{}] {}: {}", job.connection, new_path.display(), e),
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,1535 @@
+use std::{
+    env, fs, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{warn, LevelFilter};
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::ERROR_MORE_DATA,
+        Security::Cryptography::{BCryptGenRandom, BCRYPT_USE_SYSTEM_PREFERRED_RNG},
+        System::Registry::{
+            RegCloseKey, RegCreateKeyW, RegDeleteKeyValueW, RegDeleteTreeW, RegOpenKeyW,
+            RegQueryValueExW, RegSetKeyValueW, HKEY, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE,
+            REG_DWORD, REG_SZ,
+        },
+    },
+};
+
+use crate::{
+    accounting::AccountingPeriod,
+    admission::{ConnectionLimits, DeadlineAction},
+    cli::Arguments,
+    email,
+    forward_health::ForwardDown,
+    listener::{ListenerConfig, WriteMode, DEFAULT_NAME_TEMPLATE, RAW_PORT},
+    priority::{self, ProcessPriority},
+    redact::Redactor,
+    scanner::Scanner,
+    schedule::{OffHours, Schedule},
+    toml::{Document, Value},
+    ui::window::Font,
+    utf16z, util,
+};
+
+pub const DEFAULT_LOG_FONT_HEIGHT: u32 = 14;
+
+const CONFIG_FILE_NAME: &str = "miniraw.toml";
+const CONFIG_HEADER: &str =
+    "# MiniRAW NG configuration, written by the program on every change\n\n";
+const EXPORT_HEADER: &str =
+    "# MiniRAW NG settings, can be imported with Tools > Import settings\n\n";
+
+const PRESET_HEADER: &str = "# MiniRAW NG preset, applied with Tools > Presets\n\n";
+const PRESETS_DIR_NAME: &str = "presets";
+
+const ENV_PREFIX: &str = "MINIRAW_";
+
+const REG_KEY_NAME: &str = "Software\\MiniRAW NG";
+// machine-wide values set by the administrator, e.g. with a group policy
+const REG_POLICY_KEY_NAME: &str = "Software\\Policies\\MiniRAW NG";
+const REG_RUN_KEY_NAME: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+const REG_RUN_VALUE: &str = "MiniRAW NG";
+const REG_PORT: &str = "port";
+const REG_LISTEN_ADDRESS: &str = "listen_address";
+const REG_SCHEDULE: &str = "schedule";
+const REG_OFF_HOURS: &str = "off_hours";
+const REG_OUTPUT_DIR: &str = "output_dir";
+const REG_PROFILES: &str = "profiles";
+const REG_DISCARD: &str = "discard";
+const REG_LOG_FONT: &str = "log_font";
+const REG_AUTO_SCROLL: &str = "auto_scroll";
+const REG_NOTIFY_JOBS: &str = "notify_jobs";
+const REG_FLASH_JOBS: &str = "flash_jobs";
+const REG_RESEND_TARGET: &str = "resend_target";
+const REG_API_PORT: &str = "api_port";
+const REG_API_ADDRESS: &str = "api_address";
+const REG_API_TOKEN: &str = "api_token";
+const REG_GRPC_PORT: &str = "grpc_port";
+const REG_WEBHOOK_URL: &str = "webhook_url";
+const REG_SMTP_SERVER: &str = "smtp_server";
+const REG_EMAIL_FROM: &str = "email_from";
+const REG_EMAIL_TO: &str = "email_to";
+const REG_EMAIL_TRIGGERS: &str = "email_triggers";
+const REG_MQTT_BROKER: &str = "mqtt_broker";
+const REG_MQTT_TOPIC_PREFIX: &str = "mqtt_topic_prefix";
+const REG_MQTT_QOS: &str = "mqtt_qos";
+const REG_OTLP_ENDPOINT: &str = "otlp_endpoint";
+const REG_ACCOUNTING_PERIOD: &str = "accounting_period";
+const REG_ACCOUNTING_DIR: &str = "accounting_dir";
+const REG_ICAP_URL: &str = "icap_url";
+const REG_SCAN_COMMAND: &str = "scan_command";
+const REG_QUARANTINE_DIR: &str = "quarantine_dir";
+const REG_AUDIT_LOG: &str = "audit_log";
+const REG_AUDIT_ANCHOR_URL: &str = "audit_anchor_url";
+const REG_JOB_SECRET: &str = "job_secret";
+const REG_MAX_CONNECTIONS: &str = "max_connections";
+const REG_MAX_HOST_CONNECTIONS: &str = "max_host_connections";
+const REG_IDLE_TIMEOUT_SECONDS: &str = "idle_timeout_seconds";
+const REG_MIN_BYTES_PER_SECOND: &str = "min_bytes_per_second";
+const REG_MAX_JOB_MINUTES: &str = "max_job_minutes";
+const REG_JOB_DEADLINE_ACTION: &str = "job_deadline_action";
+const REG_FORWARD_CHECK_SECONDS: &str = "forward_check_seconds";
+const REG_FORWARD_CHECK_PJL: &str = "forward_check_pjl";
+const REG_FORWARD_TARGET_DOWN: &str = "forward_target_down";
+const REG_WRITE_MODE: &str = "write_mode";
+const REG_PROCESS_PRIORITY: &str = "process_priority";
+const REG_WORKER_CORES: &str = "worker_cores";
+const REG_REDACT_PATTERNS: &str = "redact_patterns";
+const REG_MIN_FREE_SPACE_MB: &str = "min_free_space_mb";
+const REG_UPDATE_CHECK_HOURS: &str = "update_check_hours";
+const REG_DOWNLOAD_UPDATES: &str = "download_updates";
+const REG_CHECK_FIREWALL: &str = "check_firewall";
+const REG_SCRIPT_PATH: &str = "script_path";
+const REG_PLUGINS: &str = "plugins";
+const REG_PRINTER_DRIVER: &str = "printer_driver";
+const REG_START_MINIMIZED: &str = "start_minimized";
+const REG_LANGUAGE: &str = "language";
+const REG_SPLIT_RATIO: &str = "split_ratio";
+const REG_LOG_LEVEL: &str = "log_level";
+const REG_LOG_MODULES: &str = "log_modules";
+const REG_HEXDUMP_SIZE: &str = "hexdump_size";
+const REG_LOG_MAX_LINES: &str = "log_max_lines";
+const REG_ACTIVE_PAGE: &str = "active_page";
+const REG_JOB_COLUMN_WIDTHS: &str = "job_column_widths";
+const REG_JOB_COLUMN_ORDER: &str = "job_column_order";
+
+// not included in the diagnostics
+const SECRET_SETTINGS: [&str; 3] = [REG_API_TOKEN, REG_WEBHOOK_URL, REG_JOB_SECRET];
+
+// the language and the window layout are kept when switching between presets
+const NON_PRESET_SETTINGS: [&str; 6] = [
+    REG_LANGUAGE,
+    REG_LOG_FONT,
+    REG_SPLIT_RATIO,
+    REG_ACTIVE_PAGE,
+    REG_JOB_COLUMN_WIDTHS,
+    REG_JOB_COLUMN_ORDER,
+];
+
+// The size of the value is queried first, so a value of any length can be read
+fn load_value(root: HKEY, key: &str, name: &str) -> Option<Vec<u8>> {
+    unsafe {
+        let mut hkey = HKEY::default();
+        let key_name = utf16z!(key);
+        let value_name = utf16z!(name);
+        if RegOpenKeyW(root, PCWSTR(key_name.as_ptr()), &mut hkey).is_err() {
+            return None;
+        }
+        let mut result = None;
+        let mut size = 0u32;
+        if RegQueryValueExW(
+            hkey,
+            PCWSTR(value_name.as_ptr()),
+            None,
+            None,
+            None,
+            Some(&mut size),
+        )
+        .is_ok()
+        {
+            // the value can be changed between the calls, then it is read again
+            loop {
+                let mut data = vec![0u8; size as usize];
+                match RegQueryValueExW(
+                    hkey,
+                    PCWSTR(value_name.as_ptr()),
+                    None,
+                    None,
+                    Some(data.as_mut_ptr()),
+                    Some(&mut size),
+                ) {
+                    ERROR_MORE_DATA => continue,
+                    rc if rc.is_ok() => {
+                        data.truncate(size as usize);
+                        result = Some(data);
+                    }
+                    _ => {}
+                }
+                break;
+            }
+        }
+        let _ = RegCloseKey(hkey);
+        result
+    }
+}
+
+fn store_value(key: &str, name: &str, kind: u32, data: &[u8]) {
+    unsafe {
+        let mut hkey = HKEY::default();
+        let key_name = utf16z!(key);
+        let value_name = utf16z!(name);
+        let rc = RegCreateKeyW(HKEY_CURRENT_USER, PCWSTR(key_name.as_ptr()), &mut hkey);
+        if rc.is_ok() {
+            let _ = RegSetKeyValueW(
+                hkey,
+                PCWSTR::null(),
+                PCWSTR(value_name.as_ptr()),
+                kind,
+                Some(data.as_ptr() as _),
+                data.len() as _,
+            );
+            let _ = RegCloseKey(hkey);
+        }
+    }
+}
+
+fn load_dword(root: HKEY, key: &str, name: &str) -> Option<u32> {
+    let data = load_value(root, key, name)?;
+    Some(u32::from_ne_bytes(data.get(..4)?.try_into().unwrap()))
+}
+
+fn store_dword(key: &str, name: &str, value: u32) {
+    store_value(key, name, REG_DWORD.0, &value.to_ne_bytes());
+}
+
+fn load_string(root: HKEY, key: &str, name: &str) -> Option<String> {
+    let data = load_value(root, key, name)?;
+    let wide = data
+        .chunks_exact(2)
+        .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16(&wide).ok()
+}
+
+fn store_string(key: &str, name: &str, value: &str) {
+    let data = utf16z!(value)
+        .iter()
+        .flat_map(|c| c.to_ne_bytes())
+        .collect::<Vec<_>>();
+    store_value(key, name, REG_SZ.0, &data);
+}
+
+// 128 bits from the random number generator of the system
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    unsafe { BCryptGenRandom(None, &mut bytes, BCRYPT_USE_SYSTEM_PREFERRED_RNG) }
+        .ok()
+        .expect("the system random number generator failed");
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn startup_command() -> String {
+    let exe = env::current_exe().unwrap_or_default();
+    format!("\"{}\" --minimized", exe.display())
+}
+
+pub fn is_run_at_startup() -> bool {
+    load_string(HKEY_CURRENT_USER, REG_RUN_KEY_NAME, REG_RUN_VALUE).is_some()
+}
+
+// Registers the application in the per-user Run key to start hidden on logon
+pub fn set_run_at_startup(flag: bool) {
+    if flag {
+        store_string(REG_RUN_KEY_NAME, REG_RUN_VALUE, &startup_command());
+    } else {
+        let key_name = utf16z!(REG_RUN_KEY_NAME);
+        let value_name = utf16z!(REG_RUN_VALUE);
+        unsafe {
+            let _ = RegDeleteKeyValueW(
+                HKEY_CURRENT_USER,
+                PCWSTR(key_name.as_ptr()),
+                PCWSTR(value_name.as_ptr()),
+            );
+        }
+    }
+}
+
+// Next to the executable, like the received jobs
+fn default_config_path() -> PathBuf {
+    util::app_dir().join(CONFIG_FILE_NAME)
+}
+
+fn value_to_u32(value: &Value) -> Option<u32> {
+    match value {
+        Value::Integer(value) => (*value).try_into().ok(),
+        _ => None,
+    }
+}
+
+fn list_to_value(list: &[i32]) -> Value {
+    Value::Array(list.iter().map(|item| Value::Integer(*item as _)).collect())
+}
+
+fn value_to_list(value: &Value) -> Option<Vec<i32>> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Integer(item) => (*item).try_into().ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+// Profiles are stored as an array of inline tables
+fn profiles_to_value(profiles: &[PortProfile]) -> Value {
+    Value::Array(
+        profiles
+            .iter()
+            .map(|profile| {
+                let mut entries = vec![("port".to_owned(), Value::Integer(profile.port as _))];
+                for (name, value) in [
+                    ("output_dir", &profile.output_dir),
+                    ("name_template", &profile.name_template),
+                    ("forward_target", &profile.forward_target),
+                ] {
+                    if !value.is_empty() {
+                        entries.push((name.to_owned(), Value::String(value.clone())));
+                    }
+                }
+                if profile.discard {
+                    entries.push(("discard".to_owned(), Value::Bool(true)));
+                }
+                Value::Table(entries)
+            })
+            .collect(),
+    )
+}
+
+// Each profile needs a port which is not used by another profile
+fn value_to_profiles(value: &Value) -> Option<Vec<PortProfile>> {
+    let Value::Array(items) = value else {
+        return None;
+    };
+
+    let mut profiles = Vec::<PortProfile>::new();
+    for item in items {
+        let Value::Table(entries) = item else {
+            return None;
+        };
+        let mut profile = PortProfile::default();
+        for (name, value) in entries {
+            match (name.as_str(), value) {
+                ("port", value) => {
+                    profile.port = value_to_u32(value).and_then(|port| port.try_into().ok())?
+                }
+                ("output_dir", Value::String(value)) => profile.output_dir = value.clone(),
+                ("name_template", Value::String(value)) => profile.name_template = value.clone(),
+                ("forward_target", Value::String(value)) => profile.forward_target = value.clone(),
+                ("discard", Value::Bool(value)) => profile.discard = *value,
+                _ => return None,
+            }
+        }
+        if profile.port == 0 || profiles.iter().any(|p| p.port == profile.port) {
+            return None;
+        }
+        profiles.push(profile);
+    }
+    Some(profiles)
+}
+
+// Other lists are stored in the registry in the TOML syntax
+fn value_from_text(text: &str) -> Option<Value> {
+    let document = Document::parse(&format!("value = {text}")).ok()?;
+    document.get("value").cloned()
+}
+
+// Lists of numbers are stored in the registry as "1,2,3"
+fn list_to_string(values: &[i32]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn list_from_string(value: &str) -> Option<Vec<i32>> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| item.trim().parse().ok())
+        .collect()
+}
+
+// Fonts are stored as "face,height,bold,italics"
+fn font_to_string(font: &Font) -> String {
+    format!(
+        "{},{},{},{}",
+        font.face, font.height, font.bold as u8, font.italics as u8
+    )
+}
+
+fn font_from_string(value: &str) -> Option<Font> {
+    let mut parts = value.rsplitn(4, ',');
+    let italics = parts.next()? == "1";
+    let bold = parts.next()? == "1";
+    let height = parts.next()?.parse().ok()?;
+    let face = parts.next()?;
+
+    Some(Font {
+        height,
+        bold,
+        italics,
+        face: face.to_owned(),
+    })
+}
+
+// A setting which differs between the current settings and an imported file
+pub struct SettingChange {
+    pub name: &'static str,
+    pub old: Value,
+    pub new: Value,
+}
+
+// An additional listening port with its own handling of the jobs, empty fields
+// use the main settings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortProfile {
+    pub port: u16,
+    pub output_dir: String,
+    // file name of the jobs with placeholders like {timestamp} and {source}
+    pub name_template: String,
+    // host:port the jobs are forwarded to while being received
+    pub forward_target: String,
+    pub discard: bool,
+}
+
+// Called with the names of the changed settings
+type ChangeHandler = Box<dyn Fn(&[&'static str]) + Send + Sync>;
+
+pub struct AppSettings {
+    // TCP port of the listener
+    pub port: AtomicU32,
+    // local address the listeners are bound to, all addresses if empty
+    pub listen_address: RwLock<String>,
+    // hours the jobs are accepted, see Schedule, always if empty
+    pub schedule: RwLock<String>,
+    // reject, discard or forward, what is done with the jobs outside the schedule
+    pub off_hours: RwLock<String>,
+    // directory of the received jobs, the directory of the executable if empty
+    pub output_dir: RwLock<String>,
+    pub profiles: RwLock<Vec<PortProfile>>,
+    pub discard: Arc<AtomicBool>,
+    pub log_font: RwLock<Font>,
+    pub auto_scroll: Arc<AtomicBool>,
+    pub notify_jobs: Arc<AtomicBool>,
+    pub flash_jobs: Arc<AtomicBool>,
+    pub start_minimized: Arc<AtomicBool>,
+    // host:port of the printer dropped files are resent to
+    pub resend_target: RwLock<String>,
+    // port of the HTTP API and the web dashboard, 0 disables them
+    pub api_port: AtomicU32,
+    // local address the API is bound to, 0.0.0.0 makes it available to other machines
+    pub api_address: RwLock<String>,
+    // bearer token required by the API, generated when the API is started without one
+    pub api_token: RwLock<String>,
+    // port of the gRPC control API on the API address, 0 disables it
+    pub grpc_port: AtomicU32,
+    // URL the job and error notifications are posted to, empty disables them
+    pub webhook_url: RwLock<String>,
+    // host or host:port of a mail relay of the local network which accepts the mail
+    // without authentication and TLS, empty disables the emails
+    pub smtp_server: RwLock<String>,
+    // sender address, miniraw@<computer name> if empty
+    pub email_from: RwLock<String>,
+    // comma-separated recipients
+    pub email_to: RwLock<String>,
+    // comma-separated events which send an email, see the email module
+    pub email_triggers: RwLock<String>,
+    // host or host:port of the MQTT broker, empty disables the publishing
+    pub mqtt_broker: RwLock<String>,
+    // the events are published to <prefix>/job/completed, <prefix>/job/aborted and
+    // <prefix>/listener
+    pub mqtt_topic_prefix: RwLock<String>,
+    // quality of service of the published messages, 0 to 2
+    pub mqtt_qos: AtomicU32,
+    // base URL of the OTLP/HTTP collector the traces and metrics are sent to, empty
+    // disables the export
+    pub otlp_endpoint: RwLock<String>,
+    // daily, weekly or monthly accounting reports, empty disables the accounting
+    pub accounting_period: RwLock<String>,
+    // directory of the accounting journals and reports, empty is the accounting
+    // subdirectory of the output directory
+    pub accounting_dir: RwLock<String>,
+    // ICAP service the saved jobs are scanned by, e.g. icap://av.example.com/avscan
+    pub icap_url: RwLock<String>,
+    // local scanner run for every saved job, {path} is the quoted path of the job
+    pub scan_command: RwLock<String>,
+    // where the jobs failing the scan are moved to, empty is the quarantine
+    // subdirectory of the output directory
+    pub quarantine_dir: RwLock<String>,
+    // hash-chained log of the received jobs, relative to the application directory,
+    // empty disables it
+    pub audit_log: RwLock<String>,
+    // URL the anchors of the audit log are posted to besides the Windows event log
+    pub audit_anchor_url: RwLock<String>,
+    // token the jobs have to start with on all the listeners, empty accepts all jobs
+    pub job_secret: RwLock<String>,
+    // connections open at a time on each listener, above it only the hosts which
+    // sent a job before are accepted for a while, 0 disables the limit
+    pub max_connections: AtomicU32,
+    // connections open at a time from one host on each listener, 0 disables the limit
+    pub max_host_connections: AtomicU32,
+    // a connection without data for this long is closed, 0 waits forever
+    pub idle_timeout_seconds: AtomicU32,
+    // a connection sending less on average is closed, 0 disables the check
+    pub min_bytes_per_second: AtomicU32,
+    // the longest a job may take, 0 disables the deadline
+    pub max_job_minutes: AtomicU32,
+    // "complete" keeps the data received by the deadline as the job, "abort" fails it
+    pub job_deadline_action: RwLock<String>,
+    // interval of the checks of the forward targets, 0 disables them
+    pub forward_check_seconds: AtomicU32,
+    // the checks ask the printers for their PJL status besides connecting
+    pub forward_check_pjl: AtomicBool,
+    // "queue", "refuse" or "forward" the jobs while their forward target is down
+    pub forward_target_down: RwLock<String>,
+    // "buffered" writes the jobs through the page cache, "unbuffered" bypassing it
+    // with the overlapped I/O and "mapped" through the mapped files, the last two
+    // can be faster for very large jobs
+    pub write_mode: RwLock<String>,
+    // priority class of the process: idle, below_normal, normal, above_normal or high
+    pub process_priority: RwLock<String>,
+    // cores the threads receiving the jobs run on, e.g. "2-3", empty for all cores
+    pub worker_cores: RwLock<String>,
+    // comma-separated patterns masked in the previews and the exported text, see the
+    // redact module
+    pub redact_patterns: RwLock<String>,
+    // free space in the output directory below which the low disk space email is sent
+    pub min_free_space_mb: AtomicU32,
+    // interval of the automatic update check, 0 disables it
+    pub update_check_hours: AtomicU32,
+    // the installer of a newer version is downloaded to the updates directory
+    pub download_updates: AtomicBool,
+    // missing firewall rules for the listening ports are offered on start
+    pub check_firewall: AtomicBool,
+    // Rhai script with the hooks of the job pipeline, relative to the directory of
+    // the executable, empty disables it
+    pub script_path: RwLock<String>,
+    // comma-separated file names of the DLLs in the plugins directory, loaded again
+    // when the list is changed, empty loads no plugins
+    pub plugins: RwLock<String>,
+    // installed driver of the MiniRAW NG printer, decides the format of its jobs
+    pub printer_driver: RwLock<String>,
+    // language code, the Windows UI language is used if empty
+    pub language: RwLock<String>,
+    // part of the Jobs page height taken by the job list, in 1/1000
+    pub split_ratio: AtomicU32,
+    pub log_level: RwLock<LevelFilter>,
+    // per-module levels in the RUST_LOG syntax, e.g. "listener=debug,ui=trace"
+    pub log_modules: RwLock<String>,
+    // the oldest log lines are removed above this count, 0 keeps all lines
+    pub log_max_lines: AtomicU32,
+    // number of the first bytes of each job logged at the debug level, 0 disables the dump
+    pub hexdump_size: Arc<AtomicU32>,
+    // tab selected when the window was closed
+    pub active_page: AtomicU32,
+    // widths and display order of the job list columns, empty for the defaults
+    pub job_column_widths: RwLock<Vec<i32>>,
+    pub job_column_order: RwLock<Vec<i32>>,
+    // entries of the configuration file which could not be applied, logged on start
+    pub config_errors: RwLock<Vec<String>>,
+    config_path: PathBuf,
+    // the stored values of the settings overridden on the command line or by the
+    // policy, these are written instead of the current values so the overrides
+    // last for one run only
+    overridden: RwLock<Document>,
+    // names of the settings enforced by the policy
+    enforced: RwLock<Vec<&'static str>>,
+    // modification time of the configuration file when it was last read or written
+    modified: Mutex<Option<SystemTime>>,
+    handlers: RwLock<Vec<ChangeHandler>>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            port: AtomicU32::new(RAW_PORT as u32),
+            listen_address: RwLock::new(String::new()),
+            schedule: RwLock::new(String::new()),
+            off_hours: RwLock::new("reject".to_owned()),
+            output_dir: RwLock::new(String::new()),
+            profiles: Default::default(),
+            discard: Arc::new(AtomicBool::new(false)),
+            log_font: RwLock::new(Font::new(DEFAULT_LOG_FONT_HEIGHT, "Consolas")),
+            auto_scroll: Arc::new(AtomicBool::new(true)),
+            notify_jobs: Arc::new(AtomicBool::new(false)),
+            flash_jobs: Arc::new(AtomicBool::new(false)),
+            start_minimized: Arc::new(AtomicBool::new(false)),
+            resend_target: RwLock::new(String::new()),
+            api_port: AtomicU32::new(0),
+            api_address: RwLock::new(Ipv4Addr::LOCALHOST.to_string()),
+            api_token: RwLock::new(String::new()),
+            grpc_port: AtomicU32::new(0),
+            webhook_url: RwLock::new(String::new()),
+            smtp_server: RwLock::new(String::new()),
+            email_from: RwLock::new(String::new()),
+            email_to: RwLock::new(String::new()),
+            email_triggers: RwLock::new(
+                [
+                    email::TRIGGER_NEW_HOST,
+                    email::TRIGGER_LOW_DISK_SPACE,
+                    email::TRIGGER_LISTENER_FAILED,
+                ]
+                .join(","),
+            ),
+            mqtt_broker: RwLock::new(String::new()),
+            mqtt_topic_prefix: RwLock::new("miniraw".to_owned()),
+            mqtt_qos: AtomicU32::new(0),
+            otlp_endpoint: RwLock::new(String::new()),
+            accounting_period: RwLock::new(String::new()),
+            accounting_dir: RwLock::new(String::new()),
+            icap_url: RwLock::new(String::new()),
+            scan_command: RwLock::new(String::new()),
+            quarantine_dir: RwLock::new(String::new()),
+            audit_log: RwLock::new(String::new()),
+            audit_anchor_url: RwLock::new(String::new()),
+            job_secret: RwLock::new(String::new()),
+            max_connections: AtomicU32::new(0),
+            max_host_connections: AtomicU32::new(0),
+            idle_timeout_seconds: AtomicU32::new(0),
+            min_bytes_per_second: AtomicU32::new(0),
+            max_job_minutes: AtomicU32::new(0),
+            job_deadline_action: RwLock::new("complete".to_owned()),
+            forward_check_seconds: AtomicU32::new(30),
+            forward_check_pjl: AtomicBool::new(false),
+            forward_target_down: RwLock::new("queue".to_owned()),
+            write_mode: RwLock::new("buffered".to_owned()),
+            process_priority: RwLock::new("normal".to_owned()),
+            worker_cores: RwLock::new(String::new()),
+            redact_patterns: RwLock::new(String::new()),
+            min_free_space_mb: AtomicU32::new(1024),
+            update_check_hours: AtomicU32::new(24),
+            download_updates: AtomicBool::new(false),
+            check_firewall: AtomicBool::new(true),
+            script_path: RwLock::new(String::new()),
+            plugins: RwLock::new(String::new()),
+            printer_driver: RwLock::new("Microsoft PS Class Driver".to_owned()),
+            language: RwLock::new(String::new()),
+            split_ratio: AtomicU32::new(400),
+            log_level: RwLock::new(LevelFilter::Info),
+            log_modules: RwLock::new(String::new()),
+            log_max_lines: AtomicU32::new(10000),
+            hexdump_size: Arc::new(AtomicU32::new(64)),
+            active_page: AtomicU32::new(1),
+            job_column_widths: Default::default(),
+            job_column_order: Default::default(),
+            config_errors: Default::default(),
+            config_path: default_config_path(),
+            overridden: Default::default(),
+            enforced: Default::default(),
+            modified: Default::default(),
+            handlers: Default::default(),
+        }
+    }
+}
+
+// How a setting is kept in AppSettings, all settings are converted from and to
+// the values of the configuration file through it
+enum Field<'a> {
+    Flag(&'a AtomicBool),
+    Number(&'a AtomicU32),
+    // values outside of the range are rejected
+    Range(&'a AtomicU32, RangeInclusive<u32>),
+    // values outside of the range are changed to the nearest valid one
+    Clamped(&'a AtomicU32, RangeInclusive<u32>),
+    Text(&'a RwLock<String>),
+    List(&'a RwLock<Vec<i32>>),
+    Font(&'a RwLock<Font>),
+    Level(&'a RwLock<LevelFilter>),
+    Profiles(&'a RwLock<Vec<PortProfile>>),
+}
+
+impl Field<'_> {
+    fn get(&self) -> Value {
+        match self {
+            Field::Flag(flag) => Value::Bool(flag.load(Ordering::SeqCst)),
+            Field::Number(number) | Field::Range(number, _) | Field::Clamped(number, _) => {
+                Value::Integer(number.load(Ordering::SeqCst) as _)
+            }
+            Field::Text(text) => Value::String(text.read().unwrap().clone()),
+            Field::List(list) => list_to_value(&list.read().unwrap()),
+            Field::Font(font) => Value::String(font_to_string(&font.read().unwrap())),
+            Field::Level(level) => Value::String(level.read().unwrap().to_string()),
+            Field::Profiles(profiles) => profiles_to_value(&profiles.read().unwrap()),
+        }
+    }
+
+    // Returns false if the value is not valid for the setting
+    fn set(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Field::Flag(flag), Value::Bool(value)) => flag.store(*value, Ordering::SeqCst),
+            (Field::Number(number), value) => match value_to_u32(value) {
+                Some(value) => number.store(value, Ordering::SeqCst),
+                None => return false,
+            },
+            (Field::Range(number, range), value) => match value_to_u32(value) {
+                Some(value) if range.contains(&value) => number.store(value, Ordering::SeqCst),
+                _ => return false,
+            },
+            (Field::Clamped(number, range), value) => match value_to_u32(value) {
+                Some(value) => {
+                    number.store(value.clamp(*range.start(), *range.end()), Ordering::SeqCst)
+                }
+                None => return false,
+            },
+            (Field::Text(text), Value::String(value)) => *text.write().unwrap() = value.clone(),
+            (Field::List(list), value) => match value_to_list(value) {
+                Some(items) => *list.write().unwrap() = items,
+                None => return false,
+            },
+            (Field::Font(font), Value::String(value)) => match font_from_string(value) {
+                Some(value) => *font.write().unwrap() = value,
+                None => return false,
+            },
+            (Field::Level(level), Value::String(value)) => match value.parse() {
+                Ok(value) => *level.write().unwrap() = value,
+                Err(_) => return false,
+            },
+            (Field::Profiles(profiles), value) => match value_to_profiles(value) {
+                Some(value) => *profiles.write().unwrap() = value,
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl AppSettings {
+    // All stored settings in the order of the configuration file, the same names
+    // are used in the registry. A new setting only needs a field and an entry here.
+    fn fields(&self) -> [(&'static str, Field<'_>); 63] {
+        [
+            (REG_PORT, Field::Range(&self.port, 1..=u16::MAX as u32)),
+            (REG_LISTEN_ADDRESS, Field::Text(&self.listen_address)),
+            (REG_SCHEDULE, Field::Text(&self.schedule)),
+            (REG_OFF_HOURS, Field::Text(&self.off_hours)),
+            (REG_OUTPUT_DIR, Field::Text(&self.output_dir)),
+            (REG_PROFILES, Field::Profiles(&self.profiles)),
+            (REG_DISCARD, Field::Flag(&self.discard)),
+            (REG_RESEND_TARGET, Field::Text(&self.resend_target)),
+            (
+                REG_API_PORT,
+                Field::Range(&self.api_port, 0..=u16::MAX as u32),
+            ),
+            (REG_API_ADDRESS, Field::Text(&self.api_address)),
+            (REG_API_TOKEN, Field::Text(&self.api_token)),
+            (
+                REG_GRPC_PORT,
+                Field::Range(&self.grpc_port, 0..=u16::MAX as u32),
+            ),
+            (REG_WEBHOOK_URL, Field::Text(&self.webhook_url)),
+            (REG_SMTP_SERVER, Field::Text(&self.smtp_server)),
+            (REG_EMAIL_FROM, Field::Text(&self.email_from)),
+            (REG_EMAIL_TO, Field::Text(&self.email_to)),
+            (REG_EMAIL_TRIGGERS, Field::Text(&self.email_triggers)),
+            (REG_MQTT_BROKER, Field::Text(&self.mqtt_broker)),
+            (REG_MQTT_TOPIC_PREFIX, Field::Text(&self.mqtt_topic_prefix)),
+            (REG_MQTT_QOS, Field::Range(&self.mqtt_qos, 0..=2)),
+            (REG_OTLP_ENDPOINT, Field::Text(&self.otlp_endpoint)),
+            (REG_ACCOUNTING_PERIOD, Field::Text(&self.accounting_period)),
+            (REG_ACCOUNTING_DIR, Field::Text(&self.accounting_dir)),
+            (REG_ICAP_URL, Field::Text(&self.icap_url)),
+            (REG_SCAN_COMMAND, Field::Text(&self.scan_command)),
+            (REG_QUARANTINE_DIR, Field::Text(&self.quarantine_dir)),
+            (REG_AUDIT_LOG, Field::Text(&self.audit_log)),
+            (REG_AUDIT_ANCHOR_URL, Field::Text(&self.audit_anchor_url)),
+            (REG_JOB_SECRET, Field::Text(&self.job_secret)),
+            (REG_MAX_CONNECTIONS, Field::Number(&self.max_connections)),
+            (
+                REG_MAX_HOST_CONNECTIONS,
+                Field::Number(&self.max_host_connections),
+            ),
+            (
+                REG_IDLE_TIMEOUT_SECONDS,
+                Field::Number(&self.idle_timeout_seconds),
+            ),
+            (
+                REG_MIN_BYTES_PER_SECOND,
+                Field::Number(&self.min_bytes_per_second),
+            ),
+            (REG_MAX_JOB_MINUTES, Field::Number(&self.max_job_minutes)),
+            (
+                REG_JOB_DEADLINE_ACTION,
+                Field::Text(&self.job_deadline_action),
+            ),
+            (
+                REG_FORWARD_CHECK_SECONDS,
+                Field::Number(&self.forward_check_seconds),
+            ),
+            (REG_FORWARD_CHECK_PJL, Field::Flag(&self.forward_check_pjl)),
+            (
+                REG_FORWARD_TARGET_DOWN,
+                Field::Text(&self.forward_target_down),
+            ),
+            (REG_WRITE_MODE, Field::Text(&self.write_mode)),
+            (REG_PROCESS_PRIORITY, Field::Text(&self.process_priority)),
+            (REG_WORKER_CORES, Field::Text(&self.worker_cores)),
+            (REG_REDACT_PATTERNS, Field::Text(&self.redact_patterns)),
+            (
+                REG_MIN_FREE_SPACE_MB,
+                Field::Number(&self.min_free_space_mb),
+            ),
+            (
+                REG_UPDATE_CHECK_HOURS,
+                Field::Number(&self.update_check_hours),
+            ),
+            (REG_DOWNLOAD_UPDATES, Field::Flag(&self.download_updates)),
+            (REG_CHECK_FIREWALL, Field::Flag(&self.check_firewall)),
+            (REG_SCRIPT_PATH, Field::Text(&self.script_path)),
+            (REG_PLUGINS, Field::Text(&self.plugins)),
+            (REG_PRINTER_DRIVER, Field::Text(&self.printer_driver)),
+            (REG_NOTIFY_JOBS, Field::Flag(&self.notify_jobs)),
+            (REG_FLASH_JOBS, Field::Flag(&self.flash_jobs)),
+            (REG_START_MINIMIZED, Field::Flag(&self.start_minimized)),
+            (REG_LANGUAGE, Field::Text(&self.language)),
+            (REG_LOG_LEVEL, Field::Level(&self.log_level)),
+            (REG_LOG_MODULES, Field::Text(&self.log_modules)),
+            (REG_LOG_MAX_LINES, Field::Number(&self.log_max_lines)),
+            (REG_HEXDUMP_SIZE, Field::Number(&self.hexdump_size)),
+            (REG_LOG_FONT, Field::Font(&self.log_font)),
+            (REG_AUTO_SCROLL, Field::Flag(&self.auto_scroll)),
+            (
+                REG_SPLIT_RATIO,
+                Field::Clamped(&self.split_ratio, 100..=900),
+            ),
+            (REG_ACTIVE_PAGE, Field::Number(&self.active_page)),
+            (REG_JOB_COLUMN_WIDTHS, Field::List(&self.job_column_widths)),
+            (REG_JOB_COLUMN_ORDER, Field::List(&self.job_column_order)),
+        ]
+    }
+
+    fn values(&self) -> Vec<(&'static str, Value)> {
+        self.fields()
+            .iter()
+            .map(|(name, field)| (*name, field.get()))
+            .collect()
+    }
+
+    // Returns false if the name is unknown or the value is not valid for it
+    fn set_value(&self, name: &str, value: &Value) -> bool {
+        self.fields()
+            .iter()
+            .find(|(n, _)| *n == name)
+            .is_some_and(|(_, field)| field.set(value))
+    }
+
+    // The configuration file takes precedence, the registry values are read when
+    // there is no file yet and the file is created by the first store. A file given
+    // on the command line starts with the defaults if it does not exist.
+    pub fn load(config_path: Option<&Path>) -> AppSettings {
+        let settings = AppSettings {
+            config_path: config_path.map_or_else(default_config_path, Path::to_owned),
+            ..Default::default()
+        };
+
+        *settings.modified.lock().unwrap() = settings.file_modified();
+        match fs::read_to_string(&settings.config_path) {
+            Ok(text) => match Document::parse(&text) {
+                Ok(document) => {
+                    let errors = settings.apply(&document);
+                    *settings.config_errors.write().unwrap() = errors;
+                }
+                Err(e) => {
+                    *settings.config_errors.write().unwrap() = vec![e.to_string()];
+                    settings.load_registry();
+                }
+            },
+            Err(_) if config_path.is_none() => settings.load_registry(),
+            Err(_) => {}
+        }
+        settings.load_policy();
+
+        settings
+    }
+
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    fn file_modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.config_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    pub fn subscribe<F>(&self, handler: F)
+    where
+        F: Fn(&[&'static str]) + Send + Sync + 'static,
+    {
+        self.handlers.write().unwrap().push(Box::new(handler));
+    }
+
+    fn notify(&self, names: &[&'static str]) {
+        if !names.is_empty() {
+            for handler in self.handlers.read().unwrap().iter() {
+                handler(names);
+            }
+        }
+    }
+
+    // Reads the configuration file again if it was changed by another program or
+    // replaced since it was last read or written. Invalid entries are logged and
+    // skipped, the new values of overridden settings are stored for the next run.
+    pub fn reload(&self) {
+        let modified = self.file_modified();
+        {
+            let mut last_modified = self.modified.lock().unwrap();
+            if modified.is_none() || modified == *last_modified {
+                return;
+            }
+            *last_modified = modified;
+        }
+
+        let path = &self.config_path;
+        let document = match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Document::parse(&text).map_err(|e| e.to_string()))
+        {
+            Ok(document) => document,
+            Err(e) => {
+                warn!("{}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let (changes, errors) = self.diff(&document);
+        for entry in errors {
+            warn!(
+                "Ignored configuration entry in {}: {}",
+                path.display(),
+                entry
+            );
+        }
+
+        let names = changes
+            .into_iter()
+            .filter(|change| self.set_stored_value(change.name, &change.new))
+            .map(|change| change.name)
+            .collect::<Vec<_>>();
+        self.notify(&names);
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.load(Ordering::SeqCst) as u16
+    }
+
+    pub fn output_dir(&self) -> PathBuf {
+        let dir = self.output_dir.read().unwrap();
+        if dir.is_empty() {
+            util::app_dir()
+        } else {
+            PathBuf::from(&*dir)
+        }
+    }
+
+    pub fn accounting_dir(&self) -> PathBuf {
+        let dir = self.accounting_dir.read().unwrap();
+        if dir.is_empty() {
+            self.output_dir().join("accounting")
+        } else {
+            PathBuf::from(&*dir)
+        }
+    }
+
+    pub fn scanner(&self) -> Scanner {
+        let quarantine_dir = self.quarantine_dir.read().unwrap();
+        Scanner {
+            icap_url: self.icap_url.read().unwrap().trim().to_owned(),
+            command: self.scan_command.read().unwrap().trim().to_owned(),
+            quarantine_dir: if quarantine_dir.is_empty() {
+                self.output_dir().join("quarantine")
+            } else {
+                PathBuf::from(&*quarantine_dir)
+            },
+        }
+    }
+
+    pub fn connection_limits(&self) -> ConnectionLimits {
+        ConnectionLimits {
+            max_connections: self.max_connections.load(Ordering::SeqCst),
+            max_host_connections: self.max_host_connections.load(Ordering::SeqCst),
+            idle_timeout_seconds: self.idle_timeout_seconds.load(Ordering::SeqCst),
+            min_bytes_per_second: self.min_bytes_per_second.load(Ordering::SeqCst),
+            max_job_minutes: self.max_job_minutes.load(Ordering::SeqCst),
+            deadline_action: DeadlineAction::parse(&self.job_deadline_action.read().unwrap())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn redactor(&self) -> Redactor {
+        Redactor::new(&self.redact_patterns.read().unwrap())
+    }
+
+    // None if there is no audit log
+    pub fn audit_log(&self) -> Option<PathBuf> {
+        let path = self.audit_log.read().unwrap();
+        if path.trim().is_empty() {
+            None
+        } else {
+            Some(util::app_dir().join(path.trim()))
+        }
+    }
+
+    // None if the value is invalid
+    pub fn accounting_period(&self) -> Option<AccountingPeriod> {
+        AccountingPeriod::parse(&self.accounting_period.read().unwrap())
+    }
+
+    // Empty if there is no script
+    pub fn script_path(&self) -> PathBuf {
+        let path = self.script_path.read().unwrap();
+        if path.is_empty() {
+            PathBuf::new()
+        } else {
+            util::app_dir().join(&*path)
+        }
+    }
+
+    // The address of the listeners, None if it is invalid
+    pub fn listen_address(&self) -> Option<IpAddr> {
+        let address = self.listen_address.read().unwrap();
+        if address.trim().is_empty() {
+            Some(Ipv4Addr::UNSPECIFIED.into())
+        } else {
+            address.trim().parse().ok()
+        }
+    }
+
+    pub fn schedule(&self) -> Result<Schedule, String> {
+        Schedule::parse(&self.schedule.read().unwrap())
+    }
+
+    // None if the value is invalid
+    pub fn off_hours(&self) -> Option<OffHours> {
+        OffHours::parse(&self.off_hours.read().unwrap())
+    }
+
+    // None if the value is invalid
+    pub fn write_mode(&self) -> Option<WriteMode> {
+        WriteMode::parse(&self.write_mode.read().unwrap())
+    }
+
+    // None if the value is invalid
+    pub fn process_priority(&self) -> Option<ProcessPriority> {
+        ProcessPriority::parse(&self.process_priority.read().unwrap())
+    }
+
+    // The affinity mask of the cores, 0 for all cores, None if the value is invalid
+    pub fn worker_cores(&self) -> Option<usize> {
+        priority::parse_cores(&self.worker_cores.read().unwrap())
+    }
+
+    pub fn plugin_names(&self) -> Vec<String> {
+        self.plugins
+            .read()
+            .unwrap()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    // The address and the port of the API, None if it is disabled or the address is invalid
+    pub fn api_address(&self) -> Option<SocketAddr> {
+        let port = self.api_port.load(Ordering::SeqCst) as u16;
+        let address = self.api_address.read().unwrap().trim().parse::<IpAddr>();
+        match address {
+            Ok(address) if port != 0 => Some(SocketAddr::new(address, port)),
+            _ => None,
+        }
+    }
+
+    // The address and the port of the gRPC API, None if it is disabled or the address
+    // is invalid
+    pub fn grpc_address(&self) -> Option<SocketAddr> {
+        let port = self.grpc_port.load(Ordering::SeqCst) as u16;
+        let address = self.api_address.read().unwrap().trim().parse::<IpAddr>();
+        match address {
+            Ok(address) if port != 0 => Some(SocketAddr::new(address, port)),
+            _ => None,
+        }
+    }
+
+    pub fn email_from(&self) -> String {
+        let from = self.email_from.read().unwrap();
+        if from.is_empty() {
+            let host = env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_owned());
+            format!("miniraw@{}", host.to_lowercase())
+        } else {
+            from.clone()
+        }
+    }
+
+    // Returns the API token, a new one is generated and stored if it is empty
+    pub fn api_token(&self) -> String {
+        let token = {
+            let mut token = self.api_token.write().unwrap();
+            if !token.is_empty() {
+                return token.clone();
+            }
+            *token = generate_token();
+            token.clone()
+        };
+        self.store();
+        token
+    }
+
+    // Sets the values for this run and returns the names of the changed settings,
+    // the stored values are kept for the configuration file
+    fn override_values(&self, overrides: Vec<(&'static str, Value)>) -> Vec<&'static str> {
+        let stored = self.values();
+        let mut overridden = self.overridden.write().unwrap();
+        overrides
+            .into_iter()
+            .filter(|(name, value)| self.set_value(name, value))
+            .map(|(name, _)| {
+                if let Some((_, old)) = stored.iter().find(|(n, _)| *n == name) {
+                    if overridden.get(name).is_none() {
+                        overridden.set(name, old.clone());
+                    }
+                }
+                name
+            })
+            .collect()
+    }
+
+    // Changes the stored value of an overridden setting or the current value of
+    // the others. Returns true if the current value was changed.
+    fn set_stored_value(&self, name: &'static str, value: &Value) -> bool {
+        let mut overridden = self.overridden.write().unwrap();
+        if overridden.get(name).is_some() {
+            overridden.set(name, value.clone());
+            false
+        } else {
+            self.set_value(name, value)
+        }
+    }
+
+    fn describe(&self, names: &[&'static str]) -> Vec<String> {
+        self.values()
+            .into_iter()
+            .filter(|(name, _)| names.contains(name))
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect()
+    }
+
+    // The settings enforced by the policy with their values
+    pub fn enforced(&self) -> Vec<String> {
+        self.describe(&self.enforced.read().unwrap())
+    }
+
+    pub fn is_enforced(&self, name: &str) -> bool {
+        self.enforced.read().unwrap().contains(&name)
+    }
+
+    pub fn is_flag_enforced(&self, flag: &AtomicBool) -> bool {
+        let enforced = self.enforced.read().unwrap();
+        self.fields().iter().any(|(name, field)| {
+            matches!(field, Field::Flag(f) if std::ptr::eq(*f, flag)) && enforced.contains(name)
+        })
+    }
+
+    // The main listener followed by the listeners of the profiles, a profile with
+    // the port of the main listener is ignored. An invalid listen_address binds
+    // all addresses.
+    pub fn listener_configs(&self) -> Vec<ListenerConfig> {
+        let address = self
+            .listen_address()
+            .unwrap_or(Ipv4Addr::UNSPECIFIED.into());
+        let port = self.port();
+        let output_dir = self.output_dir();
+        let schedule = self.schedule().unwrap_or_default();
+        let off_hours = self.off_hours().unwrap_or_default();
+        let scanner = self.scanner();
+        let job_secret = self.job_secret.read().unwrap().trim().to_owned();
+        let limits = self.connection_limits();
+        let write_mode = self.write_mode().unwrap_or_default();
+        let forward_down =
+            ForwardDown::parse(&self.forward_target_down.read().unwrap()).unwrap_or_default();
+
+        let main = ListenerConfig {
+            address,
+            port,
+            output_dir: output_dir.clone(),
+            name_template: DEFAULT_NAME_TEMPLATE.to_owned(),
+            forward_target: String::new(),
+            discard: None,
+            schedule: schedule.clone(),
+            off_hours,
+            scanner: scanner.clone(),
+            job_secret: job_secret.clone(),
+            limits,
+            write_mode,
+            forward_down,
+            primary: true,
+        };
+
+        let profiles = self.profiles.read().unwrap();
+        let profiles = profiles
+            .iter()
+            .filter(|profile| profile.port != port)
+            .map(|profile| ListenerConfig {
+                address,
+                port: profile.port,
+                output_dir: if profile.output_dir.is_empty() {
+                    output_dir.clone()
+                } else {
+                    PathBuf::from(&profile.output_dir)
+                },
+                name_template: if profile.name_template.is_empty() {
+                    DEFAULT_NAME_TEMPLATE.to_owned()
+                } else {
+                    profile.name_template.clone()
+                },
+                forward_target: profile.forward_target.clone(),
+                discard: Some(profile.discard),
+                schedule: schedule.clone(),
+                off_hours,
+                scanner: scanner.clone(),
+                job_secret: job_secret.clone(),
+                limits,
+                write_mode,
+                forward_down,
+                primary: false,
+            });
+
+        [main].into_iter().chain(profiles).collect()
+    }
+
+    // The settings given in the environment as MINIRAW_<NAME>, e.g. MINIRAW_PORT=9101
+    // or MINIRAW_DISCARD=1, and a description of each variable with an invalid value
+    fn environment(&self) -> (Vec<(&'static str, Value)>, Vec<String>) {
+        let mut overrides = Vec::new();
+        let mut errors = Vec::new();
+        for (name, default) in self.values() {
+            let variable = format!("{ENV_PREFIX}{}", name.to_uppercase());
+            let Ok(text) = env::var(&variable) else {
+                continue;
+            };
+            let value = match default {
+                Value::Bool(_) => match text.trim().to_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => Some(Value::Bool(true)),
+                    "0" | "false" | "no" | "off" => Some(Value::Bool(false)),
+                    _ => None,
+                },
+                Value::Integer(_) => text.trim().parse().ok().map(Value::Integer),
+                Value::String(_) => Some(Value::String(text.clone())),
+                Value::Array(_) | Value::Table(_) => list_from_string(&text)
+                    .map(|list| list_to_value(&list))
+                    .or_else(|| value_from_text(&text)),
+            };
+            match value {
+                Some(value) if AppSettings::default().set_value(name, &value) => {
+                    overrides.push((name, value))
+                }
+                _ => errors.push(format!("{variable}={text}")),
+            }
+        }
+        (overrides, errors)
+    }
+
+    // Changes the settings given in the environment and on the command line for
+    // this run, the command line takes precedence. Returns the overridden entries
+    // and the invalid environment variables. A setting changed in the UI afterwards
+    // is not stored. The settings enforced by the policy cannot be overridden.
+    pub fn apply_overrides(&self, arguments: &Arguments) -> (Vec<String>, Vec<String>) {
+        let (mut overrides, errors) = self.environment();
+        if let Some(port) = arguments.port {
+            overrides.push((REG_PORT, Value::Integer(port as _)));
+        }
+        if let Some(ref dir) = arguments.output_dir {
+            overrides.push((REG_OUTPUT_DIR, Value::String(dir.display().to_string())));
+        }
+        if arguments.discard {
+            overrides.push((REG_DISCARD, Value::Bool(true)));
+        }
+
+        let enforced = self.enforced.read().unwrap().clone();
+        overrides.retain(|(name, _)| !enforced.contains(name));
+        let names = self.override_values(overrides);
+        (self.describe(&names), errors)
+    }
+
+    // Sets the values of the document and returns a description of each entry
+    // which was not applied
+    fn apply(&self, document: &Document) -> Vec<String> {
+        document
+            .entries()
+            .filter(|(name, value)| !self.set_value(name, value))
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect()
+    }
+
+    // The values of the known settings found in the registry key
+    fn read_registry(&self, root: HKEY, key: &str) -> Vec<(&'static str, Value)> {
+        self.values()
+            .into_iter()
+            .filter_map(|(name, default)| {
+                let value = match default {
+                    Value::Bool(_) => load_dword(root, key, name).map(|v| Value::Bool(v != 0)),
+                    Value::Integer(_) => {
+                        load_dword(root, key, name).map(|v| Value::Integer(v as _))
+                    }
+                    Value::String(_) => load_string(root, key, name).map(Value::String),
+                    Value::Array(_) | Value::Table(_) => {
+                        load_string(root, key, name).and_then(|v| {
+                            list_from_string(&v)
+                                .map(|list| list_to_value(&list))
+                                .or_else(|| value_from_text(&v))
+                        })
+                    }
+                };
+                value.map(|value| (name, value))
+            })
+            .collect()
+    }
+
+    fn load_registry(&self) {
+        for (name, value) in self.read_registry(HKEY_CURRENT_USER, REG_KEY_NAME) {
+            self.set_value(name, &value);
+        }
+    }
+
+    // The policy values take precedence over the configuration file and the command
+    // line and cannot be changed in the program
+    fn load_policy(&self) {
+        let policy = self.read_registry(HKEY_LOCAL_MACHINE, REG_POLICY_KEY_NAME);
+        *self.enforced.write().unwrap() = self.override_values(policy);
+    }
+
+    fn store_registry(&self) {
+        for (name, value) in self.to_document().entries() {
+            match value {
+                Value::Bool(value) => store_dword(REG_KEY_NAME, name, *value as u32),
+                Value::Integer(value) => store_dword(REG_KEY_NAME, name, *value as u32),
+                Value::String(value) => store_string(REG_KEY_NAME, name, value),
+                Value::Array(_) | Value::Table(_) => {
+                    let text = match value_to_list(value) {
+                        Some(list) => list_to_string(&list),
+                        None => value.to_string(),
+                    };
+                    store_string(REG_KEY_NAME, name, &text);
+                }
+            }
+        }
+    }
+
+    // The stored settings, without the command line overrides
+    pub fn to_document(&self) -> Document {
+        let mut document = Document::default();
+        for (name, value) in self.values() {
+            document.set(name, value);
+        }
+        for (name, value) in self.overridden.read().unwrap().entries() {
+            document.set(name, value.clone());
+        }
+        document
+    }
+
+    // Writes a temporary file which replaces the configuration file, so the file
+    // is never left half-written. Falls back to the registry if the file cannot
+    // be written, e.g. when the program directory is read-only.
+    pub fn store(&self) {
+        let path = &self.config_path;
+        let temp_path = path.with_extension("toml.tmp");
+        let text = format!("{CONFIG_HEADER}{}", self.to_document());
+
+        let result = fs::write(&temp_path, text).and_then(|_| fs::rename(&temp_path, path));
+        match result {
+            // not reloaded as a change made by another program
+            Ok(()) => *self.modified.lock().unwrap() = self.file_modified(),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                warn!("{}: {}", path.display(), e);
+                self.store_registry();
+            }
+        }
+    }
+
+    // The export has the format of the configuration file, the Windows startup
+    // entry is not included as it depends on the location of the executable
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, format!("{EXPORT_HEADER}{}", self.to_document()))
+    }
+
+    // Presets are settings files in the presets directory next to the configuration
+    // file, the file name is the preset name
+    pub fn presets_dir(&self) -> PathBuf {
+        self.config_path.with_file_name(PRESETS_DIR_NAME)
+    }
+
+    // The preset files sorted by name
+    pub fn presets(&self) -> Vec<PathBuf> {
+        let mut presets = fs::read_dir(self.presets_dir())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "toml"))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        presets.sort();
+        presets
+    }
+
+    // Applied like an imported file, so a preset can also be written by hand
+    // with only the settings it changes
+    pub fn save_preset(&self, path: &Path) -> io::Result<()> {
+        let mut document = Document::default();
+        for (name, value) in self.to_document().entries() {
+            if !NON_PRESET_SETTINGS.contains(&name) {
+                document.set(name, value.clone());
+            }
+        }
+        fs::write(path, format!("{PRESET_HEADER}{document}"))
+    }
+
+    // Saves the stored settings into a backup file next to the configuration file and
+    // replaces them with the defaults. The registry values of the older versions are
+    // removed, so they are not migrated again. Returns the path of the backup.
+    pub fn reset(&self) -> io::Result<PathBuf> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let backup = self
+            .config_path
+            .with_extension(format!("{timestamp}.bak.toml"));
+        self.export(&backup)?;
+
+        let current = self.values();
+        let names = AppSettings::default()
+            .values()
+            .into_iter()
+            .filter(|item| {
+                // the stored values of the overridden settings are reset as well
+                let changed = !current.contains(item);
+                self.set_stored_value(item.0, &item.1) && changed
+            })
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        let key_name = utf16z!(REG_KEY_NAME);
+        unsafe {
+            let _ = RegDeleteTreeW(HKEY_CURRENT_USER, PCWSTR(key_name.as_ptr()));
+        }
+        self.store();
+        self.notify(&names);
+        Ok(backup)
+    }
+
+    // Checks an imported document without changing the settings. Returns the settings
+    // which the document changes or the entries which cannot be applied, settings
+    // missing from the document keep their current values.
+    pub fn changes(&self, document: &Document) -> Result<Vec<SettingChange>, Vec<String>> {
+        match self.diff(document) {
+            (changes, errors) if errors.is_empty() => Ok(changes),
+            (_, errors) => Err(errors),
+        }
+    }
+
+    // The settings changed by the valid entries of the document and a description
+    // of each invalid entry
+    fn diff(&self, document: &Document) -> (Vec<SettingChange>, Vec<String>) {
+        let imported = AppSettings::default();
+        let mut valid = Vec::new();
+        let mut errors = Vec::new();
+        for (name, value) in document.entries() {
+            if imported.set_value(name, value) {
+                valid.push(name);
+            } else {
+                errors.push(format!("{name} = {value}"));
+            }
+        }
+
+        let changes = self
+            .values()
+            .into_iter()
+            .zip(imported.values())
+            .filter(|((name, old), (_, new))| valid.contains(name) && old != new)
+            .map(|((name, old), (_, new))| SettingChange { name, old, new })
+            .collect();
+        (changes, errors)
+    }
+
+    pub fn apply_changes(&self, changes: &[SettingChange]) {
+        let names = changes
+            .iter()
+            .filter(|change| self.set_stored_value(change.name, &change.new))
+            .map(|change| change.name)
+            .collect::<Vec<_>>();
+        self.store();
+        self.notify(&names);
+    }
+
+    // The configuration file contents, used for the diagnostics
+    pub fn to_text(&self) -> String {
+        let enforced = self.enforced.read().unwrap().clone();
+        let overridden = self
+            .overridden
+            .read()
+            .unwrap()
+            .entries()
+            .filter_map(|(name, _)| self.values().into_iter().find(|(n, _)| *n == name))
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::String(text) if !text.is_empty() && SECRET_SETTINGS.contains(&name) => {
+                        Value::String("(hidden)".to_owned())
+                    }
+                    value => value,
+                };
+                if enforced.contains(&name) {
+                    format!("# enforced by the policy: {name} = {value}\n")
+                } else {
+                    format!("# overridden for this run: {name} = {value}\n")
+                }
+            })
+            .collect::<String>();
+        // the diagnostics are attached to bug reports
+        let mut document = self.to_document();
+        for name in SECRET_SETTINGS {
+            if document.get(name) != Some(&Value::String(String::new())) {
+                document.set(name, Value::String("(hidden)".to_owned()));
+            }
+        }
+        format!(
+            "# {}\n{}\n# run at Windows startup: {}\n{}",
+            self.config_path.display(),
+            document,
+            is_run_at_startup(),
+            overridden
+        )
+    }
+}
+
+// The file name of a preset without the extension
+pub fn preset_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,352 @@
+use std::sync::{atomic::Ordering, Arc, Mutex, Weak};
+
+use log::{error, LevelFilter};
+use windows::Win32::UI::{
+    Controls::{BST_CHECKED, BST_UNCHECKED, EM_SETCUEBANNER},
+    WindowsAndMessaging::*,
+};
+
+use crate::{
+    logger::WindowLogger,
+    settings::{self, AppSettings},
+    tr,
+    ui::window::{
+        Font, MessageResult, Tooltip, Window, WindowBuilder, WindowEvent, WindowGeometry,
+        WindowMessage, WindowMessageHandler, WindowRef,
+    },
+    utf16z, IDI_MAINICON, IDM_AUTO_SCROLL, IDM_DISCARD_FILES, IDM_FIREWALL_RULES, IDM_FLASH_JOBS,
+    IDM_FONT, IDM_NOTIFY_JOBS, IDM_RUN_AT_STARTUP, IDM_START_MINIMIZED,
+};
+
+// Options are toggled by forwarding the menu commands to the main window,
+// so the menu check marks and the stored settings stay in sync
+const IDC_RESEND_TARGET: u32 = 100;
+const IDC_LOG_LEVEL: u32 = 101;
+
+const LOG_LEVELS: &[LevelFilter] = &[
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+// id, title, tooltip
+const OPTIONS: &[(u32, &str, &str)] = &[
+    (
+        IDM_DISCARD_FILES,
+        "Discard received files",
+        "Jobs are logged but not saved to disk",
+    ),
+    (
+        IDM_AUTO_SCROLL,
+        "Auto-scroll log",
+        "Keep the newest log lines visible",
+    ),
+    (
+        IDM_NOTIFY_JOBS,
+        "Notify about received jobs",
+        "Show a tray notification for every received job",
+    ),
+    (
+        IDM_FLASH_JOBS,
+        "Flash taskbar button on new jobs",
+        "Flash the taskbar button when a job arrives while the window is inactive",
+    ),
+    (
+        IDM_START_MINIMIZED,
+        "Start minimized to the tray",
+        "Hide the window to the notification area when the program starts",
+    ),
+    (
+        IDM_RUN_AT_STARTUP,
+        "Run at Windows startup",
+        "Start the program minimized when you sign in to Windows",
+    ),
+];
+
+pub struct SettingsWindow {
+    // the main window keeps this object, so it is not kept alive from here
+    main_window: Weak<Window>,
+    settings: Arc<AppSettings>,
+    logger: &'static WindowLogger,
+    window: Mutex<Option<WindowRef>>,
+    resend_target: Mutex<Option<WindowRef>>,
+    log_level: Mutex<Option<WindowRef>>,
+}
+
+impl SettingsWindow {
+    pub fn new(
+        main_window: Weak<Window>,
+        settings: Arc<AppSettings>,
+        logger: &'static WindowLogger,
+    ) -> Arc<SettingsWindow> {
+        Arc::new(SettingsWindow {
+            main_window,
+            settings,
+            logger,
+            window: Mutex::new(None),
+            resend_target: Mutex::new(None),
+            log_level: Mutex::new(None),
+        })
+    }
+
+    pub fn show(self: &Arc<Self>) {
+        let window = self.window.lock().unwrap().clone();
+        if let Some(window) = window {
+            self.update_checks(&window);
+            unsafe {
+                let _ = SetForegroundWindow(window.handle());
+            }
+            return;
+        }
+
+        let geometry = WindowGeometry {
+            width: Some(300),
+            height: Some(356),
+            ..Default::default()
+        };
+
+        let style = WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_VISIBLE;
+
+        match WindowBuilder::window("miniraw_settings", None)
+            .geometry(geometry)
+            .title(tr!("Settings"))
+            .style(style.0)
+            .icon(IDI_MAINICON)
+            .message_handler(self.clone())
+            .build()
+        {
+            Ok(window) => *self.window.lock().unwrap() = Some(window),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    // Shows the current settings if the window is open
+    pub fn refresh(&self) {
+        let window = self.window.lock().unwrap().clone();
+        if let Some(window) = window {
+            self.update_checks(&window);
+        }
+    }
+
+    fn is_checked(&self, id: u32) -> bool {
+        match id {
+            IDM_DISCARD_FILES => self.settings.discard.load(Ordering::SeqCst),
+            IDM_AUTO_SCROLL => self.settings.auto_scroll.load(Ordering::SeqCst),
+            IDM_NOTIFY_JOBS => self.settings.notify_jobs.load(Ordering::SeqCst),
+            IDM_FLASH_JOBS => self.settings.flash_jobs.load(Ordering::SeqCst),
+            IDM_START_MINIMIZED => self.settings.start_minimized.load(Ordering::SeqCst),
+            IDM_RUN_AT_STARTUP => settings::is_run_at_startup(),
+            _ => false,
+        }
+    }
+
+    fn create_controls(&self, window: &WindowRef) {
+        let check_style = WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32);
+        let button_style = WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32);
+
+        let mut tools = Vec::new();
+
+        for (index, (id, title, tip)) in OPTIONS.iter().enumerate() {
+            let check = WindowBuilder::button(window.clone())
+                .id(*id)
+                .title(tr!(title))
+                .geometry(WindowGeometry {
+                    x: Some(12),
+                    y: Some(12 + index as i32 * 30),
+                    width: Some(260),
+                    height: Some(24),
+                })
+                .style(check_style.0)
+                .font(Font::new(15, "Segoe UI"))
+                .build()
+                .unwrap();
+            tools.push((check, *tip));
+        }
+
+        let font_button = WindowBuilder::button(window.clone())
+            .id(IDM_FONT)
+            .title(tr!("Log font..."))
+            .geometry(WindowGeometry {
+                x: Some(12),
+                y: Some(12 + OPTIONS.len() as i32 * 30 + 6),
+                width: Some(100),
+                height: Some(26),
+            })
+            .style(button_style.0)
+            .font(Font::new(15, "Segoe UI"))
+            .build()
+            .unwrap();
+        tools.push((font_button, "Font of the log window"));
+
+        let combo_style =
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WS_VSCROLL | WINDOW_STYLE(CBS_DROPDOWNLIST as u32);
+
+        let log_level = WindowBuilder::combo_box(window.clone())
+            .id(IDC_LOG_LEVEL)
+            .geometry(WindowGeometry {
+                x: Some(124),
+                y: Some(12 + OPTIONS.len() as i32 * 30 + 6),
+                width: Some(148),
+                height: Some(200),
+            })
+            .style(combo_style.0)
+            .font(Font::new(15, "Segoe UI"))
+            .build()
+            .unwrap();
+
+        let current = *self.settings.log_level.read().unwrap();
+        for level in LOG_LEVELS {
+            let index = log_level.add_item(&tr!("Log level: {}", level));
+            if *level == current {
+                log_level.select_item(index);
+            }
+        }
+        tools.push((
+            log_level.clone(),
+            "Least severe messages written to the log",
+        ));
+        *self.log_level.lock().unwrap() = Some(log_level);
+
+        let input_style =
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE((ES_LEFT | ES_AUTOHSCROLL) as u32);
+
+        let resend_target = WindowBuilder::edit_control(window.clone())
+            .id(IDC_RESEND_TARGET)
+            .geometry(WindowGeometry {
+                x: Some(12),
+                y: Some(12 + OPTIONS.len() as i32 * 30 + 42),
+                width: Some(260),
+                height: Some(24),
+            })
+            .style(input_style.0)
+            .extended_style(WS_EX_CLIENTEDGE.0)
+            .font(Font::new(15, "Segoe UI"))
+            .build()
+            .unwrap();
+
+        let cue = utf16z!(tr!("Resend printer (host:port)"));
+        resend_target.send_message(WindowMessage::new(
+            resend_target.clone(),
+            EM_SETCUEBANNER,
+            1,
+            cue.as_ptr() as _,
+        ));
+        let _ = resend_target.set_text(&self.settings.resend_target.read().unwrap());
+        tools.push((
+            resend_target.clone(),
+            "Printer to which dropped files and jobs are resent",
+        ));
+        *self.resend_target.lock().unwrap() = Some(resend_target);
+
+        let firewall_button = WindowBuilder::button(window.clone())
+            .id(IDM_FIREWALL_RULES)
+            .title(tr!("Add firewall rules"))
+            .geometry(WindowGeometry {
+                x: Some(12),
+                y: Some(12 + OPTIONS.len() as i32 * 30 + 78),
+                width: Some(260),
+                height: Some(26),
+            })
+            .style(button_style.0)
+            .font(Font::new(15, "Segoe UI"))
+            .build()
+            .unwrap();
+        tools.push((
+            firewall_button,
+            "Allow the incoming connections on the listening ports in the Windows Firewall",
+        ));
+
+        match Tooltip::new(window.clone()) {
+            Ok(tooltip) => {
+                for (control, tip) in tools {
+                    tooltip.add_tool(&control, tr!(tip));
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+
+        self.update_checks(window);
+    }
+
+    fn update_checks(&self, window: &WindowRef) {
+        for child in window.children() {
+            let id = child.id();
+            if !OPTIONS.iter().any(|(option, _, _)| *option == id) {
+                continue;
+            }
+            let state = if self.is_checked(id) {
+                BST_CHECKED
+            } else {
+                BST_UNCHECKED
+            };
+            child.send_message(WindowMessage::new(
+                child.clone(),
+                BM_SETCHECK,
+                state.0 as _,
+                0,
+            ));
+        }
+    }
+}
+
+impl WindowMessageHandler for SettingsWindow {
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        match message.event() {
+            WindowEvent::Create => {
+                self.create_controls(&message.window);
+                MessageResult::Processed
+            }
+            WindowEvent::Command {
+                id,
+                code: BN_CLICKED,
+            } => {
+                if let Some(main_window) = self.main_window.upgrade() {
+                    main_window.send_message(WindowMessage::new(
+                        main_window.clone(),
+                        WM_COMMAND,
+                        id as usize,
+                        0,
+                    ));
+                }
+                self.update_checks(&message.window);
+                MessageResult::Processed
+            }
+            WindowEvent::Command {
+                id: IDC_RESEND_TARGET,
+                code: EN_KILLFOCUS,
+            } => {
+                let input = self.resend_target.lock().unwrap().clone();
+                if let Some(input) = input {
+                    let target = input.get_text().unwrap_or_default().trim().to_owned();
+                    *self.settings.resend_target.write().unwrap() = target;
+                    self.settings.store();
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Command {
+                id: IDC_LOG_LEVEL,
+                code: CBN_SELCHANGE,
+            } => {
+                let combo = self.log_level.lock().unwrap().clone();
+                if let Some(level) = combo
+                    .and_then(|combo| combo.selected_item())
+                    .and_then(|index| LOG_LEVELS.get(index))
+                {
+                    self.logger.set_level(*level);
+                    *self.settings.log_level.write().unwrap() = *level;
+                    self.settings.store();
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Destroy => {
+                *self.window.lock().unwrap() = None;
+                *self.resend_target.lock().unwrap() = None;
+                *self.log_level.lock().unwrap() = None;
+                MessageResult::Processed
+            }
+            _ => MessageResult::Ignored,
+        }
+    }
+}
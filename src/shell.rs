@@ -0,0 +1,97 @@
+use std::{env, io, mem, path::Path};
+
+use windows::{
+    core::{Result, PCWSTR},
+    Win32::{
+        Foundation::CloseHandle,
+        System::Threading::{GetExitCodeProcess, WaitForSingleObject, INFINITE},
+        UI::{
+            Shell::{
+                ILCreateFromPathW, ILFree, SHOpenFolderAndSelectItems, ShellExecuteExW,
+                ShellExecuteW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW,
+            },
+            WindowsAndMessaging::{SW_HIDE, SW_SHOWNORMAL},
+        },
+    },
+};
+
+use crate::{ui::window::WindowRef, utf16z};
+
+fn shell_execute(window: &WindowRef, operation: &str, path: &Path) -> io::Result<()> {
+    let operation = utf16z!(operation);
+    let file = utf16z!(path.to_string_lossy());
+    let result = unsafe {
+        ShellExecuteW(
+            Some(window.handle()),
+            PCWSTR(operation.as_ptr()),
+            PCWSTR(file.as_ptr()),
+            PCWSTR::null(),
+            PCWSTR::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+
+    // values up to 32 are error codes
+    if result.0 as usize <= 32 {
+        Err(io::Error::other(format!("Cannot open {}", path.display())))
+    } else {
+        Ok(())
+    }
+}
+
+// Opens the file with the associated application
+pub fn open_file(window: &WindowRef, path: &Path) -> io::Result<()> {
+    shell_execute(window, "open", path)
+}
+
+// Opens the URL in the default browser
+pub fn open_url(window: &WindowRef, url: &str) -> io::Result<()> {
+    shell_execute(window, "open", Path::new(url))
+}
+
+// Opens Explorer on the parent folder with the file selected
+pub fn show_in_folder(window: &WindowRef, path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        return shell_execute(window, "explore", path);
+    }
+
+    let file = utf16z!(path.to_string_lossy());
+    unsafe {
+        let pidl = ILCreateFromPathW(PCWSTR(file.as_ptr()));
+        if pidl.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", path.display()),
+            ));
+        }
+        let result = SHOpenFolderAndSelectItems(pidl, None, 0);
+        ILFree(Some(pidl));
+        result.map_err(io::Error::other)
+    }
+}
+
+// Runs an elevated copy of the program with the parameters and waits for it,
+// Windows asks the user for the administrator rights. Returns the exit code of
+// the copy.
+pub fn run_elevated(parameters: &str) -> Result<u32> {
+    let program = utf16z!(env::current_exe().unwrap_or_default().to_string_lossy());
+    let parameters = utf16z!(parameters);
+    let verb = utf16z!("runas");
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: mem::size_of::<SHELLEXECUTEINFOW>() as _,
+        fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC,
+        lpVerb: PCWSTR(verb.as_ptr()),
+        lpFile: PCWSTR(program.as_ptr()),
+        lpParameters: PCWSTR(parameters.as_ptr()),
+        nShow: SW_HIDE.0,
+        ..Default::default()
+    };
+    unsafe {
+        ShellExecuteExW(&mut info)?;
+        WaitForSingleObject(info.hProcess, INFINITE);
+        let mut code = 0;
+        let result = GetExitCodeProcess(info.hProcess, &mut code);
+        let _ = CloseHandle(info.hProcess);
+        result.map(|_| code)
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::{
+    atomic::{AtomicI32, Ordering},
+    Arc,
+};
+
+use windows::Win32::UI::{
+    Input::KeyboardAndMouse::{ReleaseCapture, SetCapture},
+    WindowsAndMessaging::*,
+};
+
+use crate::ui::window::{
+    user_message, MessageResult, WindowBuilder, WindowError, WindowMessage, WindowMessageHandler,
+    WindowRef,
+};
+
+// Sent to the parent while the splitter is dragged, lparam holds the vertical distance
+// between the mouse and the point where the splitter was grabbed, wparam is 1 when
+// the button is released
+pub const WM_SPLITTER_MOVED: u32 = user_message(3);
+
+const NOT_DRAGGING: i32 = i32::MIN;
+
+// Horizontal bar between two vertically stacked panes, the parent does the layout
+pub struct Splitter {
+    parent: WindowRef,
+    // vertical position of the mouse in the splitter when the drag started
+    drag_start: AtomicI32,
+}
+
+impl Splitter {
+    pub fn create(parent: WindowRef) -> Result<WindowRef, WindowError> {
+        let splitter = Arc::new(Splitter {
+            parent: parent.clone(),
+            drag_start: AtomicI32::new(NOT_DRAGGING),
+        });
+
+        WindowBuilder::window("miniraw_splitter", Some(parent))
+            .style(WS_CHILD.0)
+            .message_handler(splitter)
+            .build()
+    }
+
+    fn notify_parent(&self, offset: i32, released: bool) {
+        self.parent.send_message(WindowMessage::new(
+            self.parent.clone(),
+            WM_SPLITTER_MOVED,
+            released as usize,
+            offset as isize,
+        ));
+    }
+}
+
+impl WindowMessageHandler for Splitter {
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        // mouse coordinates are signed, they are negative above the splitter
+        let y = (message.lparam >> 16) as i16 as i32;
+
+        match message.msg {
+            WM_SETCURSOR => {
+                unsafe {
+                    if let Ok(cursor) = LoadCursorW(None, IDC_SIZENS) {
+                        SetCursor(Some(cursor));
+                    }
+                }
+                MessageResult::Value(1)
+            }
+            WM_LBUTTONDOWN => {
+                self.drag_start.store(y, Ordering::SeqCst);
+                unsafe {
+                    SetCapture(message.window.handle());
+                }
+                MessageResult::Processed
+            }
+            WM_MOUSEMOVE => {
+                let start = self.drag_start.load(Ordering::SeqCst);
+                if start != NOT_DRAGGING {
+                    self.notify_parent(y - start, false);
+                }
+                MessageResult::Processed
+            }
+            WM_LBUTTONUP => {
+                let start = self.drag_start.swap(NOT_DRAGGING, Ordering::SeqCst);
+                unsafe {
+                    let _ = ReleaseCapture();
+                }
+                if start != NOT_DRAGGING {
+                    self.notify_parent(y - start, true);
+                }
+                MessageResult::Processed
+            }
+            WM_CAPTURECHANGED => {
+                self.drag_start.store(NOT_DRAGGING, Ordering::SeqCst);
+                MessageResult::Processed
+            }
+            _ => MessageResult::Ignored,
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use std::{mem, slice};
+
+use log::{error, info};
+use windows::{
+    core::{Error, Result, HRESULT, PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{
+            ERROR_ALREADY_EXISTS, ERROR_SUCCESS, E_ACCESSDENIED, E_INVALIDARG, HANDLE, WIN32_ERROR,
+        },
+        Graphics::Printing::{
+            AddPrinterW, ClosePrinter, DeletePrinter, DeletePrinterDataExW, EnumPrintersW,
+            GetPrinterDataExW, GetPrinterW, OpenPrinterW, SetPrinterDataExW, SetPrinterW, XcvDataW,
+            PORT_DATA_1, PRINTER_ACCESS_RIGHTS, PRINTER_ACCESS_USE, PRINTER_ALL_ACCESS,
+            PRINTER_ATTRIBUTE_LOCAL, PRINTER_DEFAULTSW, PRINTER_ENUM_LOCAL, PRINTER_HANDLE,
+            PRINTER_INFO_2W, PROTOCOL_RAWTCP_TYPE, SERVER_ACCESS_ADMINISTER,
+        },
+        Security::PSECURITY_DESCRIPTOR,
+        System::Registry::REG_SZ,
+    },
+};
+
+use crate::{shell, utf16z};
+
+pub const SPOOLER_ARGUMENT: &str = "--spooler";
+
+// the port monitor of the Standard TCP/IP ports, configured through XcvData
+const TCP_MONITOR: &str = ",XcvMonitor Standard TCP/IP Port";
+const PORT_PREFIX: &str = "MiniRAW_";
+// the port of a captured printer before the capture is kept in its printer data
+const DATA_KEY: &str = "MiniRAW NG";
+const ORIGINAL_PORT_VALUE: &str = "OriginalPort";
+
+// The local printer added by MiniRAW NG, shown in the print dialogs
+pub const VIRTUAL_PRINTER_NAME: &str = "MiniRAW NG";
+// the jobs are spooled and sent to the port as they come from the driver
+const PRINT_PROCESSOR: &str = "winprint";
+const DATA_TYPE: &str = "RAW";
+
+// The Standard TCP/IP port which sends the jobs to the listener on this port
+pub fn port_name(port: u16) -> String {
+    format!("{PORT_PREFIX}{port}")
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalPrinter {
+    pub name: String,
+    // printing to a MiniRAW port
+    pub captured: bool,
+}
+
+struct Printer(PRINTER_HANDLE);
+
+impl Printer {
+    // Adds a local printer printing to the port with the installed driver
+    fn add(name: &str, port_name: &str, driver: &str) -> Result<Printer> {
+        let mut name = utf16z!(name);
+        let mut port_name = utf16z!(port_name);
+        let mut driver = utf16z!(driver);
+        let mut print_processor = utf16z!(PRINT_PROCESSOR);
+        let mut data_type = utf16z!(DATA_TYPE);
+        let info = PRINTER_INFO_2W {
+            pPrinterName: PWSTR(name.as_mut_ptr()),
+            pPortName: PWSTR(port_name.as_mut_ptr()),
+            pDriverName: PWSTR(driver.as_mut_ptr()),
+            pPrintProcessor: PWSTR(print_processor.as_mut_ptr()),
+            pDatatype: PWSTR(data_type.as_mut_ptr()),
+            Attributes: PRINTER_ATTRIBUTE_LOCAL,
+            ..Default::default()
+        };
+        let handle = unsafe { AddPrinterW(PCWSTR::null(), 2, &info as *const _ as *const u8)? };
+        Ok(Printer(PRINTER_HANDLE { Value: handle.0 }))
+    }
+
+    fn open(name: &str, access: PRINTER_ACCESS_RIGHTS) -> Result<Printer> {
+        let name = utf16z!(name);
+        let defaults = PRINTER_DEFAULTSW {
+            DesiredAccess: access,
+            ..Default::default()
+        };
+        let mut handle = PRINTER_HANDLE::default();
+        unsafe { OpenPrinterW(PCWSTR(name.as_ptr()), &mut handle, Some(&defaults))? };
+        Ok(Printer(handle))
+    }
+
+    // The spooler fills the buffer with the structure followed by its strings, a
+    // buffer of u64 keeps the structure aligned
+    fn info(&self) -> Result<Vec<u64>> {
+        let mut needed = 0;
+        let _ = unsafe { GetPrinterW(self.0, 2, None, &mut needed) };
+        let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+        unsafe { GetPrinterW(self.0, 2, Some(as_bytes(&mut buffer)), &mut needed)? };
+        Ok(buffer)
+    }
+
+    fn data(&self, value: &str) -> Option<String> {
+        let key = utf16z!(DATA_KEY);
+        let value = utf16z!(value);
+        let mut data = vec![0u16; 260];
+        let mut needed = 0;
+        let status = unsafe {
+            GetPrinterDataExW(
+                self.0,
+                PCWSTR(key.as_ptr()),
+                PCWSTR(value.as_ptr()),
+                None,
+                Some(slice::from_raw_parts_mut(
+                    data.as_mut_ptr() as *mut u8,
+                    data.len() * 2,
+                )),
+                &mut needed,
+            )
+        };
+        (status == ERROR_SUCCESS.0).then(|| {
+            let data = &data[..needed as usize / 2];
+            String::from_utf16_lossy(data.split(|c| *c == 0).next().unwrap_or_default())
+        })
+    }
+
+    fn set_data(&self, value: &str, data: &str) -> Result<()> {
+        let key = utf16z!(DATA_KEY);
+        let value = utf16z!(value);
+        let data = utf16z!(data);
+        let status = unsafe {
+            SetPrinterDataExW(
+                self.0,
+                PCWSTR(key.as_ptr()),
+                PCWSTR(value.as_ptr()),
+                REG_SZ.0,
+                slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 2),
+            )
+        };
+        WIN32_ERROR(status).ok()
+    }
+
+    fn delete_data(&self, value: &str) {
+        let key = utf16z!(DATA_KEY);
+        let value = utf16z!(value);
+        unsafe { DeletePrinterDataExW(self.0, PCWSTR(key.as_ptr()), PCWSTR(value.as_ptr())) };
+    }
+
+    // Changes the port of the printer, the other settings are kept
+    fn set_port(&self, port_name: &str) -> Result<()> {
+        let mut buffer = self.info()?;
+        let port_name = utf16z!(port_name);
+        let info = unsafe { &mut *(buffer.as_mut_ptr() as *mut PRINTER_INFO_2W) };
+        info.pPortName = PWSTR(port_name.as_ptr() as _);
+        // the security descriptor is not changed
+        info.pSecurityDescriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe { SetPrinterW(self.0, 2, Some(info as *const _ as *const u8), 0) }
+    }
+
+    fn port(&self) -> Result<String> {
+        let buffer = self.info()?;
+        let info = unsafe { &*(buffer.as_ptr() as *const PRINTER_INFO_2W) };
+        Ok(unsafe { pwstr_string(info.pPortName) })
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        let _ = unsafe { ClosePrinter(self.0) };
+    }
+}
+
+fn as_bytes(buffer: &mut [u64]) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len() * 8) }
+}
+
+unsafe fn pwstr_string(text: PWSTR) -> String {
+    if text.is_null() {
+        String::new()
+    } else {
+        unsafe { text.to_string() }.unwrap_or_default()
+    }
+}
+
+fn copy_wide(target: &mut [u16], text: &str) {
+    for (target, c) in target.iter_mut().zip(text.encode_utf16()) {
+        *target = c;
+    }
+}
+
+// The printers installed on this computer sorted by name, the printer connections
+// to other computers are not included
+pub fn local_printers() -> Result<Vec<LocalPrinter>> {
+    let mut needed = 0;
+    let mut count = 0;
+    let _ = unsafe {
+        EnumPrintersW(
+            PRINTER_ENUM_LOCAL,
+            PCWSTR::null(),
+            2,
+            None,
+            &mut needed,
+            &mut count,
+        )
+    };
+    if needed == 0 {
+        return Ok(Vec::new());
+    }
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    unsafe {
+        EnumPrintersW(
+            PRINTER_ENUM_LOCAL,
+            PCWSTR::null(),
+            2,
+            Some(as_bytes(&mut buffer)),
+            &mut needed,
+            &mut count,
+        )?
+    };
+
+    let infos =
+        unsafe { slice::from_raw_parts(buffer.as_ptr() as *const PRINTER_INFO_2W, count as _) };
+    let mut printers = infos
+        .iter()
+        .map(|info| LocalPrinter {
+            name: unsafe { pwstr_string(info.pPrinterName) },
+            captured: unsafe { pwstr_string(info.pPortName) }.starts_with(PORT_PREFIX),
+        })
+        .collect::<Vec<_>>();
+    printers.sort_by_key(|printer| printer.name.to_lowercase());
+    Ok(printers)
+}
+
+pub fn has_virtual_printer() -> bool {
+    Printer::open(VIRTUAL_PRINTER_NAME, PRINTER_ACCESS_USE).is_ok()
+}
+
+// Adds the Standard TCP/IP port which sends the jobs in RAW to the port of this
+// computer, an existing port is kept
+fn add_tcp_port(port: u16) -> Result<()> {
+    let monitor = Printer::open(TCP_MONITOR, SERVER_ACCESS_ADMINISTER)?;
+
+    let mut data = PORT_DATA_1 {
+        dwVersion: 1,
+        dwProtocol: PROTOCOL_RAWTCP_TYPE,
+        cbSize: mem::size_of::<PORT_DATA_1>() as _,
+        dwPortNumber: port as _,
+        ..Default::default()
+    };
+    copy_wide(&mut data.sztPortName, &port_name(port));
+    copy_wide(&mut data.sztHostAddress, "127.0.0.1");
+
+    let command = utf16z!("AddPort");
+    let mut needed = 0;
+    let mut status = 0;
+    let input = unsafe {
+        slice::from_raw_parts(
+            &data as *const PORT_DATA_1 as *const u8,
+            mem::size_of::<PORT_DATA_1>(),
+        )
+    };
+    unsafe {
+        XcvDataW(
+            HANDLE(monitor.0.Value),
+            PCWSTR(command.as_ptr()),
+            Some(input),
+            None,
+            &mut needed,
+            Some(&mut status),
+        )
+        .ok()?
+    };
+    match WIN32_ERROR(status) {
+        ERROR_ALREADY_EXISTS => Ok(()),
+        status => status.ok(),
+    }
+}
+
+// Changes to the printer configuration, all need the administrator rights
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpoolerCommand {
+    // points the printer to the MiniRAW port of the listener
+    Capture { printer: String, port: u16 },
+    // gives the printer back its port from before the capture
+    Release { printer: String },
+    // adds the virtual printer with the driver printing to the MiniRAW port of the
+    // listener, an existing one is pointed to the port
+    AddPrinter { port: u16, driver: String },
+    // deletes the virtual printer, its port is kept for the captured printers
+    RemovePrinter,
+}
+
+// The printer names may contain any characters, they are passed to the elevated
+// copy in hex
+fn hex(text: &str) -> String {
+    text.bytes().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(text: &str) -> Option<String> {
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+impl SpoolerCommand {
+    fn run(&self) -> Result<()> {
+        match self {
+            SpoolerCommand::Capture { printer, port } => {
+                add_tcp_port(*port)?;
+                let printer = Printer::open(printer, PRINTER_ALL_ACCESS)?;
+                let original = printer.port()?;
+                // a printer captured before, possibly for another port, keeps its
+                // original port
+                if !original.starts_with(PORT_PREFIX) {
+                    printer.set_data(ORIGINAL_PORT_VALUE, &original)?;
+                }
+                printer.set_port(&port_name(*port))
+            }
+            SpoolerCommand::Release { printer } => {
+                let printer = Printer::open(printer, PRINTER_ALL_ACCESS)?;
+                let Some(original) = printer.data(ORIGINAL_PORT_VALUE) else {
+                    return Ok(());
+                };
+                printer.set_port(&original)?;
+                printer.delete_data(ORIGINAL_PORT_VALUE);
+                Ok(())
+            }
+            SpoolerCommand::AddPrinter { port, driver } => {
+                add_tcp_port(*port)?;
+                match Printer::open(VIRTUAL_PRINTER_NAME, PRINTER_ALL_ACCESS) {
+                    Ok(printer) => printer.set_port(&port_name(*port)),
+                    Err(_) => {
+                        Printer::add(VIRTUAL_PRINTER_NAME, &port_name(*port), driver).map(|_| ())
+                    }
+                }
+            }
+            SpoolerCommand::RemovePrinter => {
+                let printer = Printer::open(VIRTUAL_PRINTER_NAME, PRINTER_ALL_ACCESS)?;
+                unsafe { DeletePrinter(printer.0) }
+            }
+        }
+    }
+
+    fn argument(&self) -> String {
+        match self {
+            SpoolerCommand::Capture { printer, port } => {
+                format!("capture:{}:{}", port, hex(printer))
+            }
+            SpoolerCommand::Release { printer } => format!("release:{}", hex(printer)),
+            SpoolerCommand::AddPrinter { port, driver } => {
+                format!("add:{}:{}", port, hex(driver))
+            }
+            SpoolerCommand::RemovePrinter => "remove".to_owned(),
+        }
+    }
+
+    fn from_argument(argument: &str) -> Option<SpoolerCommand> {
+        match argument.split(':').collect::<Vec<_>>()[..] {
+            ["capture", port, printer] => Some(SpoolerCommand::Capture {
+                printer: unhex(printer)?,
+                port: port.parse().ok()?,
+            }),
+            ["release", printer] => Some(SpoolerCommand::Release {
+                printer: unhex(printer)?,
+            }),
+            ["add", port, driver] => Some(SpoolerCommand::AddPrinter {
+                port: port.parse().ok()?,
+                driver: unhex(driver)?,
+            }),
+            ["remove"] => Some(SpoolerCommand::RemovePrinter),
+            _ => None,
+        }
+    }
+
+    fn log_result(&self, result: Result<()>) {
+        match (self, result) {
+            (SpoolerCommand::Capture { printer, port }, Ok(())) => info!(
+                "The printer {} now prints to port {} of MiniRAW NG",
+                printer, port
+            ),
+            (SpoolerCommand::Release { printer }, Ok(())) => {
+                info!("The printer {} prints to its own port again", printer)
+            }
+            (SpoolerCommand::AddPrinter { port, .. }, Ok(())) => info!(
+                "Added the printer {} printing to port {} of MiniRAW NG",
+                VIRTUAL_PRINTER_NAME, port
+            ),
+            (SpoolerCommand::RemovePrinter, Ok(())) => {
+                info!("Removed the printer {}", VIRTUAL_PRINTER_NAME)
+            }
+            (
+                SpoolerCommand::Capture { printer, .. } | SpoolerCommand::Release { printer },
+                Err(e),
+            ) => error!("Cannot change the port of the printer {}: {}", printer, e),
+            (SpoolerCommand::AddPrinter { .. }, Err(e)) => {
+                error!("Cannot add the printer {}: {}", VIRTUAL_PRINTER_NAME, e)
+            }
+            (SpoolerCommand::RemovePrinter, Err(e)) => {
+                error!("Cannot remove the printer {}: {}", VIRTUAL_PRINTER_NAME, e)
+            }
+        }
+    }
+}
+
+// Runs the commands on a background thread in the order given, the first command
+// denied for the lack of the administrator rights and the ones after it are run by
+// one elevated copy of the program. The results are logged.
+pub fn execute(commands: Vec<SpoolerCommand>) {
+    std::thread::spawn(move || {
+        for (index, command) in commands.iter().enumerate() {
+            match command.run() {
+                Err(e) if e.code() == E_ACCESSDENIED => {
+                    let remaining = &commands[index..];
+                    let arguments = remaining
+                        .iter()
+                        .map(SpoolerCommand::argument)
+                        .collect::<Vec<_>>();
+                    // the copy exits with the error code of the first failed command
+                    match shell::run_elevated(&format!(
+                        "{}={}",
+                        SPOOLER_ARGUMENT,
+                        arguments.join(",")
+                    )) {
+                        Ok(0) => remaining
+                            .iter()
+                            .for_each(|command| command.log_result(Ok(()))),
+                        Ok(code) => {
+                            let e = Error::from_hresult(HRESULT(code as _));
+                            error!("Cannot change the printer ports: {}", e);
+                        }
+                        Err(e) => error!("Cannot change the printer ports: {}", e),
+                    }
+                    return;
+                }
+                result => command.log_result(result),
+            }
+        }
+    });
+}
+
+// The commands given to the elevated copy, returns its exit code
+pub fn execute_from_argument(arguments: &str) -> i32 {
+    let Some(commands) = arguments
+        .split(',')
+        .map(SpoolerCommand::from_argument)
+        .collect::<Option<Vec<_>>>()
+    else {
+        return E_INVALIDARG.0;
+    };
+    let mut code = 0;
+    for command in commands {
+        if let Err(e) = command.run() {
+            if code == 0 {
+                code = e.code().0;
+            }
+        }
+    }
+    code
+}
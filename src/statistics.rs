@@ -0,0 +1,198 @@
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::{
+    forward_health,
+    jobs::Job,
+    statistics_history::{Aggregate, StatisticsHistory},
+    throughput::ThroughputGraph,
+    tr,
+    ui::{
+        layout::{Dock, Layout},
+        window::{Font, WindowBuilder, WindowError, WindowRef},
+    },
+};
+
+const GRAPH_HEIGHT: i32 = 140;
+
+const MAX_HOURS: usize = 24;
+const MAX_DAYS: usize = 31;
+const MAX_HOSTS: usize = 5;
+
+#[derive(Default, Clone, Copy)]
+struct Totals {
+    jobs: u64,
+    bytes: u64,
+}
+
+impl Totals {
+    fn add(&mut self, job: &Job) {
+        self.jobs += 1;
+        self.bytes += job.size;
+    }
+
+    fn row(&self, label: &str) -> String {
+        format!("  {:<20}{:>8}{:>16}", label, self.jobs, self.bytes)
+    }
+}
+
+fn history_row(label: &str, aggregate: &Aggregate) -> String {
+    format!(
+        "  {:<20}{:>8}{:>16}{:>8}",
+        label, aggregate.jobs, aggregate.bytes, aggregate.errors
+    )
+}
+
+// Throughput graph above the summary of the jobs received in this session and the
+// jobs per day and hour kept across restarts, the summary is refreshed while the
+// page is shown
+pub struct StatisticsPage {
+    view: WindowRef,
+    graph: Arc<ThroughputGraph>,
+    graph_window: WindowRef,
+    history: Arc<StatisticsHistory>,
+}
+
+impl StatisticsPage {
+    pub fn new(
+        parent: WindowRef,
+        history: Arc<StatisticsHistory>,
+    ) -> Result<StatisticsPage, WindowError> {
+        let style = WS_CHILD
+            | WS_VSCROLL
+            | WINDOW_STYLE((ES_LEFT | ES_MULTILINE | ES_AUTOVSCROLL | ES_READONLY) as u32);
+
+        let graph = ThroughputGraph::new();
+        let graph_window = graph.create(parent.clone())?;
+
+        let view = WindowBuilder::edit_control(parent)
+            .style(style.0)
+            .extended_style(WS_EX_CLIENTEDGE.0)
+            .font(Font::new(14, "Consolas"))
+            .build()?;
+
+        let _ = graph_window.set_accessible_name(tr!("Throughput graph"));
+        let _ = view.set_accessible_name(tr!("Statistics summary"));
+
+        Ok(StatisticsPage {
+            view,
+            graph,
+            graph_window,
+            history,
+        })
+    }
+
+    pub fn graph(&self) -> &Arc<ThroughputGraph> {
+        &self.graph
+    }
+
+    pub fn history(&self) -> &Arc<StatisticsHistory> {
+        &self.history
+    }
+
+    // The summary is the part of the page which takes the keyboard focus
+    pub fn view(&self) -> &WindowRef {
+        &self.view
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.view.is_visible()
+    }
+
+    pub fn set_visible(&self, visible: bool) {
+        self.graph_window.set_visible(visible);
+        self.view.set_visible(visible);
+    }
+
+    // The graph is docked above the summary which fills the rest of the page
+    pub fn add_to_layout(&self, layout: &Layout) {
+        layout.add(self.graph_window.clone(), Dock::Top(GRAPH_HEIGHT), 6);
+        layout.add(self.view.clone(), Dock::Fill, 0);
+    }
+
+    pub fn update(&self, jobs: &[Job]) {
+        let _ = self
+            .view
+            .set_text(&Self::report(jobs, &self.history).join("\r\n"));
+    }
+
+    fn report(jobs: &[Job], history: &StatisticsHistory) -> Vec<String> {
+        let mut total = Totals::default();
+        let mut hosts = HashMap::<IpAddr, Totals>::new();
+
+        for job in jobs {
+            total.add(job);
+            hosts.entry(job.source.ip()).or_default().add(job);
+        }
+
+        let saved = jobs.iter().filter(|job| job.path.is_some()).count();
+        let largest = jobs.iter().map(|job| job.size).max().unwrap_or_default();
+        let average = total.bytes.checked_div(total.jobs).unwrap_or_default();
+
+        let mut lines = vec![
+            tr!("Received jobs: {}", total.jobs),
+            tr!("Saved jobs: {}", saved),
+            tr!("Discarded jobs: {}", jobs.len() - saved),
+            tr!("Total size: {} bytes", total.bytes),
+            tr!("Average job size: {} bytes", average),
+            tr!("Largest job: {} bytes", largest),
+        ];
+
+        let header = format!("  {:<20}{:>8}{:>16}", "", tr!("Jobs"), tr!("Bytes"));
+        let history_header = format!("{header}{:>8}", tr!("Errors"));
+
+        lines.extend([
+            String::new(),
+            tr!("Jobs per day").to_owned(),
+            history_header.clone(),
+        ]);
+        lines.extend(
+            history
+                .days(MAX_DAYS)
+                .iter()
+                .map(|((year, month, day), aggregate)| {
+                    history_row(&format!("{year}-{month:02}-{day:02}"), aggregate)
+                }),
+        );
+
+        lines.extend([
+            String::new(),
+            tr!("Jobs per hour").to_owned(),
+            history_header,
+        ]);
+        lines.extend(history.hours(MAX_HOURS).iter().map(
+            |((year, month, day, hour), aggregate)| {
+                history_row(
+                    &format!("{year}-{month:02}-{day:02} {hour:02}:00"),
+                    aggregate,
+                )
+            },
+        ));
+
+        let mut hosts = hosts.into_iter().collect::<Vec<_>>();
+        hosts.sort_by(|a, b| b.1.jobs.cmp(&a.1.jobs).then(b.1.bytes.cmp(&a.1.bytes)));
+
+        lines.extend([String::new(), tr!("Top source hosts").to_owned(), header]);
+        lines.extend(
+            hosts
+                .iter()
+                .take(MAX_HOSTS)
+                .map(|(host, totals)| totals.row(&host.to_string())),
+        );
+
+        let targets = forward_health::targets();
+        if !targets.is_empty() {
+            lines.extend([
+                String::new(),
+                tr!("Forward targets").to_owned(),
+                format!("  {:<28}{:>8}  {}", "", tr!("Queued"), tr!("State")),
+            ]);
+            lines.extend(targets.iter().map(|(target, health, queued)| {
+                format!("  {:<28}{:>8}  {}", target, queued, health.description())
+            }));
+        }
+
+        lines
+    }
+}
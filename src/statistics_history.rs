@@ -0,0 +1,201 @@
+use std::{collections::BTreeMap, fmt::Write as _, fs, io::ErrorKind, path::PathBuf, sync::Mutex};
+
+use log::{error, warn};
+use time::OffsetDateTime;
+
+use crate::jobs::JobEvent;
+
+// next to the configuration file
+pub const HISTORY_FILE_NAME: &str = "miniraw-statistics.txt";
+// the oldest periods are dropped when the history is saved
+const MAX_STORED_HOURS: usize = 7 * 24;
+const MAX_STORED_DAYS: usize = 366;
+
+pub type Day = (i32, u8, u8);
+pub type Hour = (i32, u8, u8, u8);
+
+// The jobs received in a period and the connections which ended without a job,
+// e.g. because of a network error or because a check dropped the job
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Aggregate {
+    pub jobs: u64,
+    pub bytes: u64,
+    pub errors: u64,
+}
+
+#[derive(Default)]
+struct State {
+    hours: BTreeMap<Hour, Aggregate>,
+    days: BTreeMap<Day, Aggregate>,
+    // changed since it was saved
+    modified: bool,
+}
+
+impl State {
+    fn aggregates(&mut self, time: OffsetDateTime) -> [&mut Aggregate; 2] {
+        let day = (time.year(), time.month() as u8, time.day());
+        [
+            self.hours
+                .entry((day.0, day.1, day.2, time.hour()))
+                .or_default(),
+            self.days.entry(day).or_default(),
+        ]
+    }
+}
+
+// "2024-05-01" or "2024-05-01 13:00"
+fn parse_period(text: &str) -> Option<(Day, Option<u8>)> {
+    let (date, hour) = match text.split_once(' ') {
+        Some((date, hour)) => (date, Some(hour.strip_suffix(":00")?.parse().ok()?)),
+        None => (text, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let day = (
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    );
+    Some((day, hour))
+}
+
+// One line per period: the period, the jobs, the bytes and the errors separated by
+// tabs, None if the line is invalid
+fn parse_line(line: &str) -> Option<(Day, Option<u8>, Aggregate)> {
+    let mut fields = line.split('\t');
+    let (day, hour) = parse_period(fields.next()?)?;
+    let aggregate = Aggregate {
+        jobs: fields.next()?.parse().ok()?,
+        bytes: fields.next()?.parse().ok()?,
+        errors: fields.next()?.parse().ok()?,
+    };
+    fields.next().is_none().then_some((day, hour, aggregate))
+}
+
+// Hourly and daily totals of the jobs kept in a small text file, so that the
+// statistics cover more than the current session. The totals are counted as the
+// jobs are received and saved periodically and on exit.
+pub struct StatisticsHistory {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl StatisticsHistory {
+    // The invalid lines of the file are logged and skipped
+    pub fn load(path: PathBuf) -> StatisticsHistory {
+        let mut state = State::default();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                for line in text.lines().filter(|line| !line.starts_with('#')) {
+                    match parse_line(line) {
+                        Some((day, Some(hour), aggregate)) => {
+                            state.hours.insert((day.0, day.1, day.2, hour), aggregate);
+                        }
+                        Some((day, None, aggregate)) => {
+                            state.days.insert(day, aggregate);
+                        }
+                        None if line.trim().is_empty() => {}
+                        None => warn!(
+                            "Ignored invalid statistics line in {}: {}",
+                            path.display(),
+                            line
+                        ),
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => error!("{}: {}", path.display(), e),
+        }
+        StatisticsHistory {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        let now = || OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+        let mut state = self.state.lock().unwrap();
+        match event {
+            JobEvent::Completed(job) => {
+                for aggregate in state.aggregates(job.time) {
+                    aggregate.jobs += 1;
+                    aggregate.bytes += job.size;
+                }
+            }
+            JobEvent::Aborted { .. } => {
+                for aggregate in state.aggregates(now()) {
+                    aggregate.errors += 1;
+                }
+            }
+            _ => return,
+        }
+        state.modified = true;
+    }
+
+    // The latest days with jobs or errors, the newest first
+    pub fn days(&self, count: usize) -> Vec<(Day, Aggregate)> {
+        let state = self.state.lock().unwrap();
+        state
+            .days
+            .iter()
+            .rev()
+            .take(count)
+            .map(|(day, aggregate)| (*day, *aggregate))
+            .collect()
+    }
+
+    // The latest hours with jobs or errors, the newest first
+    pub fn hours(&self, count: usize) -> Vec<(Hour, Aggregate)> {
+        let state = self.state.lock().unwrap();
+        state
+            .hours
+            .iter()
+            .rev()
+            .take(count)
+            .map(|(hour, aggregate)| (*hour, *aggregate))
+            .collect()
+    }
+
+    // Writes the file if the totals have changed, a temporary file replaces it so
+    // that it is never left half-written
+    pub fn save(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.modified {
+            return;
+        }
+        while state.hours.len() > MAX_STORED_HOURS {
+            state.hours.pop_first();
+        }
+        while state.days.len() > MAX_STORED_DAYS {
+            state.days.pop_first();
+        }
+
+        let mut text = String::from("# period\tjobs\tbytes\terrors\r\n");
+        for ((year, month, day), a) in &state.days {
+            let _ = write!(
+                text,
+                "{year}-{month:02}-{day:02}\t{}\t{}\t{}\r\n",
+                a.jobs, a.bytes, a.errors
+            );
+        }
+        for ((year, month, day, hour), a) in &state.hours {
+            let _ = write!(
+                text,
+                "{year}-{month:02}-{day:02} {hour:02}:00\t{}\t{}\t{}\r\n",
+                a.jobs, a.bytes, a.errors
+            );
+        }
+
+        // tried again on the next change rather than logged every time
+        state.modified = false;
+        let temp_path = self.path.with_extension("txt.tmp");
+        let result = fs::write(&temp_path, text).and_then(|_| fs::rename(&temp_path, &self.path));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp_path);
+            error!(
+                "Cannot save the statistics to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
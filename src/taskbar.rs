@@ -0,0 +1,221 @@
+use std::{cell::RefCell, collections::HashSet, mem, net::SocketAddr, sync::Mutex};
+
+use log::warn;
+use windows::{
+    core::{Result, HSTRING, PCWSTR},
+    Win32::{
+        System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
+        UI::{
+            Shell::{
+                ITaskbarList3, SHGetStockIconInfo, TaskbarList, SHGSI_ICON, SHGSI_SMALLICON,
+                SHSTOCKICONINFO, SIID_ERROR, SIID_PRINTER, TBPF_INDETERMINATE, TBPF_NOPROGRESS,
+            },
+            WindowsAndMessaging::{DestroyIcon, HICON},
+        },
+    },
+};
+
+use crate::{
+    jobs::{ActiveTransfers, JobEvent, ListenerState},
+    tr,
+    ui::window::{user_message, WindowRef},
+};
+
+// Posted to the main window with wparam set to 1 while large jobs are being received
+pub const WM_TASKBAR_PROGRESS: u32 = user_message(1);
+
+// Posted to the main window when the overlay changes, wparam is the Overlay value
+pub const WM_TASKBAR_OVERLAY: u32 = user_message(6);
+
+const LARGE_JOB_SIZE: u64 = 4 * 1024 * 1024;
+
+thread_local! {
+    // created on the first update and kept for the UI thread, a failed creation is retried
+    static TASKBAR: RefCell<Option<ITaskbarList3>> = const { RefCell::new(None) };
+}
+
+fn with_taskbar(f: impl FnOnce(&ITaskbarList3) -> Result<()>) -> Result<()> {
+    TASKBAR.with(|cell| {
+        let mut taskbar = cell.borrow_mut();
+        if taskbar.is_none() {
+            let instance = unsafe {
+                CoCreateInstance::<_, ITaskbarList3>(&TaskbarList, None, CLSCTX_INPROC_SERVER)?
+            };
+            unsafe { instance.HrInit()? };
+            *taskbar = Some(instance);
+        }
+        f(taskbar.as_ref().unwrap())
+    })
+}
+
+// Tracks large transfers from the listener threads, the taskbar itself is updated
+// on the UI thread because the COM object is bound to it
+pub struct TaskbarProgress {
+    window: WindowRef,
+    receiving: Mutex<HashSet<SocketAddr>>,
+}
+
+impl TaskbarProgress {
+    pub fn new(window: WindowRef) -> TaskbarProgress {
+        TaskbarProgress {
+            window,
+            receiving: Default::default(),
+        }
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        let (was_active, active) = {
+            let mut receiving = self.receiving.lock().unwrap();
+            let was_active = !receiving.is_empty();
+            match event {
+                JobEvent::Progress { source, received } if *received >= LARGE_JOB_SIZE => {
+                    receiving.insert(*source);
+                }
+                JobEvent::Started { .. } | JobEvent::Progress { .. } | JobEvent::Listener(_) => {}
+                JobEvent::Completed(job) => {
+                    receiving.remove(&job.source);
+                }
+                JobEvent::Aborted { source } => {
+                    receiving.remove(source);
+                }
+            }
+            (was_active, !receiving.is_empty())
+        };
+
+        if active != was_active {
+            if let Err(e) = self
+                .window
+                .post_user_message(WM_TASKBAR_PROGRESS, active as usize, 0)
+            {
+                warn!("Cannot update the taskbar progress: {}", e);
+            }
+        }
+    }
+
+    // The total size of a RAW job is unknown, so the progress is indeterminate
+    pub fn show(window: &WindowRef, active: bool) {
+        let state = if active {
+            TBPF_INDETERMINATE
+        } else {
+            TBPF_NOPROGRESS
+        };
+
+        let result =
+            with_taskbar(|taskbar| unsafe { taskbar.SetProgressState(window.handle(), state) });
+
+        if let Err(e) = result {
+            warn!("Cannot update the taskbar progress: {}", e);
+        }
+    }
+}
+
+// Badge shown over the taskbar button, a failed listener takes precedence over
+// the activity of the connections which are still open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    None,
+    Receiving,
+    ListenerFailed,
+}
+
+impl Overlay {
+    pub fn from_wparam(wparam: usize) -> Overlay {
+        match wparam {
+            1 => Overlay::Receiving,
+            2 => Overlay::ListenerFailed,
+            _ => Overlay::None,
+        }
+    }
+}
+
+struct OverlayState {
+    listener_failed: bool,
+    overlay: Overlay,
+}
+
+pub struct TaskbarOverlay {
+    window: WindowRef,
+    transfers: ActiveTransfers,
+    state: Mutex<OverlayState>,
+}
+
+impl TaskbarOverlay {
+    pub fn new(window: WindowRef) -> TaskbarOverlay {
+        TaskbarOverlay {
+            window,
+            transfers: Default::default(),
+            state: Mutex::new(OverlayState {
+                listener_failed: false,
+                overlay: Overlay::None,
+            }),
+        }
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        self.transfers.handle_event(event);
+
+        let overlay = {
+            let mut state = self.state.lock().unwrap();
+            // pausing or resuming does not bring a failed listener back, listening
+            // again after a rebind or a restart does
+            match event {
+                JobEvent::Listener(ListenerState::Failed(_)) => state.listener_failed = true,
+                JobEvent::Listener(ListenerState::Listening(_)) => state.listener_failed = false,
+                _ => {}
+            }
+
+            let overlay = if state.listener_failed {
+                Overlay::ListenerFailed
+            } else if self.transfers.count() > 0 {
+                Overlay::Receiving
+            } else {
+                Overlay::None
+            };
+
+            if overlay == state.overlay {
+                return;
+            }
+            state.overlay = overlay;
+            overlay
+        };
+
+        if let Err(e) = self
+            .window
+            .post_user_message(WM_TASKBAR_OVERLAY, overlay as usize, 0)
+        {
+            warn!("Cannot update the taskbar overlay: {}", e);
+        }
+    }
+
+    pub fn show(window: &WindowRef, overlay: Overlay) {
+        let (icon, description) = match overlay {
+            Overlay::None => (None, ""),
+            Overlay::Receiving => (Some(SIID_PRINTER), tr!("Receiving jobs")),
+            Overlay::ListenerFailed => (Some(SIID_ERROR), tr!("Listener failed")),
+        };
+
+        let result = with_taskbar(|taskbar| unsafe {
+            let mut info = SHSTOCKICONINFO {
+                cbSize: mem::size_of::<SHSTOCKICONINFO>() as _,
+                ..Default::default()
+            };
+            if let Some(icon) = icon {
+                SHGetStockIconInfo(icon, SHGSI_ICON | SHGSI_SMALLICON, &mut info)?;
+            }
+
+            let description = HSTRING::from(description);
+            let result =
+                taskbar.SetOverlayIcon(window.handle(), info.hIcon, PCWSTR(description.as_ptr()));
+
+            // the taskbar keeps its own copy of the icon
+            if info.hIcon != HICON::default() {
+                let _ = DestroyIcon(info.hIcon);
+            }
+            result
+        });
+
+        if let Err(e) = result {
+            warn!("Cannot update the taskbar overlay: {}", e);
+        }
+    }
+}
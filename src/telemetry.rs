@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    env,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+
+use crate::{
+    http::json_string,
+    http_client,
+    jobs::{Job, JobEvent},
+    settings::{generate_token, AppSettings},
+};
+
+// the spans and the metrics are sent in batches
+const EXPORT_INTERVAL: Duration = Duration::from_secs(10);
+// spans waiting to be sent, newer ones are dropped while the collector is down
+const MAX_PENDING_SPANS: usize = 1000;
+const SERVICE_NAME: &str = "miniraw";
+
+// OTLP span kind and status codes
+const SPAN_KIND_SERVER: u32 = 2;
+const STATUS_OK: u32 = 1;
+const STATUS_ERROR: u32 = 2;
+// the metrics are sums since the start of the program
+const TEMPORALITY_CUMULATIVE: u32 = 2;
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn string_attribute(key: &str, value: &str) -> String {
+    format!(
+        "{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}",
+        json_string(key),
+        json_string(value)
+    )
+}
+
+// 64-bit integers are strings in the JSON encoding of OTLP
+fn int_attribute(key: &str, value: u64) -> String {
+    format!(
+        "{{\"key\":{},\"value\":{{\"intValue\":\"{}\"}}}}",
+        json_string(key),
+        value
+    )
+}
+
+fn resource() -> String {
+    let host = env::var("COMPUTERNAME").unwrap_or_default();
+    format!(
+        "{{\"attributes\":[{},{},{},{}]}}",
+        string_attribute("service.name", SERVICE_NAME),
+        string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+        string_attribute("service.instance.id", &host),
+        string_attribute("host.name", &host)
+    )
+}
+
+fn scope() -> String {
+    format!(
+        "{{\"name\":{},\"version\":{}}}",
+        json_string(SERVICE_NAME),
+        json_string(env!("CARGO_PKG_VERSION"))
+    )
+}
+
+#[derive(Default)]
+struct Counters {
+    jobs: u64,
+    aborted: u64,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct Telemetry {
+    // when the connections of the jobs being received were accepted
+    started: HashMap<SocketAddr, SystemTime>,
+    spans: Vec<String>,
+    counters: Counters,
+}
+
+// Exports one span per connection with the job attributes and the job counters to
+// the OTLP/HTTP endpoint of the otlp_endpoint setting, e.g. http://collector:4318,
+// in the JSON encoding. Each span starts a trace of its own, the spans can be
+// correlated with the traces of other services by the time and the client address.
+pub struct TelemetryExporter {
+    settings: Arc<AppSettings>,
+    telemetry: Arc<Mutex<Telemetry>>,
+}
+
+impl TelemetryExporter {
+    pub fn new(settings: Arc<AppSettings>) -> TelemetryExporter {
+        let telemetry = Arc::new(Mutex::new(Telemetry::default()));
+        {
+            let settings = settings.clone();
+            let telemetry = telemetry.clone();
+            std::thread::spawn(move || Self::export(&settings, &telemetry));
+        }
+        TelemetryExporter {
+            settings,
+            telemetry,
+        }
+    }
+
+    fn export(settings: &AppSettings, telemetry: &Mutex<Telemetry>) {
+        let start_time = unix_nanos(SystemTime::now());
+        loop {
+            std::thread::sleep(EXPORT_INTERVAL);
+            let endpoint = settings
+                .otlp_endpoint
+                .read()
+                .unwrap()
+                .trim()
+                .trim_end_matches('/')
+                .to_owned();
+            if endpoint.is_empty() {
+                continue;
+            }
+
+            let (spans, metrics) = {
+                let mut telemetry = telemetry.lock().unwrap();
+                (
+                    std::mem::take(&mut telemetry.spans),
+                    Self::metrics(&telemetry.counters, &start_time),
+                )
+            };
+            if !spans.is_empty() {
+                let body = format!(
+                    "{{\"resourceSpans\":[{{\"resource\":{},\"scopeSpans\":[{{\"scope\":{},\"spans\":[{}]}}]}}]}}",
+                    resource(),
+                    scope(),
+                    spans.join(",")
+                );
+                Self::post(&format!("{endpoint}/v1/traces"), &body);
+            }
+            let body = format!(
+                "{{\"resourceMetrics\":[{{\"resource\":{},\"scopeMetrics\":[{{\"scope\":{},\"metrics\":[{}]}}]}}]}}",
+                resource(),
+                scope(),
+                metrics
+            );
+            Self::post(&format!("{endpoint}/v1/metrics"), &body);
+        }
+    }
+
+    fn post(url: &str, body: &str) {
+        let headers = [("Content-Type", "application/json")];
+        match http_client::send("POST", url, &headers, body.as_bytes()) {
+            Ok(response) if (200..300).contains(&response.status) => {}
+            Ok(response) => warn!("The OTLP endpoint returned HTTP status {}", response.status),
+            Err(e) => warn!("Cannot send the telemetry to {}: {}", url, e),
+        }
+    }
+
+    fn metrics(counters: &Counters, start_time: &str) -> String {
+        let now = unix_nanos(SystemTime::now());
+        [
+            ("miniraw.jobs", "{job}", counters.jobs),
+            ("miniraw.jobs.aborted", "{job}", counters.aborted),
+            ("miniraw.bytes", "By", counters.bytes),
+        ]
+        .iter()
+        .map(|(name, unit, value)| {
+            format!(
+                "{{\"name\":{},\"unit\":{},\"sum\":{{\"aggregationTemporality\":{},\"isMonotonic\":true,\
+                 \"dataPoints\":[{{\"startTimeUnixNano\":\"{}\",\"timeUnixNano\":\"{}\",\"asInt\":\"{}\"}}]}}}}",
+                json_string(name),
+                json_string(unit),
+                TEMPORALITY_CUMULATIVE,
+                start_time,
+                now,
+                value
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+
+    fn span(start: SystemTime, source: SocketAddr, job: Option<&Job>) -> String {
+        let mut attributes = vec![
+            string_attribute("client.address", &source.ip().to_string()),
+            int_attribute("client.port", source.port() as u64),
+            string_attribute(
+                "miniraw.job.outcome",
+                if job.is_some() {
+                    "completed"
+                } else {
+                    "aborted"
+                },
+            ),
+        ];
+        if let Some(job) = job {
+            attributes.push(int_attribute("miniraw.connection", job.connection.0 as u64));
+            attributes.push(int_attribute("miniraw.job.bytes", job.size));
+            attributes.push(int_attribute("miniraw.job.peak_memory", job.peak_memory));
+            attributes.push(int_attribute(
+                "miniraw.job.duration_ms",
+                job.transfer.duration_ms,
+            ));
+            attributes.push(int_attribute(
+                "miniraw.job.average_bytes_per_second",
+                job.transfer.average_bytes_per_second,
+            ));
+            attributes.push(int_attribute(
+                "miniraw.job.peak_bytes_per_second",
+                job.transfer.peak_bytes_per_second,
+            ));
+            attributes.push(int_attribute(
+                "miniraw.job.stalls",
+                job.transfer.stalls as u64,
+            ));
+            attributes.push(string_attribute(
+                "miniraw.job.format",
+                &job.format.to_string(),
+            ));
+            if let Some(ref path) = job.path {
+                attributes.push(string_attribute(
+                    "miniraw.job.path",
+                    &path.display().to_string(),
+                ));
+            }
+        }
+        format!(
+            "{{\"traceId\":\"{}\",\"spanId\":\"{}\",\"name\":\"print job\",\"kind\":{},\
+             \"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[{}],\
+             \"status\":{{\"code\":{}}}}}",
+            generate_token(),
+            &generate_token()[..16],
+            SPAN_KIND_SERVER,
+            unix_nanos(start),
+            unix_nanos(SystemTime::now()),
+            attributes.join(","),
+            if job.is_some() {
+                STATUS_OK
+            } else {
+                STATUS_ERROR
+            }
+        )
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        if self
+            .settings
+            .otlp_endpoint
+            .read()
+            .unwrap()
+            .trim()
+            .is_empty()
+        {
+            return;
+        }
+
+        let mut telemetry = self.telemetry.lock().unwrap();
+        let (source, job) = match event {
+            JobEvent::Started { source } => {
+                telemetry.started.insert(*source, SystemTime::now());
+                return;
+            }
+            JobEvent::Completed(job) => {
+                telemetry.counters.jobs += 1;
+                telemetry.counters.bytes += job.size;
+                (job.source, Some(job))
+            }
+            JobEvent::Aborted { source } => {
+                telemetry.counters.aborted += 1;
+                (*source, None)
+            }
+            _ => return,
+        };
+        // a job started before the endpoint was set has no span
+        let Some(start) = telemetry.started.remove(&source) else {
+            return;
+        };
+        if telemetry.spans.len() < MAX_PENDING_SPANS {
+            let span = Self::span(start, source, job);
+            telemetry.spans.push(span);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+
+use windows::Win32::{
+    Foundation::{POINT, RECT},
+    Graphics::Gdi::*,
+    UI::WindowsAndMessaging::*,
+};
+
+use crate::{
+    jobs::JobEvent,
+    tr,
+    ui::window::{
+        MessageResult, WindowBuilder, WindowError, WindowMessage, WindowMessageHandler, WindowRef,
+    },
+    util::rgb,
+};
+
+const HISTORY_SECONDS: usize = 180;
+const TIMER_ID: usize = 1;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn format_rate(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB/s", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB/s", bytes as f64 / 1024.0)
+    }
+}
+
+struct Samples {
+    // time of the last sample in seconds since the epoch
+    second: u64,
+    // bytes received in each second, the newest sample is the last one
+    bytes: VecDeque<u64>,
+    // bytes reported so far for each connection in progress
+    received: HashMap<SocketAddr, u64>,
+}
+
+impl Samples {
+    fn advance(&mut self, now: u64) {
+        let elapsed = now.saturating_sub(self.second).min(HISTORY_SECONDS as u64);
+        for _ in 0..elapsed {
+            self.bytes.pop_front();
+            self.bytes.push_back(0);
+        }
+        self.second = self.second.max(now);
+    }
+
+    fn add(&mut self, bytes: u64) {
+        self.advance(now());
+        if let Some(last) = self.bytes.back_mut() {
+            *last += bytes;
+        }
+    }
+}
+
+// Plots the bytes received per second during the last few minutes, the data comes
+// from the listener progress events and the plot is scrolled by a one second timer
+pub struct ThroughputGraph {
+    samples: Mutex<Samples>,
+}
+
+impl ThroughputGraph {
+    pub fn new() -> Arc<ThroughputGraph> {
+        Arc::new(ThroughputGraph {
+            samples: Mutex::new(Samples {
+                second: now(),
+                bytes: vec![0; HISTORY_SECONDS].into(),
+                received: HashMap::new(),
+            }),
+        })
+    }
+
+    pub fn create(self: &Arc<Self>, parent: WindowRef) -> Result<WindowRef, WindowError> {
+        WindowBuilder::window("miniraw_throughput", Some(parent))
+            .style((WS_CHILD | WS_BORDER).0)
+            .message_handler(self.clone())
+            .build()
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        let mut samples = self.samples.lock().unwrap();
+        match event {
+            JobEvent::Progress { source, received } => {
+                let previous = samples.received.insert(*source, *received).unwrap_or(0);
+                samples.add(received.saturating_sub(previous));
+            }
+            // progress is only reported every megabyte, the rest is counted here
+            JobEvent::Completed(job) => {
+                let previous = samples.received.remove(&job.source).unwrap_or(0);
+                samples.add(job.size.saturating_sub(previous));
+            }
+            JobEvent::Aborted { source } => {
+                samples.received.remove(source);
+            }
+            JobEvent::Started { .. } | JobEvent::Listener(_) => {}
+        }
+    }
+
+    fn paint(&self, window: &WindowRef) {
+        let (values, current) = {
+            let mut samples = self.samples.lock().unwrap();
+            samples.advance(now());
+            let values = samples.bytes.iter().copied().collect::<Vec<_>>();
+            // the last second is still in progress
+            let current = values.iter().rev().nth(1).copied().unwrap_or_default();
+            (values, current)
+        };
+        let peak = values.iter().copied().max().unwrap_or_default();
+
+        unsafe {
+            let hwnd = window.handle();
+            let mut ps = PAINTSTRUCT::default();
+            let hdc = BeginPaint(hwnd, &mut ps);
+
+            let mut rect = RECT::default();
+            let _ = GetClientRect(hwnd, &mut rect);
+            FillRect(hdc, &rect, GetSysColorBrush(COLOR_WINDOW));
+
+            if rect.right > 0 && rect.bottom > 0 {
+                let top = 20;
+                let height = (rect.bottom - top - 4).max(1);
+                let scale = peak.max(1) as f64;
+                let step = rect.right as f64 / (HISTORY_SECONDS - 1) as f64;
+
+                let points = values
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &bytes)| POINT {
+                        x: (index as f64 * step) as i32,
+                        y: rect.bottom - 2 - (bytes as f64 / scale * height as f64) as i32,
+                    })
+                    .collect::<Vec<_>>();
+
+                let pen = CreatePen(PS_SOLID, 2, rgb(0, 120, 215));
+                let old_pen = SelectObject(hdc, pen.into());
+                let _ = Polyline(hdc, &points);
+                SelectObject(hdc, old_pen);
+                let _ = DeleteObject(pen.into());
+
+                let text = tr!(
+                    "Throughput: {}, peak: {}",
+                    format_rate(current),
+                    format_rate(peak)
+                )
+                .encode_utf16()
+                .collect::<Vec<_>>();
+                let old_font = SelectObject(hdc, GetStockObject(DEFAULT_GUI_FONT));
+                SetBkMode(hdc, TRANSPARENT);
+                SetTextColor(hdc, rgb(96, 96, 96));
+                let _ = TextOutW(hdc, 4, 2, &text);
+                SelectObject(hdc, old_font);
+            }
+
+            let _ = EndPaint(hwnd, &ps);
+        }
+    }
+}
+
+impl WindowMessageHandler for ThroughputGraph {
+    fn handle_timer(&self, window: &WindowRef, _id: usize) -> MessageResult {
+        unsafe {
+            let _ = InvalidateRect(Some(window.handle()), None, false);
+        }
+        MessageResult::Processed
+    }
+
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        match message.msg {
+            WM_CREATE => {
+                if let Err(e) = message.window.set_timer(TIMER_ID, Duration::from_secs(1)) {
+                    error!("{}", e);
+                }
+                MessageResult::Processed
+            }
+            WM_PAINT => {
+                self.paint(&message.window);
+                MessageResult::Processed
+            }
+            WM_DESTROY => {
+                message.window.kill_timer(TIMER_ID);
+                MessageResult::Processed
+            }
+            _ => MessageResult::Ignored,
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt::{self, Write};
+
+// Value types of the TOML subset used by the configuration file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+    Array(Vec<Value>),
+    // inline table, e.g. { port = 9101, discard = true }, the keys are written as
+    // in a document like the keys of Document
+    Table(Vec<(String, Value)>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Integer(value) => write!(f, "{value}"),
+            Value::String(value) => {
+                f.write_char('"')?;
+                for c in value.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_char(']')
+            }
+            Value::Table(entries) if entries.is_empty() => f.write_str("{}"),
+            Value::Table(entries) => {
+                f.write_str("{ ")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{key} = {value}")?;
+                }
+                f.write_str(" }")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        while self.chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+    }
+
+    fn is_bare_key_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_' || c == '-'
+    }
+
+    // The parts of a dotted key like a.b."c d", a quoted part may contain dots
+    fn key(&mut self) -> Result<Vec<String>, &'static str> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.chars.peek() {
+                Some('"') | Some('\'') => match self.value()? {
+                    Value::String(part) => part,
+                    _ => unreachable!(),
+                },
+                _ => {
+                    let mut part = String::new();
+                    while let Some(c) = self.chars.next_if(|c| Self::is_bare_key_char(*c)) {
+                        part.push(c);
+                    }
+                    part
+                }
+            };
+            if part.is_empty() {
+                return Err("key expected");
+            }
+            parts.push(part);
+            self.skip_spaces();
+            if self.chars.next_if_eq(&'.').is_none() {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, &'static str> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '"' => return Ok(text),
+                '\\' => match self.chars.next().ok_or("unterminated string")? {
+                    '"' => text.push('"'),
+                    '\\' => text.push('\\'),
+                    'n' => text.push('\n'),
+                    'r' => text.push('\r'),
+                    't' => text.push('\t'),
+                    'u' => {
+                        let code = (0..4)
+                            .map(|_| self.chars.next())
+                            .collect::<Option<String>>()
+                            .and_then(|hex| u32::from_str_radix(&hex, 16).ok())
+                            .and_then(char::from_u32)
+                            .ok_or("invalid escape sequence")?;
+                        text.push(code);
+                    }
+                    _ => return Err("invalid escape sequence"),
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    // No escape sequences, the string ends at the next quote
+    fn literal_string(&mut self) -> Result<String, &'static str> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '\'' => return Ok(text),
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, &'static str> {
+        self.skip_spaces();
+        match self.chars.next().ok_or("value expected")? {
+            '"' => self.basic_string().map(Value::String),
+            '\'' => self.literal_string().map(Value::String),
+            '[' => {
+                let mut values = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.chars.next_if_eq(&']').is_some() {
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_spaces();
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(values)),
+                        _ => return Err("',' or ']' expected"),
+                    }
+                }
+            }
+            '{' => {
+                let mut entries = Vec::<(String, Value)>::new();
+                self.skip_spaces();
+                if self.chars.next_if_eq(&'}').is_some() {
+                    return Ok(Value::Table(entries));
+                }
+                loop {
+                    let key = format_path(&self.key()?);
+                    if self.chars.next() != Some('=') {
+                        return Err("'=' expected");
+                    }
+                    let value = self.value()?;
+                    if entries.iter().any(|(name, _)| *name == key) {
+                        return Err("duplicate key");
+                    }
+                    entries.push((key, value));
+                    self.skip_spaces();
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Value::Table(entries)),
+                        _ => return Err("',' or '}' expected"),
+                    }
+                }
+            }
+            c => {
+                let mut token = String::from(c);
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| Self::is_bare_key_char(*c) || *c == '+')
+                {
+                    token.push(c);
+                }
+                match token.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => token
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| "invalid value"),
+                }
+            }
+        }
+    }
+
+    // Only whitespace and a comment may follow a key/value pair or a table header
+    fn end_of_line(&mut self) -> Result<(), &'static str> {
+        self.skip_spaces();
+        match self.chars.next() {
+            None | Some('#') => Ok(()),
+            Some(_) => Err("unexpected characters at the end of the line"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    // the table names and the key
+    path: Vec<String>,
+    // the path as it is written in a document
+    key: String,
+    value: Value,
+}
+
+// Key/value pairs of a TOML document in the file order. Keys of tables are dotted
+// keys with the table name prefix, e.g. "[log] level = 1" as "log.level", and the
+// keys with dots or other special characters are quoted. Multi-line values,
+// floats, dates and arrays of tables are not supported, arrays of inline tables
+// can be used instead.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    entries: Vec<Entry>,
+}
+
+impl Document {
+    pub fn parse(text: &str) -> Result<Document, ParseError> {
+        let mut document = Document::default();
+        let mut table = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message| ParseError {
+                line: index + 1,
+                message,
+            };
+            let mut parser = Parser {
+                chars: line.chars().peekable(),
+            };
+
+            parser.skip_spaces();
+            match parser.chars.peek() {
+                None | Some('#') => continue,
+                Some('[') => {
+                    parser.chars.next();
+                    table = parser.key().map_err(error)?;
+                    if parser.chars.next() != Some(']') {
+                        return Err(error("']' expected"));
+                    }
+                }
+                Some(_) => {
+                    let mut path = table.clone();
+                    path.extend(parser.key().map_err(error)?);
+                    if parser.chars.next() != Some('=') {
+                        return Err(error("'=' expected"));
+                    }
+                    let value = parser.value().map_err(error)?;
+                    if document.entries.iter().any(|entry| entry.path == path) {
+                        return Err(error("duplicate key"));
+                    }
+                    document.entries.push(Entry {
+                        key: format_path(&path),
+                        path,
+                        value,
+                    });
+                }
+            }
+            parser.end_of_line().map_err(error)?;
+        }
+        Ok(document)
+    }
+
+    // The key is written as in a document, e.g. log.level or "a.b"
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let path = parse_path(key);
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .map(|entry| &entry.value)
+    }
+
+    pub fn set(&mut self, key: &str, value: Value) {
+        let path = parse_path(key);
+        match self.entries.iter_mut().find(|entry| entry.path == path) {
+            Some(entry) => entry.value = value,
+            None => self.entries.push(Entry {
+                key: format_path(&path),
+                path,
+                value,
+            }),
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.key.as_str(), &entry.value))
+    }
+}
+
+// A key which is not valid TOML is taken as it is
+fn parse_path(key: &str) -> Vec<String> {
+    let mut parser = Parser {
+        chars: key.chars().peekable(),
+    };
+    match parser.key() {
+        Ok(path) if parser.chars.peek().is_none() => path,
+        _ => vec![key.to_owned()],
+    }
+}
+
+fn format_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(Parser::is_bare_key_char) {
+        key.to_owned()
+    } else {
+        Value::String(key.to_owned()).to_string()
+    }
+}
+
+fn format_path(path: &[String]) -> String {
+    path.iter()
+        .map(|part| format_key(part))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+// The keys without a table come first, then one section per table in the order
+// of the first key of each table
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut tables = vec![&[][..]];
+        for entry in &self.entries {
+            let (table, _) = entry.path.split_at(entry.path.len() - 1);
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+
+        for table in tables {
+            if !table.is_empty() {
+                writeln!(f, "\n[{}]", format_path(table))?;
+            }
+            for entry in &self.entries {
+                let (entry_table, name) = entry.path.split_at(entry.path.len() - 1);
+                if entry_table == table {
+                    writeln!(f, "{} = {}", format_path(name), entry.value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use time::OffsetDateTime;
+
+use crate::{
+    jobs::{JobEvent, ListenerState},
+    tr,
+    ui::win32::TrayIcon,
+};
+
+#[derive(Default)]
+struct TrayStats {
+    listener: Option<ListenerState>,
+    // (year, ordinal) of the day the jobs are counted for
+    day: (i32, u16),
+    jobs_today: u64,
+    last_job: Option<(OffsetDateTime, SocketAddr)>,
+    tooltip: String,
+}
+
+// Keeps the tooltip of the tray icon updated with a short summary of the
+// listener state and the received jobs
+pub struct TrayStatus {
+    tray_icon: Arc<TrayIcon>,
+    stats: Mutex<TrayStats>,
+}
+
+impl TrayStatus {
+    pub fn new(tray_icon: Arc<TrayIcon>) -> TrayStatus {
+        let status = TrayStatus {
+            tray_icon,
+            stats: Default::default(),
+        };
+        status.refresh();
+        status
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        {
+            let mut stats = self.stats.lock().unwrap();
+            match event {
+                JobEvent::Listener(state) => stats.listener = Some(state.clone()),
+                JobEvent::Completed(job) => {
+                    let day = (job.time.year(), job.time.ordinal());
+                    if stats.day != day {
+                        stats.day = day;
+                        stats.jobs_today = 0;
+                    }
+                    stats.jobs_today += 1;
+                    stats.last_job = Some((job.time, job.source));
+                }
+                JobEvent::Started { .. } | JobEvent::Progress { .. } | JobEvent::Aborted { .. } => {
+                    return
+                }
+            }
+        }
+        self.refresh();
+    }
+
+    // Also called when the mouse is over the icon, the day may have changed
+    // since the last job was received
+    pub fn refresh(&self) {
+        let mut stats = self.stats.lock().unwrap();
+        let tooltip = Self::tooltip(&stats);
+        if tooltip != stats.tooltip {
+            self.tray_icon.set_tooltip(&tooltip);
+            stats.tooltip = tooltip;
+        }
+    }
+
+    fn tooltip(stats: &TrayStats) -> String {
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+
+        let listener = match stats.listener {
+            None => tr!("Starting listener...").to_owned(),
+            Some(ListenerState::Listening(port)) => tr!("Listening on port {}", port),
+            Some(ListenerState::Paused) => tr!("Listener paused").to_owned(),
+            Some(ListenerState::Failed(_)) => tr!("Listener failed").to_owned(),
+        };
+
+        let jobs_today = if stats.day == (now.year(), now.ordinal()) {
+            stats.jobs_today
+        } else {
+            0
+        };
+
+        let mut lines = vec![
+            format!("MiniRAW NG {}", env!("CARGO_PKG_VERSION")),
+            listener,
+            tr!("Jobs today: {}", jobs_today),
+        ];
+
+        if let Some((time, source)) = stats.last_job {
+            let (hour, minute, second) = time.to_hms();
+            let time = format!("{hour:02}:{minute:02}:{second:02}");
+            lines.push(tr!("Last job: {} from {}", time, source.ip()));
+        }
+
+        lines.join("\n")
+    }
+}
@@ -1,14 +1,27 @@
 use win32::MessageLoopProxy;
 
+pub mod dialog;
+pub mod layout;
+pub mod list_view;
 pub mod win32;
 pub mod window;
 
+// Keyboard shortcut which sends WM_COMMAND with the given command id
+#[derive(Debug, Clone, Copy)]
+pub struct Accelerator {
+    pub key: u16,
+    pub ctrl: bool,
+    pub command: u32,
+}
+
 #[derive(Default)]
 pub struct MessageLoop {
     proxy: MessageLoopProxy,
 }
 
 impl MessageLoop {
+    // Dispatches the messages of the thread until WM_QUIT, the accelerators
+    // registered with WindowBuilder::accelerators are translated before dispatch
     pub fn run(&self) {
         self.proxy.run()
     }
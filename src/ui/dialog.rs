@@ -0,0 +1,169 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::{
+    tr,
+    ui::{
+        win32::MessageLoopProxy,
+        window::{
+            Font, MessageResult, WindowBuilder, WindowError, WindowEvent, WindowGeometry,
+            WindowMessage, WindowMessageHandler, WindowRef,
+        },
+    },
+    IDI_MAINICON,
+};
+
+const BUTTON_WIDTH: i32 = 80;
+const BUTTON_HEIGHT: i32 = 26;
+const MARGIN: i32 = 12;
+
+// Contents of a modal dialog, the OK and Cancel buttons are added by the dialog itself
+// and use the IDOK and IDCANCEL ids which must not be reused by the controls
+pub trait DialogContent: Send + Sync + 'static {
+    type Output: Send + 'static;
+
+    // Controls are placed above the button row, see Dialog::content_height
+    fn create_controls(&self, window: &WindowRef);
+
+    // Called when OK or Enter is pressed, returning None keeps the dialog open
+    fn accept(&self, window: &WindowRef) -> Option<Self::Output>;
+
+    fn handle_message(&self, _message: &WindowMessage) -> MessageResult {
+        MessageResult::Ignored
+    }
+}
+
+struct DialogWindow<C: DialogContent> {
+    owner: WindowRef,
+    content: C,
+    result: Mutex<Option<C::Output>>,
+    done: AtomicBool,
+}
+
+impl<C: DialogContent> DialogWindow<C> {
+    fn create_buttons(&self, window: &WindowRef) {
+        let (width, height) = window.client_size();
+        let buttons = [
+            (IDOK.0 as u32, tr!("OK"), BS_DEFPUSHBUTTON),
+            (IDCANCEL.0 as u32, tr!("Cancel"), BS_PUSHBUTTON),
+        ];
+
+        for (index, (id, title, style)) in buttons.into_iter().enumerate() {
+            let style = WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(style as u32);
+            let _ = WindowBuilder::button(window.clone())
+                .id(id)
+                .title(title)
+                .geometry(WindowGeometry {
+                    x: Some(width - (2 - index as i32) * (BUTTON_WIDTH + MARGIN)),
+                    y: Some(height - BUTTON_HEIGHT - MARGIN),
+                    width: Some(BUTTON_WIDTH),
+                    height: Some(BUTTON_HEIGHT),
+                })
+                .style(style.0)
+                .font(Font::new(15, "Segoe UI"))
+                .build()
+                .unwrap();
+        }
+    }
+
+    fn finish(&self, window: &WindowRef, result: Option<C::Output>) {
+        *self.result.lock().unwrap() = result;
+        self.done.store(true, Ordering::SeqCst);
+        // the owner must be enabled before the dialog is gone, otherwise another
+        // application is activated
+        self.owner.set_enabled(true);
+        window.close();
+    }
+}
+
+impl<C: DialogContent> WindowMessageHandler for DialogWindow<C> {
+    fn handle_message(&self, message: WindowMessage) -> MessageResult {
+        match message.event() {
+            WindowEvent::Create => {
+                self.content.create_controls(&message.window);
+                self.create_buttons(&message.window);
+                MessageResult::Processed
+            }
+            WindowEvent::Command { id, .. } if id as i32 == IDOK.0 => {
+                if let Some(output) = self.content.accept(&message.window) {
+                    self.finish(&message.window, Some(output));
+                }
+                MessageResult::Processed
+            }
+            WindowEvent::Command { id, .. } if id as i32 == IDCANCEL.0 => {
+                self.finish(&message.window, None);
+                MessageResult::Processed
+            }
+            WindowEvent::Close => {
+                self.finish(&message.window, None);
+                MessageResult::Processed
+            }
+            _ => self.content.handle_message(&message),
+        }
+    }
+}
+
+pub struct Dialog;
+
+impl Dialog {
+    // Height available to the controls of a dialog with the given client height
+    pub fn content_height(client_height: i32) -> i32 {
+        client_height - BUTTON_HEIGHT - 2 * MARGIN
+    }
+
+    // Shows the dialog centered on the owner and disables the owner until it is closed.
+    // Returns None when the dialog is cancelled.
+    pub fn run<C>(
+        owner: &WindowRef,
+        title: &str,
+        width: i32,
+        height: i32,
+        content: C,
+    ) -> Result<Option<C::Output>, WindowError>
+    where
+        C: DialogContent,
+    {
+        let dialog = Arc::new(DialogWindow {
+            owner: owner.clone(),
+            content,
+            result: Mutex::new(None),
+            done: AtomicBool::new(false),
+        });
+
+        let style = WS_POPUP | WS_CAPTION | WS_SYSMENU;
+
+        let window = WindowBuilder::window("miniraw_dialog", None)
+            .owner(owner)
+            .geometry(WindowGeometry {
+                width: Some(width),
+                height: Some(height),
+                ..Default::default()
+            })
+            .title(title)
+            .style(style.0)
+            .extended_style(WS_EX_DLGMODALFRAME.0)
+            .icon(IDI_MAINICON)
+            .message_handler(dialog.clone())
+            .visible(false)
+            .build()?;
+
+        window.center_on(owner);
+        owner.set_enabled(false);
+        window.show();
+
+        MessageLoopProxy::run_modal(window.handle(), &|| dialog.done.load(Ordering::SeqCst));
+
+        // the loop also ends on WM_QUIT while the dialog is still open
+        if !dialog.done.load(Ordering::SeqCst) {
+            owner.set_enabled(true);
+            window.close();
+        }
+
+        let result = dialog.result.lock().unwrap().take();
+        Ok(result)
+    }
+}
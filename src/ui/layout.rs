@@ -0,0 +1,142 @@
+use std::sync::RwLock;
+
+use windows::Win32::UI::WindowsAndMessaging::{GetWindowLongPtrW, GWL_STYLE, WS_VISIBLE};
+
+use crate::ui::window::{WindowGeometry, WindowRef};
+
+// Side of the remaining area taken by a child, the size is the height for Top and
+// Bottom and the width for Left and Right. Fill takes all of the remaining area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dock {
+    Top(i32),
+    Bottom(i32),
+    Left(i32),
+    Right(i32),
+    Fill,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Margins {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Margins {
+    pub fn all(margin: i32) -> Margins {
+        Margins {
+            left: margin,
+            top: margin,
+            right: margin,
+            bottom: margin,
+        }
+    }
+}
+
+struct LayoutItem {
+    window: WindowRef,
+    dock: Dock,
+    // space between the child and the rest of the area
+    gap: i32,
+}
+
+// Docks the children of a window in the order they were added, each child takes
+// its part of the area left by the previous ones. Hidden children are skipped,
+// so several pages can share the same area. Applied on WM_SIZE by the ui layer.
+#[derive(Default)]
+pub struct Layout {
+    margins: RwLock<Margins>,
+    items: RwLock<Vec<LayoutItem>>,
+}
+
+impl Layout {
+    pub fn new(margins: Margins) -> Layout {
+        Layout {
+            margins: RwLock::new(margins),
+            items: Default::default(),
+        }
+    }
+
+    pub fn add(&self, window: WindowRef, dock: Dock, gap: i32) {
+        self.items
+            .write()
+            .unwrap()
+            .push(LayoutItem { window, dock, gap });
+    }
+
+    pub fn set_dock(&self, window: &WindowRef, dock: Dock) {
+        let mut items = self.items.write().unwrap();
+        if let Some(item) = items
+            .iter_mut()
+            .find(|item| item.window.handle() == window.handle())
+        {
+            item.dock = dock;
+        }
+    }
+
+    pub fn set_margins(&self, margins: Margins) {
+        *self.margins.write().unwrap() = margins;
+    }
+
+    // Area of the client rectangle which is not covered by the docked children
+    pub fn area(&self, width: i32, height: i32) -> WindowGeometry {
+        let margins = *self.margins.read().unwrap();
+        WindowGeometry {
+            x: Some(margins.left),
+            y: Some(margins.top),
+            width: Some((width - margins.left - margins.right).max(0)),
+            height: Some((height - margins.top - margins.bottom).max(0)),
+        }
+    }
+
+    pub fn apply(&self, width: i32, height: i32) {
+        let (mut x, mut y, mut width, mut height) = self.area(width, height).unwrap_or(0);
+
+        for item in self.items.read().unwrap().iter() {
+            let style = unsafe { GetWindowLongPtrW(item.window.handle(), GWL_STYLE) } as u32;
+            if style & WS_VISIBLE.0 == 0 {
+                continue;
+            }
+
+            let geometry = match item.dock {
+                Dock::Top(size) => {
+                    let size = size.clamp(0, height);
+                    let geometry = (x, y, width, size);
+                    let taken = (size + item.gap).min(height);
+                    y += taken;
+                    height -= taken;
+                    geometry
+                }
+                Dock::Bottom(size) => {
+                    let size = size.clamp(0, height);
+                    let geometry = (x, y + height - size, width, size);
+                    height -= (size + item.gap).min(height);
+                    geometry
+                }
+                Dock::Left(size) => {
+                    let size = size.clamp(0, width);
+                    let geometry = (x, y, size, height);
+                    let taken = (size + item.gap).min(width);
+                    x += taken;
+                    width -= taken;
+                    geometry
+                }
+                Dock::Right(size) => {
+                    let size = size.clamp(0, width);
+                    let geometry = (x + width - size, y, size, height);
+                    width -= (size + item.gap).min(width);
+                    geometry
+                }
+                Dock::Fill => (x, y, width, height),
+            };
+
+            item.window.move_window(WindowGeometry {
+                x: Some(geometry.0),
+                y: Some(geometry.1),
+                width: Some(geometry.2),
+                height: Some(geometry.3),
+            });
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    OnceLock,
+};
+
+use windows::{
+    core::PWSTR,
+    Win32::{
+        Foundation::LPARAM,
+        UI::{
+            Controls::{
+                LVCFMT_LEFT, LVCFMT_RIGHT, LVCF_FMT, LVCF_TEXT, LVCF_WIDTH, LVCOLUMNW, LVIF_PARAM,
+                LVIF_STATE, LVIF_TEXT, LVIS_SELECTED, LVITEMW, LVM_DELETEALLITEMS, LVM_DELETEITEM,
+                LVM_ENSUREVISIBLE, LVM_GETCOLUMNORDERARRAY, LVM_GETCOLUMNWIDTH, LVM_GETITEMCOUNT,
+                LVM_GETITEMW, LVM_GETNEXTITEM, LVM_INSERTCOLUMNW, LVM_INSERTITEMW,
+                LVM_SETCOLUMNORDERARRAY, LVM_SETCOLUMNWIDTH, LVM_SETEXTENDEDLISTVIEWSTYLE,
+                LVM_SETITEMCOUNT, LVM_SETITEMSTATE, LVM_SETITEMTEXTW, LVNI_SELECTED,
+                LVN_COLUMNCLICK, LVN_GETDISPINFOW, LVN_ITEMACTIVATE, LVN_ITEMCHANGED,
+                LVSICF_NOINVALIDATEALL, LVSICF_NOSCROLL, LVS_EX_FULLROWSELECT,
+                LVS_EX_HEADERDRAGDROP, NMHDR, NMLISTVIEW, NMLVDISPINFOW,
+            },
+            WindowsAndMessaging::WM_NOTIFY,
+        },
+    },
+};
+
+use crate::{
+    ui::window::{MessageResult, WindowMessage, WindowRef},
+    utf16z,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnAlign {
+    Left,
+    Right,
+}
+
+// Notifications of the list view decoded from WM_NOTIFY received by its parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListViewEvent {
+    // double click or Enter on a row
+    ItemActivated(usize),
+    SelectionChanged(Option<usize>),
+    ColumnClicked(usize),
+}
+
+// Text of the cell at the given row and column
+type DataSource = Box<dyn Fn(usize, usize) -> String + Send + Sync>;
+
+// Report-style list view, each row carries a value which is preserved when the rows
+// are reordered and is used to find the data displayed in it.
+// A list view created with LVS_OWNERDATA does not store the rows, only their count
+// is set and the visible cells are requested from the data source when painted.
+pub struct ListView {
+    window: WindowRef,
+    columns: AtomicUsize,
+    source: OnceLock<DataSource>,
+}
+
+impl ListView {
+    // The window must be created with WindowBuilder::list_view and LVS_REPORT
+    pub fn new(window: WindowRef) -> ListView {
+        let view = ListView {
+            window,
+            columns: AtomicUsize::new(0),
+            source: OnceLock::new(),
+        };
+        // the columns can be reordered by dragging their headers
+        let style = LVS_EX_FULLROWSELECT | LVS_EX_HEADERDRAGDROP;
+        view.send(LVM_SETEXTENDEDLISTVIEWSTYLE, style as _, style as _);
+        view
+    }
+
+    pub fn window(&self) -> &WindowRef {
+        &self.window
+    }
+
+    fn send(&self, msg: u32, wparam: usize, lparam: isize) -> isize {
+        match self
+            .window
+            .send_message(WindowMessage::new(self.window.clone(), msg, wparam, lparam))
+        {
+            MessageResult::Value(value) => value,
+            _ => 0,
+        }
+    }
+
+    pub fn add_column(&self, title: &str, width: i32, align: ColumnAlign) {
+        let mut text = utf16z!(title);
+        let column = LVCOLUMNW {
+            mask: LVCF_TEXT | LVCF_WIDTH | LVCF_FMT,
+            fmt: match align {
+                ColumnAlign::Left => LVCFMT_LEFT,
+                ColumnAlign::Right => LVCFMT_RIGHT,
+            },
+            cx: width,
+            pszText: PWSTR(text.as_mut_ptr()),
+            ..Default::default()
+        };
+        let index = self.columns.fetch_add(1, Ordering::SeqCst);
+        self.send(LVM_INSERTCOLUMNW, index, &column as *const _ as _);
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.columns.load(Ordering::SeqCst)
+    }
+
+    pub fn column_widths(&self) -> Vec<i32> {
+        (0..self.column_count())
+            .map(|column| self.send(LVM_GETCOLUMNWIDTH, column, 0) as i32)
+            .collect()
+    }
+
+    pub fn set_column_width(&self, column: usize, width: i32) {
+        self.send(LVM_SETCOLUMNWIDTH, column, width as _);
+    }
+
+    // Indexes of the columns in the order they are displayed
+    pub fn column_order(&self) -> Vec<i32> {
+        let mut order = vec![0i32; self.column_count()];
+        self.send(
+            LVM_GETCOLUMNORDERARRAY,
+            order.len(),
+            order.as_mut_ptr() as _,
+        );
+        order
+    }
+
+    // Ignored unless every column is listed exactly once
+    pub fn set_column_order(&self, order: &[i32]) {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        if sorted.into_iter().eq(0..self.column_count() as i32) {
+            self.send(LVM_SETCOLUMNORDERARRAY, order.len(), order.as_ptr() as _);
+        }
+    }
+
+    // Only for LVS_OWNERDATA list views, the source is called on the UI thread
+    pub fn set_data_source<F>(&self, source: F)
+    where
+        F: Fn(usize, usize) -> String + Send + Sync + 'static,
+    {
+        let _ = self.source.set(Box::new(source));
+    }
+
+    // Changes the number of rows of an LVS_OWNERDATA list view keeping the scroll
+    // position, the rows which were already shown are not repainted
+    pub fn set_row_count(&self, count: usize) {
+        self.send(
+            LVM_SETITEMCOUNT,
+            count,
+            (LVSICF_NOINVALIDATEALL | LVSICF_NOSCROLL) as _,
+        );
+    }
+
+    // Queued for the UI thread without waiting for it, the posted changes are applied
+    // in the order they were made
+    pub fn post_row_count(&self, count: usize) {
+        let _ = self.window.post_message(
+            LVM_SETITEMCOUNT,
+            count,
+            (LVSICF_NOINVALIDATEALL | LVSICF_NOSCROLL) as _,
+        );
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.send(LVM_GETITEMCOUNT, 0, 0).max(0) as usize
+    }
+
+    // Inserts a row with the cell values and returns its index
+    pub fn insert_row<T>(&self, index: usize, values: &[T], data: isize) -> usize
+    where
+        T: AsRef<str>,
+    {
+        let mut text = utf16z!(values.first().map(|v| v.as_ref()).unwrap_or_default());
+        let item = LVITEMW {
+            mask: LVIF_TEXT | LVIF_PARAM,
+            iItem: index as _,
+            pszText: PWSTR(text.as_mut_ptr()),
+            lParam: LPARAM(data),
+            ..Default::default()
+        };
+        let row = self.send(LVM_INSERTITEMW, 0, &item as *const _ as _).max(0) as usize;
+
+        for (column, value) in values.iter().enumerate().skip(1) {
+            self.set_cell(row, column, value.as_ref());
+        }
+        row
+    }
+
+    pub fn update_row<T>(&self, row: usize, values: &[T])
+    where
+        T: AsRef<str>,
+    {
+        for (column, value) in values.iter().enumerate() {
+            self.set_cell(row, column, value.as_ref());
+        }
+    }
+
+    pub fn set_cell(&self, row: usize, column: usize, value: &str) {
+        let mut text = utf16z!(value);
+        let item = LVITEMW {
+            mask: LVIF_TEXT,
+            iSubItem: column as _,
+            pszText: PWSTR(text.as_mut_ptr()),
+            ..Default::default()
+        };
+        self.send(LVM_SETITEMTEXTW, row, &item as *const _ as _);
+    }
+
+    pub fn delete_row(&self, row: usize) {
+        self.send(LVM_DELETEITEM, row, 0);
+    }
+
+    pub fn clear(&self) {
+        self.send(LVM_DELETEALLITEMS, 0, 0);
+    }
+
+    // Not available for LVS_OWNERDATA list views where the row is the index of the data
+    pub fn row_data(&self, row: usize) -> Option<isize> {
+        let mut item = LVITEMW {
+            mask: LVIF_PARAM,
+            iItem: row as _,
+            ..Default::default()
+        };
+        (self.send(LVM_GETITEMW, 0, &mut item as *mut _ as _) != 0).then_some(item.lParam.0)
+    }
+
+    pub fn selected_row(&self) -> Option<usize> {
+        let row = self.send(LVM_GETNEXTITEM, usize::MAX, LVNI_SELECTED as _);
+        (row >= 0).then_some(row as usize)
+    }
+
+    pub fn select_row(&self, row: usize) {
+        let item = LVITEMW {
+            mask: LVIF_STATE,
+            state: LVIS_SELECTED,
+            stateMask: LVIS_SELECTED,
+            ..Default::default()
+        };
+        self.send(LVM_SETITEMSTATE, row, &item as *const _ as _);
+        self.ensure_visible(row);
+    }
+
+    pub fn ensure_visible(&self, row: usize) {
+        self.send(LVM_ENSUREVISIBLE, row, 0);
+    }
+
+    pub fn post_ensure_visible(&self, row: usize) {
+        let _ = self.window.post_message(LVM_ENSUREVISIBLE, row, 0);
+    }
+
+    // Copies the text of the requested cell from the data source into the buffer
+    // provided by the control, the text is truncated to fit
+    fn fill_cell(&self, info: &NMLVDISPINFOW) {
+        let item = &info.item;
+        let Some(source) = self.source.get() else {
+            return;
+        };
+        if item.mask & LVIF_TEXT != LVIF_TEXT || item.pszText.is_null() || item.cchTextMax <= 0 {
+            return;
+        }
+
+        let text = source(item.iItem as usize, item.iSubItem as usize)
+            .encode_utf16()
+            .take(item.cchTextMax as usize - 1)
+            .collect::<Vec<_>>();
+        let buffer =
+            unsafe { std::slice::from_raw_parts_mut(item.pszText.0, item.cchTextMax as usize) };
+        buffer[..text.len()].copy_from_slice(&text);
+        buffer[text.len()] = 0;
+    }
+
+    // Decodes a WM_NOTIFY message received by the parent if it comes from this list view,
+    // requests for the cell text of an LVS_OWNERDATA list view are answered here
+    pub fn notification(&self, message: &WindowMessage) -> Option<ListViewEvent> {
+        if message.msg != WM_NOTIFY || !message.is_received() {
+            return None;
+        }
+
+        let header = unsafe { &*(message.lparam as *const NMHDR) };
+        if header.hwndFrom != self.window.handle() {
+            return None;
+        }
+
+        match header.code {
+            LVN_ITEMACTIVATE => self.selected_row().map(ListViewEvent::ItemActivated),
+            LVN_ITEMCHANGED => {
+                let info = unsafe { &*(message.lparam as *const NMLISTVIEW) };
+                let changed = (info.uOldState ^ info.uNewState) & LVIS_SELECTED.0 != 0;
+                changed.then(|| ListViewEvent::SelectionChanged(self.selected_row()))
+            }
+            LVN_GETDISPINFOW => {
+                self.fill_cell(unsafe { &*(message.lparam as *const NMLVDISPINFOW) });
+                None
+            }
+            LVN_COLUMNCLICK => {
+                let info = unsafe { &*(message.lparam as *const NMLISTVIEW) };
+                Some(ListViewEvent::ColumnClicked(info.iSubItem as usize))
+            }
+            _ => None,
+        }
+    }
+}
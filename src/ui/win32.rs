@@ -1,23 +1,66 @@
-use std::{fmt, mem};
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    fmt, mem,
+    os::windows::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    sync::Weak,
+    time::Duration,
+};
 
 use windows::{
-    core::{PCWSTR, PWSTR},
+    core::{GUID, PCWSTR, PWSTR},
     Win32::{
-        Foundation::*, Graphics::Gdi::*, System::LibraryLoader::GetModuleHandleW,
-        UI::WindowsAndMessaging::*,
+        Foundation::*,
+        Graphics::Gdi::*,
+        System::{
+            Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_INPROC_SERVER},
+            DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData},
+            LibraryLoader::{GetModuleHandleW, LoadLibraryW},
+            Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+        },
+        UI::{
+            Accessibility::{CAccPropServices, IAccPropServices, PROPID_ACC_NAME},
+            Controls::{
+                Dialogs::{
+                    ChooseFontW, CF_INITTOLOGFONTSTRUCT, CF_NOVERTFONTS, CF_SCREENFONTS,
+                    CHOOSEFONTW,
+                },
+                InitCommonControlsEx, EM_GETSEL, ICC_BAR_CLASSES, ICC_LISTVIEW_CLASSES,
+                ICC_PROGRESS_CLASS, ICC_TAB_CLASSES, INITCOMMONCONTROLSEX, TCIF_TEXT, TCITEMW,
+                TCM_ADJUSTRECT, TCM_GETCURSEL, TCM_INSERTITEMW, TCM_SETCURSEL, TTF_IDISHWND,
+                TTF_SUBCLASS, TTM_ADDTOOLW, TTM_SETMAXTIPWIDTH, TTM_UPDATETIPTEXTW, TTTOOLINFOW,
+            },
+            Input::KeyboardAndMouse::{EnableWindow, GetFocus, SetFocus},
+            Shell::{
+                Common::COMDLG_FILTERSPEC, DefSubclassProc, DragFinish, DragQueryFileW,
+                FileOpenDialog, FileSaveDialog, IFileDialog, IShellItem, RemoveWindowSubclass,
+                SHCreateItemFromParsingName, SetWindowSubclass, Shell_NotifyIconW,
+                FILEOPENDIALOGOPTIONS, FOS_FILEMUSTEXIST, FOS_FORCEFILESYSTEM, FOS_OVERWRITEPROMPT,
+                FOS_PICKFOLDERS, HDROP, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_INFO,
+                NIM_ADD, NIM_DELETE, NIM_MODIFY, NIN_BALLOONUSERCLICK, NOTIFYICONDATAW,
+                SIGDN_FILESYSPATH,
+            },
+            WindowsAndMessaging::*,
+        },
     },
 };
 
 use crate::{
-    ui::window::{
-        ControlKind, MessageResult, WindowBuilder, WindowError, WindowGeometry, WindowMessage,
-        WindowRef,
+    ui::{
+        window::{
+            ControlKind, Font, Menu, MenuEntry, MessageFilter, MessageResult, Window,
+            WindowBuilder, WindowError, WindowGeometry, WindowMessage, WindowRef,
+        },
+        Accelerator,
     },
     utf16z,
 };
 
 pub(crate) type HandleType = HWND;
 
+const CF_UNICODETEXT: u32 = 13;
+
 unsafe extern "system" fn window_proc(
     hwnd: HWND,
     msg: u32,
@@ -36,16 +79,92 @@ unsafe extern "system" fn window_proc(
         let data = GetWindowLongPtrW(hwnd, GWL_USERDATA);
         if data != 0 {
             let proxy = data as *mut WinProxy;
-            LRESULT((*proxy).window_proc(msg, wparam.0, lparam.0))
+            let result = (*proxy).window_proc(msg, wparam.0, lparam.0);
+
+            // the last message of the window, the native window stops keeping its object
+            // alive, which may release the proxy, so it must not be used after this point
+            if msg == WM_NCDESTROY {
+                SetWindowLongPtrW(hwnd, GWL_USERDATA, 0);
+                (*proxy).hwnd = HWND::default();
+                let owner = (*proxy).owner.take();
+                drop(owner);
+            }
+            LRESULT(result)
         } else {
             DefWindowProcW(hwnd, msg, wparam, lparam)
         }
     }
 }
 
+// Standard controls are not handled by window_proc, this subclass releases their
+// object on WM_NCDESTROY in the same way. The data is the proxy.
+unsafe extern "system" fn control_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    id: usize,
+    data: usize,
+) -> LRESULT {
+    if msg != WM_NCDESTROY {
+        return DefSubclassProc(hwnd, msg, wparam, lparam);
+    }
+
+    let _ = RemoveWindowSubclass(hwnd, Some(control_proc), id);
+    let result = DefSubclassProc(hwnd, msg, wparam, lparam);
+
+    // may release the proxy, it must not be used after this point
+    let proxy = data as *mut WinProxy;
+    (*proxy).hwnd = HWND::default();
+    let owner = (*proxy).owner.take();
+    drop(owner);
+    result
+}
+
+struct Subclass {
+    window: Weak<Window>,
+    filter: MessageFilter,
+}
+
+// The address of the subclass data is used as the subclass id, so a window can have
+// several filters. The data is released with the native window.
+unsafe extern "system" fn subclass_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    id: usize,
+    data: usize,
+) -> LRESULT {
+    if msg == WM_NCDESTROY {
+        let _ = RemoveWindowSubclass(hwnd, Some(subclass_proc), id);
+        drop(Box::from_raw(data as *mut Subclass));
+        return DefSubclassProc(hwnd, msg, wparam, lparam);
+    }
+
+    let subclass = &*(data as *const Subclass);
+    let result = match subclass.window.upgrade() {
+        Some(window) => {
+            (subclass.filter)(&WindowMessage::received(window, msg, wparam.0, lparam.0))
+        }
+        None => MessageResult::Ignored,
+    };
+
+    match result {
+        MessageResult::Processed => LRESULT(0),
+        MessageResult::Ignored => DefSubclassProc(hwnd, msg, wparam, lparam),
+        MessageResult::Value(value) => LRESULT(value),
+    }
+}
+
+// Native part of a Window. While the native window exists the proxy holds a reference
+// to its Window, so a window stays alive until it is destroyed even if the code which
+// created it drops its reference, and a Window is never released while its native
+// window may still receive messages. The reference is released on WM_NCDESTROY.
 pub(crate) struct WinProxy {
     hwnd: HWND,
     owner: Option<WindowRef>,
+    font: HFONT,
 }
 
 impl fmt::Debug for WinProxy {
@@ -59,6 +178,7 @@ impl WinProxy {
         Box::into_raw(Box::new(WinProxy {
             hwnd: HWND::default(),
             owner: None,
+            font: HFONT::default(),
         }))
     }
 
@@ -104,6 +224,53 @@ impl WinProxy {
                     name
                 }
                 ControlKind::Edit => utf16z!("EDIT"),
+                ControlKind::RichEdit => {
+                    let module = utf16z!("Msftedit.dll");
+                    LoadLibraryW(PCWSTR(module.as_ptr()))?;
+                    utf16z!("RICHEDIT50W")
+                }
+                ControlKind::ListView => {
+                    let icc = INITCOMMONCONTROLSEX {
+                        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as _,
+                        dwICC: ICC_LISTVIEW_CLASSES,
+                    };
+                    let _ = InitCommonControlsEx(&icc);
+                    utf16z!("SysListView32")
+                }
+                ControlKind::Button => utf16z!("BUTTON"),
+                ControlKind::ComboBox => utf16z!("COMBOBOX"),
+                ControlKind::StatusBar => {
+                    let icc = INITCOMMONCONTROLSEX {
+                        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as _,
+                        dwICC: ICC_BAR_CLASSES,
+                    };
+                    let _ = InitCommonControlsEx(&icc);
+                    utf16z!("msctls_statusbar32")
+                }
+                ControlKind::TabControl => {
+                    let icc = INITCOMMONCONTROLSEX {
+                        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as _,
+                        dwICC: ICC_TAB_CLASSES,
+                    };
+                    let _ = InitCommonControlsEx(&icc);
+                    utf16z!("SysTabControl32")
+                }
+                ControlKind::Tooltip => {
+                    let icc = INITCOMMONCONTROLSEX {
+                        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as _,
+                        dwICC: ICC_BAR_CLASSES,
+                    };
+                    let _ = InitCommonControlsEx(&icc);
+                    utf16z!("tooltips_class32")
+                }
+                ControlKind::ProgressBar => {
+                    let icc = INITCOMMONCONTROLSEX {
+                        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as _,
+                        dwICC: ICC_PROGRESS_CLASS,
+                    };
+                    let _ = InitCommonControlsEx(&icc);
+                    utf16z!("msctls_progress32")
+                }
             };
 
             let title = utf16z!(builder.title);
@@ -112,10 +279,19 @@ impl WinProxy {
                 .parent
                 .as_ref()
                 .map(|p| (*p.proxy).hwnd)
+                .or(builder.owner)
                 .unwrap_or_default();
 
             let (x, y, width, height) = builder.geometry.unwrap_or(CW_USEDEFAULT);
 
+            let menu = if !builder.menus.is_empty() {
+                Some(Self::create_menu_bar(builder)?)
+            } else if builder.id != 0 {
+                Some(HMENU(builder.id as usize as _))
+            } else {
+                None
+            };
+
             self.hwnd = CreateWindowExW(
                 WINDOW_EX_STYLE(builder.extended_style),
                 PCWSTR(class_u16.as_ptr()),
@@ -126,36 +302,30 @@ impl WinProxy {
                 width,
                 height,
                 Some(parent),
-                None,
+                menu,
                 Some(hinstance),
                 Some(self as *mut WinProxy as _),
             )?;
+            if !matches!(builder.kind, ControlKind::Window(_))
+                && !SetWindowSubclass(self.hwnd, Some(control_proc), 0, self as *mut WinProxy as _)
+                    .as_bool()
+            {
+                // the control is kept by its parent only
+                self.owner = None;
+            }
+
             if let Some(ref font) = builder.font {
-                let face = utf16z!(font.face);
+                self.set_font(font);
+            }
 
-                let hfont = CreateFontW(
-                    font.height as i32,
-                    0,
-                    0,
-                    0,
-                    if font.bold { FW_BOLD.0 } else { FW_NORMAL.0 } as _,
-                    font.italics as u32,
-                    0,
-                    0,
-                    DEFAULT_CHARSET,
-                    FONT_OUTPUT_PRECISION::default(),
-                    FONT_CLIP_PRECISION::default(),
-                    DEFAULT_QUALITY,
-                    DEFAULT_PITCH.0 as _,
-                    PCWSTR(face.as_ptr()),
-                );
-                if !hfont.is_invalid() {
-                    self.send_message(WM_SETFONT, hfont.0 as _, 1);
-                }
+            if !builder.accelerators.is_empty() {
+                register_accelerators(self.hwnd, &builder.accelerators)?;
             }
 
-            let _ = ShowWindow(self.hwnd, SW_SHOW);
-            let _ = UpdateWindow(self.hwnd);
+            if builder.visible {
+                let _ = ShowWindow(self.hwnd, SW_SHOW);
+                let _ = UpdateWindow(self.hwnd);
+            }
 
             let sys_menu = GetSystemMenu(self.hwnd, false);
             for item in builder.sys_menu_items.iter() {
@@ -177,10 +347,145 @@ impl WinProxy {
         }
     }
 
+    fn create_popup_menu(menu: &Menu) -> Result<HMENU, WindowError> {
+        unsafe {
+            let popup = CreatePopupMenu()?;
+            for entry in menu.entries.iter() {
+                Self::insert_menu_entry(popup, u32::MAX, entry)?;
+            }
+            Ok(popup)
+        }
+    }
+
+    // Inserts the entry before the one at the position, u32::MAX appends it
+    fn insert_menu_entry(menu: HMENU, position: u32, entry: &MenuEntry) -> Result<(), WindowError> {
+        unsafe {
+            match entry {
+                MenuEntry::Item(item) => {
+                    let text = utf16z!(item.text);
+                    let flags = if item.checked {
+                        MF_STRING | MF_CHECKED
+                    } else {
+                        MF_STRING
+                    };
+                    InsertMenuW(
+                        menu,
+                        position,
+                        MF_BYPOSITION | flags,
+                        item.id as _,
+                        PCWSTR(text.as_ptr()),
+                    )?;
+                }
+                MenuEntry::Separator => InsertMenuW(
+                    menu,
+                    position,
+                    MF_BYPOSITION | MF_SEPARATOR,
+                    0,
+                    PCWSTR::null(),
+                )?,
+                MenuEntry::Submenu(submenu) => {
+                    let child = Self::create_popup_menu(submenu)?;
+                    let title = utf16z!(submenu.title);
+                    InsertMenuW(
+                        menu,
+                        position,
+                        MF_BYPOSITION | MF_POPUP,
+                        child.0 as _,
+                        PCWSTR(title.as_ptr()),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The menu or submenu which contains the item
+    fn find_popup_menu(menu: HMENU, item: u32) -> Option<HMENU> {
+        unsafe {
+            for position in 0..GetMenuItemCount(Some(menu)) {
+                if GetMenuItemID(menu, position) == item {
+                    return Some(menu);
+                }
+                let submenu = GetSubMenu(menu, position);
+                if !submenu.is_invalid() {
+                    if let Some(found) = Self::find_popup_menu(submenu, item) {
+                        return Some(found);
+                    }
+                }
+            }
+            None
+        }
+    }
+
+    pub(crate) fn replace_menu_entries(&self, anchor: u32, menu: &Menu) -> Result<(), WindowError> {
+        unsafe {
+            let Some(popup) = Self::find_popup_menu(GetMenu(self.hwnd), anchor) else {
+                return Ok(());
+            };
+            while GetMenuItemID(popup, 0) != anchor {
+                DeleteMenu(popup, 0, MF_BYPOSITION)?;
+            }
+            for (position, entry) in menu.entries.iter().enumerate() {
+                Self::insert_menu_entry(popup, position as u32, entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn show_context_menu(
+        &self,
+        menu: &Menu,
+        position: Option<(i32, i32)>,
+    ) -> Result<(), WindowError> {
+        unsafe {
+            let popup = Self::create_popup_menu(menu)?;
+
+            let (x, y) = position.unwrap_or_else(|| {
+                let focus = GetFocus();
+                let mut rect = RECT::default();
+                let _ = GetWindowRect(
+                    if focus.is_invalid() { self.hwnd } else { focus },
+                    &mut rect,
+                );
+                (rect.left, rect.top)
+            });
+
+            // the menu is not dismissed by clicking outside of it unless the window
+            // is in the foreground
+            let _ = SetForegroundWindow(self.hwnd);
+            let result = TrackPopupMenu(
+                popup,
+                TPM_LEFTALIGN | TPM_RIGHTBUTTON,
+                x,
+                y,
+                None,
+                self.hwnd,
+                None,
+            );
+            let _ = DestroyMenu(popup);
+            result.ok()?;
+        }
+        Ok(())
+    }
+
+    fn create_menu_bar(builder: &WindowBuilder) -> Result<HMENU, WindowError> {
+        unsafe {
+            let menu_bar = CreateMenu()?;
+            for menu in builder.menus.iter() {
+                let popup = Self::create_popup_menu(menu)?;
+                let title = utf16z!(menu.title);
+                AppendMenuW(menu_bar, MF_POPUP, popup.0 as _, PCWSTR(title.as_ptr()))?;
+            }
+            Ok(menu_bar)
+        }
+    }
+
+    // Called when the Window is released, the native window is already destroyed
+    // or was never created
     pub(crate) fn destroy(&mut self) {
         unsafe {
-            if !self.hwnd.is_invalid() {
-                let _ = DestroyWindow(self.hwnd);
+            if !self.font.is_invalid() {
+                let _ = DeleteObject(self.font.into());
             }
             let _ = Box::from_raw(self);
         }
@@ -192,14 +497,50 @@ impl WinProxy {
             let _ = MoveWindow(self.hwnd, x, y, width, height, true);
         }
     }
+    pub(crate) fn client_size(&self) -> (i32, i32) {
+        let mut rect = RECT::default();
+        unsafe {
+            let _ = GetClientRect(self.hwnd, &mut rect);
+        }
+        (rect.right - rect.left, rect.bottom - rect.top)
+    }
+
+    pub(crate) fn size(&self) -> (i32, i32) {
+        let mut rect = RECT::default();
+        unsafe {
+            let _ = GetWindowRect(self.hwnd, &mut rect);
+        }
+        (rect.right - rect.left, rect.bottom - rect.top)
+    }
+
     pub(crate) fn send_message(&self, msg: u32, wparam: usize, lparam: isize) -> LRESULT {
         unsafe { SendMessageW(self.hwnd, msg, Some(WPARAM(wparam)), Some(LPARAM(lparam))) }
     }
 
+    pub(crate) fn post_message(
+        &self,
+        msg: u32,
+        wparam: usize,
+        lparam: isize,
+    ) -> Result<(), WindowError> {
+        unsafe {
+            Ok(PostMessageW(
+                Some(self.hwnd),
+                msg,
+                WPARAM(wparam),
+                LPARAM(lparam),
+            )?)
+        }
+    }
+
     pub(crate) fn handle(&self) -> HandleType {
         self.hwnd
     }
 
+    pub(crate) fn id(&self) -> u32 {
+        unsafe { GetDlgCtrlID(self.hwnd) as u32 }
+    }
+
     pub(crate) fn check_sys_menu_item(&self, item: u32, flag: bool) {
         unsafe {
             CheckMenuItem(
@@ -210,6 +551,400 @@ impl WinProxy {
         }
     }
 
+    pub(crate) fn show(&self) {
+        unsafe {
+            let command = if IsIconic(self.hwnd).as_bool() {
+                SW_RESTORE
+            } else {
+                SW_SHOW
+            };
+            let _ = ShowWindow(self.hwnd, command);
+            let _ = SetForegroundWindow(self.hwnd);
+        }
+    }
+
+    pub(crate) fn hide(&self) {
+        unsafe {
+            let _ = ShowWindow(self.hwnd, SW_HIDE);
+        }
+    }
+
+    pub(crate) fn is_visible(&self) -> bool {
+        unsafe { IsWindowVisible(self.hwnd).as_bool() }
+    }
+
+    pub(crate) fn set_timer(&self, id: usize, interval: Duration) -> Result<(), WindowError> {
+        let interval = interval.as_millis().clamp(1, u32::MAX as u128) as u32;
+        match unsafe { SetTimer(Some(self.hwnd), id, interval, None) } {
+            0 => Err(WindowError::from_win32()),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn kill_timer(&self, id: usize) {
+        unsafe {
+            let _ = KillTimer(Some(self.hwnd), id);
+        }
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        unsafe {
+            let _ = EnableWindow(self.hwnd, enabled);
+        }
+    }
+
+    pub(crate) fn center_on(&self, other: HWND) {
+        unsafe {
+            let mut outer = RECT::default();
+            let mut rect = RECT::default();
+            let _ = GetWindowRect(other, &mut outer);
+            let _ = GetWindowRect(self.hwnd, &mut rect);
+            let width = rect.right - rect.left;
+            let height = rect.bottom - rect.top;
+            let _ = SetWindowPos(
+                self.hwnd,
+                None,
+                outer.left + (outer.right - outer.left - width) / 2,
+                outer.top + (outer.bottom - outer.top - height) / 2,
+                0,
+                0,
+                SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    pub(crate) fn set_focus(&self) {
+        unsafe {
+            let _ = SetFocus(Some(self.hwnd));
+        }
+    }
+
+    pub(crate) fn has_focus(&self) -> bool {
+        unsafe { GetFocus() == self.hwnd }
+    }
+
+    // Overrides the name reported by the default accessible object of the window,
+    // the annotation is removed by the system when the window is destroyed
+    pub(crate) fn set_accessible_name(&self, name: &str) -> Result<(), WindowError> {
+        unsafe {
+            let services: IAccPropServices =
+                CoCreateInstance(&CAccPropServices, None, CLSCTX_INPROC_SERVER)?;
+            let name = utf16z!(name);
+            services.SetHwndPropStr(
+                self.hwnd,
+                OBJID_CLIENT.0 as u32,
+                CHILDID_SELF,
+                PROPID_ACC_NAME,
+                PCWSTR(name.as_ptr()),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn subclass(
+        &self,
+        window: Weak<Window>,
+        filter: MessageFilter,
+    ) -> Result<(), WindowError> {
+        let data = Box::into_raw(Box::new(Subclass { window, filter }));
+        unsafe {
+            if SetWindowSubclass(self.hwnd, Some(subclass_proc), data as usize, data as usize)
+                .as_bool()
+            {
+                Ok(())
+            } else {
+                drop(Box::from_raw(data));
+                Err(WindowError::from_win32())
+            }
+        }
+    }
+
+    // Releases the reference to the Window when the native window could not be created
+    pub(crate) fn release_owner(&mut self) {
+        self.owner = None;
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        !self.hwnd.is_invalid()
+    }
+
+    pub(crate) fn close(&self) {
+        unsafe {
+            let _ = DestroyWindow(self.hwnd);
+        }
+    }
+
+    pub(crate) fn set_visible(&self, visible: bool) {
+        unsafe {
+            let _ = ShowWindow(self.hwnd, if visible { SW_SHOWNA } else { SW_HIDE });
+        }
+    }
+
+    pub(crate) fn insert_tab(&self, index: usize, title: &str) {
+        let mut text = utf16z!(title);
+        let item = TCITEMW {
+            mask: TCIF_TEXT,
+            pszText: PWSTR(text.as_mut_ptr()),
+            ..Default::default()
+        };
+        self.send_message(TCM_INSERTITEMW, index, &item as *const TCITEMW as _);
+    }
+
+    pub(crate) fn selected_tab(&self) -> usize {
+        self.send_message(TCM_GETCURSEL, 0, 0).0.max(0) as usize
+    }
+
+    pub(crate) fn select_tab(&self, index: usize) {
+        self.send_message(TCM_SETCURSEL, index, 0);
+    }
+
+    // The display rectangle of a tab control starts below the tab strip
+    pub(crate) fn tab_strip_height(&self, width: i32) -> i32 {
+        let mut rect = RECT {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: 1000,
+        };
+        self.send_message(TCM_ADJUSTRECT, 0, &mut rect as *mut RECT as _);
+        rect.top
+    }
+
+    // The tool is identified by its window handle, the tooltip subclasses it to see the mouse
+    fn tool_info(&self, tool: HWND, text: &mut [u16]) -> TTTOOLINFOW {
+        TTTOOLINFOW {
+            cbSize: mem::size_of::<TTTOOLINFOW>() as _,
+            uFlags: TTF_IDISHWND | TTF_SUBCLASS,
+            hwnd: unsafe { GetParent(tool).unwrap_or_default() },
+            uId: tool.0 as usize,
+            lpszText: PWSTR(text.as_mut_ptr()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn add_tool(&self, tool: HWND, text: &str) {
+        let mut text = utf16z!(text);
+        let info = self.tool_info(tool, &mut text);
+        self.send_message(TTM_SETMAXTIPWIDTH, 0, 320);
+        self.send_message(TTM_ADDTOOLW, 0, &info as *const TTTOOLINFOW as _);
+    }
+
+    pub(crate) fn set_tool_text(&self, tool: HWND, text: &str) {
+        let mut text = utf16z!(text);
+        let info = self.tool_info(tool, &mut text);
+        self.send_message(TTM_UPDATETIPTEXTW, 0, &info as *const TTTOOLINFOW as _);
+    }
+
+    // Flashes the taskbar button until the window comes to the foreground
+    pub(crate) fn flash_taskbar(&self) {
+        unsafe {
+            if GetForegroundWindow() == self.hwnd {
+                return;
+            }
+            let info = FLASHWINFO {
+                cbSize: mem::size_of::<FLASHWINFO>() as _,
+                hwnd: self.hwnd,
+                dwFlags: FLASHW_TRAY | FLASHW_TIMERNOFG,
+                uCount: 0,
+                dwTimeout: 0,
+            };
+            let _ = FlashWindowEx(&info);
+        }
+    }
+
+    pub(crate) fn check_menu_item(&self, item: u32, flag: bool) {
+        unsafe {
+            CheckMenuItem(
+                GetMenu(self.hwnd),
+                item,
+                if flag { MF_CHECKED.0 } else { MF_UNCHECKED.0 },
+            );
+        }
+    }
+
+    pub(crate) fn set_font(&mut self, font: &Font) {
+        unsafe {
+            let face = utf16z!(font.face);
+
+            let hfont = CreateFontW(
+                font.height as i32,
+                0,
+                0,
+                0,
+                if font.bold { FW_BOLD.0 } else { FW_NORMAL.0 } as _,
+                font.italics as u32,
+                0,
+                0,
+                DEFAULT_CHARSET,
+                FONT_OUTPUT_PRECISION::default(),
+                FONT_CLIP_PRECISION::default(),
+                DEFAULT_QUALITY,
+                DEFAULT_PITCH.0 as _,
+                PCWSTR(face.as_ptr()),
+            );
+            if !hfont.is_invalid() {
+                self.send_message(WM_SETFONT, hfont.0 as _, 1);
+                if !self.font.is_invalid() {
+                    let _ = DeleteObject(self.font.into());
+                }
+                self.font = hfont;
+            }
+        }
+    }
+
+    // Runs a file dialog created from the given class and returns the chosen file system path
+    fn show_file_dialog(
+        &self,
+        class: &GUID,
+        options: FILEOPENDIALOGOPTIONS,
+        title: &str,
+        filters: &[(&str, &str)],
+        initial: Option<&Path>,
+    ) -> windows::core::Result<PathBuf> {
+        unsafe {
+            let dialog: IFileDialog = CoCreateInstance(class, None, CLSCTX_INPROC_SERVER)?;
+
+            dialog.SetOptions(dialog.GetOptions()? | options | FOS_FORCEFILESYSTEM)?;
+
+            let title = utf16z!(title);
+            dialog.SetTitle(PCWSTR(title.as_ptr()))?;
+
+            let names = filters.iter().map(|(n, _)| utf16z!(n)).collect::<Vec<_>>();
+            let specs = filters.iter().map(|(_, s)| utf16z!(s)).collect::<Vec<_>>();
+            let types = names
+                .iter()
+                .zip(specs.iter())
+                .map(|(name, spec)| COMDLG_FILTERSPEC {
+                    pszName: PCWSTR(name.as_ptr()),
+                    pszSpec: PCWSTR(spec.as_ptr()),
+                })
+                .collect::<Vec<_>>();
+            if !types.is_empty() {
+                dialog.SetFileTypes(&types)?;
+            }
+
+            if let Some(initial) = initial {
+                // a file name preselects the file in its folder
+                let (folder, name) = if initial.is_dir() {
+                    (Some(initial), None)
+                } else {
+                    (initial.parent(), initial.file_name())
+                };
+                if let Some(folder) = folder.filter(|f| f.is_dir()) {
+                    let folder = utf16z!(folder.to_string_lossy());
+                    let item: IShellItem =
+                        SHCreateItemFromParsingName(PCWSTR(folder.as_ptr()), None)?;
+                    dialog.SetFolder(&item)?;
+                }
+                if let Some(name) = name {
+                    let name = utf16z!(name.to_string_lossy());
+                    dialog.SetFileName(PCWSTR(name.as_ptr()))?;
+                }
+            }
+
+            // the extension of the first filter is appended to names typed without one
+            if let Some(ext) = filters
+                .first()
+                .and_then(|(_, spec)| spec.split(';').next())
+                .and_then(|spec| spec.strip_prefix("*."))
+                .filter(|ext| !ext.contains('*'))
+            {
+                let ext = utf16z!(ext);
+                dialog.SetDefaultExtension(PCWSTR(ext.as_ptr()))?;
+            }
+
+            dialog.Show(Some(self.hwnd))?;
+
+            let name = dialog.GetResult()?.GetDisplayName(SIGDN_FILESYSPATH)?;
+            let path = PathBuf::from(OsString::from_wide(name.as_wide()));
+            CoTaskMemFree(Some(name.0 as _));
+            Ok(path)
+        }
+    }
+
+    pub(crate) fn open_file(&self, title: &str, filters: &[(&str, &str)]) -> Option<PathBuf> {
+        self.show_file_dialog(&FileOpenDialog, FOS_FILEMUSTEXIST, title, filters, None)
+            .ok()
+    }
+
+    pub(crate) fn save_file(
+        &self,
+        title: &str,
+        filters: &[(&str, &str)],
+        initial: &Path,
+    ) -> Option<PathBuf> {
+        self.show_file_dialog(
+            &FileSaveDialog,
+            FOS_OVERWRITEPROMPT,
+            title,
+            filters,
+            Some(initial),
+        )
+        .ok()
+    }
+
+    pub(crate) fn choose_folder(&self, title: &str, initial: Option<&Path>) -> Option<PathBuf> {
+        self.show_file_dialog(&FileOpenDialog, FOS_PICKFOLDERS, title, &[], initial)
+            .ok()
+    }
+
+    pub(crate) fn choose_font(&self, initial: &Font) -> Option<Font> {
+        unsafe {
+            let mut log_font = LOGFONTW {
+                lfHeight: initial.height as i32,
+                lfWeight: if initial.bold { FW_BOLD.0 } else { FW_NORMAL.0 } as _,
+                lfItalic: initial.italics as u8,
+                ..Default::default()
+            };
+            for (dst, src) in log_font
+                .lfFaceName
+                .iter_mut()
+                .zip(initial.face.encode_utf16().take(LF_FACESIZE as usize - 1))
+            {
+                *dst = src;
+            }
+
+            let mut choose_font = CHOOSEFONTW {
+                lStructSize: mem::size_of::<CHOOSEFONTW>() as _,
+                hwndOwner: self.hwnd,
+                lpLogFont: &mut log_font,
+                Flags: CF_SCREENFONTS | CF_INITTOLOGFONTSTRUCT | CF_NOVERTFONTS,
+                ..Default::default()
+            };
+
+            if !ChooseFontW(&mut choose_font).as_bool() {
+                return None;
+            }
+
+            // the dialog returns the character height, fonts are created with the cell height
+            let hfont = CreateFontIndirectW(&log_font);
+            let hdc = GetDC(Some(self.hwnd));
+            let old_font = SelectObject(hdc, hfont.into());
+            let mut metrics = TEXTMETRICW::default();
+            let height = if GetTextMetricsW(hdc, &mut metrics).as_bool() {
+                metrics.tmHeight as u32
+            } else {
+                log_font.lfHeight.unsigned_abs()
+            };
+            SelectObject(hdc, old_font);
+            ReleaseDC(Some(self.hwnd), hdc);
+            let _ = DeleteObject(hfont.into());
+
+            let face_len = log_font
+                .lfFaceName
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(log_font.lfFaceName.len());
+
+            Some(Font {
+                height,
+                bold: log_font.lfWeight >= FW_BOLD.0 as i32,
+                italics: log_font.lfItalic != 0,
+                face: String::from_utf16_lossy(&log_font.lfFaceName[..face_len]),
+            })
+        }
+    }
+
     pub fn get_text(&self) -> Result<String, WindowError> {
         let mut lresult = self.send_message(WM_GETTEXTLENGTH, 0, 0);
 
@@ -239,11 +974,110 @@ impl WinProxy {
         }
     }
 
+    pub fn selection(&self) -> (usize, usize) {
+        let mut start = 0u32;
+        let mut end = 0u32;
+        self.send_message(
+            EM_GETSEL,
+            &mut start as *mut u32 as _,
+            &mut end as *mut u32 as _,
+        );
+        (start as usize, end as usize)
+    }
+
+    pub fn copy_to_clipboard(&self, text: &str) -> Result<(), WindowError> {
+        let data = utf16z!(text);
+        unsafe {
+            OpenClipboard(Some(self.hwnd))?;
+
+            let result = EmptyClipboard().and_then(|_| {
+                let size = data.len() * mem::size_of::<u16>();
+                let hglobal = GlobalAlloc(GMEM_MOVEABLE, size)?;
+                let ptr = GlobalLock(hglobal) as *mut u16;
+                if ptr.is_null() {
+                    let _ = GlobalFree(Some(hglobal));
+                    return Err(windows::core::Error::from_win32());
+                }
+                ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+                let _ = GlobalUnlock(hglobal);
+
+                // the clipboard owns the memory once the call succeeds
+                SetClipboardData(CF_UNICODETEXT, Some(HANDLE(hglobal.0))).inspect_err(|_| {
+                    let _ = GlobalFree(Some(hglobal));
+                })
+            });
+
+            let _ = CloseClipboard();
+            result.map(|_| ()).map_err(WindowError::from)
+        }
+    }
+
+    pub(crate) fn add_item(&self, text: &str) -> usize {
+        let text = utf16z!(text);
+        self.send_message(CB_ADDSTRING, 0, text.as_ptr() as _)
+            .0
+            .max(0) as usize
+    }
+
+    pub(crate) fn clear_items(&self) {
+        self.send_message(CB_RESETCONTENT, 0, 0);
+    }
+
+    pub(crate) fn item_count(&self) -> usize {
+        self.send_message(CB_GETCOUNT, 0, 0).0.max(0) as usize
+    }
+
+    pub(crate) fn item_text(&self, index: usize) -> Option<String> {
+        let len = self.send_message(CB_GETLBTEXTLEN, index, 0).0;
+        if len < 0 {
+            return None;
+        }
+
+        let mut buffer = vec![0u16; len as usize + 1];
+        let len = self
+            .send_message(CB_GETLBTEXT, index, buffer.as_mut_ptr() as _)
+            .0;
+        (len >= 0).then(|| String::from_utf16_lossy(&buffer[..len as usize]))
+    }
+
+    pub(crate) fn selected_item(&self) -> Option<usize> {
+        let index = self.send_message(CB_GETCURSEL, 0, 0).0;
+        (index != CB_ERR as isize).then_some(index as usize)
+    }
+
+    pub(crate) fn select_item(&self, index: usize) {
+        self.send_message(CB_SETCURSEL, index, 0);
+    }
+
     unsafe fn window_proc(&mut self, msg: u32, wparam: usize, lparam: isize) -> isize {
         let owner = self.owner.as_ref().unwrap().clone();
-        let message = WindowMessage::new(owner.clone(), msg, wparam, lparam);
+        let message = WindowMessage::received(owner.clone(), msg, wparam, lparam);
+
+        if msg == WM_NCDESTROY {
+            unregister_accelerators(self.hwnd);
+        }
 
-        match owner.handler.handle_message(message) {
+        // menu items and accelerators don't have a control handle
+        let command = if msg == WM_COMMAND && lparam == 0 {
+            owner
+                .handler
+                .handle_command(&owner, (wparam & 0xffff) as u32)
+        } else if msg == WM_TIMER && lparam == 0 {
+            owner.handler.handle_timer(&owner, wparam)
+        } else {
+            MessageResult::Ignored
+        };
+
+        let result = match command {
+            MessageResult::Ignored => owner.handler.handle_message(message),
+            result => result,
+        };
+
+        if msg == WM_SIZE {
+            owner.update_layout();
+        }
+
+        match result {
             MessageResult::Processed => 0,
             MessageResult::Ignored => {
                 DefWindowProcW(self.hwnd, msg, WPARAM(wparam), LPARAM(lparam)).0
@@ -253,6 +1087,85 @@ impl WinProxy {
     }
 }
 
+// Reads the file names from the WM_DROPFILES handle and releases it
+pub(crate) fn query_dropped_files(hdrop: usize) -> Vec<PathBuf> {
+    unsafe {
+        let hdrop = HDROP(hdrop as _);
+        let count = DragQueryFileW(hdrop, u32::MAX, None);
+
+        let files = (0..count)
+            .map(|index| {
+                let len = DragQueryFileW(hdrop, index, None) as usize;
+                let mut buffer = vec![0u16; len + 1];
+                let len = DragQueryFileW(hdrop, index, Some(&mut buffer)) as usize;
+                PathBuf::from(OsString::from_wide(&buffer[..len]))
+            })
+            .collect();
+
+        DragFinish(hdrop);
+        files
+    }
+}
+
+thread_local! {
+    // accelerator tables of the top-level windows created on the UI thread
+    static ACCELERATORS: RefCell<Vec<(HWND, HACCEL)>> = const { RefCell::new(Vec::new()) };
+}
+
+pub(crate) fn register_accelerators(
+    hwnd: HWND,
+    accelerators: &[Accelerator],
+) -> Result<(), WindowError> {
+    let table = accelerators
+        .iter()
+        .map(|a| ACCEL {
+            fVirt: if a.ctrl {
+                FVIRTKEY | FCONTROL
+            } else {
+                FVIRTKEY
+            },
+            key: a.key,
+            cmd: a.command as _,
+        })
+        .collect::<Vec<_>>();
+
+    let haccel = unsafe { CreateAcceleratorTableW(&table)? };
+    ACCELERATORS.with(|tables| tables.borrow_mut().push((hwnd, haccel)));
+    Ok(())
+}
+
+fn unregister_accelerators(hwnd: HWND) {
+    ACCELERATORS.with(|tables| {
+        tables.borrow_mut().retain(|(window, haccel)| {
+            if *window == hwnd {
+                unsafe {
+                    let _ = DestroyAcceleratorTable(*haccel);
+                }
+            }
+            *window != hwnd
+        })
+    });
+}
+
+// Keyboard messages are translated with the table of the top-level window which
+// contains the focused control
+fn translate_accelerator(message: &MSG) -> bool {
+    let root = unsafe { GetAncestor(message.hwnd, GA_ROOT) };
+    // copied out because the command is handled before TranslateAcceleratorW returns
+    let table = ACCELERATORS.with(|tables| {
+        tables
+            .borrow()
+            .iter()
+            .find(|(hwnd, _)| *hwnd == root)
+            .copied()
+    });
+
+    match table {
+        Some((hwnd, haccel)) => unsafe { TranslateAcceleratorW(hwnd, haccel, message) != 0 },
+        None => false,
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct MessageLoopProxy;
 
@@ -262,6 +1175,9 @@ impl MessageLoopProxy {
             let mut message: MSG = mem::zeroed();
 
             while GetMessageW(&mut message, None, 0, 0).0 > 0 {
+                if translate_accelerator(&message) {
+                    continue;
+                }
                 let _ = TranslateMessage(&message);
                 DispatchMessageW(&message);
             }
@@ -273,4 +1189,147 @@ impl MessageLoopProxy {
             PostQuitMessage(0);
         }
     }
+
+    // Nested loop of a modal dialog, Tab, Enter and Escape are handled by IsDialogMessage.
+    // WM_QUIT ends the loop and is posted again for the outer loop.
+    pub(crate) fn run_modal(dialog: HWND, done: &dyn Fn() -> bool) {
+        unsafe {
+            let mut message: MSG = mem::zeroed();
+
+            while !done() {
+                if GetMessageW(&mut message, None, 0, 0).0 <= 0 {
+                    PostQuitMessage(message.wParam.0 as _);
+                    break;
+                }
+                if translate_accelerator(&message) || IsDialogMessageW(dialog, &message).as_bool() {
+                    continue;
+                }
+                let _ = TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        }
+    }
+}
+
+fn copy_text(dst: &mut [u16], text: &str) {
+    let len = dst.len() - 1;
+    for (d, s) in dst.iter_mut().zip(text.encode_utf16().take(len).chain([0])) {
+        *d = s;
+    }
+}
+
+// Mouse and notification events of a tray icon decoded from its callback message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayEvent {
+    Click,
+    DoubleClick,
+    RightClick,
+    NotificationClicked,
+    // the mouse is over the icon
+    Hover,
+}
+
+// Posts the message to the top-level window of the given class created by another
+// instance of the application, returns false if there is no such window
+pub fn post_to_instance(class: &str, msg: u32, wparam: usize, lparam: isize) -> bool {
+    let class = utf16z!(class);
+    unsafe {
+        match FindWindowW(PCWSTR(class.as_ptr()), PCWSTR::null()) {
+            Ok(hwnd) => PostMessageW(Some(hwnd), msg, WPARAM(wparam), LPARAM(lparam)).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+// Icon in the notification area, the events are sent to the window as the callback
+// message and decoded with TrayIcon::event. The icon is removed when dropped.
+pub struct TrayIcon {
+    window: WindowRef,
+    id: u32,
+    message: u32,
+}
+
+impl TrayIcon {
+    pub fn add(
+        window: WindowRef,
+        id: u32,
+        message: u32,
+        icon: u32,
+        tooltip: &str,
+    ) -> Result<TrayIcon, WindowError> {
+        let tray_icon = TrayIcon {
+            window,
+            id,
+            message,
+        };
+
+        let mut data = tray_icon.data();
+        data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+        data.uCallbackMessage = message;
+        copy_text(&mut data.szTip, tooltip);
+
+        unsafe {
+            let module = GetModuleHandleW(PCWSTR::null())?;
+            data.hIcon = LoadIconW(Some(module.into()), PCWSTR(icon as *const u16))?;
+            Shell_NotifyIconW(NIM_ADD, &data).ok()?;
+        }
+        Ok(tray_icon)
+    }
+
+    fn data(&self) -> NOTIFYICONDATAW {
+        NOTIFYICONDATAW {
+            cbSize: mem::size_of::<NOTIFYICONDATAW>() as _,
+            hWnd: self.window.handle(),
+            uID: self.id,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_tooltip(&self, tooltip: &str) {
+        let mut data = self.data();
+        data.uFlags = NIF_TIP;
+        copy_text(&mut data.szTip, tooltip);
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &data);
+        }
+    }
+
+    // Shown as a toast on Windows 10 and later
+    pub fn show_notification(&self, title: &str, text: &str) {
+        let mut data = self.data();
+        data.uFlags = NIF_INFO;
+        data.dwInfoFlags = NIIF_INFO;
+        copy_text(&mut data.szInfoTitle, title);
+        copy_text(&mut data.szInfo, text);
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &data);
+        }
+    }
+
+    pub fn remove(&self) {
+        let data = self.data();
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+        }
+    }
+
+    pub fn event(&self, message: &WindowMessage) -> Option<TrayEvent> {
+        if message.msg != self.message || message.wparam as u32 != self.id {
+            return None;
+        }
+        match (message.lparam & 0xffff) as u32 {
+            WM_LBUTTONUP => Some(TrayEvent::Click),
+            WM_LBUTTONDBLCLK => Some(TrayEvent::DoubleClick),
+            WM_RBUTTONUP => Some(TrayEvent::RightClick),
+            NIN_BALLOONUSERCLICK => Some(TrayEvent::NotificationClicked),
+            WM_MOUSEMOVE => Some(TrayEvent::Hover),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for TrayIcon {
+    fn drop(&mut self) {
+        self.remove();
+    }
 }
@@ -1,14 +1,43 @@
 use std::{
     fmt,
-    sync::{Arc, RwLock},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::Duration,
 };
 
+use windows::Win32::{
+    Foundation::HWND,
+    System::SystemServices::MK_CONTROL,
+    UI::{
+        Controls::{NMHDR, PBM_SETPOS, PBM_SETRANGE32, PBS_SMOOTH, TTS_ALWAYSTIP, TTS_NOPREFIX},
+        WindowsAndMessaging::{
+            WM_APP, WM_CLOSE, WM_COMMAND, WM_CONTEXTMENU, WM_CREATE, WM_DESTROY, WM_MOUSEWHEEL,
+            WM_NOTIFY, WM_SIZE, WM_TIMER, WS_CHILD, WS_CLIPSIBLINGS, WS_EX_TOPMOST, WS_POPUP,
+            WS_VISIBLE,
+        },
+    },
+};
+
+use crate::ui::{layout::Layout, Accelerator};
+
 #[cfg(windows)]
-use crate::ui::win32::{HandleType, WinProxy};
+use crate::ui::win32::{query_dropped_files, HandleType, WinProxy};
 
 pub type WindowRef = Arc<Window>;
+
+// Receives the messages of a subclassed window before its window procedure
+pub type MessageFilter = Box<dyn Fn(&WindowMessage) -> MessageResult + Send + Sync + 'static>;
 pub type WindowHandle = HandleType;
 
+// Range of the app-defined messages which can be posted from other threads
+pub const USER_MESSAGES: Range<u32> = WM_APP..0xc000;
+
+// App-defined message with the given number, each feature declares its own
+pub const fn user_message(index: u32) -> u32 {
+    WM_APP + index
+}
+
 #[derive(Debug)]
 pub enum WindowError {
     Win32Error(windows::core::Error),
@@ -91,6 +120,14 @@ impl WindowGeometry {
 pub(crate) enum ControlKind {
     Window(String),
     Edit,
+    RichEdit,
+    ListView,
+    Button,
+    StatusBar,
+    TabControl,
+    ComboBox,
+    Tooltip,
+    ProgressBar,
 }
 
 pub(crate) struct MenuItem {
@@ -99,54 +136,140 @@ pub(crate) struct MenuItem {
     pub(crate) checked: bool,
 }
 
+pub(crate) enum MenuEntry {
+    Item(MenuItem),
+    Separator,
+    Submenu(Menu),
+}
+
+// Popup menu of the menu bar, the item ids are delivered to
+// WindowMessageHandler::handle_command
+pub struct Menu {
+    pub(crate) title: String,
+    pub(crate) entries: Vec<MenuEntry>,
+}
+
+impl Menu {
+    pub fn new<T>(title: T) -> Menu
+    where
+        T: AsRef<str>,
+    {
+        Menu {
+            title: title.as_ref().to_owned(),
+            entries: Vec::new(),
+        }
+    }
+
+    // The check mark is shown when checked is true and toggled with Window::check_menu_item
+    pub fn item<T>(mut self, id: u32, text: T, checked: bool) -> Self
+    where
+        T: AsRef<str>,
+    {
+        self.entries.push(MenuEntry::Item(MenuItem {
+            id,
+            text: text.as_ref().to_owned(),
+            checked,
+        }));
+        self
+    }
+
+    pub fn separator(mut self) -> Self {
+        self.entries.push(MenuEntry::Separator);
+        self
+    }
+
+    pub fn submenu(mut self, menu: Menu) -> Self {
+        self.entries.push(MenuEntry::Submenu(menu));
+        self
+    }
+}
+
 struct DummyMessageHandler;
 impl WindowMessageHandler for DummyMessageHandler {}
 
 pub struct WindowBuilder {
     pub(crate) kind: ControlKind,
+    pub(crate) id: u32,
     pub(crate) title: String,
     pub(crate) geometry: WindowGeometry,
     pub(crate) style: u32,
     pub(crate) extended_style: u32,
     pub(crate) parent: Option<WindowRef>,
+    pub(crate) owner: Option<WindowHandle>,
     pub(crate) handler: Arc<dyn WindowMessageHandler + Send + Sync + 'static>,
     pub(crate) font: Option<Font>,
     pub(crate) icon: Option<u32>,
     pub(crate) sys_menu_items: Vec<MenuItem>,
+    pub(crate) menus: Vec<Menu>,
+    pub(crate) accelerators: Vec<Accelerator>,
+    pub(crate) visible: bool,
 }
 
 impl WindowBuilder {
-    pub fn window<S>(class: S, parent: Option<WindowRef>) -> WindowBuilder
-    where
-        S: AsRef<str>,
-    {
+    fn new(
+        kind: ControlKind,
+        parent: Option<WindowRef>,
+        geometry: WindowGeometry,
+    ) -> WindowBuilder {
         WindowBuilder {
-            kind: ControlKind::Window(class.as_ref().to_owned()),
+            kind,
+            id: 0,
             title: String::new(),
-            geometry: WindowGeometry::default(),
+            geometry,
             style: 0,
             extended_style: 0,
             parent,
+            owner: None,
             handler: Arc::new(DummyMessageHandler),
             font: None,
             icon: None,
             sys_menu_items: Vec::new(),
+            menus: Vec::new(),
+            accelerators: Vec::new(),
+            visible: true,
         }
     }
 
+    pub fn window<S>(class: S, parent: Option<WindowRef>) -> WindowBuilder
+    where
+        S: AsRef<str>,
+    {
+        Self::new(
+            ControlKind::Window(class.as_ref().to_owned()),
+            parent,
+            WindowGeometry::default(),
+        )
+    }
+
     pub fn edit_control(parent: WindowRef) -> WindowBuilder {
-        WindowBuilder {
-            kind: ControlKind::Edit,
-            title: String::new(),
-            geometry: WindowGeometry::zero(),
-            style: 0,
-            extended_style: 0,
-            parent: Some(parent),
-            handler: Arc::new(DummyMessageHandler),
-            font: None,
-            icon: None,
-            sys_menu_items: Vec::new(),
-        }
+        Self::new(ControlKind::Edit, Some(parent), WindowGeometry::zero())
+    }
+
+    pub fn rich_edit_control(parent: WindowRef) -> WindowBuilder {
+        Self::new(ControlKind::RichEdit, Some(parent), WindowGeometry::zero())
+    }
+
+    pub fn list_view(parent: WindowRef) -> WindowBuilder {
+        Self::new(ControlKind::ListView, Some(parent), WindowGeometry::zero())
+    }
+
+    pub fn button(parent: WindowRef) -> WindowBuilder {
+        Self::new(ControlKind::Button, Some(parent), WindowGeometry::zero())
+    }
+
+    // The height includes the drop-down list
+    pub fn combo_box(parent: WindowRef) -> WindowBuilder {
+        Self::new(ControlKind::ComboBox, Some(parent), WindowGeometry::zero())
+    }
+
+    // Positions itself at the bottom of the parent when the parent forwards WM_SIZE
+    pub fn status_bar(parent: WindowRef) -> WindowBuilder {
+        Self::new(ControlKind::StatusBar, Some(parent), WindowGeometry::zero())
+    }
+
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
     }
 
     pub fn title<T>(mut self, title: T) -> Self
@@ -202,40 +325,302 @@ impl WindowBuilder {
         self
     }
 
+    pub fn menu(mut self, menu: Menu) -> Self {
+        self.menus.push(menu);
+        self
+    }
+
+    // Keyboard shortcuts of a top-level window, translated by MessageLoop::run into
+    // WM_COMMAND with the id of the corresponding menu item
+    pub fn accelerators(mut self, accelerators: &[Accelerator]) -> Self {
+        self.accelerators.extend_from_slice(accelerators);
+        self
+    }
+
+    // Top-level window which stays above the owner and is not its child
+    pub fn owner(mut self, owner: &WindowRef) -> Self {
+        self.owner = Some(owner.handle());
+        self
+    }
+
+    pub fn visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
     pub fn build(mut self) -> Result<WindowRef, WindowError> {
         let window = Arc::new(Window {
             proxy: WinProxy::new(),
             children: Default::default(),
             handler: self.handler.clone(),
+            layout: OnceLock::new(),
         });
 
         if let Some(parent) = self.parent.as_mut() {
             parent.add_child(window.clone());
         }
 
-        window.proxy().create(&self, window.clone())?;
+        if let Err(e) = window.proxy().create(&self, window.clone()) {
+            window.proxy().release_owner();
+            return Err(e);
+        }
 
         Ok(window)
     }
 }
 
+// Strip of tabs, the pages are sibling windows placed below it and shown by the
+// owner when TCN_SELCHANGE is received
+pub struct TabControl {
+    window: WindowRef,
+}
+
+impl TabControl {
+    pub fn new<T>(parent: WindowRef, tabs: &[T]) -> Result<TabControl, WindowError>
+    where
+        T: AsRef<str>,
+    {
+        let window = WindowBuilder::new(
+            ControlKind::TabControl,
+            Some(parent),
+            WindowGeometry::zero(),
+        )
+        .style((WS_CHILD | WS_VISIBLE | WS_CLIPSIBLINGS).0)
+        .font(Font::new(15, "Segoe UI"))
+        .build()?;
+
+        for (index, title) in tabs.iter().enumerate() {
+            window.proxy().insert_tab(index, title.as_ref());
+        }
+
+        Ok(TabControl { window })
+    }
+
+    pub fn window(&self) -> &WindowRef {
+        &self.window
+    }
+
+    pub fn selected(&self) -> usize {
+        self.window.proxy().selected_tab()
+    }
+
+    // Does not send TCN_SELCHANGE to the parent
+    pub fn select(&self, index: usize) {
+        self.window.proxy().select_tab(index)
+    }
+
+    // Height of the tab strip when the control is as wide as the given width
+    pub fn strip_height(&self, width: i32) -> i32 {
+        self.window.proxy().tab_strip_height(width)
+    }
+}
+
+// Hover text for the controls of a window, one tooltip serves all of them
+pub struct Tooltip {
+    window: WindowRef,
+}
+
+impl Tooltip {
+    pub fn new(parent: WindowRef) -> Result<Tooltip, WindowError> {
+        let window = WindowBuilder::new(ControlKind::Tooltip, Some(parent), WindowGeometry::zero())
+            .style(WS_POPUP.0 | TTS_ALWAYSTIP | TTS_NOPREFIX)
+            .extended_style(WS_EX_TOPMOST.0)
+            .visible(false)
+            .build()?;
+
+        Ok(Tooltip { window })
+    }
+
+    pub fn window(&self) -> &WindowRef {
+        &self.window
+    }
+
+    // The control must be a child of the tooltip parent
+    pub fn add_tool(&self, control: &WindowRef, text: &str) {
+        self.window.proxy().add_tool(control.handle(), text)
+    }
+
+    pub fn set_tool_text(&self, control: &WindowRef, text: &str) {
+        self.window.proxy().set_tool_text(control.handle(), text)
+    }
+}
+
+// Horizontal bar showing the progress of an operation between 0 and the range maximum
+pub struct ProgressBar {
+    window: WindowRef,
+}
+
+impl ProgressBar {
+    pub fn new(parent: WindowRef) -> Result<ProgressBar, WindowError> {
+        let window = WindowBuilder::new(
+            ControlKind::ProgressBar,
+            Some(parent),
+            WindowGeometry::zero(),
+        )
+        .style((WS_CHILD | WS_CLIPSIBLINGS).0 | PBS_SMOOTH)
+        .visible(false)
+        .build()?;
+
+        Ok(ProgressBar { window })
+    }
+
+    pub fn window(&self) -> &WindowRef {
+        &self.window
+    }
+
+    fn send(&self, msg: u32, wparam: usize, lparam: isize) {
+        self.window
+            .send_message(WindowMessage::new(self.window.clone(), msg, wparam, lparam));
+    }
+
+    // The range starts at 0, the default maximum is 100
+    pub fn set_range(&self, max: u32) {
+        self.send(PBM_SETRANGE32, 0, max as _);
+    }
+
+    pub fn set_position(&self, position: u32) {
+        self.send(PBM_SETPOS, position as _, 0);
+    }
+}
+
+// Window messages with the parameters unpacked, messages which are not listed
+// here are reported as Other and must be decoded by the handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEvent {
+    Create,
+    // new client area size
+    Size {
+        width: i32,
+        height: i32,
+    },
+    // id is the menu item, accelerator or control id, code is the notification
+    // code of a control or 0 for menus
+    Command {
+        id: u32,
+        code: u32,
+    },
+    Notify {
+        from: WindowHandle,
+        code: u32,
+    },
+    Timer {
+        id: usize,
+    },
+    // right click or the menu key over the window or one of its children, the
+    // position is in screen coordinates and is None for the keyboard
+    ContextMenu {
+        from: WindowHandle,
+        position: Option<(i32, i32)>,
+    },
+    // the delta is positive when the wheel is rotated forward, a multiple of 120
+    MouseWheel {
+        delta: i32,
+        ctrl: bool,
+    },
+    Close,
+    Destroy,
+    // app-defined message, see user_message
+    Custom {
+        msg: u32,
+        wparam: usize,
+        lparam: isize,
+    },
+    Other(u32),
+}
+
 #[derive(Debug, Clone)]
 pub struct WindowMessage {
     pub window: WindowRef,
     pub msg: u32,
     pub wparam: usize,
     pub lparam: isize,
+    // received by a window procedure, only then lparam may be used as a pointer
+    received: bool,
 }
 
 impl WindowMessage {
+    // A message to send, its lparam is never used as a pointer by event
     pub fn new(window: WindowRef, msg: u32, wparam: usize, lparam: isize) -> WindowMessage {
         WindowMessage {
             window,
             msg,
             wparam,
             lparam,
+            received: false,
+        }
+    }
+
+    // Safety: the parameters must be the ones passed by Windows to a window procedure,
+    // so that the lparam of a message like WM_NOTIFY points to its structure
+    pub(crate) unsafe fn received(
+        window: WindowRef,
+        msg: u32,
+        wparam: usize,
+        lparam: isize,
+    ) -> WindowMessage {
+        WindowMessage {
+            received: true,
+            ..WindowMessage::new(window, msg, wparam, lparam)
+        }
+    }
+
+    // True if the message was received by a window procedure, the structures pointed
+    // to by lparam may only be read then
+    pub fn is_received(&self) -> bool {
+        self.received
+    }
+
+    pub fn event(&self) -> WindowEvent {
+        let low = |value: isize| (value & 0xffff) as u16;
+        let high = |value: isize| ((value >> 16) & 0xffff) as u16;
+
+        match self.msg {
+            WM_CREATE => WindowEvent::Create,
+            WM_SIZE => WindowEvent::Size {
+                width: low(self.lparam) as i32,
+                height: high(self.lparam) as i32,
+            },
+            WM_COMMAND => WindowEvent::Command {
+                id: low(self.wparam as isize) as u32,
+                code: high(self.wparam as isize) as u32,
+            },
+            WM_NOTIFY if self.received => {
+                // lparam of WM_NOTIFY always points to a structure starting with NMHDR
+                let header = unsafe { &*(self.lparam as *const NMHDR) };
+                WindowEvent::Notify {
+                    from: header.hwndFrom,
+                    code: header.code,
+                }
+            }
+            WM_TIMER => WindowEvent::Timer { id: self.wparam },
+            WM_CONTEXTMENU => WindowEvent::ContextMenu {
+                from: HWND(self.wparam as _),
+                position: (self.lparam as i32 != -1).then(|| {
+                    (
+                        low(self.lparam) as i16 as i32,
+                        high(self.lparam) as i16 as i32,
+                    )
+                }),
+            },
+            WM_MOUSEWHEEL => WindowEvent::MouseWheel {
+                delta: high(self.wparam as isize) as i16 as i32,
+                ctrl: low(self.wparam as isize) as u32 & MK_CONTROL.0 != 0,
+            },
+            WM_CLOSE => WindowEvent::Close,
+            WM_DESTROY => WindowEvent::Destroy,
+            msg if USER_MESSAGES.contains(&msg) => WindowEvent::Custom {
+                msg,
+                wparam: self.wparam,
+                lparam: self.lparam,
+            },
+            msg => WindowEvent::Other(msg),
         }
     }
+
+    // Must only be called once for the WM_DROPFILES message
+    pub fn dropped_files(&self) -> Vec<PathBuf> {
+        query_dropped_files(self.wparam)
+    }
 }
 
 pub enum MessageResult {
@@ -248,6 +633,7 @@ pub struct Window {
     pub(crate) proxy: *mut WinProxy,
     pub(crate) children: RwLock<Vec<WindowRef>>,
     pub(crate) handler: Arc<dyn WindowMessageHandler + Send + Sync + 'static>,
+    pub(crate) layout: OnceLock<Layout>,
 }
 
 unsafe impl Send for Window {}
@@ -275,14 +661,61 @@ impl Window {
         MessageResult::Value(result.0)
     }
 
+    // Queues the message for the UI thread without waiting for it to be handled,
+    // safe to call from any thread. The parameters must not point to borrowed data.
+    pub fn post_message(&self, msg: u32, wparam: usize, lparam: isize) -> Result<(), WindowError> {
+        self.proxy().post_message(msg, wparam, lparam)
+    }
+
+    // Posts a message declared with user_message
+    pub fn post_user_message(
+        &self,
+        msg: u32,
+        wparam: usize,
+        lparam: isize,
+    ) -> Result<(), WindowError> {
+        debug_assert!(USER_MESSAGES.contains(&msg), "not an app-defined message");
+        self.post_message(msg, wparam, lparam)
+    }
+
     pub fn move_window(&self, geometry: WindowGeometry) {
         self.proxy().move_window(geometry)
     }
 
+    pub fn client_size(&self) -> (i32, i32) {
+        self.proxy().client_size()
+    }
+
+    // Outer width and height of the window
+    pub fn size(&self) -> (i32, i32) {
+        self.proxy().size()
+    }
+
     pub fn handle(&self) -> WindowHandle {
         self.proxy().handle()
     }
 
+    // Control id given with WindowBuilder::id, 0 for a top-level window
+    pub fn id(&self) -> u32 {
+        self.proxy().id()
+    }
+
+    // The layout is applied after the handler has processed WM_SIZE
+    pub fn set_layout(&self, layout: Layout) {
+        let _ = self.layout.set(layout);
+    }
+
+    pub fn layout(&self) -> Option<&Layout> {
+        self.layout.get()
+    }
+
+    pub fn update_layout(&self) {
+        if let Some(layout) = self.layout.get() {
+            let (width, height) = self.client_size();
+            layout.apply(width, height);
+        }
+    }
+
     pub fn add_child(&self, child: WindowRef) {
         self.children.write().unwrap().push(child)
     }
@@ -291,6 +724,131 @@ impl Window {
         self.proxy().check_sys_menu_item(item, flag)
     }
 
+    pub fn set_font(&self, font: &Font) {
+        self.proxy().set_font(font)
+    }
+
+    pub fn choose_font(&self, initial: &Font) -> Option<Font> {
+        self.proxy().choose_font(initial)
+    }
+
+    // File dialogs owned by this window, filters are (description, pattern) pairs
+    // like ("Log files", "*.log"). None is returned when the dialog is cancelled.
+    pub fn open_file(&self, title: &str, filters: &[(&str, &str)]) -> Option<PathBuf> {
+        self.proxy().open_file(title, filters)
+    }
+
+    // The initial path selects the folder and the proposed file name
+    pub fn save_file(
+        &self,
+        title: &str,
+        filters: &[(&str, &str)],
+        initial: &Path,
+    ) -> Option<PathBuf> {
+        self.proxy().save_file(title, filters, initial)
+    }
+
+    pub fn choose_folder(&self, title: &str, initial: Option<&Path>) -> Option<PathBuf> {
+        self.proxy().choose_folder(title, initial)
+    }
+
+    // Shows the menu until an item is chosen or the menu is dismissed, the chosen item
+    // is sent to WindowMessageHandler::handle_command of this window. Without a position
+    // the menu is shown at the control which has the keyboard focus.
+    pub fn show_context_menu(
+        &self,
+        menu: &Menu,
+        position: Option<(i32, i32)>,
+    ) -> Result<(), WindowError> {
+        self.proxy().show_context_menu(menu, position)
+    }
+
+    pub fn check_menu_item(&self, item: u32, flag: bool) {
+        self.proxy().check_menu_item(item, flag)
+    }
+
+    // Replaces the entries above the anchor item in the menu bar popup which contains
+    // it with the entries of the menu, used for the lists which change at run time
+    pub fn replace_menu_entries(&self, anchor: u32, menu: &Menu) -> Result<(), WindowError> {
+        self.proxy().replace_menu_entries(anchor, menu)
+    }
+
+    // Shows the window restoring it if minimized and brings it to the foreground
+    pub fn show(&self) {
+        self.proxy().show()
+    }
+
+    pub fn hide(&self) {
+        self.proxy().hide()
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.proxy().is_visible()
+    }
+
+    // Shows or hides a child window without activating it
+    pub fn set_visible(&self, visible: bool) {
+        self.proxy().set_visible(visible)
+    }
+
+    // Starts or restarts the timer with the given id, WM_TIMER is delivered to
+    // WindowMessageHandler::handle_timer every interval
+    pub fn set_timer(&self, id: usize, interval: Duration) -> Result<(), WindowError> {
+        self.proxy().set_timer(id, interval)
+    }
+
+    pub fn kill_timer(&self, id: usize) {
+        self.proxy().kill_timer(id)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.proxy().set_enabled(enabled)
+    }
+
+    // Moves the window to the center of the other window keeping its size
+    pub fn center_on(&self, other: &WindowRef) {
+        self.proxy().center_on(other.handle())
+    }
+
+    // Moves the keyboard focus to the window
+    pub fn set_focus(&self) {
+        self.proxy().set_focus()
+    }
+
+    pub fn has_focus(&self) -> bool {
+        self.proxy().has_focus()
+    }
+
+    // Name announced by screen readers, needed for the controls without a caption
+    // like edits, lists and custom windows
+    pub fn set_accessible_name(&self, name: &str) -> Result<(), WindowError> {
+        self.proxy().set_accessible_name(name)
+    }
+
+    // Passes the messages of the window to the filter first, the messages the filter
+    // ignores are handled as before. Used to change the behavior of standard controls.
+    pub fn subclass<F>(self: &Arc<Self>, filter: F) -> Result<(), WindowError>
+    where
+        F: Fn(&WindowMessage) -> MessageResult + Send + Sync + 'static,
+    {
+        self.proxy()
+            .subclass(Arc::downgrade(self), Box::new(filter))
+    }
+
+    // False once the native window is destroyed, the methods of a closed window do nothing
+    pub fn is_open(&self) -> bool {
+        self.proxy().is_open()
+    }
+
+    // Destroys the native window, the object itself is released when the last reference is dropped
+    pub fn close(&self) {
+        self.proxy().close()
+    }
+
+    pub fn flash_taskbar(&self) {
+        self.proxy().flash_taskbar()
+    }
+
     pub fn get_text(&self) -> Result<String, WindowError> {
         self.proxy().get_text()
     }
@@ -298,6 +856,40 @@ impl Window {
     pub fn set_text(&self, text: &str) -> Result<(), WindowError> {
         self.proxy().set_text(text)
     }
+
+    pub fn selection(&self) -> (usize, usize) {
+        self.proxy().selection()
+    }
+
+    pub fn copy_to_clipboard(&self, text: &str) -> Result<(), WindowError> {
+        self.proxy().copy_to_clipboard(text)
+    }
+
+    // Appends an item to a combo box and returns its index
+    pub fn add_item(&self, text: &str) -> usize {
+        self.proxy().add_item(text)
+    }
+
+    pub fn clear_items(&self) {
+        self.proxy().clear_items()
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.proxy().item_count()
+    }
+
+    pub fn item_text(&self, index: usize) -> Option<String> {
+        self.proxy().item_text(index)
+    }
+
+    pub fn selected_item(&self) -> Option<usize> {
+        self.proxy().selected_item()
+    }
+
+    // Does not send CBN_SELCHANGE to the parent
+    pub fn select_item(&self, index: usize) {
+        self.proxy().select_item(index)
+    }
 }
 
 impl Drop for Window {
@@ -310,4 +902,15 @@ pub trait WindowMessageHandler {
     fn handle_message(&self, _message: WindowMessage) -> MessageResult {
         MessageResult::Ignored
     }
+
+    // WM_COMMAND sent by a menu item or an accelerator, called before handle_message
+    // which receives the message if the command is ignored
+    fn handle_command(&self, _window: &WindowRef, _id: u32) -> MessageResult {
+        MessageResult::Ignored
+    }
+
+    // WM_TIMER of a timer started with Window::set_timer
+    fn handle_timer(&self, _window: &WindowRef, _id: usize) -> MessageResult {
+        MessageResult::Ignored
+    }
 }
@@ -0,0 +1,288 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::{
+    audit, http_client,
+    listener::safe_file_name,
+    settings::AppSettings,
+    ui::window::{user_message, WindowRef},
+    util,
+};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/ancwrd1/miniraw/releases/latest";
+const RELEASE_PAGE_URL: &str = "https://github.com/ancwrd1/miniraw/releases/tag/";
+// the first asset with one of these extensions is downloaded
+const INSTALLER_EXTENSIONS: [&str; 3] = [".msi", ".exe", ".zip"];
+// next to the executable
+const UPDATES_DIR_NAME: &str = "updates";
+
+// Posted to the main window when a check is finished, wparam is 1 if the check
+// was requested by the user
+pub const WM_UPDATE_CHECKED: u32 = user_message(12);
+
+pub struct Release {
+    pub version: String,
+    pub page_url: String,
+    installer_url: Option<String>,
+    // "sha256:<hex>" computed by GitHub for the uploaded asset
+    installer_digest: Option<String>,
+    // the downloaded installer
+    pub installer: Option<PathBuf>,
+}
+
+pub enum UpdateCheck {
+    UpToDate,
+    Available(Release),
+    Failed(String),
+}
+
+// "v1.2.3" or "1.2.3" as [1, 2, 3]
+fn parse_version(text: &str) -> Option<Vec<u32>> {
+    text.trim()
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect()
+}
+
+fn is_newer(version: &str, current: &str) -> bool {
+    match (parse_version(version), parse_version(current)) {
+        (Some(version), Some(current)) => version > current,
+        _ => false,
+    }
+}
+
+// The string values of the key anywhere in the JSON text, enough for the few fields
+// of the release
+fn json_string_values(json: &str, key: &str) -> Vec<String> {
+    let pattern = format!("\"{key}\"");
+    let mut values = Vec::new();
+    let mut rest = json;
+    while let Some(index) = rest.find(&pattern) {
+        rest = &rest[index + pattern.len()..];
+        let Some(value) = rest
+            .trim_start()
+            .strip_prefix(':')
+            .and_then(|value| value.trim_start().strip_prefix('"'))
+        else {
+            continue;
+        };
+
+        let mut text = String::new();
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('u') => {
+                        let code = chars.by_ref().take(4).collect::<String>();
+                        text.push(
+                            u32::from_str_radix(&code, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .unwrap_or(char::REPLACEMENT_CHARACTER),
+                        );
+                    }
+                    Some(c) => text.push(c),
+                    None => break,
+                },
+                c => text.push(c),
+            }
+        }
+        values.push(text);
+    }
+    values
+}
+
+// The objects of the "assets" array of the release, so that the fields of an asset
+// are not mixed up with those of another one
+fn asset_objects(json: &str) -> Vec<&str> {
+    let Some(array) = json
+        .find("\"assets\"")
+        .and_then(|start| json[start..].find('[').map(|index| start + index + 1))
+    else {
+        return Vec::new();
+    };
+    let body = &json[array..];
+    let mut objects = Vec::new();
+    let mut depth = 0;
+    let mut object_start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in body.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => {
+                if depth == 0 {
+                    object_start = index;
+                }
+                depth += 1;
+            }
+            '}' | ']' => {
+                // the end of the array
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+                if depth == 0 && c == '}' {
+                    objects.push(&body[object_start..=index]);
+                }
+            }
+            _ => {}
+        }
+    }
+    objects
+}
+
+fn fetch_latest_release() -> Result<Release, String> {
+    let headers = [("Accept", "application/vnd.github+json")];
+    let response =
+        http_client::send("GET", LATEST_RELEASE_URL, &headers, &[]).map_err(|e| e.to_string())?;
+    if response.status != 200 {
+        return Err(format!("HTTP status {}", response.status));
+    }
+
+    let json = String::from_utf8_lossy(&response.body);
+    let Some(version) = json_string_values(&json, "tag_name").into_iter().next() else {
+        return Err("No release found".to_owned());
+    };
+    let assets = asset_objects(&json)
+        .into_iter()
+        .filter_map(|asset| {
+            let url = json_string_values(asset, "browser_download_url")
+                .into_iter()
+                .next()?;
+            let digest = json_string_values(asset, "digest").into_iter().next();
+            Some((url, digest))
+        })
+        .collect::<Vec<_>>();
+    let installer = INSTALLER_EXTENSIONS.iter().find_map(|extension| {
+        assets
+            .iter()
+            .find(|(url, _)| url.to_ascii_lowercase().ends_with(extension))
+            .cloned()
+    });
+    let (installer_url, installer_digest) = installer.unzip();
+    Ok(Release {
+        page_url: format!("{RELEASE_PAGE_URL}{version}"),
+        version: version.trim_start_matches('v').to_owned(),
+        installer_url,
+        installer_digest: installer_digest.flatten(),
+        installer: None,
+    })
+}
+
+// The installer is offered only if its SHA-256 hash is the digest of the release
+// asset, an installer downloaded by an earlier check is not downloaded again
+fn download_installer(url: &str, digest: Option<&str>) -> Result<PathBuf, String> {
+    let Some(expected) = digest.and_then(|digest| digest.strip_prefix("sha256:")) else {
+        return Err("the release has no SHA-256 digest of the installer".to_owned());
+    };
+    // Windows drops the trailing dots, so ".." would be the parent directory
+    let name = safe_file_name(url.rsplit('/').next().unwrap_or_default());
+    let name = name.trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        return Err(format!("Invalid file name: {}", url));
+    }
+    let dir = util::app_dir().join(UPDATES_DIR_NAME);
+    let path = dir.join(name);
+    let is_valid = |path: &PathBuf| {
+        audit::file_sha256(path).is_ok_and(|hash| hash.eq_ignore_ascii_case(expected))
+    };
+    if !is_valid(&path) {
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        http_client::download(url, &path).map_err(|e| e.to_string())?;
+        if !is_valid(&path) {
+            let _ = fs::remove_file(&path);
+            return Err("the SHA-256 hash of the download does not match the release".to_owned());
+        }
+    }
+    Ok(path)
+}
+
+// Checks the latest GitHub release every update_check_hours and when requested
+// from the Help menu, the installer is downloaded with the download_updates setting
+pub struct UpdateChecker {
+    settings: Arc<AppSettings>,
+    window: WindowRef,
+    last_check: Mutex<Option<Instant>>,
+    result: Mutex<Option<UpdateCheck>>,
+}
+
+impl UpdateChecker {
+    pub fn new(settings: Arc<AppSettings>, window: WindowRef) -> UpdateChecker {
+        UpdateChecker {
+            settings,
+            window,
+            last_check: Default::default(),
+            result: Default::default(),
+        }
+    }
+
+    // The automatic check is enabled and was not done for update_check_hours
+    pub fn is_due(&self) -> bool {
+        let hours = self.settings.update_check_hours.load(Ordering::SeqCst);
+        hours != 0
+            && self
+                .last_check
+                .lock()
+                .unwrap()
+                .is_none_or(|time| time.elapsed() >= Duration::from_secs(hours as u64 * 3600))
+    }
+
+    // The check runs on a background thread, the main window takes the result when
+    // it gets WM_UPDATE_CHECKED
+    pub fn start(self: &Arc<Self>, manual: bool) {
+        *self.last_check.lock().unwrap() = Some(Instant::now());
+        let checker = self.clone();
+        std::thread::spawn(move || {
+            let result = checker.check();
+            *checker.result.lock().unwrap() = Some(result);
+            let _ = checker
+                .window
+                .post_user_message(WM_UPDATE_CHECKED, manual as usize, 0);
+        });
+    }
+
+    pub fn take_result(&self) -> Option<UpdateCheck> {
+        self.result.lock().unwrap().take()
+    }
+
+    fn check(&self) -> UpdateCheck {
+        let mut release = match fetch_latest_release() {
+            Ok(release) => release,
+            Err(e) => return UpdateCheck::Failed(e),
+        };
+        if !is_newer(&release.version, env!("CARGO_PKG_VERSION")) {
+            return UpdateCheck::UpToDate;
+        }
+
+        if self.settings.download_updates.load(Ordering::SeqCst) {
+            if let Some(ref url) = release.installer_url {
+                match download_installer(url, release.installer_digest.as_deref()) {
+                    Ok(path) => release.installer = Some(path),
+                    Err(e) => warn!("Cannot download {}: {}", url, e),
+                }
+            }
+        }
+        UpdateCheck::Available(release)
+    }
+}
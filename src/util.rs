@@ -1,6 +1,52 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use windows::{
+    core::PCWSTR,
+    Win32::{Foundation::COLORREF, Storage::FileSystem::GetDiskFreeSpaceExW},
+};
+
 #[macro_export]
 macro_rules! utf16z {
     ($str: expr) => {
         $str.encode_utf16().chain([0]).collect::<Vec<_>>()
     };
 }
+
+pub const fn rgb(r: u8, g: u8, b: u8) -> COLORREF {
+    COLORREF(r as u32 | (g as u32) << 8 | (b as u32) << 16)
+}
+
+// Directory of the executable where received jobs and saved logs are stored
+pub fn app_dir() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.to_owned()))
+        .unwrap_or_default()
+}
+
+// Compares the secrets in a time which depends only on their lengths, so that the
+// time of a failed comparison does not tell how much of a guess was right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Bytes available to the user on the volume of the path, which the disk quota of
+// the user can limit, and the free bytes of the volume
+pub fn free_space(path: &Path) -> Option<(u64, u64)> {
+    let path = utf16z!(path.as_os_str().to_string_lossy());
+    let mut available = 0u64;
+    let mut free = 0u64;
+    unsafe {
+        GetDiskFreeSpaceExW(
+            PCWSTR(path.as_ptr()),
+            Some(&mut available),
+            None,
+            Some(&mut free),
+        )
+    }
+    .ok()
+    .map(|_| (available, free))
+}
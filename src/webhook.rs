@@ -0,0 +1,113 @@
+use std::{
+    env,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+};
+
+use log::warn;
+
+use crate::{
+    api::job_json,
+    http::json_string,
+    http_client,
+    jobs::{JobEvent, ListenerState},
+    settings::AppSettings,
+};
+
+// Notifications waiting to be sent, newer ones are dropped while the queue is full
+const QUEUE_SIZE: usize = 100;
+
+// Posts a JSON notification to the webhook_url setting when a job is received or
+// aborted and when the listener fails. The "text" field is shown by Teams and Slack
+// webhooks, the other fields are meant for the automation. The requests are sent
+// one by one on a background thread, so the listener threads never wait for them.
+pub struct WebhookNotifier {
+    settings: Arc<AppSettings>,
+    sender: SyncSender<String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(settings: Arc<AppSettings>) -> WebhookNotifier {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+        {
+            let settings = settings.clone();
+            std::thread::spawn(move || Self::send_payloads(&settings, receiver));
+        }
+        WebhookNotifier { settings, sender }
+    }
+
+    fn send_payloads(settings: &AppSettings, receiver: Receiver<String>) {
+        for payload in receiver {
+            let url = settings.webhook_url.read().unwrap().clone();
+            if url.is_empty() {
+                continue;
+            }
+            let headers = [("Content-Type", "application/json")];
+            match http_client::send("POST", &url, &headers, payload.as_bytes()) {
+                Ok(response) if (200..300).contains(&response.status) => {}
+                Ok(response) => warn!("The webhook returned HTTP status {}", response.status),
+                Err(e) => warn!("Cannot send the webhook notification: {}", e),
+            }
+        }
+    }
+
+    // The job content URL of the API, the token is still needed to download it
+    fn download_url(&self, id: u32) -> Option<String> {
+        let address = self.settings.api_address()?;
+        // the socket address puts an IPv6 address into brackets
+        let host = if address.ip().is_unspecified() {
+            let name = env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_owned());
+            format!("{}:{}", name, address.port())
+        } else {
+            address.to_string()
+        };
+        Some(format!("http://{}/api/jobs/{}/content", host, id))
+    }
+
+    pub fn handle_event(&self, event: &JobEvent) {
+        if self.settings.webhook_url.read().unwrap().is_empty() {
+            return;
+        }
+
+        let (name, text, fields) = match event {
+            JobEvent::Completed(job) => (
+                "job_completed",
+                format!(
+                    "Print job received from {} ({} bytes, {})",
+                    job.source, job.size, job.format
+                ),
+                format!(
+                    "\"job\":{},\"download_url\":{}",
+                    job_json(job),
+                    self.download_url(job.connection.0)
+                        .map(|url| json_string(&url))
+                        .unwrap_or_else(|| "null".to_owned())
+                ),
+            ),
+            JobEvent::Aborted { source } => (
+                "job_aborted",
+                format!("The print job from {} was aborted", source),
+                format!("\"source\":{}", json_string(&source.to_string())),
+            ),
+            JobEvent::Listener(ListenerState::Failed(error)) => (
+                "listener_failed",
+                format!("Listener failed: {}", error),
+                format!("\"error\":{}", json_string(error)),
+            ),
+            _ => return,
+        };
+
+        let payload = format!(
+            "{{\"event\":{},\"text\":{},\"host\":{},{}}}",
+            json_string(name),
+            json_string(&format!("MiniRAW NG: {text}")),
+            json_string(&env::var("COMPUTERNAME").unwrap_or_default()),
+            fields
+        );
+        if self.sender.try_send(payload).is_err() {
+            warn!("Too many webhook notifications, one was dropped");
+        }
+    }
+}
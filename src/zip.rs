@@ -0,0 +1,140 @@
+use std::io::{self, Write};
+
+use time::OffsetDateTime;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const VERSION: u16 = 20;
+// the names are stored in UTF-8
+const FLAGS: u16 = 0x0800;
+const METHOD_STORED: u16 = 0;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+// Writes a ZIP archive with the entries stored without compression,
+// which is enough for the small text files of the diagnostics
+pub struct ZipWriter<W: Write> {
+    inner: W,
+    entries: Vec<Entry>,
+    offset: u32,
+    // modification time of all entries in the MS-DOS format
+    dos_time: u16,
+    dos_date: u16,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W, time: OffsetDateTime) -> Self {
+        let (hour, minute, second) = time.to_hms();
+        ZipWriter {
+            inner,
+            entries: Vec::new(),
+            offset: 0,
+            dos_time: ((hour as u16) << 11) | ((minute as u16) << 5) | (second as u16 / 2),
+            dos_date: (((time.year() - 1980).clamp(0, 127) as u16) << 9)
+                | ((time.month() as u16) << 5)
+                | time.day() as u16,
+        }
+    }
+
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.inner.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.inner.write_all(&value.to_le_bytes())
+    }
+
+    // Fields shared by the local and the central headers, from the version needed
+    // to extract up to the extra field length
+    fn write_common_header(&mut self, entry_index: usize) -> io::Result<()> {
+        let (crc, size, name_len) = {
+            let entry = &self.entries[entry_index];
+            (entry.crc, entry.size, entry.name.len() as u16)
+        };
+        self.write_u16(VERSION)?;
+        self.write_u16(FLAGS)?;
+        self.write_u16(METHOD_STORED)?;
+        self.write_u16(self.dos_time)?;
+        self.write_u16(self.dos_date)?;
+        self.write_u32(crc)?;
+        self.write_u32(size)?;
+        self.write_u32(size)?;
+        self.write_u16(name_len)?;
+        self.write_u16(0)
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let size = u32::try_from(data.len()).map_err(io::Error::other)?;
+        self.entries.push(Entry {
+            name: name.to_owned(),
+            crc: crc32(data),
+            size,
+            offset: self.offset,
+        });
+
+        self.write_u32(LOCAL_HEADER_SIGNATURE)?;
+        self.write_common_header(self.entries.len() - 1)?;
+        self.inner.write_all(name.as_bytes())?;
+        self.inner.write_all(data)?;
+
+        self.offset += 30 + name.len() as u32 + size;
+        Ok(())
+    }
+
+    // Writes the central directory and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let directory_offset = self.offset;
+        let mut directory_size = 0;
+
+        for index in 0..self.entries.len() {
+            self.write_u32(CENTRAL_HEADER_SIGNATURE)?;
+            self.write_u16(VERSION)?;
+            self.write_common_header(index)?;
+            // comment length, disk number, internal and external attributes
+            self.write_u16(0)?;
+            self.write_u16(0)?;
+            self.write_u16(0)?;
+            self.write_u32(0)?;
+
+            let entry = &self.entries[index];
+            let (offset, name) = (entry.offset, entry.name.clone());
+            self.write_u32(offset)?;
+            self.inner.write_all(name.as_bytes())?;
+            directory_size += 46 + name.len() as u32;
+        }
+
+        let count = self.entries.len() as u16;
+        self.write_u32(END_SIGNATURE)?;
+        self.write_u16(0)?;
+        self.write_u16(0)?;
+        self.write_u16(count)?;
+        self.write_u16(count)?;
+        self.write_u32(directory_size)?;
+        self.write_u32(directory_offset)?;
+        self.write_u16(0)?;
+
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}